@@ -0,0 +1,25 @@
+#version 120
+// CRT look: curved glass, dark gaps between the lines and a dimmer rim.
+// chip-8 config: shader = "shaders/crt.glsl"
+
+uniform sampler2D frame;
+uniform vec2 frame_size;
+uniform vec2 output_size;
+uniform float time;
+
+void main() {
+    vec2 uv = gl_TexCoord[0].xy * 2.0 - 1.0;
+    uv *= 1.0 + dot(uv, uv) * 0.04;
+    uv = uv * 0.5 + 0.5;
+    if (uv.x < 0.0 || uv.x > 1.0 || uv.y < 0.0 || uv.y > 1.0) {
+        gl_FragColor = vec4(0.0, 0.0, 0.0, 1.0);
+        return;
+    }
+
+    vec3 color = texture2D(frame, uv).rgb;
+    float line = abs(sin(uv.y * frame_size.y * 3.14159265));
+    color *= 0.7 + 0.3 * line;
+    vec2 edge = uv * (1.0 - uv);
+    color *= pow(edge.x * edge.y * 16.0, 0.2);
+    gl_FragColor = vec4(color, 1.0);
+}
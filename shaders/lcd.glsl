@@ -0,0 +1,20 @@
+#version 120
+// LCD look: a thin darker grid between the pixels, like the cells of a handheld screen.
+// chip-8 config: shader = "shaders/lcd.glsl"
+
+uniform sampler2D frame;
+uniform vec2 frame_size;
+uniform vec2 output_size;
+uniform float time;
+
+void main() {
+    vec2 uv = gl_TexCoord[0].xy;
+    vec3 color = texture2D(frame, uv).rgb;
+
+    // Position inside the pixel's cell, and one output pixel in the same units.
+    vec2 cell = fract(uv * frame_size);
+    vec2 line = frame_size / output_size;
+    vec2 grid = step(cell, line);
+    color *= 1.0 - 0.35 * max(grid.x, grid.y);
+    gl_FragColor = vec4(color, 1.0);
+}
@@ -0,0 +1,244 @@
+use crate::game_config::GameConfig;
+use crate::i18n::tr_with;
+use crate::text::{Anchor, draw_label};
+use chip_8::Chip8;
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fmt;
+use std::str::FromStr;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tracing::info;
+
+/// How long a toast stays on screen.
+const TOAST_TIME: Duration = Duration::from_secs(3);
+
+const GOLD: Color = Color::RGB(255, 200, 40);
+
+/// Value a condition looks at.
+#[derive(Clone, Copy, PartialEq)]
+enum Operand {
+    Register(u8),
+    I,
+    Pc,
+    Dt,
+    St,
+    /// Byte of RAM, written `[0x3F0]`
+    Ram(u16),
+    /// Current score, see `GameConfig::score`
+    Score,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Ge,
+    Le,
+    Gt,
+    Lt,
+}
+
+/// Operators as written, two-character ones first so `>=` is not read as `>`.
+const COMPARISONS: [(&str, Comparison); 6] = [
+    ("==", Comparison::Eq),
+    ("!=", Comparison::Ne),
+    (">=", Comparison::Ge),
+    ("<=", Comparison::Le),
+    (">", Comparison::Gt),
+    ("<", Comparison::Lt),
+];
+
+/// Comparisons over machine state that all have to hold, e.g. `score >= 100 && [0x3F0] == 5`.
+/// Operands are `V0`-`VF`, `I`, `PC`, `DT`, `ST`, a RAM byte `[addr]` or `score`,
+/// compared with a decimal or `0x` hex number.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Condition {
+    terms: Vec<(Operand, Comparison, u32)>,
+}
+
+impl Condition {
+    pub fn holds(&self, chip8: &Chip8, game: &GameConfig) -> bool {
+        self.terms.iter().all(|&(operand, comparison, value)| {
+            let actual = match operand {
+                Operand::Register(reg) => chip8.registers[reg as usize] as u32,
+                Operand::I => chip8.i as u32,
+                Operand::Pc => chip8.pc as u32,
+                Operand::Dt => chip8.dt as u32,
+                Operand::St => chip8.st as u32,
+                Operand::Ram(addr) => chip8.bus.ram[addr as usize] as u32,
+                Operand::Score => match game.score.and_then(|source| source.read(chip8)) {
+                    Some(score) => score,
+                    None => return false,
+                },
+            };
+            match comparison {
+                Comparison::Eq => actual == value,
+                Comparison::Ne => actual != value,
+                Comparison::Ge => actual >= value,
+                Comparison::Le => actual <= value,
+                Comparison::Gt => actual > value,
+                Comparison::Lt => actual < value,
+            }
+        })
+    }
+}
+
+impl FromStr for Condition {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Condition, String> {
+        let terms = text
+            .split("&&")
+            .map(|term| {
+                let (op, comparison, at) = COMPARISONS
+                    .iter()
+                    .filter_map(|&(op, comparison)| Some((op, comparison, term.find(op)?)))
+                    .min_by_key(|&(op, _, at)| (at, usize::MAX - op.len()))
+                    .ok_or_else(|| format!("no comparison in `{}`", term.trim()))?;
+                let operand = parse_operand(term[..at].trim())?;
+                let value = parse_number(term[at + op.len()..].trim())?;
+                Ok((operand, comparison, value))
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        Ok(Condition { terms })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (n, &(operand, comparison, value)) in self.terms.iter().enumerate() {
+            if n > 0 {
+                write!(f, " && ")?;
+            }
+            match operand {
+                Operand::Register(reg) => write!(f, "V{:X}", reg)?,
+                Operand::I => write!(f, "I")?,
+                Operand::Pc => write!(f, "PC")?,
+                Operand::Dt => write!(f, "DT")?,
+                Operand::St => write!(f, "ST")?,
+                Operand::Ram(addr) => write!(f, "[{:#05x}]", addr)?,
+                Operand::Score => write!(f, "score")?,
+            }
+            let op = COMPARISONS
+                .iter()
+                .find(|(_, c)| *c == comparison)
+                .unwrap()
+                .0;
+            write!(f, " {} {}", op, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for Condition {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Condition, String> {
+        text.parse()
+    }
+}
+
+impl From<Condition> for String {
+    fn from(condition: Condition) -> String {
+        condition.to_string()
+    }
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_ascii_uppercase();
+    match upper.as_str() {
+        "I" => return Ok(Operand::I),
+        "PC" => return Ok(Operand::Pc),
+        "DT" => return Ok(Operand::Dt),
+        "ST" => return Ok(Operand::St),
+        "SCORE" => return Ok(Operand::Score),
+        _ => {}
+    }
+    if let Some(reg) = upper.strip_prefix('V')
+        && reg.len() == 1
+        && let Ok(reg) = u8::from_str_radix(reg, 16)
+    {
+        return Ok(Operand::Register(reg));
+    }
+    if let Some(addr) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        let addr = parse_number(addr.trim())?;
+        if addr < 4096 {
+            return Ok(Operand::Ram(addr as u16));
+        }
+        return Err(format!("address {:#x} is outside of RAM", addr));
+    }
+    Err(format!("unknown operand `{}`", text))
+}
+
+fn parse_number(text: &str) -> Result<u32, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("`{}` is not a number", text))
+}
+
+/// Checks the game's achievements every frame, persists the ones met
+/// and shows a toast for each.
+#[derive(Default)]
+pub struct AchievementWatcher {
+    /// Names waiting to be shown, the front one shown since the instant, if any
+    toasts: VecDeque<(String, Option<Instant>)>,
+}
+
+impl AchievementWatcher {
+    pub fn update(&mut self, chip8: &Chip8, game: &mut GameConfig) {
+        let mut unlocked = false;
+        for index in 0..game.achievements.len() {
+            let achievement = &game.achievements[index];
+            if achievement.unlocked.is_some() || !achievement.when.holds(chip8, game) {
+                continue;
+            }
+            let achievement = &mut game.achievements[index];
+            achievement.unlocked = Some(
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .map_or(0, |since| since.as_secs()),
+            );
+            info!(name = %achievement.name, "Achievement unlocked");
+            self.toasts.push_back((achievement.name.clone(), None));
+            unlocked = true;
+        }
+        if unlocked {
+            game.save();
+        }
+    }
+
+    /// Forget pending toasts, e.g. when switching games.
+    pub fn clear(&mut self) {
+        self.toasts.clear();
+    }
+
+    /// Whether a toast is up or waiting, so the window has to be drawn.
+    pub fn showing(&self) -> bool {
+        !self.toasts.is_empty()
+    }
+
+    /// Current toast along the bottom of the window.
+    pub fn draw(&mut self, canvas: &mut WindowCanvas) {
+        while let Some((_, Some(shown))) = self.toasts.front()
+            && shown.elapsed() > TOAST_TIME
+        {
+            self.toasts.pop_front();
+        }
+        let Some((name, shown)) = self.toasts.front_mut() else {
+            return;
+        };
+        shown.get_or_insert_with(Instant::now);
+
+        draw_label(
+            canvas,
+            &tr_with("achievement.unlocked", &[("name", name)]),
+            Anchor::BottomLeft,
+            GOLD,
+        );
+    }
+}
@@ -0,0 +1,123 @@
+use chip_8::{AsmOptions, MAX_BANKED_ROM_LEN, MAX_ROM_LEN, Variant, assemble_files};
+use clap::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::error;
+
+#[derive(Args)]
+pub struct AsmArgs {
+    /// Octo source files, modules of one program sharing their labels
+    #[arg(required = true)]
+    sources: Vec<PathBuf>,
+
+    /// Path of the ROM, defaults to the first source path with a `.ch8` extension
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    /// Platform to assemble for (chip-8, superchip, xo-chip), which `:if` can test
+    #[arg(long, default_value = "xo-chip", value_parser = parse_variant)]
+    variant: Variant,
+
+    /// Also write a listing of every line with its address and bytes, to the ROM path
+    /// with a `.lst` extension unless FILE is given
+    #[arg(long, value_name = "FILE")]
+    listing: Option<Option<PathBuf>>,
+
+    /// Also write the label addresses for `--symbols` of the monitor, to the ROM path
+    /// with a `.sym` extension unless FILE is given
+    #[arg(long, value_name = "FILE")]
+    symbols: Option<Option<PathBuf>>,
+
+    /// Also write where every section went and what each file put there, to the ROM path
+    /// with a `.map` extension unless FILE is given
+    #[arg(long, value_name = "FILE")]
+    map: Option<Option<PathBuf>>,
+
+    /// Start address of a section, e.g. `--place data=0x1000`, repeatable; sections not placed
+    /// follow the one before them
+    #[arg(long = "place", value_name = "SECTION=ADDR", value_parser = parse_place)]
+    places: Vec<(String, usize)>,
+}
+
+/// `chip-8 asm`: assemble Octo source files and the files they include into a ROM.
+///
+/// Exits with 0 on success, 1 when the source has errors or the ROM would not fit in RAM
+/// and 2 when a file could not be written.
+pub fn asm(args: AsmArgs) -> ExitCode {
+    let options = AsmOptions {
+        variant: args.variant,
+        sections: args.places.into_iter().collect(),
+    };
+    let assembly = match assemble_files(&args.sources, &options) {
+        Ok(assembly) => assembly,
+        Err(err) => {
+            error!(%err, "Could not assemble");
+            return ExitCode::FAILURE;
+        }
+    };
+    // Banked programs only need RAM for what is not in banks past the first.
+    let banked = assembly
+        .sections
+        .iter()
+        .any(|section| section.bank.is_some());
+    let max = if banked {
+        MAX_BANKED_ROM_LEN
+    } else {
+        MAX_ROM_LEN
+    };
+    if args.variant != Variant::XoChip && assembly.rom.len() > max {
+        error!(
+            bytes = assembly.rom.len(),
+            max, "ROM would not fit below 0x1000"
+        );
+        return ExitCode::FAILURE;
+    }
+    let output = args
+        .output
+        .unwrap_or_else(|| args.sources[0].with_extension("ch8"));
+    let extras = [
+        (args.listing, "lst", assembly.listing),
+        (args.symbols, "sym", assembly.symbols.to_string()),
+        (args.map, "map", assembly.map),
+    ];
+    let files = [(output.clone(), assembly.rom)]
+        .into_iter()
+        .chain(extras.into_iter().filter_map(|(path, extension, text)| {
+            let path = path?.unwrap_or_else(|| output.with_extension(extension));
+            Some((path, text.into_bytes()))
+        }));
+    for (path, bytes) in files {
+        if let Err(err) = write(&path, &bytes) {
+            return err;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+fn write(path: &Path, bytes: &[u8]) -> Result<(), ExitCode> {
+    if let Err(err) = fs::write(path, bytes) {
+        error!(file = %path.display(), %err, "Could not write");
+        return Err(ExitCode::from(2));
+    }
+    println!("{}: {} bytes", path.display(), bytes.len());
+    Ok(())
+}
+
+fn parse_place(text: &str) -> Result<(String, usize), String> {
+    let (name, addr) = text
+        .split_once('=')
+        .ok_or_else(|| "expected SECTION=ADDR".to_string())?;
+    let digits = addr.strip_prefix("0x").unwrap_or(addr);
+    match usize::from_str_radix(digits, 16) {
+        Ok(addr) if addr <= 0xFFFF => Ok((name.to_string(), addr)),
+        _ => Err(format!("invalid address {}", addr)),
+    }
+}
+
+fn parse_variant(name: &str) -> Result<Variant, String> {
+    Variant::named(name).ok_or_else(|| {
+        let names: Vec<&str> = Variant::NAMES.iter().map(|(name, _)| *name).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
@@ -0,0 +1,1647 @@
+//! Assembler for Octo, the structured CHIP-8 assembly language most homebrew is written in.
+//!
+//! Covers labels, `:alias`, `:const`, `:unpack`, `:next`, `:org`, `:byte`, `:pointer` and `:call`,
+//! every instruction mnemonic including the SUPER-CHIP and XO-CHIP ones, the `<` `>` `<=` `>=`
+//! comparison pseudo-ops, `if ... then`, `if ... begin ... else ... end` and `loop ... while ... again`.
+//! `:calc` is not supported.
+//!
+//! `:macro name param... { body }` defines a macro, used like an instruction: `name arg...` assembles
+//! the body with every parameter token replaced by its argument. `:if NAME`, `:unless NAME`, `:else`
+//! and `:end` assemble code only when a number or constant is non-zero, or zero for `:unless`, with the
+//! constants `CHIP8`, `SUPERCHIP` and `XOCHIP` telling the [`Variant`] assembled for, 1 for it and
+//! 0 for the others. `:include "file"` assembles another file in place, with a path relative
+//! to the including file; it needs [`assemble_files`]. So does `:image "file.png" N`, the sprite data of
+//! a PNG cut into 8 pixel wide sprites of N rows, or 16x16 ones for 0, left to right and top to bottom.
+//! Light opaque pixels are set, dark or transparent ones clear.
+//!
+//! `:section NAME` puts the code and data that follow into a section, `code` being the one programs
+//! start in. [`assemble_files`] assembles several modules as one program, each starting in `code`,
+//! with labels shared between them. Sections take the pieces of every module in order and are placed
+//! one after the other from 0x200, in the order they first come up, unless [`AsmOptions`] fixes
+//! their address; large XO-CHIP programs keep code below 0x1000 that way and data above it.
+//!
+//! `:bank N` is an extension of this crate for CHIP-8 programs larger than RAM: what follows goes into
+//! bank N, 1 to 15, assembled for the window at 0x800 that `Bus` swaps banks into, and put in the ROM
+//! after RAM and the banks before it. Bank 0 is what the program has at 0x800 itself.
+//!
+//! `:print` followed by text up to the end of the line is an extension of this crate: it sends the text
+//! and a newline to the `Serial` port, using V0 and I. So are the host calls of
+//! `Chip8::enable_host_calls`, `:dump` logging every register and `:log vX` logging one.
+
+use crate::bus::{BANK_WINDOW, MAX_BANKS};
+use crate::serial::Serial;
+use crate::symbols::Symbols;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::{self, Write};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Address programs are loaded at.
+const START_ADDR: usize = 0x200;
+
+/// Addressable memory, the XO-CHIP 64 KB.
+const MEMORY_SIZE: usize = 0x10000;
+
+/// Section programs start in.
+const CODE: &str = "code";
+
+/// Tokens macro expansion may grow a program to before it is taken for one that never ends.
+const MAX_TOKENS: usize = 1 << 20;
+
+/// Why a program could not be assembled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsmError {
+    /// Source file, `None` when assembling a string
+    pub file: Option<PathBuf>,
+    /// 1-based source line, 0 when the error is about the whole file
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.file, self.line) {
+            (Some(file), 0) => write!(f, "{}: {}", file.display(), self.message),
+            (Some(file), line) => write!(f, "{}:{}: {}", file.display(), line, self.message),
+            (None, 0) => write!(f, "{}", self.message),
+            (None, line) => write!(f, "line {}: {}", line, self.message),
+        }
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+/// Platform a program is assembled for, which `:if CHIP8`, `:if SUPERCHIP` and `:if XOCHIP` test.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Variant {
+    Chip8,
+    SuperChip,
+    #[default]
+    XoChip,
+}
+
+impl Variant {
+    /// Names as cartridges give them, in the order frontends should offer them.
+    pub const NAMES: [(&'static str, Variant); 3] = [
+        ("chip-8", Variant::Chip8),
+        ("superchip", Variant::SuperChip),
+        ("xo-chip", Variant::XoChip),
+    ];
+
+    /// Constants telling the variant in sources, paired with the variant they are 1 for.
+    const CONSTANTS: [(&'static str, Variant); 3] = [
+        ("CHIP8", Variant::Chip8),
+        ("SUPERCHIP", Variant::SuperChip),
+        ("XOCHIP", Variant::XoChip),
+    ];
+
+    pub fn named(name: &str) -> Option<Variant> {
+        Variant::NAMES
+            .iter()
+            .find(|(variant, _)| variant.eq_ignore_ascii_case(name))
+            .map(|(_, variant)| *variant)
+    }
+}
+
+/// How [`assemble_files`] builds a program.
+#[derive(Clone, Debug, Default)]
+pub struct AsmOptions {
+    pub variant: Variant,
+    /// Start addresses of sections placed by hand; the others follow the section before them
+    pub sections: BTreeMap<String, usize>,
+}
+
+/// An assembled program.
+pub struct Assembly {
+    /// Bytes loaded at 0x200
+    pub rom: Vec<u8>,
+    pub symbols: Symbols,
+    /// Address and bytes of every source line that assembled into some, next to the line
+    pub listing: String,
+    pub sections: Vec<Section>,
+    /// Address range of every section holding something, with the bytes each file put there
+    pub map: String,
+}
+
+/// Where a section of a program went.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Section {
+    pub name: String,
+    /// Address in RAM, or where the bank is in the ROM plus 0x200 for banks
+    pub start: usize,
+    pub len: usize,
+    /// Bank the section is, `None` for RAM
+    pub bank: Option<usize>,
+}
+
+/// Assemble Octo `source` for XO-CHIP into the ROM bytes loaded at 0x200.
+pub fn assemble(source: &str) -> Result<Vec<u8>, AsmError> {
+    let lines = code_lines(source);
+    let tokens = tokenize(0, &lines);
+    link(&AsmOptions::default(), Vec::new(), vec![lines], tokens).map(|assembly| assembly.rom)
+}
+
+/// Assemble the Octo source files at `paths`, with the files they include, as modules of one program.
+pub fn assemble_files(paths: &[PathBuf], options: &AsmOptions) -> Result<Assembly, AsmError> {
+    let mut sources = Vec::new();
+    for path in paths {
+        if !sources.iter().any(|(loaded, _)| loaded == path) {
+            load(path, &mut sources, &mut Vec::new())?;
+        }
+    }
+    let lines: Vec<Vec<&str>> = sources.iter().map(|(_, text)| code_lines(text)).collect();
+    let mut tokens = Vec::new();
+    for path in paths {
+        let Some(module) = sources.iter().position(|(loaded, _)| loaded == path) else {
+            continue;
+        };
+        // Every module starts out in the `code` section.
+        for text in [":section", CODE] {
+            tokens.push(Token {
+                text,
+                file: module,
+                line: 1,
+            });
+        }
+        tokens.extend(spliced(module, &sources, &lines));
+    }
+    let files = sources.iter().map(|(path, _)| path.as_path()).collect();
+    link(options, files, lines, tokens)
+}
+
+/// Assemble `tokens` once to learn how long the sections are and, when there is more to place
+/// than `code` at 0x200, again with the sections placed.
+fn link<'a>(
+    options: &AsmOptions,
+    files: Vec<&'a Path>,
+    lines: Vec<Vec<&'a str>>,
+    tokens: Vec<Token<'a>>,
+) -> Result<Assembly, AsmError> {
+    let sizing = Assembler::new(
+        options.variant,
+        HashMap::new(),
+        files.clone(),
+        lines.clone(),
+        tokens.clone(),
+    )
+    .run()?;
+    if sizing.sections.len() == 1 && options.sections.is_empty() {
+        return Ok(sizing);
+    }
+    // Banks stay where they are in the ROM.
+    let mut fixed = options.sections.clone();
+    for section in &sizing.sections {
+        if section.bank.is_some() {
+            fixed.insert(section.name.clone(), section.start);
+        }
+    }
+    let bases = place(&sizing.sections, &fixed)?;
+    Assembler::new(options.variant, bases, files, lines, tokens).run()
+}
+
+/// Start address of every section: the one in `fixed`, or the end of the section before.
+fn place(
+    sections: &[Section],
+    fixed: &BTreeMap<String, usize>,
+) -> Result<HashMap<String, usize>, AsmError> {
+    let error = |message| AsmError {
+        file: None,
+        line: 0,
+        message,
+    };
+    if let Some(name) = fixed
+        .keys()
+        .find(|name| !sections.iter().any(|section| section.name == **name))
+    {
+        return Err(error(format!("there is no section `{}` to place", name)));
+    }
+    let mut bases = HashMap::new();
+    let mut placed: Vec<(usize, usize, &str)> = Vec::new();
+    let mut next = START_ADDR;
+    for section in sections {
+        let start = fixed.get(&section.name).copied().unwrap_or(next);
+        next = start + section.len;
+        if next > MEMORY_SIZE {
+            return Err(error(format!(
+                "section `{}` does not fit in memory",
+                section.name
+            )));
+        }
+        if let Some((_, _, other)) = placed
+            .iter()
+            .find(|&&(other_start, other_end, _)| start < other_end && other_start < next)
+        {
+            let message = format!("sections `{}` and `{}` overlap", other, section.name);
+            return Err(error(message));
+        }
+        placed.push((start, next, &section.name));
+        bases.insert(section.name.clone(), start);
+    }
+    Ok(bases)
+}
+
+/// Read `path` and, depth first, the files it includes into `sources`, each once.
+/// `including` is the chain of files that led to `path`, to refuse includes going in circles.
+fn load(
+    path: &Path,
+    sources: &mut Vec<(PathBuf, String)>,
+    including: &mut Vec<PathBuf>,
+) -> Result<(), AsmError> {
+    let error = |line, message| AsmError {
+        file: Some(path.to_path_buf()),
+        line,
+        message,
+    };
+    let text =
+        fs::read_to_string(path).map_err(|err| error(0, format!("could not read: {}", err)))?;
+    let lines = code_lines(&text);
+    let tokens = tokenize(0, &lines);
+    let mut includes = Vec::new();
+    for (index, token) in tokens.iter().enumerate() {
+        if token.text != ":include" {
+            continue;
+        }
+        let Some(name) = tokens.get(index + 1) else {
+            return Err(error(token.line, "`:include` needs a file".to_string()));
+        };
+        let Some(file) = included(path, name.text) else {
+            let message = format!("expected a file name in quotes, found `{}`", name.text);
+            return Err(error(name.line, message));
+        };
+        includes.push((file, name.line));
+    }
+    drop(tokens);
+    sources.push((path.to_path_buf(), text));
+
+    including.push(path.to_path_buf());
+    for (file, line) in includes {
+        if including.contains(&file) {
+            return Err(error(line, format!("{} includes itself", file.display())));
+        }
+        if !sources.iter().any(|(loaded, _)| *loaded == file) {
+            load(&file, sources, including)?;
+        }
+    }
+    including.pop();
+    Ok(())
+}
+
+/// Tokens of `sources[file]` with the tokens of each file it includes in place of the `:include`.
+fn spliced<'a>(
+    file: usize,
+    sources: &[(PathBuf, String)],
+    lines: &[Vec<&'a str>],
+) -> Vec<Token<'a>> {
+    let mut tokens = Vec::new();
+    let mut own = tokenize(file, &lines[file]).into_iter();
+    while let Some(token) = own.next() {
+        if token.text != ":include" {
+            tokens.push(token);
+            continue;
+        }
+        // Loading checked that the name is there and the file got read.
+        let Some(path) = own
+            .next()
+            .and_then(|name| included(&sources[file].0, name.text))
+        else {
+            continue;
+        };
+        if let Some(index) = sources.iter().position(|(loaded, _)| *loaded == path) {
+            tokens.extend(spliced(index, sources, lines));
+        }
+    }
+    tokens
+}
+
+/// Path of the file `name` of an `:include` in `from` refers to, with `..` resolved
+/// so that the same file gets the same path however it is reached.
+fn included(from: &Path, name: &str) -> Option<PathBuf> {
+    let name = name.strip_prefix('"')?.strip_suffix('"')?;
+    let mut path = PathBuf::new();
+    for component in from
+        .parent()
+        .unwrap_or(Path::new(""))
+        .join(name)
+        .components()
+    {
+        match component {
+            Component::ParentDir
+                if matches!(path.components().next_back(), Some(Component::Normal(_))) =>
+            {
+                path.pop();
+            }
+            Component::CurDir => {}
+            component => path.push(component),
+        }
+    }
+    Some(path)
+}
+
+/// Source lines without comments.
+fn code_lines(source: &str) -> Vec<&str> {
+    source
+        .lines()
+        .map(|line| line.split('#').next().unwrap_or(""))
+        .collect()
+}
+
+fn tokenize<'a>(file: usize, lines: &[&'a str]) -> Vec<Token<'a>> {
+    lines
+        .iter()
+        .copied()
+        .enumerate()
+        .flat_map(|(index, code)| {
+            code.split_whitespace().map(move |text| Token {
+                text,
+                file,
+                line: index + 1,
+            })
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+struct Token<'a> {
+    text: &'a str,
+    /// Index into the assembled files
+    file: usize,
+    line: usize,
+}
+
+/// How a label used before its definition gets patched in.
+#[derive(Clone, Copy)]
+enum Patch {
+    /// Low 12 bits of the instruction
+    Address,
+    /// Two `vX := NN` instructions loading the high and low byte, the high one or'ed with a nybble
+    Unpack(u8),
+    /// Big endian 16 bit word
+    Word,
+}
+
+struct Fixup<'a> {
+    at: usize,
+    name: &'a str,
+    patch: Patch,
+    file: usize,
+    line: usize,
+}
+
+/// Bytes a statement assembled into, for the listing.
+struct Listed {
+    addr: usize,
+    len: usize,
+    file: usize,
+    line: usize,
+}
+
+/// A section while assembling.
+struct Placement {
+    name: String,
+    start: usize,
+    here: usize,
+    /// End of the highest byte written
+    end: usize,
+    /// How far past the addresses the code runs at the bytes go, for banks
+    offset: usize,
+    bank: Option<usize>,
+}
+
+struct Macro<'a> {
+    params: Vec<&'a str>,
+    body: Vec<Token<'a>>,
+}
+
+/// Condition of an `if` or `while`: instructions to run first, then the skip taken when it holds
+/// and the skip taken when it does not.
+struct Condition {
+    setup: Vec<u16>,
+    skip_if_true: u16,
+    skip_if_false: u16,
+}
+
+struct Assembler<'a> {
+    /// Path of every file, empty when assembling a string
+    files: Vec<&'a Path>,
+    /// Source lines without comments of every file, for `:print`
+    lines: Vec<Vec<&'a str>>,
+    tokens: Vec<Token<'a>>,
+    pos: usize,
+    ram: Vec<u8>,
+    here: usize,
+    /// End of the highest byte written
+    end: usize,
+    /// Bytes written so far
+    written: usize,
+    listed: Vec<Listed>,
+    /// Start addresses of the sections, 0x200 for any not in there
+    bases: HashMap<String, usize>,
+    sections: Vec<Placement>,
+    /// Index of the section assembled into
+    section: usize,
+    labels: HashMap<&'a str, u16>,
+    constants: HashMap<&'a str, u16>,
+    aliases: HashMap<&'a str, u8>,
+    fixups: Vec<Fixup<'a>>,
+    macros: HashMap<&'a str, Macro<'a>>,
+    /// Open `:if`s, whether their `:else` was seen
+    conditionals: Vec<bool>,
+    /// Open `loop`s: start address and the `while` jumps to patch at `again`
+    loops: Vec<(usize, Vec<usize>)>,
+    /// Open `begin` blocks: jump to patch at `else` or `end`, and whether `else` was seen
+    branches: Vec<(usize, bool)>,
+}
+
+impl<'a> Assembler<'a> {
+    fn new(
+        variant: Variant,
+        bases: HashMap<String, usize>,
+        files: Vec<&'a Path>,
+        lines: Vec<Vec<&'a str>>,
+        tokens: Vec<Token<'a>>,
+    ) -> Assembler<'a> {
+        let constants = Variant::CONSTANTS
+            .iter()
+            .map(|&(name, target)| (name, (target == variant) as u16))
+            .collect();
+        let start = bases.get(CODE).copied().unwrap_or(START_ADDR);
+        Assembler {
+            files,
+            lines,
+            tokens,
+            pos: 0,
+            ram: vec![0; MEMORY_SIZE],
+            here: start,
+            end: START_ADDR,
+            written: 0,
+            listed: Vec::new(),
+            bases,
+            sections: vec![Placement {
+                name: CODE.to_string(),
+                start,
+                here: start,
+                end: start,
+                offset: 0,
+                bank: None,
+            }],
+            section: 0,
+            labels: HashMap::new(),
+            constants,
+            aliases: HashMap::new(),
+            fixups: Vec::new(),
+            macros: HashMap::new(),
+            conditionals: Vec::new(),
+            loops: Vec::new(),
+            branches: Vec::new(),
+        }
+    }
+
+    fn run(mut self) -> Result<Assembly, AsmError> {
+        while !self.at_end() {
+            let (first, written) = (self.tokens[self.pos], self.written);
+            self.statement()?;
+            let len = self.written - written;
+            if len == 0 {
+                continue;
+            }
+            // Statements write their bytes in one piece; several of them on a line list together.
+            let addr = self.here - len;
+            match self.listed.last_mut() {
+                Some(last)
+                    if (last.file, last.line) == (first.file, first.line)
+                        && last.addr + last.len == addr =>
+                {
+                    last.len += len;
+                }
+                _ => self.listed.push(Listed {
+                    addr,
+                    len,
+                    file: first.file,
+                    line: first.line,
+                }),
+            }
+        }
+        self.finish()
+    }
+
+    fn at_end(&self) -> bool {
+        self.pos >= self.tokens.len()
+    }
+
+    /// Token taken last: errors come up right after the offending token was taken.
+    fn current(&self) -> Option<&Token<'a>> {
+        let index = self
+            .pos
+            .saturating_sub(1)
+            .min(self.tokens.len().saturating_sub(1));
+        self.tokens.get(index)
+    }
+
+    fn line(&self) -> usize {
+        self.current().map_or(0, |token| token.line)
+    }
+
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, AsmError> {
+        let file = self.current().map_or(0, |token| token.file);
+        Err(self.error_at(file, self.line(), message.into()))
+    }
+
+    fn error_at(&self, file: usize, line: usize, message: String) -> AsmError {
+        AsmError {
+            file: self.files.get(file).map(|path| path.to_path_buf()),
+            line,
+            message,
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, AsmError> {
+        match self.tokens.get(self.pos) {
+            Some(token) => {
+                self.pos += 1;
+                Ok(token.text)
+            }
+            None => self.error("unexpected end of program"),
+        }
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.pos).map(|token| token.text)
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+        let text = self.next()?;
+        if text != expected {
+            return self.error(format!("expected `{}`, found `{}`", expected, text));
+        }
+        Ok(())
+    }
+
+    fn byte(&mut self, value: u8) -> Result<(), AsmError> {
+        if self.here >= MEMORY_SIZE {
+            return self.error("program does not fit in memory");
+        }
+        self.ram[self.here] = value;
+        self.here += 1;
+        self.written += 1;
+        self.end = self.end.max(self.here);
+        let section = &mut self.sections[self.section];
+        section.end = section.end.max(self.here);
+        Ok(())
+    }
+
+    fn instruction(&mut self, opcode: u16) -> Result<(), AsmError> {
+        self.byte((opcode >> 8) as u8)?;
+        self.byte(opcode as u8)
+    }
+
+    fn register(&mut self) -> Result<u8, AsmError> {
+        let text = self.next()?;
+        match parse_register(text).or_else(|| self.aliases.get(text).copied()) {
+            Some(reg) => Ok(reg),
+            None => self.error(format!("expected a register, found `{}`", text)),
+        }
+    }
+
+    fn is_register(&self, text: &str) -> bool {
+        parse_register(text).is_some() || self.aliases.contains_key(text)
+    }
+
+    /// Number or constant.
+    fn number(&mut self) -> Result<i32, AsmError> {
+        let text = self.next()?;
+        match parse_number(text).or_else(|| self.constants.get(text).map(|&value| value as i32)) {
+            Some(value) => Ok(value),
+            None => self.error(format!("expected a number, found `{}`", text)),
+        }
+    }
+
+    fn immediate(&mut self, bits: u32) -> Result<u16, AsmError> {
+        let value = self.number()?;
+        let max = 1 << bits;
+        if value < -(max >> 1) || value >= max {
+            return self.error(format!("{} does not fit in {} bits", value, bits));
+        }
+        Ok((value & (max - 1)) as u16)
+    }
+
+    /// Address of a number, constant or label, patched in once a later label is defined.
+    fn address(&mut self, patch: Patch, at: usize) -> Result<u16, AsmError> {
+        let text = self.next()?;
+        if let Some(value) =
+            parse_number(text).or_else(|| self.constants.get(text).map(|&v| v as i32))
+        {
+            return Ok(value as u16);
+        }
+        if let Some(&addr) = self.labels.get(text) {
+            return Ok(addr);
+        }
+        if self.is_register(text) || !is_name(text) {
+            return self.error(format!("expected an address, found `{}`", text));
+        }
+        self.fixups.push(Fixup {
+            at,
+            name: text,
+            patch,
+            file: self.current().map_or(0, |token| token.file),
+            line: self.line(),
+        });
+        Ok(0)
+    }
+
+    /// Instruction with a 12 bit address, e.g. `1NNN`.
+    fn address_instruction(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let at = self.here;
+        let addr = self.address(Patch::Address, at)?;
+        if addr > 0xFFF {
+            return self.error(format!("address {:#x} does not fit in 12 bits", addr));
+        }
+        self.instruction(opcode | addr)
+    }
+
+    fn statement(&mut self) -> Result<(), AsmError> {
+        let text = self.next()?;
+        match text {
+            ":" => {
+                let name = self.name()?;
+                self.define_label(name, self.addr())
+            }
+            ":alias" => {
+                let name = self.name()?;
+                let reg = self.register()?;
+                self.aliases.insert(name, reg);
+                Ok(())
+            }
+            ":const" => {
+                let name = self.name()?;
+                let value = self.number()?;
+                self.constants.insert(name, value as u16);
+                Ok(())
+            }
+            ":unpack" => {
+                let nybble = if self.peek() == Some("long") {
+                    self.pos += 1;
+                    None
+                } else {
+                    Some(self.immediate(4)? as u8)
+                };
+                let at = self.here;
+                let patch = Patch::Unpack(nybble.unwrap_or(0));
+                let addr = self.address(patch, at)?;
+                let high = (addr >> 8) as u8 | nybble.map_or(0, |nybble| nybble << 4);
+                self.instruction(0x6000 | high as u16)?;
+                self.instruction(0x6100 | (addr & 0xFF))
+            }
+            ":next" => {
+                let name = self.name()?;
+                self.define_label(name, self.addr() + 1)
+            }
+            ":org" => {
+                let addr = self.number()?;
+                if !(0..MEMORY_SIZE as i32).contains(&addr) {
+                    return self.error(format!("{:#x} is outside of memory", addr));
+                }
+                self.here = addr as usize + self.sections[self.section].offset;
+                Ok(())
+            }
+            ":byte" => {
+                let value = self.immediate(8)?;
+                self.byte(value as u8)
+            }
+            ":pointer" => {
+                let at = self.here;
+                let addr = self.address(Patch::Word, at)?;
+                self.instruction(addr)
+            }
+            ":section" => {
+                let name = self.name()?;
+                self.switch_section(name);
+                Ok(())
+            }
+            ":bank" => self.switch_bank(),
+            ":call" => self.address_instruction(0x2000),
+            ":print" => self.print(),
+            ":dump" => self.instruction(0x0FF0),
+            ":log" => {
+                let x = self.register()? as u16;
+                self.instruction(0x0FE0 | x)
+            }
+            ":breakpoint" | ":proto" => self.name().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                self.next().map(|_| ())
+            }
+            ":macro" => self.define_macro(),
+            ":if" | ":unless" => {
+                let holds = (self.number()? != 0) == (text == ":if");
+                if holds || self.skip_conditional(true)? {
+                    self.conditionals.push(!holds);
+                }
+                Ok(())
+            }
+            ":else" => match self.conditionals.pop() {
+                Some(false) => self.skip_conditional(false).map(|_| ()),
+                Some(true) => self.error("second `:else` for the same `:if`"),
+                None => self.error("`:else` without `:if`"),
+            },
+            ":end" => match self.conditionals.pop() {
+                Some(_) => Ok(()),
+                None => self.error("`:end` without `:if`"),
+            },
+            ":include" => self.error("`:include` needs the program to be assembled from a file"),
+            ":image" => self.image(),
+            ":calc" | ":stringmode" | ":assert" => {
+                self.error(format!("`{}` is not supported", text))
+            }
+            ";" | "return" => self.instruction(0x00EE),
+            "clear" => self.instruction(0x00E0),
+            "hires" => self.instruction(0x00FF),
+            "lores" => self.instruction(0x00FE),
+            "exit" => self.instruction(0x00FD),
+            "scroll-left" => self.instruction(0x00FC),
+            "scroll-right" => self.instruction(0x00FB),
+            "scroll-down" => {
+                let rows = self.immediate(4)?;
+                self.instruction(0x00C0 | rows)
+            }
+            "scroll-up" => {
+                let rows = self.immediate(4)?;
+                self.instruction(0x00D0 | rows)
+            }
+            "audio" => self.instruction(0xF002),
+            "plane" => {
+                let mask = self.immediate(4)?;
+                self.instruction(0xF001 | mask << 8)
+            }
+            "bcd" => self.register_instruction(0xF033),
+            "saveflags" => self.register_instruction(0xF075),
+            "loadflags" => self.register_instruction(0xF085),
+            "save" | "load" => {
+                let x = self.register()? as u16;
+                if self.peek() == Some("-") {
+                    self.pos += 1;
+                    let y = self.register()? as u16;
+                    let opcode = if text == "save" { 0x5002 } else { 0x5003 };
+                    return self.instruction(opcode | x << 8 | y << 4);
+                }
+                let opcode = if text == "save" { 0xF055 } else { 0xF065 };
+                self.instruction(opcode | x << 8)
+            }
+            "sprite" => {
+                let x = self.register()? as u16;
+                let y = self.register()? as u16;
+                let rows = self.immediate(4)?;
+                self.instruction(0xD000 | x << 8 | y << 4 | rows)
+            }
+            "jump" => self.address_instruction(0x1000),
+            "jump0" => self.address_instruction(0xB000),
+            "native" => self.address_instruction(0x0000),
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? as u16;
+                let opcode = match text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.instruction(opcode | x << 8)
+            }
+            "i" => self.assign_i(),
+            "if" => self.branch(),
+            "else" => self.else_branch(),
+            "end" => self.end_branch(),
+            "loop" => {
+                self.loops.push((self.addr(), Vec::new()));
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                for opcode in condition.setup {
+                    self.instruction(opcode)?;
+                }
+                self.instruction(condition.skip_if_true)?;
+                let at = self.here;
+                match self.loops.last_mut() {
+                    Some((_, breaks)) => breaks.push(at),
+                    None => return self.error("`while` outside of a loop"),
+                }
+                self.instruction(0x1000)
+            }
+            "again" => {
+                let Some((start, breaks)) = self.loops.pop() else {
+                    return self.error("`again` without `loop`");
+                };
+                self.instruction(0x1000 | start as u16)?;
+                for at in breaks {
+                    self.patch_address(at, self.addr())?;
+                }
+                Ok(())
+            }
+            _ if self.macros.contains_key(text) => self.expand(text),
+            _ if self.is_register(text) => {
+                self.pos -= 1;
+                self.assign_register()
+            }
+            _ if parse_number(text).is_some() => {
+                self.pos -= 1;
+                let value = self.immediate(8)?;
+                self.byte(value as u8)
+            }
+            _ if self.constants.contains_key(text) => {
+                let value = self.constants[text];
+                if value > 0xFF {
+                    return self.error(format!("{} does not fit in 8 bits", value));
+                }
+                self.byte(value as u8)
+            }
+            _ if is_name(text) => {
+                self.pos -= 1;
+                self.address_instruction(0x2000)
+            }
+            _ => self.error(format!("unexpected `{}`", text)),
+        }
+    }
+
+    /// Code sending the rest of the line and a newline to the serial port, a byte at a time
+    /// through V0, pointing I at the port again before it could have moved past it.
+    fn print(&mut self) -> Result<(), AsmError> {
+        let (file, line) = self
+            .current()
+            .map_or((0, 1), |token| (token.file, token.line));
+        let code = self.lines[file][line - 1];
+        let text = code
+            .split_once(":print")
+            .map_or("", |(_, text)| text.trim());
+        while self
+            .tokens
+            .get(self.pos)
+            .is_some_and(|token| token.file == file && token.line == line)
+        {
+            self.pos += 1;
+        }
+        let bytes: Vec<u8> = text.bytes().chain([b'\n']).collect();
+        for chunk in bytes.chunks(Serial::LEN as usize) {
+            self.instruction(0xA000 | Serial::ADDR)?;
+            for &byte in chunk {
+                self.instruction(0x6000 | byte as u16)?;
+                self.instruction(0xF055)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Carry on assembling where section `name` left off, or at its start when new.
+    fn switch_section(&mut self, name: &str) {
+        let start = self.bases.get(name).copied().unwrap_or(START_ADDR);
+        self.enter(name, start, None);
+    }
+
+    /// `:bank N`, carry on assembling bank N: at the addresses of `BANK_WINDOW`,
+    /// and after the end of RAM and the banks before it in the ROM.
+    fn switch_bank(&mut self) -> Result<(), AsmError> {
+        let bank = self.number()?;
+        if !(1..MAX_BANKS as i32).contains(&bank) {
+            return self.error(format!(
+                "banks go from 1 to {}, not {}",
+                MAX_BANKS - 1,
+                bank
+            ));
+        }
+        let bank = bank as usize;
+        let start = BANK_WINDOW.end + (bank - 1) * BANK_WINDOW.len();
+        self.enter(&format!("bank {}", bank), start, Some(bank));
+        Ok(())
+    }
+
+    fn enter(&mut self, name: &str, start: usize, bank: Option<usize>) {
+        self.sections[self.section].here = self.here;
+        self.section = match self
+            .sections
+            .iter()
+            .position(|section| section.name == name)
+        {
+            Some(index) => index,
+            None => {
+                self.sections.push(Placement {
+                    name: name.to_string(),
+                    start,
+                    here: start,
+                    end: start,
+                    offset: bank.map_or(0, |_| start - BANK_WINDOW.start),
+                    bank,
+                });
+                self.sections.len() - 1
+            }
+        };
+        self.here = self.sections[self.section].here;
+    }
+
+    /// Address the code at `here` runs at, which differs in banks.
+    fn addr(&self) -> usize {
+        self.here - self.sections[self.section].offset
+    }
+
+    /// `:image "file.png" N`, the sprite data of the image.
+    fn image(&mut self) -> Result<(), AsmError> {
+        let name = self.next()?;
+        let file = self.current().map_or(0, |token| token.file);
+        let Some(&from) = self.files.get(file) else {
+            return self.error("`:image` needs the program to be assembled from a file");
+        };
+        let Some(path) = included(from, name) else {
+            return self.error(format!("expected a file name in quotes, found `{}`", name));
+        };
+        let rows = self.immediate(4)? as usize;
+        let (width, height, lit) = match pixels(&path) {
+            Ok(image) => image,
+            Err(err) => return self.error(format!("could not read {}: {}", path.display(), err)),
+        };
+
+        let (sprite_width, sprite_height) = if rows == 0 { (16, 16) } else { (8, rows) };
+        if height % sprite_height != 0 {
+            return self.error(format!(
+                "{} rows do not split into sprites of {}",
+                height, sprite_height
+            ));
+        }
+        for top in (0..height).step_by(sprite_height) {
+            for left in (0..width).step_by(sprite_width) {
+                for y in top..top + sprite_height {
+                    for byte_left in (left..left + sprite_width).step_by(8) {
+                        // Columns past the right edge are blank.
+                        let byte = (byte_left..byte_left + 8).fold(0, |byte, x| {
+                            byte << 1 | (x < width && lit[y * width + x]) as u8
+                        });
+                        self.byte(byte)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// `:macro name param... { body }`, nested braces included in the body.
+    fn define_macro(&mut self) -> Result<(), AsmError> {
+        let name = self.name()?;
+        let mut params = Vec::new();
+        loop {
+            match self.next()? {
+                "{" => break,
+                param => params.push(param),
+            }
+        }
+        let mut body = Vec::new();
+        let mut depth = 0;
+        loop {
+            let Some(&token) = self.tokens.get(self.pos) else {
+                return self.error(format!("macro `{}` without `}}`", name));
+            };
+            self.pos += 1;
+            match token.text {
+                "}" if depth == 0 => break,
+                "}" => depth -= 1,
+                "{" => depth += 1,
+                _ => {}
+            }
+            body.push(token);
+        }
+        self.macros.insert(name, Macro { params, body });
+        Ok(())
+    }
+
+    /// Put the body of macro `name` in place of its use, with the parameters replaced
+    /// by the arguments that follow.
+    fn expand(&mut self, name: &str) -> Result<(), AsmError> {
+        let mut args = Vec::new();
+        for _ in 0..self.macros[name].params.len() {
+            args.push(self.next()?);
+        }
+        let definition = &self.macros[name];
+        let body: Vec<Token<'a>> = definition
+            .body
+            .iter()
+            .map(|token| {
+                match definition
+                    .params
+                    .iter()
+                    .position(|&param| param == token.text)
+                {
+                    Some(index) => Token {
+                        text: args[index],
+                        ..*token
+                    },
+                    None => *token,
+                }
+            })
+            .collect();
+        if self.tokens.len() + body.len() > MAX_TOKENS {
+            return self.error(format!("expanding macro `{}` does not end", name));
+        }
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    /// Skip the part of an `:if` that is not assembled, up to its `:else` when `to_else`
+    /// or else its `:end`. Returns whether it stopped at `:else`.
+    fn skip_conditional(&mut self, to_else: bool) -> Result<bool, AsmError> {
+        let mut depth = 0;
+        loop {
+            if self.at_end() {
+                return self.error("`:if` without `:end`");
+            }
+            match self.next()? {
+                ":if" | ":unless" => depth += 1,
+                ":else" if depth == 0 && to_else => return Ok(true),
+                ":end" if depth == 0 => return Ok(false),
+                ":end" => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn name(&mut self) -> Result<&'a str, AsmError> {
+        let text = self.next()?;
+        if !is_name(text) || self.is_register(text) {
+            return self.error(format!("`{}` is not a valid name", text));
+        }
+        Ok(text)
+    }
+
+    fn define_label(&mut self, name: &'a str, addr: usize) -> Result<(), AsmError> {
+        if self.labels.insert(name, addr as u16).is_some() {
+            return self.error(format!("`{}` is defined twice", name));
+        }
+        Ok(())
+    }
+
+    fn register_instruction(&mut self, opcode: u16) -> Result<(), AsmError> {
+        let x = self.register()? as u16;
+        self.instruction(opcode | x << 8)
+    }
+
+    fn assign_i(&mut self) -> Result<(), AsmError> {
+        match self.next()? {
+            ":=" => match self.peek() {
+                Some("hex") => {
+                    self.pos += 1;
+                    self.register_instruction(0xF029)
+                }
+                Some("bighex") => {
+                    self.pos += 1;
+                    self.register_instruction(0xF030)
+                }
+                Some("long") => {
+                    self.pos += 1;
+                    self.instruction(0xF000)?;
+                    let at = self.here;
+                    let addr = self.address(Patch::Word, at)?;
+                    self.instruction(addr)
+                }
+                _ => self.address_instruction(0xA000),
+            },
+            "+=" => self.register_instruction(0xF01E),
+            other => self.error(format!("unexpected `{}` after `i`", other)),
+        }
+    }
+
+    fn assign_register(&mut self) -> Result<(), AsmError> {
+        let x = self.register()? as u16;
+        let op = self.next()?;
+        if op == ":=" {
+            match self.peek() {
+                Some("key") => {
+                    self.pos += 1;
+                    return self.instruction(0xF00A | x << 8);
+                }
+                Some("delay") => {
+                    self.pos += 1;
+                    return self.instruction(0xF007 | x << 8);
+                }
+                Some("random") => {
+                    self.pos += 1;
+                    let mask = self.immediate(8)?;
+                    return self.instruction(0xC000 | x << 8 | mask);
+                }
+                _ => {}
+            }
+        }
+
+        let operand = self.peek().unwrap_or("");
+        if self.is_register(operand) {
+            let y = self.register()? as u16;
+            let kind = match op {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return self.error(format!("unknown operator `{}`", op)),
+            };
+            return self.instruction(0x8000 | x << 8 | y << 4 | kind);
+        }
+
+        let value = self.immediate(8)?;
+        match op {
+            ":=" => self.instruction(0x6000 | x << 8 | value),
+            "+=" => self.instruction(0x7000 | x << 8 | value),
+            "-=" => self.instruction(0x7000 | x << 8 | (value as u8).wrapping_neg() as u16),
+            _ => self.error(format!("`{}` needs a register on the right", op)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, AsmError> {
+        let x = self.register()? as u16;
+        let op = self.next()?;
+        match op {
+            "key" | "-key" => {
+                let (pressed, released) = (0xE09E | x << 8, 0xE0A1 | x << 8);
+                let pressed_holds = op == "key";
+                return Ok(Condition {
+                    setup: Vec::new(),
+                    skip_if_true: if pressed_holds { pressed } else { released },
+                    skip_if_false: if pressed_holds { released } else { pressed },
+                });
+            }
+            "==" | "!=" | "<" | ">" | "<=" | ">=" => {}
+            _ => return self.error(format!("unknown comparison `{}`", op)),
+        }
+
+        let y = if self.is_register(self.peek().unwrap_or("")) {
+            Some(self.register()? as u16)
+        } else {
+            None
+        };
+        let value = match y {
+            Some(_) => 0,
+            None => self.immediate(8)?,
+        };
+        let (equal, unequal) = match y {
+            Some(y) => (0x5000 | x << 8 | y << 4, 0x9000 | x << 8 | y << 4),
+            None => (0x3000 | x << 8 | value, 0x4000 | x << 8 | value),
+        };
+        if op == "==" || op == "!=" {
+            let holds_when_equal = op == "==";
+            return Ok(Condition {
+                setup: Vec::new(),
+                skip_if_true: if holds_when_equal { equal } else { unequal },
+                skip_if_false: if holds_when_equal { unequal } else { equal },
+            });
+        }
+
+        // Subtract in VF so that its borrow flag says which side is larger:
+        // after `vf := a ; vf -= b`, VF is 1 when a >= b.
+        let swapped = op == ">" || op == "<=";
+        let setup = match (y, swapped) {
+            (Some(y), false) => vec![0x8F00 | x << 4, 0x8F05 | y << 4],
+            (Some(y), true) => vec![0x8F00 | y << 4, 0x8F05 | x << 4],
+            (None, false) => vec![0x6F00 | value, 0x8F07 | x << 4],
+            (None, true) => vec![0x6F00 | value, 0x8F05 | x << 4],
+        };
+        let flag = (op == ">=" || op == "<=") as u16;
+        Ok(Condition {
+            setup,
+            skip_if_true: 0x3F00 | flag,
+            skip_if_false: 0x4F00 | flag,
+        })
+    }
+
+    fn branch(&mut self) -> Result<(), AsmError> {
+        let condition = self.condition()?;
+        for &opcode in &condition.setup {
+            self.instruction(opcode)?;
+        }
+        match self.next()? {
+            "then" => self.instruction(condition.skip_if_false),
+            "begin" => {
+                self.instruction(condition.skip_if_true)?;
+                self.branches.push((self.here, false));
+                self.instruction(0x1000)
+            }
+            other => self.error(format!("expected `then` or `begin`, found `{}`", other)),
+        }
+    }
+
+    fn else_branch(&mut self) -> Result<(), AsmError> {
+        match self.branches.pop() {
+            Some((at, false)) => {
+                let skip_else = self.here;
+                self.instruction(0x1000)?;
+                self.patch_address(at, self.addr())?;
+                self.branches.push((skip_else, true));
+                Ok(())
+            }
+            Some((_, true)) => self.error("second `else` for the same `begin`"),
+            None => self.error("`else` without `begin`"),
+        }
+    }
+
+    fn end_branch(&mut self) -> Result<(), AsmError> {
+        match self.branches.pop() {
+            Some((at, _)) => self.patch_address(at, self.addr()),
+            None => self.error("`end` without `begin`"),
+        }
+    }
+
+    fn patch_address(&mut self, at: usize, addr: usize) -> Result<(), AsmError> {
+        if addr > 0xFFF {
+            return self.error(format!("address {:#x} does not fit in 12 bits", addr));
+        }
+        self.ram[at] = self.ram[at] & 0xF0 | (addr >> 8) as u8;
+        self.ram[at + 1] = addr as u8;
+        Ok(())
+    }
+
+    /// Listing of the bytes in `listed`: address, up to 8 bytes a row, source location and code.
+    fn listing(&self) -> String {
+        let locations: Vec<String> = self
+            .listed
+            .iter()
+            .map(|listed| match self.files.get(listed.file) {
+                Some(path) => format!("{}:{}", path.display(), listed.line),
+                None => listed.line.to_string(),
+            })
+            .collect();
+        let width = locations.iter().map(String::len).max().unwrap_or(0);
+        let mut text = String::new();
+        for (listed, location) in self.listed.iter().zip(&locations) {
+            let code = self.lines[listed.file][listed.line - 1].trim();
+            let bytes = &self.ram[listed.addr..listed.addr + listed.len];
+            for (row, chunk) in bytes.chunks(8).enumerate() {
+                let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02x}", byte)).collect();
+                let addr = listed.addr + row * 8;
+                if row == 0 {
+                    writeln!(
+                        text,
+                        "{:#06x}  {:<23}  {:<width$}  {}",
+                        addr,
+                        hex.join(" "),
+                        location,
+                        code
+                    )
+                    .unwrap();
+                } else {
+                    writeln!(text, "{:#06x}  {}", addr, hex.join(" ")).unwrap();
+                }
+            }
+        }
+        text
+    }
+
+    /// Map of `sections`: address range, name and size of the ones holding something,
+    /// each followed by the bytes every file put there.
+    fn map(&self, sections: &[Section]) -> String {
+        let mut sorted: Vec<&Section> = sections.iter().filter(|section| section.len > 0).collect();
+        sorted.sort_by_key(|section| section.start);
+        let mut text = String::new();
+        for section in sorted {
+            let range = section.start..section.start + section.len;
+            writeln!(
+                text,
+                "{:#06x}..{:#06x}  {}, {} bytes",
+                range.start, range.end, section.name, section.len
+            )
+            .unwrap();
+            for (file, path) in self.files.iter().enumerate() {
+                let bytes: usize = self
+                    .listed
+                    .iter()
+                    .filter(|listed| listed.file == file && range.contains(&listed.addr))
+                    .map(|listed| listed.len)
+                    .sum();
+                if bytes > 0 {
+                    writeln!(text, "    {}, {} bytes", path.display(), bytes).unwrap();
+                }
+            }
+        }
+        text
+    }
+
+    fn finish(mut self) -> Result<Assembly, AsmError> {
+        if !self.conditionals.is_empty() {
+            return self.error("`:if` without `:end`");
+        }
+        if !self.loops.is_empty() {
+            return self.error("`loop` without `again`");
+        }
+        if !self.branches.is_empty() {
+            return self.error("`begin` without `end`");
+        }
+        for fixup in std::mem::take(&mut self.fixups) {
+            let Some(&addr) = self.labels.get(fixup.name) else {
+                let message = format!("`{}` is not defined", fixup.name);
+                return Err(self.error_at(fixup.file, fixup.line, message));
+            };
+            match fixup.patch {
+                Patch::Address => {
+                    if addr > 0xFFF {
+                        let message = format!("address {:#x} does not fit in 12 bits", addr);
+                        return Err(self.error_at(fixup.file, fixup.line, message));
+                    }
+                    self.patch_address(fixup.at, addr as usize)?;
+                }
+                Patch::Unpack(nybble) => {
+                    self.ram[fixup.at + 1] = (addr >> 8) as u8 | nybble << 4;
+                    self.ram[fixup.at + 3] = addr as u8;
+                }
+                Patch::Word => {
+                    self.ram[fixup.at..fixup.at + 2].copy_from_slice(&addr.to_be_bytes());
+                }
+            }
+        }
+        let mut symbols = Symbols::default();
+        for (&name, &addr) in &self.labels {
+            symbols.insert(name, addr);
+        }
+        let sections: Vec<Section> = self
+            .sections
+            .iter()
+            .map(|section| Section {
+                name: section.name.clone(),
+                start: section.start,
+                len: section.end.saturating_sub(section.start),
+                bank: section.bank,
+            })
+            .collect();
+        if let Some(section) = sections
+            .iter()
+            .find(|section| section.bank.is_some() && section.len > BANK_WINDOW.len())
+        {
+            let message = format!(
+                "{} takes {} bytes, more than the {} of a bank",
+                section.name,
+                section.len,
+                BANK_WINDOW.len()
+            );
+            return Err(AsmError {
+                file: None,
+                line: 0,
+                message,
+            });
+        }
+        let listing = self.listing();
+        let map = self.map(&sections);
+        self.ram.truncate(self.end);
+        Ok(Assembly {
+            rom: self.ram.split_off(START_ADDR),
+            symbols,
+            listing,
+            sections,
+            map,
+        })
+    }
+}
+
+/// Width, height and, row by row, whether each pixel is set in the PNG at `path`.
+#[cfg(feature = "png")]
+fn pixels(path: &Path) -> std::io::Result<(usize, usize, Vec<bool>)> {
+    use std::io;
+
+    let mut decoder = png::Decoder::new(fs::File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(io::Error::other)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(io::Error::other)?;
+    let (width, height) = (info.width as usize, info.height as usize);
+    let samples = info.color_type.samples();
+    let lit = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (y, x)))
+        .map(|(y, x)| {
+            let pixel = &buffer[y * info.line_size + x * samples..][..samples];
+            let (color, alpha) = match info.color_type {
+                png::ColorType::GrayscaleAlpha | png::ColorType::Rgba => {
+                    (&pixel[..samples - 1], pixel[samples - 1])
+                }
+                _ => (pixel, 0xFF),
+            };
+            let brightness = color.iter().map(|&c| c as usize).sum::<usize>() / color.len();
+            alpha >= 0x80 && brightness >= 0x80
+        })
+        .collect();
+    Ok((width, height, lit))
+}
+
+#[cfg(not(feature = "png"))]
+fn pixels(_path: &Path) -> std::io::Result<(usize, usize, Vec<bool>)> {
+    Err(std::io::Error::other("built without the png feature"))
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let digit = text.strip_prefix(['v', 'V'])?;
+    if digit.len() != 1 {
+        return None;
+    }
+    u8::from_str_radix(digit, 16).ok()
+}
+
+fn parse_number(text: &str) -> Option<i32> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x") {
+        i32::from_str_radix(hex, 16).ok()?
+    } else if let Some(binary) = digits.strip_prefix("0b") {
+        i32::from_str_radix(binary, 2).ok()?
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn is_name(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+        && text
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Empty directory of its own for the source files of a test.
+    fn scratch(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("chip8-asm-{}-{}", std::process::id(), test));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn write(dir: &Path, name: &str, source: &str) -> PathBuf {
+        let path = dir.join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, source).unwrap();
+        path
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        let err = assemble(source).unwrap_err();
+        (err.line, err.message)
+    }
+
+    #[test]
+    fn labels_are_patched_in_before_and_after_their_definition() {
+        let source = "
+            : main
+              v0 := 0x05
+              i := smiley
+              sprite v0 v1 2
+              jump main
+            : smiley
+              0xF0 0x90
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x60, 0x05, 0xA2, 0x08, 0xD0, 0x12, 0x12, 0x00, 0xF0, 0x90]
+        );
+    }
+
+    #[test]
+    fn macros_replace_their_parameters() {
+        let source = "
+            :macro twice reg amount { reg += amount reg += amount }
+            twice v3 2
+            twice va 0x10
+        ";
+        assert_eq!(
+            assemble(source).unwrap(),
+            [0x73, 0x02, 0x73, 0x02, 0x7A, 0x10, 0x7A, 0x10]
+        );
+    }
+
+    #[test]
+    fn conditionals_follow_the_variant() {
+        let source = "
+            :if XOCHIP 0x01 :else 0x02 :end
+            :unless CHIP8 0x03 :end
+            :if SUPERCHIP 0x04 :end
+        ";
+        assert_eq!(assemble(source).unwrap(), [0x01, 0x03]);
+
+        let dir = scratch("conditionals");
+        let file = write(&dir, "main.8o", source);
+        let options = AsmOptions {
+            variant: Variant::Chip8,
+            ..AsmOptions::default()
+        };
+        assert_eq!(assemble_files(&[file], &options).unwrap().rom, [0x02]);
+    }
+
+    #[test]
+    fn includes_assemble_in_place_and_show_in_the_listing_and_map() {
+        let dir = scratch("include");
+        let main = write(
+            &dir,
+            "main.8o",
+            ":include \"lib/smiley.8o\"\n: main\n  i := smiley\n  jump main\n",
+        );
+        write(&dir, "lib/smiley.8o", ": smiley\n  0x3C 0x42\n");
+        let assembly = assemble_files(&[main], &AsmOptions::default()).unwrap();
+        assert_eq!(assembly.rom, [0x3C, 0x42, 0xA2, 0x00, 0x12, 0x02]);
+
+        let (main, smiley) = (dir.join("main.8o"), dir.join("lib/smiley.8o"));
+        let (main, smiley) = (main.display(), smiley.display());
+        let width = format!("{}:2", smiley).len();
+        assert_eq!(
+            assembly.listing,
+            format!(
+                "0x0200  3c 42                    {:<width$}  0x3C 0x42\n\
+                 0x0202  a2 00                    {:<width$}  i := smiley\n\
+                 0x0204  12 02                    {:<width$}  jump main\n",
+                format!("{}:2", smiley),
+                format!("{}:3", main),
+                format!("{}:4", main),
+            )
+        );
+        assert_eq!(
+            assembly.map,
+            format!(
+                "0x0200..0x0206  code, 6 bytes\n    {}, 4 bytes\n    {}, 2 bytes\n",
+                main, smiley
+            )
+        );
+    }
+
+    #[test]
+    fn modules_share_labels_and_sections_follow_each_other() {
+        let dir = scratch("modules");
+        let game = write(
+            &dir,
+            "game.8o",
+            ": main\n  i := table\n  helper\n  jump main\n:section data\n: table\n  0xAA\n",
+        );
+        let helper = write(&dir, "helper.8o", ": helper\n  v0 := 1\n  ;\n");
+        let assembly = assemble_files(&[game, helper], &AsmOptions::default()).unwrap();
+        assert_eq!(
+            assembly.rom,
+            [
+                0xA2, 0x0A, 0x22, 0x06, 0x12, 0x00, 0x60, 0x01, 0x00, 0xEE, 0xAA
+            ]
+        );
+        let placed: Vec<(&str, usize, usize)> = assembly
+            .sections
+            .iter()
+            .map(|section| (section.name.as_str(), section.start, section.len))
+            .collect();
+        assert_eq!(placed, [("code", 0x200, 10), ("data", 0x20A, 1)]);
+    }
+
+    #[test]
+    fn labels_defined_twice_or_never_are_reported_where_they_are_used() {
+        assert_eq!(
+            error(": start\n  v0 := 1\n: start\n"),
+            (3, "`start` is defined twice".to_string())
+        );
+        assert_eq!(
+            error("v0 := 1\njump nowhere\n"),
+            (2, "`nowhere` is not defined".to_string())
+        );
+        assert_eq!(
+            error(":if XOCHIP\n  v0 := 1\n"),
+            (2, "`:if` without `:end`".to_string())
+        );
+    }
+
+    #[test]
+    fn includes_going_in_circles_are_refused() {
+        let dir = scratch("cycle");
+        let first = write(&dir, "first.8o", ":include \"second.8o\"\n");
+        let second = write(&dir, "second.8o", "v0 := 1\n:include \"first.8o\"\n");
+        let err = assemble_files(std::slice::from_ref(&first), &AsmOptions::default()).err();
+        assert_eq!(
+            err,
+            Some(AsmError {
+                file: Some(second),
+                line: 2,
+                message: format!("{} includes itself", first.display()),
+            })
+        );
+    }
+
+    #[cfg(feature = "png")]
+    #[test]
+    fn images_are_cut_into_sprites_left_to_right() {
+        let dir = scratch("image");
+        // 16 by 2 pixels, row by row: 0xAA and 0xFF, then 0x55 and 0x00.
+        let pixels: Vec<u8> = [0xAA_u8, 0xFF, 0x55, 0x00]
+            .iter()
+            .flat_map(|&byte| (0..8).map(move |bit| (byte << bit & 0x80 != 0) as u8 * 0xFF))
+            .collect();
+        let file = fs::File::create(dir.join("sprites.png")).unwrap();
+        let mut encoder = png::Encoder::new(file, 16, 2);
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+
+        let main = write(&dir, "main.8o", ":image \"sprites.png\" 2\n");
+        let assembly = assemble_files(&[main], &AsmOptions::default()).unwrap();
+        assert_eq!(assembly.rom, [0xAA, 0x55, 0xFF, 0x00]);
+    }
+}
@@ -0,0 +1,153 @@
+use chip_8::TIMER_HZ;
+use sdl2::AudioSubsystem;
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use std::time::{Duration, Instant};
+use tracing::info;
+
+const TONE_HZ: f32 = 440.0;
+const SAMPLE_RATE: i32 = 44100;
+const MIN_BUFFER: u16 = 64;
+const MAX_BUFFER: u16 = 8192;
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
+    playing: bool,
+    /// Samples per 60 Hz tick of the sound timer
+    tick_samples: u32,
+    /// Samples left of the shortest the current beep may be
+    hold: u32,
+    /// Stop once `hold` ran out, at the end of a wave period
+    stopping: bool,
+    /// Time one buffer lasts, callbacks further apart than that came too late
+    buffer_time: Duration,
+    last_callback: Option<Instant>,
+    xruns: u64,
+}
+
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        let now = Instant::now();
+        if let Some(last) = self.last_callback
+            && now.duration_since(last) > self.buffer_time * 3 / 2
+        {
+            // SDL played silence while waiting for this buffer.
+            self.xruns += 1;
+        }
+        self.last_callback = Some(now);
+
+        if !self.playing {
+            out.fill(0.0);
+            return;
+        }
+        for sample in out.iter_mut() {
+            if !self.playing {
+                *sample = 0.0;
+                continue;
+            }
+            *sample = if self.phase < 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase += self.phase_inc;
+            let period_done = self.phase >= 1.0;
+            self.phase %= 1.0;
+            self.hold = self.hold.saturating_sub(1);
+            // Cutting the wave off in the middle of a period clicks.
+            if self.stopping && self.hold == 0 && period_done {
+                self.playing = false;
+                self.stopping = false;
+            }
+        }
+    }
+}
+
+/// Timing of the audio device.
+pub struct AudioStats {
+    /// Buffers delivered too late to play without a gap
+    pub xruns: u64,
+}
+
+/// Square wave tone played while the sound timer is running.
+///
+/// The device runs all the time and outputs silence when the tone is off,
+/// so starting and stopping the tone costs at most one buffer of latency.
+pub struct Beeper {
+    device: AudioDevice<SquareWave>,
+}
+
+impl Beeper {
+    /// `buffer` is the device buffer size in samples, rounded to a power of two.
+    pub fn new(audio: &AudioSubsystem, volume: u8, buffer: u16) -> Result<Beeper, String> {
+        let spec = AudioSpecDesired {
+            freq: Some(SAMPLE_RATE),
+            channels: Some(1),
+            samples: Some(buffer.clamp(MIN_BUFFER, MAX_BUFFER).next_power_of_two()),
+        };
+        let device = audio.open_playback(None, &spec, |spec| SquareWave {
+            phase_inc: TONE_HZ / spec.freq as f32,
+            phase: 0.0,
+            volume: amplitude(volume),
+            playing: false,
+            tick_samples: spec.freq as u32 / TIMER_HZ,
+            hold: 0,
+            stopping: false,
+            buffer_time: Duration::from_secs_f64(spec.samples as f64 / spec.freq as f64),
+            last_callback: None,
+            xruns: 0,
+        })?;
+        device.resume();
+
+        let spec = device.spec();
+        info!(
+            samples = spec.samples,
+            freq = spec.freq,
+            latency_ms = spec.samples as u64 * 1000 / spec.freq as u64,
+            "Audio opened"
+        );
+        Ok(Beeper { device })
+    }
+
+    /// `volume` in percent.
+    pub fn set_volume(&mut self, volume: u8) {
+        self.device.lock().volume = amplitude(volume);
+    }
+
+    /// Switch the tone on or off right away, e.g. to mute it.
+    pub fn set_playing(&mut self, playing: bool) {
+        let mut wave = self.device.lock();
+        wave.playing = playing;
+        wave.hold = 0;
+        wave.stopping = false;
+    }
+
+    /// Start the tone for a sound timer set to `ticks`, playing at least that long
+    /// even when `stop` comes right after.
+    pub fn beep(&mut self, ticks: u8) {
+        let mut wave = self.device.lock();
+        wave.playing = true;
+        wave.hold = ticks as u32 * wave.tick_samples;
+        wave.stopping = false;
+    }
+
+    /// Stop the tone once it played as long as `beep` asked, at the end of a wave period.
+    pub fn stop(&mut self) {
+        let mut wave = self.device.lock();
+        wave.stopping = wave.playing;
+    }
+
+    pub fn stats(&mut self) -> AudioStats {
+        AudioStats {
+            xruns: self.device.lock().xruns,
+        }
+    }
+}
+
+/// Full scale square waves are unpleasantly loud, cap at a quarter.
+fn amplitude(volume: u8) -> f32 {
+    volume.min(100) as f32 / 100.0 * 0.25
+}
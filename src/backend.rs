@@ -0,0 +1,45 @@
+use chip_8::{Chip8, Chip8Error, StepResult};
+use clap::ValueEnum;
+
+/// How instructions are executed.
+#[derive(Clone, Copy, Default, PartialEq, ValueEnum)]
+pub enum BackendKind {
+    /// Decode and execute one instruction at a time
+    #[default]
+    Interpreter,
+
+    /// Compile basic blocks to native code (experimental)
+    #[cfg(feature = "jit")]
+    Jit,
+}
+
+pub enum Backend {
+    Interpreter,
+    #[cfg(feature = "jit")]
+    Jit(Box<chip_8::jit::Jit>),
+}
+
+impl Backend {
+    pub fn new(kind: BackendKind) -> Result<Backend, String> {
+        match kind {
+            BackendKind::Interpreter => Ok(Backend::Interpreter),
+            #[cfg(feature = "jit")]
+            BackendKind::Jit => Ok(Backend::Jit(Box::new(chip_8::jit::Jit::new()?))),
+        }
+    }
+
+    /// Execute `cycles` instructions, returning the result of the last one.
+    pub fn run(&mut self, chip8: &mut Chip8, cycles: u32) -> Result<StepResult, Chip8Error> {
+        match self {
+            Backend::Interpreter => {
+                let mut result = StepResult::Executed;
+                for _ in 0..cycles {
+                    result = chip8.cycle()?;
+                }
+                Ok(result)
+            }
+            #[cfg(feature = "jit")]
+            Backend::Jit(jit) => jit.run(chip8, cycles),
+        }
+    }
+}
@@ -0,0 +1,124 @@
+use crate::backend::{Backend, BackendKind};
+use crate::remote;
+use crate::run::parse_quirks;
+use chip_8::{Cartridge, Chip8, Chip8Error, Quirks};
+use clap::Args;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+use tracing::error;
+
+/// Frames run between clock checks.
+const FRAMES_PER_CHECK: u64 = 64;
+
+#[derive(Args)]
+pub struct BenchArgs {
+    /// Path to the ROM to run
+    rom: PathBuf,
+
+    /// How long to run
+    #[arg(long, default_value_t = 5)]
+    seconds: u64,
+
+    /// Quirks preset (modern, cosmac, superchip)
+    #[arg(long, default_value = "modern", value_parser = parse_quirks)]
+    quirks: Quirks,
+
+    /// Instructions per 60 Hz frame, used for the frame rate and timer ticks
+    #[arg(long, default_value_t = 10)]
+    ticks_per_frame: u32,
+}
+
+/// `chip-8 bench`: run the core headless as fast as possible and report its throughput.
+pub fn bench(args: BenchArgs, backend: BackendKind) -> ExitCode {
+    let mut chip8 = Chip8::new();
+    chip8.quirks = args.quirks;
+    chip8.seed(0);
+    if let Err(err) = remote::local_file(&args.rom)
+        .and_then(Cartridge::load)
+        .and_then(|cart| chip8.load_rom_bytes(&cart.rom))
+    {
+        error!(rom = %args.rom.display(), %err, "Could not load ROM");
+        return ExitCode::from(2);
+    }
+
+    let mut backend = match Backend::new(backend) {
+        Ok(backend) => backend,
+        Err(err) => {
+            error!(%err, "Could not start backend");
+            return ExitCode::from(2);
+        }
+    };
+    // Timing single instructions only makes sense when they run one by one.
+    let time_draws = matches!(backend, Backend::Interpreter);
+
+    let limit = Duration::from_secs(args.seconds);
+    let ticks_per_frame = args.ticks_per_frame.max(1);
+    let mut instructions: u64 = 0;
+    let mut frames: u64 = 0;
+    let mut draws: u64 = 0;
+    let mut draw_time = Duration::ZERO;
+    let start = Instant::now();
+    while start.elapsed() < limit {
+        for _ in 0..FRAMES_PER_CHECK {
+            let result = if time_draws {
+                run_timing_draws(&mut chip8, ticks_per_frame, &mut draws, &mut draw_time)
+            } else {
+                backend.run(&mut chip8, ticks_per_frame).map(|_| ())
+            };
+            if let Err(err) = result {
+                error!(frames, %err, "Machine stopped");
+                return ExitCode::from(2);
+            }
+            instructions += ticks_per_frame as u64;
+            chip8.tick_timers();
+            frames += 1;
+        }
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    println!("time          {:.2} s", elapsed);
+    println!(
+        "instructions  {} ({:.2} M/s)",
+        instructions,
+        instructions as f64 / elapsed / 1e6
+    );
+    println!(
+        "frames        {} ({:.0}/s, {:.0}x real time)",
+        frames,
+        frames as f64 / elapsed,
+        frames as f64 / elapsed / 60.0
+    );
+    if time_draws {
+        println!(
+            "DXYN          {} ({:.1} ms, {:.1}% of run time)",
+            draws,
+            draw_time.as_secs_f64() * 1000.0,
+            draw_time.as_secs_f64() / elapsed * 100.0
+        );
+    } else {
+        println!("DXYN          not measured with this backend");
+    }
+    ExitCode::SUCCESS
+}
+
+/// Interpret `cycles` instructions, adding up the time spent in DXYN.
+fn run_timing_draws(
+    chip8: &mut Chip8,
+    cycles: u32,
+    draws: &mut u64,
+    draw_time: &mut Duration,
+) -> Result<(), Chip8Error> {
+    for _ in 0..cycles {
+        let pc = chip8.pc as usize;
+        if chip8.bus.ram.get(pc).is_some_and(|byte| byte >> 4 == 0xD) {
+            let draw_start = Instant::now();
+            chip8.cycle()?;
+            *draw_time += draw_start.elapsed();
+            *draws += 1;
+        } else {
+            chip8.cycle()?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,172 @@
+//! Bevy integration: CHIP-8 machines as entities, e.g. arcade cabinets inside a game.
+//!
+//! Add `Chip8Plugin`, spawn a `Chip8Machine` and put its `image` on a sprite or material.
+//! Keys go in as `Chip8Input` messages, so the game decides when a cabinet has the player's
+//! attention. Machines run at 60 frames per second on `Time`, pausing with virtual time.
+//!
+//! ```ignore
+//! fn spawn_cabinet(mut commands: Commands, mut images: ResMut<Assets<Image>>) {
+//!     let mut chip8 = Chip8::new();
+//!     chip8.load_rom("pong.ch8").unwrap();
+//!     let machine = Chip8Machine::new(chip8, &mut images);
+//!     commands.spawn((Sprite::from_image(machine.image.clone()), machine));
+//! }
+//! ```
+
+use crate::chip8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH};
+use crate::clock::Clock;
+use crate::palette::{PALETTES, Palette};
+use bevy_app::{App, Plugin, Update};
+use bevy_asset::{Assets, Handle, RenderAssetUsages};
+use bevy_ecs::prelude::*;
+use bevy_image::{Image, ImageSampler};
+use bevy_time::Time;
+use wgpu_types::{Extent3d, TextureDimension, TextureFormat};
+
+/// Frames run at once to catch up after a stall, anything beyond is dropped.
+const MAX_CATCH_UP: u32 = 5;
+
+/// Steps every `Chip8Machine` and keeps its texture up to date.
+pub struct Chip8Plugin;
+
+impl Plugin for Chip8Plugin {
+    fn build(&self, app: &mut App) {
+        app.add_message::<Chip8Input>()
+            .add_message::<Chip8Halted>()
+            .add_systems(Update, (apply_input, step_machines).chain());
+    }
+}
+
+/// A CHIP-8 machine with its display as a texture.
+#[derive(Component)]
+pub struct Chip8Machine {
+    pub chip8: Chip8,
+
+    /// Instructions per frame
+    pub ticks_per_frame: u32,
+
+    /// Display texture, one texel per CHIP-8 pixel with nearest sampling
+    pub image: Handle<Image>,
+
+    /// Colors of the texture
+    pub palette: &'static Palette,
+
+    /// Stops stepping, e.g. while the player walks away from the cabinet
+    pub paused: bool,
+
+    /// Game time towards the next frame, one per timer tick
+    clock: Clock,
+
+    /// Error the machine stopped on, see `Chip8Halted`
+    halted: Option<Chip8Error>,
+}
+
+impl Chip8Machine {
+    /// Machine running `chip8`, with a new texture added to `images`.
+    pub fn new(chip8: Chip8, images: &mut Assets<Image>) -> Chip8Machine {
+        let palette = &PALETTES[0];
+        let mut image = Image::new(
+            Extent3d {
+                width: DISP_WIDTH as u32,
+                height: DISP_HEIGHT as u32,
+                depth_or_array_layers: 1,
+            },
+            TextureDimension::D2,
+            chip8.render_rgba(palette),
+            TextureFormat::Rgba8UnormSrgb,
+            RenderAssetUsages::default(),
+        );
+        image.sampler = ImageSampler::nearest();
+
+        Chip8Machine {
+            chip8,
+            ticks_per_frame: 10,
+            image: images.add(image),
+            palette,
+            paused: false,
+            clock: Clock::new(),
+            halted: None,
+        }
+    }
+
+    /// Error the machine stopped on, if any.
+    pub fn halted(&self) -> Option<Chip8Error> {
+        self.halted
+    }
+
+    /// Start over after a halt, with the state the caller left in `chip8`.
+    pub fn resume(&mut self) {
+        self.halted = None;
+        self.clock.reset();
+    }
+}
+
+/// Key change for the machine on entity `machine`.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct Chip8Input {
+    pub machine: Entity,
+
+    /// Key 0x0..=0xF
+    pub key: u8,
+
+    pub pressed: bool,
+}
+
+/// Sent once when a machine stops on a fatal error.
+#[derive(Message, Clone, Copy, Debug)]
+pub struct Chip8Halted {
+    pub machine: Entity,
+    pub error: Chip8Error,
+}
+
+fn apply_input(mut input: MessageReader<Chip8Input>, mut machines: Query<&mut Chip8Machine>) {
+    for event in input.read() {
+        if let Ok(mut machine) = machines.get_mut(event.machine) {
+            machine
+                .chip8
+                .set_key_value(event.key as usize & 0xF, event.pressed as u8);
+        }
+    }
+}
+
+fn step_machines(
+    time: Res<Time>,
+    mut machines: Query<(Entity, &mut Chip8Machine)>,
+    mut images: ResMut<Assets<Image>>,
+    mut halted: MessageWriter<Chip8Halted>,
+) {
+    for (entity, mut machine) in &mut machines {
+        if machine.paused || machine.halted.is_some() {
+            continue;
+        }
+
+        let frames = machine.clock.advance(time.delta());
+        if frames == 0 {
+            continue;
+        }
+
+        let machine = &mut *machine;
+        for _ in 0..frames.min(MAX_CATCH_UP) {
+            if let Err(error) = run_frame(&mut machine.chip8, machine.ticks_per_frame) {
+                machine.halted = Some(error);
+                halted.write(Chip8Halted {
+                    machine: entity,
+                    error,
+                });
+                break;
+            }
+        }
+
+        if let Some(mut image) = images.get_mut(&machine.image) {
+            image.data = Some(machine.chip8.render_rgba(machine.palette));
+        }
+    }
+}
+
+fn run_frame(chip8: &mut Chip8, ticks_per_frame: u32) -> Result<(), Chip8Error> {
+    for _ in 0..ticks_per_frame {
+        chip8.cycle()?;
+    }
+    chip8.tick_timers();
+    Ok(())
+}
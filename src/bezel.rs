@@ -0,0 +1,143 @@
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+use serde::Deserialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Bezel layout file, see `Config::bezel`.
+#[derive(Deserialize)]
+struct Layout {
+    /// PNG artwork, relative to the layout file
+    image: PathBuf,
+
+    /// Where the display goes, in image pixels
+    screen: Screen,
+}
+
+#[derive(Deserialize)]
+struct Screen {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Artwork drawn around the display, e.g. a console or cabinet.
+pub struct Bezel<'a> {
+    texture: Texture<'a>,
+    width: u32,
+    height: u32,
+    screen: Rect,
+}
+
+impl<'a> Bezel<'a> {
+    /// Read the layout at `path` and the image it names.
+    pub fn load(path: &Path, textures: &'a TextureCreator<WindowContext>) -> io::Result<Bezel<'a>> {
+        let layout: Layout = toml::from_str(&fs::read_to_string(path)?).map_err(invalid)?;
+        let image = path.parent().unwrap_or(Path::new("")).join(&layout.image);
+        let (width, height, pixels) = read_png(&image)?;
+        let screen = layout.screen;
+        if screen.width == 0
+            || screen.height == 0
+            || screen.x + screen.width > width
+            || screen.y + screen.height > height
+        {
+            return Err(invalid(format!(
+                "screen does not fit in the {}x{} image",
+                width, height
+            )));
+        }
+
+        let mut texture = textures
+            .create_texture_static(PixelFormatEnum::RGBA32, width, height)
+            .map_err(io::Error::other)?;
+        texture
+            .update(None, &pixels, width as usize * 4)
+            .map_err(io::Error::other)?;
+        texture.set_blend_mode(BlendMode::Blend);
+        Ok(Bezel {
+            texture,
+            width,
+            height,
+            screen: Rect::new(
+                screen.x as i32,
+                screen.y as i32,
+                screen.width,
+                screen.height,
+            ),
+        })
+    }
+
+    /// Draw the artwork as large as fits in the output, keeping its shape,
+    /// and return the part of the output the display belongs in.
+    pub fn draw(&self, canvas: &mut WindowCanvas) -> Rect {
+        let (output_width, output_height) = canvas.output_size().expect("Error when drawing");
+        let (width, height) = (self.width as u64, self.height as u64);
+        let (w, h) = if output_width as u64 * height > output_height as u64 * width {
+            (output_height as u64 * width / height, output_height as u64)
+        } else {
+            (output_width as u64, output_width as u64 * height / width)
+        };
+        let x = (output_width as i64 - w as i64) / 2;
+        let y = (output_height as i64 - h as i64) / 2;
+        canvas
+            .copy(
+                &self.texture,
+                None,
+                Rect::new(x as i32, y as i32, w.max(1) as u32, h.max(1) as u32),
+            )
+            .expect("Error when drawing");
+
+        // Image pixels to output pixels, edges rounded separately like display pixels.
+        let column = |image_x: i32| x + image_x as i64 * w as i64 / width as i64;
+        let row = |image_y: i32| y + image_y as i64 * h as i64 / height as i64;
+        let (left, top) = (column(self.screen.left()), row(self.screen.top()));
+        let (right, bottom) = (column(self.screen.right()), row(self.screen.bottom()));
+        Rect::new(
+            left as i32,
+            top as i32,
+            (right - left).max(1) as u32,
+            (bottom - top).max(1) as u32,
+        )
+    }
+}
+
+/// Width, height and RGBA pixels of a PNG file.
+#[cfg(feature = "png")]
+fn read_png(path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    let mut decoder = png::Decoder::new(io::BufReader::new(fs::File::open(path)?));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(invalid)?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer).map_err(invalid)?;
+    let buffer = &buffer[..info.buffer_size()];
+    let pixels = match info.color_type {
+        png::ColorType::Rgba => buffer.to_vec(),
+        png::ColorType::Rgb => buffer
+            .chunks_exact(3)
+            .flat_map(|rgb| [rgb[0], rgb[1], rgb[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => buffer
+            .chunks_exact(2)
+            .flat_map(|ga| [ga[0], ga[0], ga[0], ga[1]])
+            .collect(),
+        png::ColorType::Grayscale => buffer.iter().flat_map(|&g| [g, g, g, 255]).collect(),
+        png::ColorType::Indexed => unreachable!("palettes are expanded"),
+    };
+    Ok((info.width, info.height, pixels))
+}
+
+#[cfg(not(feature = "png"))]
+fn read_png(_path: &Path) -> io::Result<(u32, u32, Vec<u8>)> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "bezels need the `png` feature",
+    ))
+}
+
+fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,92 @@
+use crate::dirs::cache_dir;
+use crate::i18n::tr;
+use crate::menu::{draw_entry, draw_footer};
+use crate::text::{LINE_HEIGHT, MARGIN, TEXT_SCALE, WHITE, dim, draw_text};
+use sdl2::keyboard::Keycode;
+use sdl2::render::WindowCanvas;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use tracing::error;
+
+/// Public-domain demos built into the binary, assembled from their sources in `roms/`.
+pub const ROMS: [(&str, &[u8]); 3] = [
+    ("bounce", include_bytes!("../roms/bounce.ch8")),
+    ("maze", include_bytes!("../roms/maze.ch8")),
+    ("keypad", include_bytes!("../roms/keypad.ch8")),
+];
+
+/// File to play bundled ROM `n` from, its copy in the cache directory, so it gets
+/// settings, save states and a place in the recent list like any other ROM.
+pub fn extract(n: usize) -> io::Result<PathBuf> {
+    let (name, rom) = ROMS[n];
+    let dir = cache_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?
+        .join("bundled");
+    let path = dir.join(format!("{}.ch8", name));
+    if fs::read(&path).ok().as_deref() != Some(rom) {
+        fs::create_dir_all(&dir)?;
+        fs::write(&path, rom)?;
+    }
+    Ok(path)
+}
+
+pub enum PickerAction {
+    None,
+    Close,
+    Load(PathBuf),
+}
+
+/// Menu of the bundled ROMs shown at startup when no ROM was given, over the first one.
+pub struct Picker {
+    selected: usize,
+}
+
+impl Picker {
+    pub fn new() -> Picker {
+        Picker { selected: 0 }
+    }
+
+    pub fn handle_key(&mut self, key: Keycode) -> PickerAction {
+        let count = ROMS.len();
+        match key {
+            Keycode::Escape => PickerAction::Close,
+            Keycode::Up => {
+                self.selected = (self.selected + count - 1) % count;
+                PickerAction::None
+            }
+            Keycode::Down => {
+                self.selected = (self.selected + 1) % count;
+                PickerAction::None
+            }
+            Keycode::Return | Keycode::KpEnter => match extract(self.selected) {
+                Ok(path) => PickerAction::Load(path),
+                Err(err) => {
+                    error!(rom = ROMS[self.selected].0, %err, "Could not extract ROM");
+                    PickerAction::None
+                }
+            },
+            _ => PickerAction::None,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        dim(canvas);
+        draw_text(
+            canvas,
+            tr("bundled.title"),
+            MARGIN,
+            MARGIN,
+            TEXT_SCALE,
+            WHITE,
+        );
+
+        let mut y = MARGIN + 2 * LINE_HEIGHT;
+        for (n, (name, _)) in ROMS.iter().enumerate() {
+            draw_entry(canvas, &name.to_uppercase(), MARGIN, y, n == self.selected);
+            y += LINE_HEIGHT;
+        }
+
+        draw_footer(canvas, tr("bundled.footer"));
+    }
+}
@@ -0,0 +1,199 @@
+//! Address space of the machine: RAM, with the fonts in it, and the devices mapped over it.
+
+use crate::cartridge::MAX_ROM_LEN;
+use crate::peripheral::Peripheral;
+use std::ops::Range;
+use tracing::debug;
+
+/// Addresses banks are swapped into, the top 2 kB of RAM.
+pub const BANK_WINDOW: Range<usize> = 0x800..0x1000;
+
+/// Register selecting the bank in `BANK_WINDOW`: storing N there swaps bank N in,
+/// loading from it gives the bank that is in.
+pub const BANK_SELECT: u16 = 0x1E0;
+
+/// Banks a ROM can have, bank 0 being what it loads into the window itself.
+pub const MAX_BANKS: usize = 16;
+
+/// Largest ROM there is room for with banking: RAM from 0x200 up and the banks past bank 0.
+pub const MAX_BANKED_ROM_LEN: usize =
+    MAX_ROM_LEN + (MAX_BANKS - 1) * (BANK_WINDOW.end - BANK_WINDOW.start);
+
+// CHIP-8 built in fonts
+// used by DXYN draw function in user programs.
+// Hex digits 0-9 and A-F, 5 bytes each
+pub(crate) const FONTS: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
+    0x20, 0x60, 0x20, 0x20, 0x70, // 1
+    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
+    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
+    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
+    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
+    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
+    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
+    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
+    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
+    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
+    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
+    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
+    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
+    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
+    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
+];
+
+/// Memory as instructions see it, which they all go through: 4 kB of RAM holding the fonts
+/// from 0x000, and devices answering parts of the address space in place of RAM.
+///
+/// Opcodes are always fetched from RAM; devices only answer `read` and `write`.
+///
+/// ROMs too large for RAM are banked, see `load_banks`: the part that does not fit is cut into banks
+/// the size of `BANK_WINDOW` that the program swaps in through `BANK_SELECT`.
+pub struct Bus {
+    /// 4 kB of RAM memory;
+    /// Addresses from 0x000 to 0xFF;
+    /// 0x000 - 0x200 Interpreter,
+    /// 0x200 - 0x600 User programs,
+    /// 0x600 - 0xFFF ETI 660 User programs,
+    pub ram: [u8; 4096],
+
+    /// Devices by the addresses they answer, see `map`
+    peripherals: Vec<(Range<usize>, Box<dyn Peripheral>)>,
+
+    /// Contents of every bank, stale for the one in the window; empty unless banked
+    banks: Vec<Box<[u8]>>,
+
+    /// Bank in the window
+    bank: usize,
+}
+
+impl Default for Bus {
+    fn default() -> Bus {
+        Bus::new()
+    }
+}
+
+impl Bus {
+    /// RAM with the fonts loaded and zero everywhere else, nothing mapped.
+    pub fn new() -> Bus {
+        let mut bus = Bus {
+            ram: [0; 4096],
+            peripherals: Vec::new(),
+            banks: Vec::new(),
+            bank: 0,
+        };
+        bus.ram[..FONTS.len()].copy_from_slice(&FONTS);
+        bus
+    }
+
+    /// Have `device` answer the accesses to `range` instead of RAM.
+    /// Where ranges overlap, the device mapped first answers.
+    pub fn map(&mut self, range: Range<u16>, device: impl Peripheral + 'static) {
+        self.peripherals
+            .push((range.start as usize..range.end as usize, Box::new(device)));
+    }
+
+    /// Whether a device answers `addr` instead of RAM.
+    pub fn mapped(&self, addr: usize) -> bool {
+        self.peripherals
+            .iter()
+            .any(|(range, _)| range.contains(&addr))
+    }
+
+    /// Whether anything answers `addr`, a device or RAM.
+    pub fn contains(&self, addr: usize) -> bool {
+        addr < self.ram.len() || self.mapped(addr)
+    }
+
+    /// Turn on banking with `banks` after bank 0, the window as it is now, each padded with zeros
+    /// to the size of the window. Bank 0 is swapped in.
+    pub fn load_banks<'a>(&mut self, banks: impl IntoIterator<Item = &'a [u8]>) {
+        let window = &self.ram[BANK_WINDOW];
+        self.banks = vec![window.into()];
+        for bank in banks {
+            let mut contents = vec![0; BANK_WINDOW.len()];
+            contents[..bank.len()].copy_from_slice(bank);
+            self.banks.push(contents.into());
+        }
+        self.bank = 0;
+        debug!(banks = self.banks.len(), "Banks loaded");
+    }
+
+    /// Bank in `BANK_WINDOW`, `None` unless banked.
+    pub fn bank(&self) -> Option<usize> {
+        (!self.banks.is_empty()).then_some(self.bank)
+    }
+
+    /// Contents of every bank, the one in the window as RAM holds it now; empty unless banked.
+    pub fn banks(&self) -> Vec<Vec<u8>> {
+        let mut banks: Vec<Vec<u8>> = self.banks.iter().map(|bank| bank.to_vec()).collect();
+        if let Some(bank) = banks.get_mut(self.bank) {
+            bank.copy_from_slice(&self.ram[BANK_WINDOW]);
+        }
+        banks
+    }
+
+    /// Banking as `banks` returned it, with bank `bank` in the window, which RAM already holds.
+    /// No banks turn banking off. Every bank is the size of `BANK_WINDOW`.
+    pub fn restore_banks(&mut self, banks: &[Vec<u8>], bank: usize) {
+        self.banks = banks.iter().map(|bank| bank[..].into()).collect();
+        self.bank = bank.min(banks.len().saturating_sub(1));
+    }
+
+    /// Swap bank `bank` into the window, keeping the one in there as the program left it.
+    /// Banks that do not exist are ignored.
+    fn select_bank(&mut self, bank: usize) {
+        if bank == self.bank || bank >= self.banks.len() {
+            return;
+        }
+        self.banks[self.bank].copy_from_slice(&self.ram[BANK_WINDOW]);
+        self.ram[BANK_WINDOW].copy_from_slice(&self.banks[bank]);
+        self.bank = bank;
+    }
+
+    /// Whether `addr` is `BANK_SELECT` of a banked bus.
+    fn is_bank_select(&self, addr: usize) -> bool {
+        addr == BANK_SELECT as usize && !self.banks.is_empty()
+    }
+
+    /// Byte at `addr`, from the device mapped there or RAM, `None` when nothing is there.
+    pub fn read(&mut self, addr: usize) -> Option<u8> {
+        if self.is_bank_select(addr) {
+            return Some(self.bank as u8);
+        }
+        match self.peripheral(addr) {
+            Some(device) => Some(device.read(addr as u16)),
+            None => self.ram.get(addr).copied(),
+        }
+    }
+
+    /// Store `value` at `addr`, to the device mapped there or RAM; false when nothing is there.
+    pub fn write(&mut self, addr: usize, value: u8) -> bool {
+        if self.is_bank_select(addr) {
+            self.select_bank(value as usize);
+            return true;
+        }
+        match self.peripheral(addr) {
+            Some(device) => device.write(addr as u16, value),
+            None => match self.ram.get_mut(addr) {
+                Some(byte) => *byte = value,
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// Big-endian opcode at `addr` in RAM, `None` when it does not fit.
+    pub fn opcode(&self, addr: usize) -> Option<u16> {
+        match self.ram.get(addr..addr + 2) {
+            Some(&[high, low]) => Some((high as u16) << 8 | low as u16),
+            _ => None,
+        }
+    }
+
+    fn peripheral(&mut self, addr: usize) -> Option<&mut Box<dyn Peripheral>> {
+        self.peripherals
+            .iter_mut()
+            .find(|(range, _)| range.contains(&addr))
+            .map(|(_, device)| device)
+    }
+}
@@ -0,0 +1,207 @@
+use crate::bus::MAX_BANKED_ROM_LEN;
+use crate::chip8::Quirks;
+use crate::palette::{PALETTES, Palette, palette};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Start of every `.c8x` file.
+const MAGIC: &[u8; 4] = b"C8X\x1A";
+
+/// Layout version, bumped on incompatible changes.
+const VERSION: u8 = 1;
+
+/// Bytes before the metadata: magic, version and metadata length.
+const HEADER_LEN: usize = 4 + 1 + 4;
+
+/// Largest ROM that fits in RAM above the interpreter area.
+pub const MAX_ROM_LEN: usize = 4096 - 0x200;
+
+/// Information about a game stored along with its ROM.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub title: Option<String>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub author: Option<String>,
+
+    /// Platform the game was written for, e.g. `chip-8`, `superchip` or `xo-chip`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub variant: Option<String>,
+
+    /// Quirks the game needs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quirks: Option<CartridgeQuirks>,
+
+    /// Palette the game looks best in, see `PALETTES`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub palette: Option<String>,
+
+    /// Display colors as RGB, replacing the ones of `palette`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub foreground: Option<[u8; 3]>,
+
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub background: Option<[u8; 3]>,
+
+    /// Instructions per 60 Hz frame the game is meant to run at
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ticks_per_frame: Option<u32>,
+
+    /// What the game uses keys for, by CHIP-8 key as a hex digit: `"5" = "FIRE"`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<String, String>,
+}
+
+impl Metadata {
+    /// Label of CHIP-8 `key`, if the game gave it one.
+    pub fn key_label(&self, key: u8) -> Option<&str> {
+        self.keys
+            .iter()
+            .find(|(name, _)| u8::from_str_radix(name, 16) == Ok(key))
+            .map(|(_, label)| label.as_str())
+    }
+
+    /// Colors the game asks for, if any.
+    pub fn palette(&self) -> Option<Palette> {
+        if self.palette.is_none() && self.foreground.is_none() && self.background.is_none() {
+            return None;
+        }
+        let base = self.palette.as_deref().map_or(&PALETTES[0], palette);
+        Some(Palette {
+            name: base.name,
+            foreground: self.foreground.unwrap_or(base.foreground),
+            background: self.background.unwrap_or(base.background),
+            ..base.clone()
+        })
+    }
+}
+
+/// Quirks of a cartridge, written as a preset name, `quirks = "cosmac"`,
+/// or with every flag spelled out, `quirks = { shift_uses_vy = true, ... }`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum CartridgeQuirks {
+    Preset(String),
+    Custom(Quirks),
+}
+
+impl CartridgeQuirks {
+    /// `None` for an unknown preset.
+    pub fn resolve(&self) -> Option<Quirks> {
+        match self {
+            CartridgeQuirks::Preset(name) => Quirks::preset(name),
+            CartridgeQuirks::Custom(quirks) => Some(*quirks),
+        }
+    }
+}
+
+impl fmt::Display for CartridgeQuirks {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CartridgeQuirks::Preset(name) => write!(f, "{}", name),
+            CartridgeQuirks::Custom(quirks) => write!(f, "{}", quirks.preset_name()),
+        }
+    }
+}
+
+/// A ROM with its metadata, read from a `.c8x` container or a plain ROM file.
+///
+/// `.c8x` layout: `C8X` 0x1A, a version byte, the metadata length as a little endian `u32`,
+/// the metadata as UTF-8 TOML, then the ROM bytes.
+#[derive(Clone, Default)]
+pub struct Cartridge {
+    pub metadata: Metadata,
+    pub rom: Vec<u8>,
+}
+
+impl Cartridge {
+    /// Read a `.c8x` container or an Octo `.gif` cartridge,
+    /// or take `bytes` as a plain ROM without metadata.
+    pub fn from_bytes(bytes: Vec<u8>) -> io::Result<Cartridge> {
+        if bytes.starts_with(b"GIF8") {
+            #[cfg(feature = "octo")]
+            return crate::octo::read_cart(&bytes);
+            #[cfg(not(feature = "octo"))]
+            return Err(invalid("Octo cartridges need the `octo` feature"));
+        }
+        if !bytes.starts_with(MAGIC) {
+            return Ok(Cartridge {
+                metadata: Metadata::default(),
+                rom: bytes,
+            });
+        }
+        if bytes.len() < HEADER_LEN {
+            return Err(invalid("truncated .c8x header"));
+        }
+        if bytes[4] != VERSION {
+            return Err(invalid(format!("unsupported .c8x version {}", bytes[4])));
+        }
+        let len = u32::from_le_bytes(bytes[5..HEADER_LEN].try_into().unwrap()) as usize;
+        let metadata = bytes
+            .get(HEADER_LEN..HEADER_LEN + len)
+            .ok_or_else(|| invalid("truncated .c8x metadata"))?;
+        let metadata = std::str::from_utf8(metadata).map_err(invalid)?;
+        let metadata: Metadata = toml::from_str(metadata).map_err(invalid)?;
+        if let Some(name) = metadata
+            .keys
+            .keys()
+            .find(|name| u8::from_str_radix(name, 16).map_or(true, |key| key > 0xF))
+        {
+            return Err(invalid(format!("no such key {}", name)));
+        }
+
+        Ok(Cartridge {
+            metadata,
+            rom: bytes[HEADER_LEN + len..].to_vec(),
+        })
+    }
+
+    /// The cartridge as a `.c8x` container.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let metadata = toml::to_string(&self.metadata).expect("Metadata should serialize");
+        let mut bytes = Vec::with_capacity(HEADER_LEN + metadata.len() + self.rom.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&(metadata.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(metadata.as_bytes());
+        bytes.extend_from_slice(&self.rom);
+        bytes
+    }
+
+    /// Apply the IPS patch at `path` to the ROM, see `ips::apply`.
+    pub fn patch<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        crate::ips::apply(&mut self.rom, &fs::read(path)?)?;
+        if self.rom.len() > MAX_BANKED_ROM_LEN {
+            return Err(invalid(format!(
+                "patched ROM too large: {} bytes",
+                self.rom.len()
+            )));
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Cartridge> {
+        let cartridge = Cartridge::from_bytes(fs::read(path)?)?;
+        if cartridge.rom.len() > MAX_BANKED_ROM_LEN {
+            return Err(invalid(format!(
+                "ROM too large: {} bytes",
+                cartridge.rom.len()
+            )));
+        }
+        Ok(cartridge)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+}
+
+pub(crate) fn invalid<E: Into<Box<dyn std::error::Error + Send + Sync>>>(err: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, err)
+}
@@ -0,0 +1,58 @@
+use chip_8::{DISP_HEIGHT, DISP_WIDTH};
+use sdl2::pixels::Color;
+
+/// Pixels turned on since the last drawn frame.
+pub const TURNED_ON: Color = Color::RGB(0x40, 0xE0, 0x40);
+
+/// Pixels turned off since the last drawn frame.
+pub const TURNED_OFF: Color = Color::RGB(0xE0, 0x40, 0x40);
+
+/// Pixels that changed between drawn frames, for seeing what draw calls touch. A sprite
+/// erased in one frame and drawn again in the next, the usual source of flicker, shows up
+/// red and then green; with frame skip both frames count towards the same drawn one.
+pub struct Changes {
+    previous: [bool; DISP_WIDTH * DISP_HEIGHT],
+    changed: [bool; DISP_WIDTH * DISP_HEIGHT],
+}
+
+impl Changes {
+    pub fn new(video: &[bool; DISP_WIDTH * DISP_HEIGHT]) -> Changes {
+        Changes {
+            previous: *video,
+            changed: [false; DISP_WIDTH * DISP_HEIGHT],
+        }
+    }
+
+    /// Note the pixels that differ from the last frame, called after every emulated frame.
+    pub fn update(&mut self, video: &[bool; DISP_WIDTH * DISP_HEIGHT]) {
+        for ((changed, previous), &pixel) in
+            self.changed.iter_mut().zip(&mut self.previous).zip(video)
+        {
+            *changed |= *previous != pixel;
+            *previous = pixel;
+        }
+    }
+
+    /// Changed pixels by index into the display with their color, forgetting them
+    /// for the next drawn frame.
+    pub fn take(&mut self) -> Vec<(usize, Color)> {
+        let changes = self
+            .changed
+            .iter()
+            .enumerate()
+            .filter(|(_, changed)| **changed)
+            .map(|(idx, _)| {
+                (
+                    idx,
+                    if self.previous[idx] {
+                        TURNED_ON
+                    } else {
+                        TURNED_OFF
+                    },
+                )
+            })
+            .collect();
+        self.changed = [false; DISP_WIDTH * DISP_HEIGHT];
+        changes
+    }
+}
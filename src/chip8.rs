@@ -1,40 +1,361 @@
-use rand;
-use rand::random;
+use crate::bus::{BANK_WINDOW, Bus, FONTS, MAX_BANKED_ROM_LEN};
+use crate::cartridge::MAX_ROM_LEN;
+use crate::display::{Display, Edges};
+use crate::hash::fnv1a;
+use crate::instruction::Instruction;
+use crate::palette::Palette;
+use crate::peripheral::Peripheral;
+use crate::postprocess::RgbaFrame;
+use crate::random::{RandomSource, VipRandom};
+use crate::state::SaveState;
+use rand::{RngCore, SeedableRng, random};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use std::fmt;
 use std::fs;
+#[cfg(feature = "png")]
+use std::fs::File;
+use std::io;
+#[cfg(feature = "png")]
+use std::io::BufWriter;
+use std::ops::Range;
+use std::path::Path;
+use tracing::{debug, info, trace};
 
 pub const DISP_WIDTH: usize = 64;
 pub const DISP_HEIGHT: usize = 32;
 
-// CHIP-8 built in fonts
-// used by DXYN draw function in user programs.
-// Hex digits 0-9 and A-F, 5 bytes each
-const FONTS: [u8; 80] = [
-    0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
-    0x20, 0x60, 0x20, 0x20, 0x70, // 1
-    0xF0, 0x10, 0xF0, 0x80, 0xF0, // 2
-    0xF0, 0x10, 0xF0, 0x10, 0xF0, // 3
-    0x90, 0x90, 0xF0, 0x10, 0x10, // 4
-    0xF0, 0x80, 0xF0, 0x10, 0xF0, // 5
-    0xF0, 0x80, 0xF0, 0x90, 0xF0, // 6
-    0xF0, 0x10, 0x20, 0x40, 0x40, // 7
-    0xF0, 0x90, 0xF0, 0x90, 0xF0, // 8
-    0xF0, 0x90, 0xF0, 0x10, 0xF0, // 9
-    0xF0, 0x90, 0xF0, 0x90, 0x90, // A
-    0xE0, 0x90, 0xE0, 0x90, 0xE0, // B
-    0xF0, 0x80, 0x80, 0x80, 0xF0, // C
-    0xE0, 0x90, 0x90, 0x90, 0xE0, // D
-    0xF0, 0x80, 0xF0, 0x80, 0xF0, // E
-    0xF0, 0x80, 0xF0, 0x80, 0x80, // F
-];
+/// Bytes of the display packed one bit per pixel, see `Chip8::framebuffer_bits`.
+pub const FRAMEBUFFER_BYTES: usize = DISP_WIDTH * DISP_HEIGHT / 8;
+
 const START_ADDR: usize = 0x200;
 
+/// Tone changes kept for `Chip8::take_sound_events`, older ones are dropped
+/// when a frontend never takes them.
+const MAX_SOUND_EVENTS: usize = 64;
+
+/// Smallest ST that sounds the tone. The COSMAC VIP documents that it does not respond to 1,
+/// too short a pulse for its speaker circuit, and interpreters since do the same.
+pub const MIN_BEEP_TICKS: u8 = 2;
+
+/// Fatal errors stopping the machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    /// Opcode at `pc` is not a CHIP-8 instruction
+    IllegalOpcode { opcode: u16, pc: u16 },
+
+    /// PC points outside of RAM
+    InvalidAddress(u16),
+
+    /// Instruction at `pc` reads or writes `addr`, past the end of RAM, with I-relative addressing
+    MemoryFault { addr: usize, pc: u16 },
+
+    /// `RET` at `pc` with nothing on the stack
+    StackUnderflow { pc: u16 },
+
+    /// `CALL` at `pc` with all 16 stack entries in use
+    StackOverflow { pc: u16 },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::IllegalOpcode { opcode, pc } => {
+                write!(f, "Illegal OP {:#06x} at {:#05x}", opcode, pc)
+            }
+            Chip8Error::InvalidAddress(addr) => write!(f, "Not supported address {:#x}", addr),
+            Chip8Error::MemoryFault { addr, pc } => {
+                write!(
+                    f,
+                    "Memory access at {:#x} outside of RAM at {:#05x}",
+                    addr, pc
+                )
+            }
+            Chip8Error::StackUnderflow { pc } => {
+                write!(f, "Return with an empty stack at {:#05x}", pc)
+            }
+            Chip8Error::StackOverflow { pc } => {
+                write!(f, "Call with a full stack at {:#05x}", pc)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
+
+/// Outcome of a successful `cycle`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepResult {
+    /// Instruction executed
+    Executed,
+
+    /// FX0A is waiting for a key to be pressed and released and runs again next cycle
+    WaitingForKey,
+}
+
+/// What to do about an opcode that is not a CHIP-8 instruction, decided by the handler
+/// given to `Chip8::set_trap_handler`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Trap {
+    /// The handler carried the opcode out itself, continue at PC
+    Handled,
+
+    /// Treat the opcode as doing nothing and continue after it
+    Skip,
+
+    /// Stop with `Chip8Error::IllegalOpcode`, like without a handler
+    Halt,
+}
+
+/// Names of the flags set in `quirks`, space separated, `none` without any.
+#[cfg(feature = "png")]
+fn quirk_list(quirks: &Quirks) -> String {
+    let set: Vec<&str> = quirks
+        .flags()
+        .into_iter()
+        .filter_map(|(name, set)| set.then_some(name))
+        .collect();
+    if set.is_empty() {
+        "none".to_string()
+    } else {
+        set.join(" ")
+    }
+}
+
+/// `video` packed like `Chip8::framebuffer_bits` does.
+pub(crate) fn pack_video(video: &[bool; DISP_WIDTH * DISP_HEIGHT]) -> [u8; FRAMEBUFFER_BYTES] {
+    let mut bits = [0; FRAMEBUFFER_BYTES];
+    for (byte, pixels) in bits.iter_mut().zip(video.chunks(8)) {
+        *byte = pixels.iter().fold(0, |byte, &on| byte << 1 | on as u8);
+    }
+    bits
+}
+
+/// Handler of illegal opcodes, see `Chip8::set_trap_handler`.
+type TrapHandler = Box<dyn FnMut(&mut Chip8, u16) -> Trap + Send + Sync>;
+
+/// Tone switching on or off, see `Chip8::take_sound_events`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SoundEvent {
+    /// ST was set to `MIN_BEEP_TICKS` or more while silent. `ticks` is the value it was set to,
+    /// the 60 Hz ticks the tone lasts unless ST is set again, so backends can play short beeps
+    /// in full even when `Stopped` comes in the same frame.
+    Started { ticks: u8 },
+
+    /// ST reached zero while sounding, counting down or set to it
+    Stopped,
+}
+
+/// Sprite drawn by DXYN, see `Chip8::last_draw`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct SpriteDraw {
+    /// Where the sprite data starts, I at the time
+    pub addr: u16,
+
+    /// Top-left corner, wrapped onto the display
+    pub x: u8,
+    pub y: u8,
+
+    /// Rows drawn, N of the instruction
+    pub height: u8,
+
+    /// A pixel was turned off, VF set to 1
+    pub collision: bool,
+}
+
+/// Where a screenshot comes from, written into it by `Chip8::save_screenshot`
+/// so shared captures carry what is needed to reproduce them.
+#[derive(Clone, Debug, Default)]
+pub struct CaptureInfo {
+    /// Name of the ROM file
+    pub rom: String,
+
+    /// FNV-1a hash of the ROM bytes, see `hash::fnv1a`
+    pub rom_hash: u64,
+
+    /// Frames emulated since the ROM or a save state was loaded
+    pub frame: u64,
+}
+
+impl CaptureInfo {
+    /// Info for a capture of the ROM `rom` loaded from `path`, at `frame`.
+    pub fn new(path: &Path, rom: &[u8], frame: u64) -> CaptureInfo {
+        CaptureInfo {
+            rom: path.file_name().map_or_else(
+                || path.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            ),
+            rom_hash: fnv1a(rom.iter().copied()),
+            frame,
+        }
+    }
+}
+
+/// Behaviour differences between CHIP-8 interpreters that ROMs rely on.
+/// Flags left out when reading one are taken from `Quirks::MODERN`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Quirks {
+    /// 8XY6 and 8XYE shift Vy into Vx instead of shifting Vx in place (COSMAC VIP).
+    pub shift_uses_vy: bool,
+
+    /// BNNN jumps to XNN + Vx instead of NNN + V0 (SUPER-CHIP).
+    pub jump_uses_vx: bool,
+
+    /// 8XY1, 8XY2 and 8XY3 reset VF to 0 (COSMAC VIP).
+    pub vf_reset: bool,
+
+    /// DXYN cuts sprites off at the display edges instead of wrapping them around
+    /// to the other side; only the starting position wraps (COSMAC VIP, SUPER-CHIP).
+    pub clip_sprites: bool,
+
+    /// CXNN draws from `VipRandom` instead of a uniform generator, so its results
+    /// follow the frame they are asked in, in the style of the COSMAC VIP.
+    pub vip_random: bool,
+
+    /// FX1E sets VF to 1 when I + Vx goes past 0xFFF and to 0 when it does not
+    /// (Amiga interpreter, relied on by Spacefight 2091!).
+    pub add_i_sets_vf: bool,
+
+    /// FX55 and FX65 leave I pointing past the last byte they stored or loaded,
+    /// at I + X + 1, instead of leaving it alone (COSMAC VIP).
+    pub memory_increments_i: bool,
+
+    /// DXYN, FX33, FX55 and FX65 wrap addresses past the end of RAM around to 0
+    /// instead of stopping the machine with `Chip8Error::MemoryFault`.
+    pub wrap_memory: bool,
+}
+
+impl Quirks {
+    /// What most ROMs written since the CHIP-48 expect, used by default: shifts work on Vx
+    /// in place, BNNN adds V0, logic ops leave VF alone, sprites wrap around the edges,
+    /// RND is uniform, FX55 and FX65 leave I alone and memory past 0xFFF faults.
+    pub const MODERN: Quirks = Quirks {
+        shift_uses_vy: false,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        vip_random: false,
+        add_i_sets_vf: false,
+        memory_increments_i: false,
+        wrap_memory: false,
+    };
+
+    /// Original COSMAC VIP interpreter.
+    pub const COSMAC: Quirks = Quirks {
+        shift_uses_vy: true,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        vip_random: true,
+        add_i_sets_vf: false,
+        memory_increments_i: true,
+        wrap_memory: false,
+    };
+
+    /// SUPER-CHIP 1.1 on the HP48.
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        vip_random: false,
+        add_i_sets_vf: false,
+        memory_increments_i: false,
+        wrap_memory: false,
+    };
+
+    /// Named presets, in the order frontends should offer them.
+    pub const PRESETS: [(&'static str, Quirks); 3] = [
+        ("modern", Quirks::MODERN),
+        ("cosmac", Quirks::COSMAC),
+        ("superchip", Quirks::SUPER_CHIP),
+    ];
+
+    /// Flags as they are named in cartridges and instruction references.
+    pub const NAMES: [&'static str; 8] = [
+        "shift_uses_vy",
+        "jump_uses_vx",
+        "vf_reset",
+        "clip_sprites",
+        "vip_random",
+        "add_i_sets_vf",
+        "memory_increments_i",
+        "wrap_memory",
+    ];
+
+    /// Every flag by its name in `NAMES`, in that order.
+    pub fn flags(&self) -> [(&'static str, bool); 8] {
+        let values = [
+            self.shift_uses_vy,
+            self.jump_uses_vx,
+            self.vf_reset,
+            self.clip_sprites,
+            self.vip_random,
+            self.add_i_sets_vf,
+            self.memory_increments_i,
+            self.wrap_memory,
+        ];
+        std::array::from_fn(|flag| (Quirks::NAMES[flag], values[flag]))
+    }
+
+    /// Name of the preset these quirks match, `custom` when they match none.
+    pub fn preset_name(&self) -> &'static str {
+        Quirks::PRESETS
+            .iter()
+            .find(|(_, preset)| preset == self)
+            .map_or("custom", |(name, _)| name)
+    }
+
+    pub fn preset(name: &str) -> Option<Quirks> {
+        Quirks::PRESETS
+            .iter()
+            .find(|(preset, _)| preset.eq_ignore_ascii_case(name))
+            .map(|(_, quirks)| *quirks)
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks::MODERN
+    }
+}
+
+/// What RAM holds before a ROM is loaded, see `Chip8::init_ram`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RamInit {
+    /// Every byte 0x00, what this interpreter always did
+    #[default]
+    Zero,
+
+    /// Every byte 0xFF
+    Ones,
+
+    /// Bytes from the RND source, so `Chip8::seed` makes them reproducible;
+    /// closest to the leftover contents of real hardware
+    Random,
+}
+
+impl RamInit {
+    /// Patterns as they are named on the command line and in settings.
+    pub const NAMES: [(&'static str, RamInit); 3] = [
+        ("zero", RamInit::Zero),
+        ("ones", RamInit::Ones),
+        ("random", RamInit::Random),
+    ];
+
+    pub fn from_name(name: &str) -> Option<RamInit> {
+        RamInit::NAMES
+            .iter()
+            .find(|(pattern, _)| pattern.eq_ignore_ascii_case(name))
+            .map(|(_, init)| *init)
+    }
+}
+
 pub struct Chip8 {
-    /// 4 kB of RAM memory;
-    /// Addresses from 0x000 to 0xFF;
-    /// 0x000 - 0x200 Interpreter,
-    /// 0x200 - 0x600 User programs,
-    /// 0x600 - 0xFFF ETI 660 User programs,
-    pub ram: [u8; 4096],
+    /// RAM and the devices mapped over it, which every memory access goes through
+    pub bus: Bus,
 
     /// 16 8-bit registers V0,V1...VF
     pub registers: [u8; 16],
@@ -63,12 +384,48 @@ pub struct Chip8 {
 
     /// Code of current operation
     pub opcode: u16,
+
+    /// Interpreter behaviour for ambiguous opcodes
+    pub quirks: Quirks,
+
+    /// Source of RND results, see `seed`
+    rng: ChaCha8Rng,
+
+    /// Source of RND results with `Quirks::vip_random`, seeded along with `rng`
+    vip_rng: VipRandom,
+
+    /// Keys found pressed by EX9E, EXA1 or FX0A, one bit per key, see `take_observed_keys`
+    observed_keys: u16,
+
+    /// Tone changes since the last `take_sound_events`
+    sound_events: Vec<SoundEvent>,
+
+    /// Tone is on, see `beeping`
+    beeping: bool,
+
+    /// Key FX0A saw pressed, the instruction finishes when it is released
+    key_awaited_release: Option<usize>,
+
+    /// Called on opcodes that are not instructions, see `set_trap_handler`
+    trap_handler: Option<TrapHandler>,
+
+    /// Most recent DXYN, see `last_draw`
+    last_draw: Option<SpriteDraw>,
+
+    /// Opcode last seen at every address and its decoded form, see `fetch`
+    decoded: Box<[(u16, Instruction)]>,
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
 }
 
 impl Chip8 {
     pub fn new() -> Chip8 {
-        let mut chip = Chip8 {
-            ram: [0; 4096],
+        Chip8 {
+            bus: Bus::new(),
             registers: [0; 16],
             stack: [0; 16],
             keypad: [0; 16],
@@ -79,97 +436,434 @@ impl Chip8 {
             st: 0,
             dt: 0,
             opcode: 0,
-        };
+            quirks: Quirks::default(),
+            rng: ChaCha8Rng::seed_from_u64(random()),
+            vip_rng: VipRandom::new(random()),
+            observed_keys: 0,
+            sound_events: Vec::new(),
+            beeping: false,
+            key_awaited_release: None,
+            trap_handler: None,
+            last_draw: None,
+            // RAM starts zeroed, which makes every entry valid.
+            decoded: vec![(0, Instruction::decode(0)); 4096].into_boxed_slice(),
+        }
+    }
 
-        // Load fonts into memory
-        chip.ram[..FONTS.len()].copy_from_slice(&FONTS);
+    /// Make RND results reproducible: the same seed, ROM and input
+    /// give the same run on every platform.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = ChaCha8Rng::seed_from_u64(seed);
+        self.vip_rng = VipRandom::new(seed as u16);
+    }
 
-        chip
+    /// Fill RAM past the fonts with `init`, for ROMs that read memory they never wrote.
+    /// Call it after `seed` and before loading the ROM.
+    pub fn init_ram(&mut self, init: RamInit) {
+        let ram = &mut self.bus.ram[FONTS.len()..];
+        match init {
+            RamInit::Zero => ram.fill(0),
+            RamInit::Ones => ram.fill(0xFF),
+            RamInit::Random => self.rng.fill_bytes(ram),
+        }
     }
 
-    pub fn load_rom(&mut self, fname: &str) {
-        let contents = fs::read(fname).expect("Should have been able to read the file");
-        let mut idx = START_ADDR;
-        for content in contents {
-            if idx > 0xFFF {
-                panic!("Not supported address {:#x}", idx)
-            }
-            self.ram[idx] = content;
-            idx += 1;
+    pub fn load_rom<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
+        self.load_rom_bytes(&fs::read(path)?)
+    }
+
+    /// Copy `rom` to the program area, for ROMs that do not come from a plain file.
+    ///
+    /// A ROM larger than RAM is banked: what fits goes to RAM as usual, and the rest is cut
+    /// into banks for `BANK_WINDOW`, see `Bus`.
+    pub fn load_rom_bytes(&mut self, rom: &[u8]) -> io::Result<()> {
+        if rom.len() > MAX_BANKED_ROM_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("ROM too large: {} bytes", rom.len()),
+            ));
         }
+        let (fixed, banked) = rom.split_at(rom.len().min(MAX_ROM_LEN));
+        self.bus.ram[START_ADDR..START_ADDR + fixed.len()].copy_from_slice(fixed);
+        if !banked.is_empty() {
+            self.bus.load_banks(banked.chunks(BANK_WINDOW.len()));
+        }
+        debug!(bytes = rom.len(), "ROM loaded");
+        Ok(())
     }
 
+    /// Count the delay and sound timers down by one, meant to happen 60 times a second.
+    /// Frontends without a 60 Hz loop can have a `Clock` call it.
     pub fn tick_timers(&mut self) {
+        self.vip_rng.tick();
         if self.dt > 0 {
             self.dt -= 1;
         }
 
         if self.st > 0 {
-            if self.st == 1 {
-                // BEEP
-            }
-            self.st -= 1;
+            self.set_sound_timer(self.st - 1);
         }
     }
 
+    /// Set ST, noting the tone starting or stopping. Writing `st` directly does not.
+    ///
+    /// Values below `MIN_BEEP_TICKS` do not start the tone, they only count down silently;
+    /// once started, it lasts until ST reaches zero.
+    pub fn set_sound_timer(&mut self, st: u8) {
+        if !self.beeping && st >= MIN_BEEP_TICKS {
+            self.beeping = true;
+            self.push_sound_event(SoundEvent::Started { ticks: st });
+        } else if self.beeping && st == 0 {
+            self.beeping = false;
+            self.push_sound_event(SoundEvent::Stopped);
+        }
+        self.st = st;
+    }
+
+    /// Whether the tone is on: ST is counting down from `MIN_BEEP_TICKS` or more.
+    pub fn beeping(&self) -> bool {
+        self.beeping
+    }
+
+    fn push_sound_event(&mut self, event: SoundEvent) {
+        if self.sound_events.len() >= MAX_SOUND_EVENTS {
+            // Drop a start and stop pair, so the events still alternate.
+            self.sound_events.drain(..2);
+        }
+        self.sound_events.push(event);
+    }
+
+    /// Tone changes since the last call, oldest first, so audio backends can switch
+    /// the tone without polling `st`. A frame can both start and stop it.
+    pub fn take_sound_events(&mut self) -> Vec<SoundEvent> {
+        std::mem::take(&mut self.sound_events)
+    }
+
+    /// Geometry DXYN draws on, with the edges `clip_sprites` asks for.
+    pub fn display(&self) -> Display {
+        let edges = if self.quirks.clip_sprites {
+            Edges::Clip
+        } else {
+            Edges::Wrap
+        };
+        Display::new(DISP_WIDTH, DISP_HEIGHT, edges)
+    }
+
+    /// Sprite the last DXYN drew, for debugging views and traces.
+    pub fn last_draw(&self) -> Option<SpriteDraw> {
+        self.last_draw
+    }
+
     pub fn set_key_value(&mut self, key: usize, value: u8) {
         self.keypad[key] = value;
     }
 
-    pub fn cycle(&mut self) {
-        if self.pc > 0xFFF {
-            panic!("Not supported address {:#x}", self.pc)
+    /// Whether the instruction at PC is FX0A, which keeps the machine there
+    /// until a key is pressed and released.
+    pub fn waiting_for_key(&self) -> bool {
+        matches!(self.bus.opcode(self.pc as usize), Some(opcode) if opcode & 0xF0FF == 0xF00A)
+    }
+
+    /// Keys the program saw pressed since the last call, bit N set for key N.
+    /// Lets frontends measure when input actually reaches the game.
+    pub fn take_observed_keys(&mut self) -> u16 {
+        std::mem::take(&mut self.observed_keys)
+    }
+
+    pub fn save_state(&self) -> SaveState {
+        SaveState {
+            ram: self.bus.ram,
+            registers: self.registers,
+            stack: self.stack,
+            sp: self.sp,
+            pc: self.pc,
+            i: self.i,
+            dt: self.dt,
+            st: self.st,
+            keypad: self.keypad,
+            video: self.video,
+            quirks: self.quirks,
+            rng_seed: self.rng.get_seed(),
+            rng_stream: self.rng.get_stream(),
+            rng_word_pos: self.rng.get_word_pos(),
+            vip_rng: self.vip_rng,
+            bank: self.bus.bank().unwrap_or(0) as u8,
+            banks: self.bus.banks(),
+        }
+    }
+
+    /// Hash of the whole machine, `SaveState::hash` of `save_state`: equal in two runs exactly
+    /// when they are in the same state. Stable across platforms and versions as long as
+    /// the save state format stays the same, so replays and golden tests can record it.
+    pub fn state_hash(&self) -> u64 {
+        self.save_state().hash()
+    }
+
+    /// Hash of the display alone, one byte per pixel, stable across platforms and versions.
+    /// What `chip-8 run --expect-hash` compares.
+    pub fn frame_hash(&self) -> u64 {
+        fnv1a(self.video.iter().map(|&pixel| pixel as u8))
+    }
+
+    /// Display packed 8 pixels per byte, MSB first, row by row: byte `y * 8 + x / 8` holds
+    /// pixel (`x`, `y`) in bit `7 - x % 8`. An eighth of `video`, for recording many frames.
+    pub fn framebuffer_bits(&self) -> [u8; FRAMEBUFFER_BYTES] {
+        pack_video(&self.video)
+    }
+
+    /// Continue from `state`, replacing everything including ROM and quirks.
+    pub fn load_state(&mut self, state: &SaveState) {
+        self.bus.ram = state.ram;
+        self.bus.restore_banks(&state.banks, state.bank as usize);
+        self.registers = state.registers;
+        self.stack = state.stack;
+        self.sp = state.sp;
+        self.pc = state.pc;
+        self.i = state.i;
+        self.dt = state.dt;
+        self.set_sound_timer(state.st);
+        self.keypad = state.keypad;
+        self.video = state.video;
+        self.quirks = state.quirks;
+        self.rng = ChaCha8Rng::from_seed(state.rng_seed);
+        self.rng.set_stream(state.rng_stream);
+        self.rng.set_word_pos(state.rng_word_pos);
+        self.vip_rng = state.vip_rng;
+        self.key_awaited_release = None;
+        self.last_draw = None;
+    }
+
+    /// Display as text, one line per row, `on` for lit pixels and `off` for dark ones.
+    pub fn render_ascii(&self, on: char, off: char) -> String {
+        let mut screen = String::with_capacity((DISP_WIDTH + 1) * DISP_HEIGHT);
+        for row in self.video.chunks(DISP_WIDTH) {
+            screen.extend(row.iter().map(|&pixel| if pixel { on } else { off }));
+            screen.push('\n');
         }
-        let opcode: u16 =
-            (self.ram[self.pc as usize] as u16) << 8 | self.ram[self.pc as usize + 1] as u16;
+        screen
+    }
+
+    /// Display as RGBA bytes in `palette` colors, row by row, one pixel per CHIP-8 pixel.
+    pub fn render_rgba(&self, palette: &Palette) -> Vec<u8> {
+        RgbaFrame::from_video(&self.video, palette).pixels
+    }
+
+    /// Save the display as PNG, every CHIP-8 pixel drawn as a `scale` x `scale` square.
+    #[cfg(feature = "png")]
+    pub fn render_png<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &Palette,
+        scale: usize,
+    ) -> io::Result<()> {
+        self.write_png(path.as_ref(), palette, scale, Vec::new())
+    }
+
+    /// Save the display as PNG like `render_png`, with `info` and the quirks
+    /// in its text chunks: `Title`, `ROM hash`, `Quirks` (the flags set, space separated,
+    /// or `none`) and `Frame`.
+    #[cfg(feature = "png")]
+    pub fn save_screenshot<P: AsRef<Path>>(
+        &self,
+        path: P,
+        palette: &Palette,
+        scale: usize,
+        info: &CaptureInfo,
+    ) -> io::Result<()> {
+        let text = vec![
+            ("Title", info.rom.clone()),
+            ("ROM hash", format!("{:016x}", info.rom_hash)),
+            ("Quirks", quirk_list(&self.quirks)),
+            ("Frame", info.frame.to_string()),
+        ];
+        self.write_png(path.as_ref(), palette, scale, text)
+    }
+
+    /// PNG of the display with `text` and the emulator version as `Software` in text chunks.
+    #[cfg(feature = "png")]
+    fn write_png(
+        &self,
+        path: &Path,
+        palette: &Palette,
+        scale: usize,
+        text: Vec<(&str, String)>,
+    ) -> io::Result<()> {
+        let scale = scale.max(1);
+        let (width, height) = (DISP_WIDTH * scale, DISP_HEIGHT * scale);
+        let rgba = self.render_rgba(palette);
+        let mut image = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = ((y / scale) * DISP_WIDTH + x / scale) * 4;
+                image.extend_from_slice(&rgba[idx..idx + 4]);
+            }
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let software = (
+            "Software",
+            concat!("chip-8 ", env!("CARGO_PKG_VERSION")).to_string(),
+        );
+        for (keyword, text) in text.into_iter().chain([software]) {
+            encoder
+                .add_text_chunk(keyword.to_string(), text)
+                .map_err(io::Error::other)?;
+        }
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&image).map_err(io::Error::other)
+    }
+
+    pub fn cycle(&mut self) -> Result<StepResult, Chip8Error> {
+        if self.pc as usize + 1 >= self.bus.ram.len() {
+            return Err(Chip8Error::InvalidAddress(self.pc));
+        }
+        let (opcode, instruction) = self.fetch();
 
         self.opcode = opcode;
         self.pc += 2;
 
-        let digit_1 = (opcode & 0xF000) >> 12;
-        let digit_2 = (opcode & 0x0F00) >> 8;
-        let digit_3 = (opcode & 0x00F0) >> 4;
-        let digit_4 = opcode & 0x000F;
-
-        match (digit_1, digit_2, digit_3, digit_4) {
-            (0, 0, 0xE, 0) => self.op_00e0(),
-            (0, 0, 0xE, 0xE) => self.op_00ee(),
-            (1, _, _, _) => self.op_1nnn(),
-            (2, _, _, _) => self.op_2nnn(),
-            (3, _, _, _) => self.op_3xnn(),
-            (4, _, _, _) => self.op_4xnn(),
-            (5, _, _, 0) => self.op_5xy0(),
-            (6, _, _, _) => self.op_6xnn(),
-            (7, _, _, _) => self.op_7xnn(),
-            (8, _, _, 0) => self.op_8xy0(),
-            (8, _, _, 1) => self.op_8xy1(),
-            (8, _, _, 2) => self.op_8xy2(),
-            (8, _, _, 3) => self.op_8xy3(),
-            (8, _, _, 4) => self.op_8xy4(),
-            (8, _, _, 5) => self.op_8xy5(),
-            (8, _, _, 6) => self.op_8xy6(),
-            (8, _, _, 7) => self.op_8xy7(),
-            (8, _, _, 0xE) => self.op_8xye(),
-            (9, _, _, 0) => self.op_9xy0(),
-            (0xA, _, _, _) => self.op_annn(),
-            (0xB, _, _, _) => self.op_bnnn(),
-            (0xC, _, _, _) => self.op_cxnn(),
-            (0xD, _, _, _) => self.op_dxyn(),
-            (0xE, _, 9, 0xE) => self.op_ex9e(),
-            (0xE, _, 0xA, 1) => self.op_exa1(),
-            (0xF, _, 0, 7) => self.op_fx07(),
-            (0xF, _, 0, 0xA) => self.op_fx0a(),
-            (0xF, _, 1, 5) => self.op_fx15(),
-            (0xF, _, 1, 8) => self.op_fx18(),
-            (0xF, _, 1, 0xE) => self.op_fx1e(),
-            (0xF, _, 2, 9) => self.op_fx29(),
-            (0xF, _, 3, 3) => self.op_fx33(),
-            (0xF, _, 5, 5) => self.op_fx55(),
-            (0xF, _, 6, 5) => self.op_fx65(),
-            _ => {
-                panic!("Illegal OP {:#x}", opcode);
-            }
+        match instruction {
+            Instruction::Cls => self.op_00e0(),
+            Instruction::Ret => self.op_00ee()?,
+            Instruction::Jump { nnn } => self.op_1nnn(nnn),
+            Instruction::Call { nnn } => self.op_2nnn(nnn)?,
+            Instruction::SkipEqImm { x, nn } => self.op_3xnn(x as usize, nn),
+            Instruction::SkipNeImm { x, nn } => self.op_4xnn(x as usize, nn),
+            Instruction::SkipEq { x, y } => self.op_5xy0(x as usize, y as usize),
+            Instruction::LoadImm { x, nn } => self.op_6xnn(x as usize, nn),
+            Instruction::AddImm { x, nn } => self.op_7xnn(x as usize, nn),
+            Instruction::Load { x, y } => self.op_8xy0(x as usize, y as usize),
+            Instruction::Or { x, y } => self.op_8xy1(x as usize, y as usize),
+            Instruction::And { x, y } => self.op_8xy2(x as usize, y as usize),
+            Instruction::Xor { x, y } => self.op_8xy3(x as usize, y as usize),
+            Instruction::Add { x, y } => self.op_8xy4(x as usize, y as usize),
+            Instruction::Sub { x, y } => self.op_8xy5(x as usize, y as usize),
+            Instruction::ShiftRight { x, y } => self.op_8xy6(x as usize, y as usize),
+            Instruction::SubN { x, y } => self.op_8xy7(x as usize, y as usize),
+            Instruction::ShiftLeft { x, y } => self.op_8xye(x as usize, y as usize),
+            Instruction::SkipNe { x, y } => self.op_9xy0(x as usize, y as usize),
+            Instruction::LoadI { nnn } => self.op_annn(nnn),
+            Instruction::JumpOffset { x, nnn } => self.op_bnnn(x as usize, nnn),
+            Instruction::Random { x, nn } => self.op_cxnn(x as usize, nn),
+            Instruction::Draw { x, y, n } => self.op_dxyn(x as usize, y as usize, n)?,
+            Instruction::SkipKey { x } => self.op_ex9e(x as usize),
+            Instruction::SkipNoKey { x } => self.op_exa1(x as usize),
+            Instruction::LoadDelay { x } => self.op_fx07(x as usize),
+            Instruction::WaitKey { x } => return Ok(self.op_fx0a(x as usize)),
+            Instruction::SetDelay { x } => self.op_fx15(x as usize),
+            Instruction::SetSound { x } => self.op_fx18(x as usize),
+            Instruction::AddI { x } => self.op_fx1e(x as usize),
+            Instruction::LoadFont { x } => self.op_fx29(x as usize),
+            Instruction::Bcd { x } => self.op_fx33(x as usize)?,
+            Instruction::Store { x } => self.op_fx55(x as usize)?,
+            Instruction::LoadMem { x } => self.op_fx65(x as usize)?,
+            Instruction::Data(_) => return self.trap(opcode),
         };
+        Ok(StepResult::Executed)
+    }
+
+    /// Let `handler` decide about opcodes that are not instructions instead of stopping on them,
+    /// e.g. to emulate experimental extensions. It gets the machine with PC already past the
+    /// opcode, and the opcode; handlers of longer instructions or jumps move PC themselves.
+    pub fn set_trap_handler(
+        &mut self,
+        handler: impl FnMut(&mut Chip8, u16) -> Trap + Send + Sync + 'static,
+    ) {
+        self.trap_handler = Some(Box::new(handler));
+    }
+
+    /// Stop on illegal opcodes again.
+    pub fn clear_trap_handler(&mut self) {
+        self.trap_handler = None;
+    }
+
+    /// Turn on the debugging opcodes for ROM developers, logged at info level:
+    /// `0FF0` logs every register and `0FEX` logs VX. Other illegal opcodes still stop
+    /// the machine. Takes the place of any trap handler.
+    pub fn enable_host_calls(&mut self) {
+        self.set_trap_handler(host_call);
+    }
+
+    /// Have `device` answer the I-based memory accesses to `range` instead of RAM.
+    /// Where ranges overlap, the device mapped first answers.
+    pub fn map_peripheral(&mut self, range: Range<u16>, device: impl Peripheral + 'static) {
+        self.bus.map(range, device);
+    }
+
+    /// Byte at `addr` for an I-based instruction, from the device mapped there or RAM.
+    fn mem_read(&mut self, addr: usize) -> Result<u8, Chip8Error> {
+        let at = self.wrapped(addr);
+        self.bus.read(at).ok_or_else(|| self.fault(addr))
+    }
+
+    /// Store `value` at `addr` for an I-based instruction, to the device mapped there or RAM.
+    fn mem_write(&mut self, addr: usize, value: u8) -> Result<(), Chip8Error> {
+        let at = self.wrapped(addr);
+        if self.bus.write(at, value) {
+            Ok(())
+        } else {
+            Err(self.fault(addr))
+        }
+    }
+
+    /// Fail with `MemoryFault` unless all of the `len` bytes from `addr` can be accessed,
+    /// so instructions going through several bytes do nothing rather than half of their work.
+    fn mem_check(&self, addr: usize, len: usize) -> Result<(), Chip8Error> {
+        match (addr..addr + len).find(|&addr| !self.bus.contains(self.wrapped(addr))) {
+            Some(addr) => Err(self.fault(addr)),
+            None => Ok(()),
+        }
+    }
+
+    /// I-relative `addr` past the end of RAM wrapped around with `wrap_memory`,
+    /// unless a device answers it.
+    fn wrapped(&self, addr: usize) -> usize {
+        if self.quirks.wrap_memory && !self.bus.contains(addr) {
+            addr % self.bus.ram.len()
+        } else {
+            addr
+        }
+    }
+
+    fn fault(&self, addr: usize) -> Chip8Error {
+        Chip8Error::MemoryFault {
+            addr,
+            pc: self.pc.wrapping_sub(2),
+        }
+    }
+
+    fn trap(&mut self, opcode: u16) -> Result<StepResult, Chip8Error> {
+        let pc = self.pc - 2;
+        let Some(mut handler) = self.trap_handler.take() else {
+            return Err(Chip8Error::IllegalOpcode { opcode, pc });
+        };
+        let trap = handler(self, opcode);
+        // Unless the handler installed another one meanwhile.
+        if self.trap_handler.is_none() {
+            self.trap_handler = Some(handler);
+        }
+        match trap {
+            Trap::Handled | Trap::Skip => Ok(StepResult::Executed),
+            Trap::Halt => Err(Chip8Error::IllegalOpcode { opcode, pc }),
+        }
+    }
+
+    /// Opcode at PC and its decoded form.
+    ///
+    /// Decoding is cached per address. A cache entry stays valid only while
+    /// RAM still holds the opcode it was decoded from, so any write to RAM,
+    /// by the program or through `bus.ram` directly, invalidates it.
+    fn fetch(&mut self) -> (u16, Instruction) {
+        let pc = self.pc as usize;
+        let opcode = self.bus.opcode(pc).expect("PC checked by cycle");
+        let cached = &mut self.decoded[pc];
+        if cached.0 != opcode {
+            *cached = (opcode, Instruction::decode(opcode));
+        }
+        *cached
     }
 
     /// `CLS`
@@ -181,32 +875,42 @@ impl Chip8 {
     /// `RET`
     /// Return from subroutine.
     /// Pop address from stack and set PC to popped address.
-    fn op_00ee(&mut self) {
-        self.sp -= 1;
-        self.pc = self.stack[self.sp as usize];
+    fn op_00ee(&mut self) -> Result<(), Chip8Error> {
+        let Some(sp) = self.sp.checked_sub(1) else {
+            return Err(Chip8Error::StackUnderflow {
+                pc: self.pc.wrapping_sub(2),
+            });
+        };
+        self.sp = sp;
+        self.pc = self.stack[sp as usize];
+        Ok(())
     }
 
     /// `JMP NNN`
     /// Jump to address NNN.
     /// PC = NNN
-    fn op_1nnn(&mut self) {
-        self.pc = self.opcode & 0x0FFF;
+    fn op_1nnn(&mut self, nnn: u16) {
+        self.pc = nnn;
     }
 
     /// `CALL NNN`
     /// Put current PC on stack
     /// PC = NNN
-    fn op_2nnn(&mut self) {
-        self.stack[self.sp as usize] = self.pc;
+    fn op_2nnn(&mut self, nnn: u16) -> Result<(), Chip8Error> {
+        let Some(entry) = self.stack.get_mut(self.sp as usize) else {
+            return Err(Chip8Error::StackOverflow {
+                pc: self.pc.wrapping_sub(2),
+            });
+        };
+        *entry = self.pc;
         self.sp += 1;
-        self.pc = self.opcode & 0x0FFF;
+        self.pc = nnn;
+        Ok(())
     }
 
     /// `SE Vx, NN`
     /// Skip next operation if Vx == NN.
-    fn op_3xnn(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let nn: u8 = (self.opcode & 0x00FF) as u8;
+    fn op_3xnn(&mut self, x: usize, nn: u8) {
         if self.registers[x] == nn {
             self.pc += 2;
         }
@@ -214,9 +918,7 @@ impl Chip8 {
 
     /// `SNE Vx, NN`
     /// Skip next operation if Vx != NN.
-    fn op_4xnn(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let nn: u8 = (self.opcode & 0x00FF) as u8;
+    fn op_4xnn(&mut self, x: usize, nn: u8) {
         if self.registers[x] != nn {
             self.pc += 2;
         }
@@ -224,9 +926,7 @@ impl Chip8 {
 
     /// `SE Vx, VY`
     /// Skip next operation if Vx == Vy.
-    fn op_5xy0(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
+    fn op_5xy0(&mut self, x: usize, y: usize) {
         if self.registers[x] == self.registers[y] {
             self.pc += 2;
         }
@@ -234,107 +934,110 @@ impl Chip8 {
 
     /// `LD Vx, NN`
     /// Vx = NN
-    fn op_6xnn(&mut self) {
-        let x: usize = (self.opcode as usize & 0x0F00) >> 8;
-        let nn: u8 = (self.opcode & 0xFF) as u8;
+    fn op_6xnn(&mut self, x: usize, nn: u8) {
         self.registers[x] = nn;
     }
 
     /// `ADD Vx, NN`
     /// Vx = Vx + NN
-    fn op_7xnn(&mut self) {
-        let x: usize = (self.opcode as usize & 0x0F00) >> 8;
-        let nn: u8 = (self.opcode & 0xFF) as u8;
+    fn op_7xnn(&mut self, x: usize, nn: u8) {
         self.registers[x] = self.registers[x].wrapping_add(nn);
     }
 
     /// `LD Vx, Vy`
     /// Vy = Vx
-    fn op_8xy0(&mut self) {
-        let v_x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        self.registers[v_x] = self.registers[v_y];
+    fn op_8xy0(&mut self, x: usize, y: usize) {
+        self.registers[x] = self.registers[y];
     }
 
     /// `OR Vx, Vy`
     /// Vx = Vx | Vy
-    fn op_8xy1(&mut self) {
-        let v_x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        self.registers[v_x] |= self.registers[v_y];
+    fn op_8xy1(&mut self, x: usize, y: usize) {
+        self.registers[x] |= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     /// `AND Vx, Vy`
     /// Vx = Vx & Vy
-    fn op_8xy2(&mut self) {
-        let v_x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        self.registers[v_x] &= self.registers[v_y];
+    fn op_8xy2(&mut self, x: usize, y: usize) {
+        self.registers[x] &= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     /// `XOR Vx, Vy`
     /// Vx = Vx ^ Vy
-    fn op_8xy3(&mut self) {
-        let v_x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        self.registers[v_x] ^= self.registers[v_y];
+    fn op_8xy3(&mut self, x: usize, y: usize) {
+        self.registers[x] ^= self.registers[y];
+        if self.quirks.vf_reset {
+            self.registers[0xF] = 0;
+        }
     }
 
     /// `ADD Vx, Vy`
     /// Vx = Vx + Vy
-    fn op_8xy4(&mut self) {
-        let v_x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        let (new_vx, carry) = self.registers[v_x].overflowing_add(self.registers[v_y]);
-        self.registers[0xF] = if carry { 1 } else { 0 };
-        self.registers[v_x] = new_vx;
+    /// VF = 1 on carry, else 0
+    fn op_8xy4(&mut self, x: usize, y: usize) {
+        let (new_vx, carry) = self.registers[x].overflowing_add(self.registers[y]);
+        self.set_with_flag(x, new_vx, carry);
     }
 
     /// `SUB Vx, Vy`
     /// Vx = Vx - Vy
-    fn op_8xy5(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
+    /// if underflows VF = 0, else VF = 1
+    fn op_8xy5(&mut self, x: usize, y: usize) {
         let (new_vx, borrow) = self.registers[x].overflowing_sub(self.registers[y]);
-        self.registers[0xF] = if borrow { 0 } else { 1 };
-        self.registers[x] = new_vx;
+        self.set_with_flag(x, new_vx, !borrow);
     }
 
     /// `SHR Vx, Vy`
+    /// VF = least significant bit of Vx;
     /// Vx = Vx >> 1
-    /// Vy ignored
-    fn op_8xy6(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.registers[0xF] = self.registers[x] & 1;
-        self.registers[x] >>= 1;
+    /// Vy ignored, unless `shift_uses_vy` quirk is set, then Vx = Vy >> 1
+    fn op_8xy6(&mut self, x: usize, y: usize) {
+        let source = self.shift_source(x, y);
+        self.set_with_flag(x, source >> 1, source & 1 != 0);
     }
 
     /// `SUBN Vy, Vx`
     /// Vx to Vy - Vx
     /// if underflows VF = 0, else VF = 1
-    fn op_8xy7(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
+    fn op_8xy7(&mut self, x: usize, y: usize) {
         let (new_vx, borrow) = self.registers[y].overflowing_sub(self.registers[x]);
-        self.registers[0xF] = if borrow { 0 } else { 1 };
-        self.registers[x] = new_vx;
+        self.set_with_flag(x, new_vx, !borrow);
     }
 
     /// `SHL Vx, VY`
-    /// VF = most significant bit of Vx;
+    /// VF = most significant bit of Vx, as 0 or 1;
     /// Vx = Vx << 1
-    fn op_8xye(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    /// Vy ignored, unless `shift_uses_vy` quirk is set, then Vx = Vy << 1
+    fn op_8xye(&mut self, x: usize, y: usize) {
+        let source = self.shift_source(x, y);
+        self.set_with_flag(x, source << 1, source & 0x80 != 0);
+    }
 
-        self.registers[0xF] = self.registers[x] & (0x1 << 7);
-        self.registers[x] <<= 1;
+    /// Register 8XY6 and 8XYE shift: Vy with the `shift_uses_vy` quirk, else Vx.
+    fn shift_source(&self, x: usize, y: usize) -> u8 {
+        if self.quirks.shift_uses_vy {
+            self.registers[y]
+        } else {
+            self.registers[x]
+        }
+    }
+
+    /// Store the result of an 8XYN arithmetic op in Vx, then its flag in VF as 0 or 1,
+    /// so with X = F the flag is what stays.
+    fn set_with_flag(&mut self, x: usize, value: u8, flag: bool) {
+        self.registers[x] = value;
+        self.registers[0xF] = flag as u8;
     }
 
     /// `SNE Vx, Vy`
     /// Skip next instruction if Vx != Vy.
-    fn op_9xy0(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
+    fn op_9xy0(&mut self, x: usize, y: usize) {
         if self.registers[x] != self.registers[y] {
             self.pc += 2;
         }
@@ -342,55 +1045,59 @@ impl Chip8 {
 
     /// `LD I, NNN`
     /// I = NNN
-    fn op_annn(&mut self) {
-        self.i = self.opcode & 0x0FFF;
+    fn op_annn(&mut self, nnn: u16) {
+        self.i = nnn;
     }
 
     /// `JMP V0, NNN`
     /// PC = V0 + NNN
-    fn op_bnnn(&mut self) {
-        let nnn = self.opcode & 0xFFF;
-        self.pc = (self.registers[0] as u16) + nnn;
+    /// With `jump_uses_vx` quirk: PC = Vx + XNN
+    fn op_bnnn(&mut self, x: usize, nnn: u16) {
+        let x = if self.quirks.jump_uses_vx { x } else { 0 };
+        self.pc = (self.registers[x] as u16) + nnn;
     }
 
     /// `RND Vx, NN`
     /// Vx = random byte & NN
-    fn op_cxnn(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let nn: u8 = (self.opcode & 0x00FF) as u8;
-        let rng: u8 = random();
+    fn op_cxnn(&mut self, x: usize, nn: u8) {
+        let rng = if self.quirks.vip_random {
+            self.vip_rng.next_byte()
+        } else {
+            self.rng.next_byte()
+        };
         self.registers[x] = nn & rng;
     }
 
     /// `DRW Vx, Vy, N`
     /// Draw N-byte sized sprite from `RAM[I]` to display at `[Vx][Vy]`.
-    fn op_dxyn(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let y: usize = ((self.opcode & 0x00F0) >> 4) as usize;
-        let sprite_length: u8 = (self.opcode & 0x000F) as u8;
-
-        let x_coord = self.registers[x] % DISP_WIDTH as u8;
-        let y_coord = self.registers[y] % DISP_WIDTH as u8;
+    fn op_dxyn(&mut self, x: usize, y: usize, sprite_length: u8) -> Result<(), Chip8Error> {
+        self.mem_check(self.i as usize, sprite_length as usize)?;
+        let display = self.display();
+        let (x_coord, y_coord) = display.origin(self.registers[x], self.registers[y]);
 
         let mut collision = false;
 
         // Draw sprite byte after byte, bottom up
         for row in 0..sprite_length {
             // Load another byte of sprite data from RAM at I
-            let ram_idx: usize = (self.i + row as u16) as usize;
-            let sprite_byte = self.ram[ram_idx];
+            let ram_idx: usize = self.i as usize + row as usize;
+            let sprite_byte = self.mem_read(ram_idx)?;
 
             // Current y coord of sprite
-            let curr_y = (y_coord + row) as usize % DISP_HEIGHT;
+            let Some(curr_y) = display.row(y_coord, row as usize) else {
+                break;
+            };
 
             // Draw all bits in row
             for col in 0..8 {
                 // Current x coord of sprite
-                let curr_x = (x_coord + col) as usize % DISP_WIDTH;
-                let idx = curr_y * DISP_WIDTH + curr_x;
+                let Some(curr_x) = display.column(x_coord, col) else {
+                    break;
+                };
+                let idx = display.index(curr_x, curr_y);
 
                 // Get another sprite bit and draw it
-                let sprite_bit = sprite_byte & (0x1 << 7 - col);
+                let sprite_bit = sprite_byte & (0x1 << (7 - col));
                 if sprite_bit > 0 {
                     // Collision -> bit of sprite is already set on display
                     if self.video[idx] {
@@ -402,70 +1109,98 @@ impl Chip8 {
         }
 
         self.registers[0xF] = if collision { 1 } else { 0 };
+
+        let draw = SpriteDraw {
+            addr: self.i,
+            x: x_coord as u8,
+            y: y_coord as u8,
+            height: sprite_length,
+            collision,
+        };
+        trace!(
+            addr = format!("{:#05x}", draw.addr),
+            draw.x, draw.y, draw.height, draw.collision, "Sprite drawn"
+        );
+        self.last_draw = Some(draw);
+        Ok(())
     }
 
     /// `SKP Vx`
-    /// Skip next instruction if key with value of Vx is pressed
-    fn op_ex9e(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        if self.keypad[self.registers[x] as usize] > 0 {
+    /// Skip next instruction if key with value of Vx is pressed.
+    /// Only the low nibble of Vx counts, like on the COSMAC VIP.
+    fn op_ex9e(&mut self, x: usize) {
+        let key = (self.registers[x] & 0xF) as usize;
+        if self.keypad[key] > 0 {
+            self.observed_keys |= 1 << key;
             self.pc += 2;
         }
     }
 
     /// `SKNP Vx`
-    /// Skip next instruction if key with value of Vx is not pressed
-    fn op_exa1(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        if self.keypad[self.registers[x] as usize] == 0 {
+    /// Skip next instruction if key with value of Vx is not pressed.
+    /// Only the low nibble of Vx counts, like on the COSMAC VIP.
+    fn op_exa1(&mut self, x: usize) {
+        let key = (self.registers[x] & 0xF) as usize;
+        if self.keypad[key] == 0 {
             self.pc += 2;
+        } else {
+            self.observed_keys |= 1 << key;
         }
     }
 
     /// `LD Vx, DT`
     /// DT = Vx (set delay timer to Vx)
-    fn op_fx07(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    fn op_fx07(&mut self, x: usize) {
         self.registers[x] = self.dt;
     }
 
     /// `LD Vx, KEY`
-    /// Wait for KEY press and store KEY value in Vx
-    fn op_fx0a(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-
-        if self.keypad[x] > 0 {
-            self.registers[x] = x as u8;
-        } else {
-            self.pc -= 2;
+    /// Wait for a key to be pressed and released, as on the COSMAC VIP, and store it in Vx;
+    /// the lowest one when several are pressed together
+    fn op_fx0a(&mut self, x: usize) -> StepResult {
+        match self.key_awaited_release {
+            Some(key) if self.keypad[key] == 0 => {
+                self.key_awaited_release = None;
+                self.registers[x] = key as u8;
+                return StepResult::Executed;
+            }
+            Some(_) => {}
+            None => {
+                if let Some(key) = self.keypad.iter().position(|&key| key > 0) {
+                    self.observed_keys |= 1 << key;
+                    self.key_awaited_release = Some(key);
+                }
+            }
         }
+        self.pc -= 2;
+        StepResult::WaitingForKey
     }
 
     /// `LD DT, Vx`
     /// DT = Vx (set delay timer to Vx)
-    fn op_fx15(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    fn op_fx15(&mut self, x: usize) {
         self.dt = self.registers[x];
     }
 
     /// `LD ST, Vx`
     /// ST = Vx (set sount timer to Vx)
-    fn op_fx18(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.st = self.registers[x];
+    fn op_fx18(&mut self, x: usize) {
+        self.set_sound_timer(self.registers[x]);
     }
 
     /// `ADD I, Vx`
-    /// Vx = Vx + I
-    fn op_fx1e(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        self.i = self.i.wrapping_add(self.registers[x] as u16);
+    /// I = I + Vx, and VF = 1 when the sum passes 0xFFF under `add_i_sets_vf`
+    fn op_fx1e(&mut self, x: usize) {
+        let sum = self.i as u32 + self.registers[x] as u32;
+        self.i = sum as u16;
+        if self.quirks.add_i_sets_vf {
+            self.registers[0xF] = (sum > 0xFFF) as u8;
+        }
     }
 
     /// `LD I, FONT(Vx)`
     /// Load 5-byte Font character representing Vx to I.
-    fn op_fx29(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    fn op_fx29(&mut self, x: usize) {
         self.i = self.registers[x] as u16 * 5;
     }
 
@@ -474,35 +1209,641 @@ impl Chip8 {
     /// Set `RAM[I], RAM[I+1], RAM[I+2]` to hundreds, tens and ones.
     ///
     /// ex. for `Vx = 123` => `RAM[I] = 1; RAM[I+1] = 2; RAM[I+2] = 3`
-    fn op_fx33(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
-        let v_x: u8 = self.registers[x];
-
-        let ones: u8 = v_x % 10;
-        let tens: u8 = (v_x % 100 - ones) / 10;
-        let hundreds: u8 = (v_x - (tens * 10) - ones) / 100;
-
-        self.ram[self.i as usize] = hundreds;
-        self.ram[(self.i + 1) as usize] = tens;
-        self.ram[(self.i + 2) as usize] = ones;
+    fn op_fx33(&mut self, x: usize) -> Result<(), Chip8Error> {
+        let addr = self.i as usize;
+        self.mem_check(addr, 3)?;
+        let v_x = self.registers[x];
+        for (offset, digit) in [v_x / 100, v_x / 10 % 10, v_x % 10].into_iter().enumerate() {
+            self.mem_write(addr + offset, digit)?;
+        }
+        Ok(())
     }
 
     /// `LD [I], VX`
     /// Load values of registers from V0 to Vx to memory starting at address I.
-    fn op_fx55(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    fn op_fx55(&mut self, x: usize) -> Result<(), Chip8Error> {
+        self.mem_check(self.i as usize, x + 1)?;
         for i in 0..=x {
-            self.ram[(self.i + i as u16) as usize] = self.registers[i];
+            self.mem_write(self.i as usize + i, self.registers[i])?;
         }
+        self.advance_i(x);
+        Ok(())
     }
 
     /// `LD VX, [I]`
     /// Load values from memory starting at address I to registers from V0 to Vx.
-    fn op_fx65(&mut self) {
-        let x: usize = ((self.opcode & 0x0F00) >> 8) as usize;
+    fn op_fx65(&mut self, x: usize) -> Result<(), Chip8Error> {
+        self.mem_check(self.i as usize, x + 1)?;
         for i in 0..=x {
-            self.registers[i] = self.ram[self.i as usize + i];
+            self.registers[i] = self.mem_read(self.i as usize + i)?;
+        }
+        self.advance_i(x);
+        Ok(())
+    }
+
+    /// Move I past the X + 1 bytes FX55 and FX65 went through, with `memory_increments_i`.
+    fn advance_i(&mut self, x: usize) {
+        if self.quirks.memory_increments_i {
+            self.i = self.i.wrapping_add((x + 1) as u16);
+        }
+    }
+}
+
+/// Trap handler of `Chip8::enable_host_calls`.
+fn host_call(chip8: &mut Chip8, opcode: u16) -> Trap {
+    let pc = chip8.pc - 2;
+    match opcode {
+        0x0FF0 => {
+            let registers: Vec<String> = chip8
+                .registers
+                .iter()
+                .enumerate()
+                .map(|(n, value)| format!("V{:X}={:02x}", n, value))
+                .collect();
+            info!(
+                pc = format!("{:#05x}", pc),
+                registers = registers.join(" "),
+                i = format!("{:#05x}", chip8.i),
+                sp = chip8.sp,
+                dt = chip8.dt,
+                st = chip8.st,
+                "Host call: registers"
+            );
+            Trap::Handled
+        }
+        0x0FE0..=0x0FEF => {
+            let x = (opcode & 0xF) as usize;
+            let value = chip8.registers[x];
+            info!(
+                pc = format!("{:#05x}", pc),
+                register = format!("V{:X}", x),
+                value,
+                "Host call: value"
+            );
+            Trap::Handled
+        }
+        _ => Trap::Halt,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::Rng;
+
+    /// Machine with `program` loaded and `quirks` set.
+    fn machine(program: &[u16], quirks: Quirks) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.quirks = quirks;
+        let rom: Vec<u8> = program.iter().flat_map(|op| op.to_be_bytes()).collect();
+        chip8.load_rom_bytes(&rom).unwrap();
+        chip8
+    }
+
+    #[test]
+    fn add_i_leaves_vf_alone_by_default() {
+        let mut chip8 = machine(&[0xF11E], Quirks::MODERN);
+        chip8.i = 0xFFF;
+        chip8.registers[1] = 1;
+        chip8.registers[0xF] = 7;
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.i, 0x1000);
+        assert_eq!(chip8.registers[0xF], 7);
+    }
+
+    #[test]
+    fn add_i_flags_overflow_past_0xfff() {
+        let quirks = Quirks {
+            add_i_sets_vf: true,
+            ..Quirks::MODERN
+        };
+        let mut chip8 = machine(&[0xF11E, 0xF11E], quirks);
+        chip8.i = 0xFFE;
+        chip8.registers[1] = 1;
+        chip8.registers[0xF] = 1;
+        chip8.cycle().unwrap();
+        assert_eq!((chip8.i, chip8.registers[0xF]), (0xFFF, 0));
+        chip8.cycle().unwrap();
+        assert_eq!((chip8.i, chip8.registers[0xF]), (0x1000, 1));
+    }
+
+    #[test]
+    fn add_i_sums_before_vf_is_written() {
+        let quirks = Quirks {
+            add_i_sets_vf: true,
+            ..Quirks::MODERN
+        };
+        let mut chip8 = machine(&[0xFF1E], quirks);
+        chip8.i = 0xF00;
+        chip8.registers[0xF] = 0xFF;
+        chip8.cycle().unwrap();
+        assert_eq!((chip8.i, chip8.registers[0xF]), (0xFFF, 0));
+    }
+
+    #[test]
+    fn bcd_decodes_every_byte() {
+        for value in 0..=255u8 {
+            let mut chip8 = machine(&[0xF533], Quirks::MODERN);
+            chip8.i = 0x300;
+            chip8.registers[5] = value;
+            chip8.cycle().unwrap();
+            let digits: Vec<u8> = format!("{:03}", value).bytes().map(|b| b - b'0').collect();
+            assert_eq!(&chip8.bus.ram[0x300..0x303], digits, "BCD of {}", value);
+            assert_eq!(chip8.i, 0x300, "BCD of {}", value);
+        }
+    }
+
+    #[test]
+    fn bcd_fills_the_last_bytes_of_ram() {
+        let mut chip8 = machine(&[0xF033], Quirks::MODERN);
+        chip8.i = 0xFFD;
+        chip8.registers[0] = 209;
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.bus.ram[0xFFD..], [2, 0, 9]);
+    }
+
+    #[test]
+    fn bcd_past_the_end_of_ram_writes_nothing() {
+        for i in [0xFFE, 0xFFF, 0xFFFF] {
+            let mut chip8 = machine(&[0xF033], Quirks::MODERN);
+            chip8.i = i;
+            chip8.registers[0] = 255;
+            assert_eq!(
+                chip8.cycle(),
+                Err(Chip8Error::MemoryFault {
+                    addr: chip8.bus.ram.len().max(i as usize),
+                    pc: 0x200
+                })
+            );
+            assert!(chip8.bus.ram[0xFFD..].iter().all(|&byte| byte == 0));
+        }
+    }
+
+    #[test]
+    fn stack_misuse_halts_instead_of_panicking() {
+        let mut chip8 = machine(&[0x00EE], Quirks::MODERN);
+        assert_eq!(chip8.cycle(), Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+
+        // Calls itself until the stack is full.
+        let mut chip8 = machine(&[0x2200], Quirks::MODERN);
+        for _ in 0..16 {
+            chip8.cycle().unwrap();
+        }
+        assert_eq!(chip8.cycle(), Err(Chip8Error::StackOverflow { pc: 0x200 }));
+        assert_eq!(chip8.sp, 16);
+    }
+
+    #[test]
+    fn wait_key_stores_whichever_key_is_pressed() {
+        // LD V3, KEY; LD V4, KEY
+        let mut chip8 = machine(&[0xF30A, 0xF40A], Quirks::MODERN);
+        assert!(chip8.waiting_for_key());
+        assert_eq!(chip8.cycle(), Ok(StepResult::WaitingForKey));
+        assert_eq!(chip8.pc, 0x200);
+        chip8.set_key_value(0xB, 1);
+        assert_eq!(chip8.cycle(), Ok(StepResult::WaitingForKey));
+        chip8.set_key_value(0xB, 0);
+        assert_eq!(chip8.cycle(), Ok(StepResult::Executed));
+        assert_eq!(chip8.registers[3], 0xB);
+
+        // A held key counts once, when it is released.
+        chip8.set_key_value(0x5, 1);
+        for _ in 0..10 {
+            assert_eq!(chip8.cycle(), Ok(StepResult::WaitingForKey));
+        }
+        assert_eq!(chip8.pc, 0x202);
+        chip8.set_key_value(0x5, 0);
+        assert_eq!(chip8.cycle(), Ok(StepResult::Executed));
+        assert_eq!(chip8.registers[4], 0x5);
+        assert!(!chip8.waiting_for_key());
+    }
+
+    #[test]
+    fn key_skips_use_the_low_nibble_of_vx() {
+        let mut chip8 = machine(&[0x6025, 0xE09E], Quirks::MODERN);
+        chip8.set_key_value(5, 1);
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.pc, 0x206);
+    }
+
+    #[test]
+    fn store_and_load_leave_i_alone_by_default() {
+        let mut chip8 = machine(&[0xF255, 0xF265], Quirks::MODERN);
+        chip8.i = 0x300;
+        chip8.registers[..3].copy_from_slice(&[1, 2, 3]);
+        chip8.cycle().unwrap();
+        assert_eq!(
+            (chip8.i, &chip8.bus.ram[0x300..0x304]),
+            (0x300, &[1, 2, 3, 0][..])
+        );
+        chip8.registers[..3].fill(0);
+        chip8.cycle().unwrap();
+        assert_eq!((chip8.i, &chip8.registers[..3]), (0x300, &[1, 2, 3][..]));
+    }
+
+    #[test]
+    fn store_and_load_both_move_i_with_memory_increments_i() {
+        let mut chip8 = machine(&[0xF255, 0xF265], Quirks::COSMAC);
+        chip8.i = 0x300;
+        chip8.registers[..3].copy_from_slice(&[1, 2, 3]);
+        chip8.bus.ram[0x303..0x306].copy_from_slice(&[4, 5, 6]);
+        chip8.cycle().unwrap();
+        assert_eq!(
+            (chip8.i, &chip8.bus.ram[0x300..0x303]),
+            (0x303, &[1, 2, 3][..])
+        );
+        chip8.cycle().unwrap();
+        assert_eq!((chip8.i, &chip8.registers[..3]), (0x306, &[4, 5, 6][..]));
+    }
+
+    #[test]
+    fn only_cosmac_moves_i_after_store_and_load() {
+        for (name, quirks) in Quirks::PRESETS {
+            assert_eq!(quirks.memory_increments_i, name == "cosmac", "{}", name);
+        }
+    }
+
+    /// The 8XYN ops that set Vx from Vx and Vy.
+    const ARITHMETIC: [u16; 8] = [0x1, 0x2, 0x3, 0x4, 0x5, 0x6, 0x7, 0xE];
+
+    /// Vx and VF after 8XYN on `vx` and `vy` with `Quirks::MODERN`, the way the flags test ROM
+    /// checks them; VF is `None` where the op leaves it alone.
+    fn arithmetic(n: u16, vx: u8, vy: u8) -> (u8, Option<u8>) {
+        match n {
+            0x1 => (vx | vy, None),
+            0x2 => (vx & vy, None),
+            0x3 => (vx ^ vy, None),
+            0x4 => (
+                vx.wrapping_add(vy),
+                Some((vx as u16 + vy as u16 > 0xFF) as u8),
+            ),
+            0x5 => (vx.wrapping_sub(vy), Some((vx >= vy) as u8)),
+            0x6 => (vx >> 1, Some(vx & 1)),
+            0x7 => (vy.wrapping_sub(vx), Some((vy >= vx) as u8)),
+            _ => (vx << 1, Some(vx >> 7)),
+        }
+    }
+
+    #[test]
+    fn arithmetic_sets_vf_to_0_or_1() {
+        for n in ARITHMETIC {
+            let mut chip8 = machine(&[0x8120 | n], Quirks::MODERN);
+            for vx in 0..=255 {
+                for vy in 0..=255 {
+                    chip8.pc = 0x200;
+                    chip8.registers[1] = vx;
+                    chip8.registers[2] = vy;
+                    chip8.registers[0xF] = 0xAA;
+                    chip8.cycle().unwrap();
+                    let (result, flag) = arithmetic(n, vx, vy);
+                    assert_eq!(
+                        (chip8.registers[1], chip8.registers[0xF]),
+                        (result, flag.unwrap_or(0xAA)),
+                        "8XY{:X} on {} and {}",
+                        n,
+                        vx,
+                        vy
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic_into_vf_keeps_the_flag() {
+        for n in ARITHMETIC {
+            let mut chip8 = machine(&[0x8F10 | n], Quirks::MODERN);
+            for (vf, v1) in [(0, 0), (1, 0xFF), (0x80, 0x7F), (0xFF, 1), (0x0F, 0xF0)] {
+                chip8.pc = 0x200;
+                chip8.registers[0xF] = vf;
+                chip8.registers[1] = v1;
+                chip8.cycle().unwrap();
+                let (result, flag) = arithmetic(n, vf, v1);
+                assert_eq!(
+                    chip8.registers[0xF],
+                    flag.unwrap_or(result),
+                    "8FY{:X} on {} and {}",
+                    n,
+                    vf,
+                    v1
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn arithmetic_reads_vf_before_setting_it() {
+        for n in ARITHMETIC {
+            let mut chip8 = machine(&[0x81F0 | n], Quirks::MODERN);
+            for (v1, vf) in [(0, 0), (1, 0xFF), (0x80, 0x7F), (0xFF, 1), (0x0F, 0xF0)] {
+                chip8.pc = 0x200;
+                chip8.registers[1] = v1;
+                chip8.registers[0xF] = vf;
+                chip8.cycle().unwrap();
+                let (result, flag) = arithmetic(n, v1, vf);
+                assert_eq!(
+                    (chip8.registers[1], chip8.registers[0xF]),
+                    (result, flag.unwrap_or(vf)),
+                    "8XF{:X} on {} and {}",
+                    n,
+                    v1,
+                    vf
+                );
+            }
+        }
+    }
+
+    /// Lit pixels as (x, y), row by row.
+    type Pixels = &'static [(usize, usize)];
+
+    /// Pixels lit after drawing a 2x2 block at (`vx`, `vy`).
+    fn block(vx: u8, vy: u8, quirks: Quirks) -> (Vec<(usize, usize)>, SpriteDraw) {
+        let mut chip8 = machine(&[0xA300, 0xD012], quirks);
+        chip8.bus.ram[0x300..0x302].copy_from_slice(&[0xC0, 0xC0]);
+        chip8.registers[0] = vx;
+        chip8.registers[1] = vy;
+        chip8.cycle().unwrap();
+        chip8.cycle().unwrap();
+        let lit = (0..DISP_HEIGHT)
+            .flat_map(|y| (0..DISP_WIDTH).map(move |x| (x, y)))
+            .filter(|&(x, y)| chip8.video[y * DISP_WIDTH + x])
+            .collect();
+        (lit, chip8.last_draw().unwrap())
+    }
+
+    #[test]
+    fn sprites_wrap_at_every_edge_and_corner() {
+        let cases: [(u8, u8, Pixels); 7] = [
+            (0, 0, &[(0, 0), (1, 0), (0, 1), (1, 1)]),
+            (63, 10, &[(0, 10), (63, 10), (0, 11), (63, 11)]),
+            (10, 31, &[(10, 0), (11, 0), (10, 31), (11, 31)]),
+            (63, 0, &[(0, 0), (63, 0), (0, 1), (63, 1)]),
+            (0, 31, &[(0, 0), (1, 0), (0, 31), (1, 31)]),
+            (63, 31, &[(0, 0), (63, 0), (0, 31), (63, 31)]),
+            (127, 63, &[(0, 0), (63, 0), (0, 31), (63, 31)]),
+        ];
+        for (vx, vy, expected) in cases {
+            let (lit, _) = block(vx, vy, Quirks::MODERN);
+            assert_eq!(lit, expected, "block at ({}, {})", vx, vy);
+        }
+    }
+
+    #[test]
+    fn sprites_clip_at_every_edge_and_corner() {
+        let cases: [(u8, u8, Pixels); 7] = [
+            (0, 0, &[(0, 0), (1, 0), (0, 1), (1, 1)]),
+            (63, 10, &[(63, 10), (63, 11)]),
+            (10, 31, &[(10, 31), (11, 31)]),
+            (63, 0, &[(63, 0), (63, 1)]),
+            (0, 31, &[(0, 31), (1, 31)]),
+            (63, 31, &[(63, 31)]),
+            (127, 63, &[(63, 31)]),
+        ];
+        for (vx, vy, expected) in cases {
+            let (lit, _) = block(vx, vy, Quirks::SUPER_CHIP);
+            assert_eq!(lit, expected, "block at ({}, {})", vx, vy);
+        }
+    }
+
+    #[test]
+    fn sprite_start_wraps_by_width_and_height() {
+        for quirks in [Quirks::MODERN, Quirks::SUPER_CHIP] {
+            let (lit, draw) = block(70, 40, quirks);
+            assert_eq!(lit, [(6, 8), (7, 8), (6, 9), (7, 9)]);
+            assert_eq!((draw.x, draw.y), (6, 8));
+        }
+    }
+
+    #[test]
+    fn i_relative_access_past_ram_faults_without_effect() {
+        // DRW V0, V0, 4; LD [I], V3; LD V3, [I]
+        for opcode in [0xD004, 0xF355, 0xF365] {
+            let mut chip8 = machine(&[opcode], Quirks::MODERN);
+            chip8.i = 0xFFE;
+            chip8.registers[..4].copy_from_slice(&[1, 2, 3, 4]);
+            chip8.bus.ram[0xFFE..].copy_from_slice(&[0xFF, 0xFF]);
+            let before = (chip8.bus.ram, chip8.registers, chip8.video);
+            assert_eq!(
+                chip8.cycle(),
+                Err(Chip8Error::MemoryFault {
+                    addr: 0x1000,
+                    pc: 0x200
+                }),
+                "{:04X}",
+                opcode
+            );
+            assert!(
+                before == (chip8.bus.ram, chip8.registers, chip8.video),
+                "{:04X}",
+                opcode
+            );
+        }
+    }
+
+    #[test]
+    fn wrap_memory_wraps_i_relative_access_to_0() {
+        let quirks = Quirks {
+            wrap_memory: true,
+            ..Quirks::MODERN
+        };
+        let mut chip8 = machine(&[0xF255, 0xF233, 0xF265], quirks);
+        chip8.i = 0xFFE;
+        chip8.registers[..3].copy_from_slice(&[1, 2, 123]);
+        chip8.cycle().unwrap();
+        assert_eq!(
+            (chip8.bus.ram[0xFFE], chip8.bus.ram[0xFFF], chip8.bus.ram[0]),
+            (1, 2, 123)
+        );
+        chip8.cycle().unwrap();
+        assert_eq!(
+            (chip8.bus.ram[0xFFE], chip8.bus.ram[0xFFF], chip8.bus.ram[0]),
+            (1, 2, 3)
+        );
+        chip8.registers[..3].fill(0);
+        chip8.cycle().unwrap();
+        assert_eq!(chip8.registers[..3], [1, 2, 3]);
+    }
+
+    #[test]
+    fn banks_swap_into_the_window_through_bank_select() {
+        let rom = crate::assembler::assemble(
+            ": main
+               i := 0x1E0
+               v0 := 2  save v0  banked
+               v0 := 1  save v0  banked
+               v0 := 9  save v0
+               load v0
+             : done jump done
+             :bank 1
+             : banked v1 := 0x11 return
+             :bank 2
+               v2 := 0x22 return",
+        )
+        .unwrap();
+        assert_eq!(rom.len(), MAX_ROM_LEN + BANK_WINDOW.len() + 4);
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        assert_eq!(chip8.bus.bank(), Some(0));
+        for _ in 0..14 {
+            chip8.cycle().unwrap();
+        }
+        // Bank 9 does not exist, so bank 1 stays in.
+        assert_eq!(chip8.bus.bank(), Some(1));
+        assert_eq!(chip8.registers[..3], [1, 0x11, 0x22]);
+    }
+
+    #[test]
+    fn save_states_keep_the_banks() {
+        let mut rom = vec![0; MAX_ROM_LEN + BANK_WINDOW.len()];
+        rom[BANK_WINDOW.start - START_ADDR] = 0xCD;
+        rom[MAX_ROM_LEN] = 0xAB;
+        let mut chip8 = Chip8::new();
+        chip8.load_rom_bytes(&rom).unwrap();
+        let select = crate::bus::BANK_SELECT as usize;
+        chip8.bus.write(select, 1);
+        assert_eq!(chip8.bus.ram[BANK_WINDOW.start], 0xAB);
+        chip8.bus.ram[BANK_WINDOW.start] = 0xEF;
+
+        let state = chip8.save_state();
+        for state in [
+            SaveState::from_bytes(&state.to_bytes()).unwrap(),
+            SaveState::from_json(&state.to_json()).unwrap(),
+        ] {
+            let mut loaded = Chip8::new();
+            loaded.load_state(&state);
+            assert_eq!(loaded.bus.bank(), Some(1));
+            assert_eq!(loaded.state_hash(), chip8.state_hash());
+            loaded.bus.write(select, 0);
+            assert_eq!(loaded.bus.ram[BANK_WINDOW.start], 0xCD);
+            loaded.bus.write(select, 1);
+            assert_eq!(loaded.bus.ram[BANK_WINDOW.start], 0xEF);
+        }
+    }
+
+    #[test]
+    fn save_states_with_the_stack_pointer_past_the_stack_are_refused() {
+        let mut state = Chip8::new().save_state();
+        state.sp = 16;
+        assert!(SaveState::from_bytes(&state.to_bytes()).is_ok());
+        state.sp = 17;
+        for err in [
+            SaveState::from_bytes(&state.to_bytes()).err(),
+            SaveState::from_json(&state.to_json()).err(),
+        ] {
+            assert_eq!(err.map(|err| err.kind()), Some(io::ErrorKind::InvalidData));
+        }
+    }
+
+    #[test]
+    fn roms_past_the_banks_are_refused() {
+        let mut chip8 = Chip8::new();
+        assert!(chip8.load_rom_bytes(&[0; MAX_ROM_LEN]).is_ok());
+        assert_eq!(chip8.bus.bank(), None);
+        assert!(
+            chip8
+                .load_rom_bytes(&vec![0; MAX_BANKED_ROM_LEN + 1])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn st_below_the_minimum_is_silent() {
+        for st in [0, 1] {
+            let mut chip8 = Chip8::new();
+            chip8.set_sound_timer(st);
+            assert!(!chip8.beeping());
+            chip8.tick_timers();
+            chip8.tick_timers();
+            assert_eq!(chip8.st, 0);
+            assert_eq!(chip8.take_sound_events(), []);
+        }
+    }
+
+    #[test]
+    fn st_at_the_minimum_beeps_for_its_ticks() {
+        for st in [MIN_BEEP_TICKS, 3, 255] {
+            let mut chip8 = Chip8::new();
+            chip8.set_sound_timer(st);
+            assert_eq!(
+                chip8.take_sound_events(),
+                [SoundEvent::Started { ticks: st }]
+            );
+            for _ in 1..st {
+                chip8.tick_timers();
+                assert!(chip8.beeping());
+            }
+            // Counting down through 1 keeps the tone on until 0.
+            assert_eq!(chip8.st, 1);
+            chip8.tick_timers();
+            assert!(!chip8.beeping());
+            assert_eq!(chip8.take_sound_events(), [SoundEvent::Stopped]);
+        }
+    }
+
+    #[test]
+    fn st_set_again_only_starts_or_stops_on_the_edges() {
+        let mut chip8 = Chip8::new();
+        chip8.set_sound_timer(1);
+        chip8.set_sound_timer(2);
+        chip8.set_sound_timer(1);
+        chip8.set_sound_timer(5);
+        chip8.set_sound_timer(0);
+        chip8.set_sound_timer(0);
+        assert_eq!(
+            chip8.take_sound_events(),
+            [SoundEvent::Started { ticks: 2 }, SoundEvent::Stopped]
+        );
+    }
+
+    #[test]
+    fn framebuffer_bits_pack_rows_msb_first() {
+        let mut chip8 = Chip8::new();
+        assert_eq!(chip8.framebuffer_bits(), [0; FRAMEBUFFER_BYTES]);
+        for (x, y) in [(0, 0), (9, 0), (63, 0), (0, 1), (63, 31)] {
+            chip8.video[y * DISP_WIDTH + x] = true;
+        }
+        let bits = chip8.framebuffer_bits();
+        assert_eq!(bits[..9], [0x80, 0x40, 0, 0, 0, 0, 0, 0x01, 0x80]);
+        assert_eq!(bits[FRAMEBUFFER_BYTES - 1], 0x01);
+        assert_eq!(bits.iter().map(|byte| byte.count_ones()).sum::<u32>(), 5);
+    }
+
+    /// Frames of state hashes of `rom` run on a thread of its own, seeded with `seed`
+    /// and with keys flipped by a generator seeded the same.
+    fn hashes_on_a_thread(rom: &'static [u8], seed: u64) -> std::thread::JoinHandle<Vec<u64>> {
+        std::thread::spawn(move || {
+            let mut chip8 = Chip8::new();
+            chip8.seed(seed);
+            chip8.load_rom_bytes(rom).unwrap();
+            let mut keys = ChaCha8Rng::seed_from_u64(seed);
+            (0..2000)
+                .map(|_| {
+                    if keys.gen_bool(0.1) {
+                        chip8.keypad[keys.gen_range(0..16)] ^= 1;
+                    }
+                    for _ in 0..10 {
+                        chip8.cycle().unwrap();
+                    }
+                    chip8.tick_timers();
+                    chip8.state_hash()
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn twins_on_two_threads_stay_in_lockstep() {
+        let roms: [&[u8]; 3] = [
+            include_bytes!("../roms/bounce.ch8"),
+            include_bytes!("../roms/keypad.ch8"),
+            include_bytes!("../roms/maze.ch8"),
+        ];
+        for (seed, rom) in roms.into_iter().enumerate() {
+            let twins = [0, 1].map(|_| hashes_on_a_thread(rom, seed as u64));
+            let [first, second] = twins.map(|twin| twin.join().unwrap());
+            let diverged = first.iter().zip(&second).position(|(a, b)| a != b);
+            assert_eq!(diverged, None, "ROM {} diverged in that frame", seed);
         }
-        self.i = self.i.wrapping_add((x + 1) as u16);
     }
 }
@@ -0,0 +1,80 @@
+use crate::chip8::Chip8;
+use std::time::Duration;
+
+const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+/// Rate the delay and sound timers count down at.
+pub const TIMER_HZ: u32 = 60;
+
+/// Turns time measured by the host into 60 Hz timer ticks, for frontends that have no
+/// 60 Hz loop of their own: the audio callback, a display vblank at another rate or an
+/// OS timer each report the time that passed, and the clock says how many ticks are due.
+///
+/// Time is kept in whole nanoseconds times the rate, so the ticks never drift however
+/// the elapsed time is sliced.
+#[derive(Clone, Debug)]
+pub struct Clock {
+    hz: u32,
+    /// Progress towards the next tick, in nanoseconds times `hz`
+    phase: u64,
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::new()
+    }
+}
+
+impl Clock {
+    /// Clock ticking at the timer rate.
+    pub fn new() -> Clock {
+        Clock::with_rate(TIMER_HZ)
+    }
+
+    /// Clock ticking `hz` times per second, e.g. to run the timers fast or slow.
+    pub fn with_rate(hz: u32) -> Clock {
+        Clock {
+            hz: hz.max(1),
+            phase: 0,
+        }
+    }
+
+    /// Count `elapsed` time and return the number of ticks it completed.
+    pub fn advance(&mut self, elapsed: Duration) -> u32 {
+        let phase = self.phase as u128 + elapsed.as_nanos() * self.hz as u128;
+        self.phase = (phase % NANOS_PER_SEC as u128) as u64;
+        (phase / NANOS_PER_SEC as u128).min(u32::MAX as u128) as u32
+    }
+
+    /// Count `samples` of audio played at `sample_rate`, for clocks driven by an audio callback.
+    pub fn advance_samples(&mut self, samples: u32, sample_rate: u32) -> u32 {
+        let nanos = samples as u64 * NANOS_PER_SEC / sample_rate.max(1) as u64;
+        self.advance(Duration::from_nanos(nanos))
+    }
+
+    /// Count `elapsed` time and tick the timers of `chip8` as often as it completed,
+    /// see `Chip8::tick_timers`. Returns the number of ticks.
+    pub fn tick(&mut self, chip8: &mut Chip8, elapsed: Duration) -> u32 {
+        let ticks = self.advance(elapsed);
+        for _ in 0..ticks {
+            chip8.tick_timers();
+        }
+        ticks
+    }
+
+    /// How far into the current tick the clock is, from `0.0` just after a tick towards `1.0`.
+    pub fn fraction(&self) -> f64 {
+        self.phase as f64 / NANOS_PER_SEC as f64
+    }
+
+    /// Time left until the next tick is due.
+    pub fn until_next_tick(&self) -> Duration {
+        let left = NANOS_PER_SEC - self.phase;
+        Duration::from_nanos(left.div_ceil(self.hz as u64))
+    }
+
+    /// Start the current tick over, e.g. after the emulation was paused.
+    pub fn reset(&mut self) {
+        self.phase = 0;
+    }
+}
@@ -0,0 +1,215 @@
+use crate::dirs::config_dir;
+use crate::hotkeys::Hotkey;
+use crate::window::WindowState;
+use chip_8::{Effect, Quirks, RamInit};
+use sdl2::keyboard::{Keycode, Scancode};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use tracing::warn;
+
+/// User settings, stored in `config.toml` in the config directory, see `dirs`.
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Name of the display palette
+    pub palette: String,
+
+    /// Draw in black, white and grays only, whatever the palette, see `Palette::high_contrast`
+    pub high_contrast: bool,
+
+    /// Show the registers over the game, toggled with the `hud` hotkey
+    pub hud: bool,
+
+    /// Highlight the pixels changed since the last drawn frame, toggled with the `changes` hotkey
+    pub changes: bool,
+
+    /// Graph the time spent per frame, toggled with the `timing_graph` hotkey
+    pub timing_graph: bool,
+
+    /// Frame the sprite drawn last, to see what the game is drawing
+    pub outline_sprites: bool,
+
+    /// Instructions executed per 60 Hz frame
+    pub ticks_per_frame: u32,
+
+    /// Frames left undrawn after every drawn one; emulation still runs every frame
+    pub frame_skip: u32,
+
+    /// On displays faster than 60 Hz, present a blend of the last two emulated frames
+    /// at every refresh, for less flicker; emulation stays at 60 Hz and `frame_skip` is ignored
+    pub interpolate: bool,
+
+    /// Present only frames that look different and wait for input between frames,
+    /// for longer battery life; what it saved is logged on exit
+    pub power_saving: bool,
+
+    /// Name of the quirks preset
+    pub quirks: String,
+
+    /// What RAM holds before a ROM is loaded, for games that read memory they never wrote
+    pub ram_init: RamInit,
+
+    /// Seed for `ram_init = "random"`, the same bytes every time; a new seed every load if unset
+    pub ram_seed: Option<u64>,
+
+    /// Beep volume in percent
+    pub volume: u8,
+
+    /// Audio device buffer in samples at 44.1 kHz, a power of two;
+    /// smaller means less beep latency but more risk of crackling
+    pub audio_buffer: u16,
+
+    /// Controller rumble strength in percent while the sound timer runs, `0` is off
+    pub rumble: u8,
+
+    /// Shortest rumble pulse in milliseconds, so very short beeps can still be felt
+    pub rumble_ms: u32,
+
+    /// Host key name bound to every CHIP-8 key, indexed by key value
+    pub keys: [String; 16],
+
+    /// Read `keys` as physical key positions (named as on a US QWERTY keyboard)
+    /// instead of the symbols printed on them, keeping the keypad grid on any layout
+    pub scancodes: bool,
+
+    /// Host keys that rapidly press and release a CHIP-8 key while held
+    pub turbo: Vec<TurboKey>,
+
+    /// Hotkey profile: `default`, `left-handed` or one of `hotkey_profiles`
+    pub hotkeys: String,
+
+    /// Named hotkey profiles, each binding actions to key combinations like `Ctrl+S`;
+    /// actions left out keep their `default` binding
+    pub hotkey_profiles: BTreeMap<String, BTreeMap<Hotkey, String>>,
+
+    /// Window size, position and scaling, saved on exit
+    pub window: WindowState,
+
+    /// Bezel layout file: artwork drawn around the display, see `Bezel`
+    pub bezel: Option<PathBuf>,
+
+    /// Effects the display goes through before it is shown, in order, see `PostChain`
+    pub effects: Vec<Effect>,
+
+    /// GLSL fragment shader file the display is drawn with, after `effects`, see `Shader`
+    pub shader: Option<PathBuf>,
+}
+
+/// Turbo button, see `Config::turbo`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TurboKey {
+    /// Host key name, read like `Config::keys`
+    pub key: String,
+
+    /// CHIP-8 key pressed while `key` is held
+    pub chip8: u8,
+
+    /// Presses per second, at most 30 as the keypad is sampled once per 60 Hz frame
+    pub hz: u32,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        Config {
+            palette: "classic".to_string(),
+            high_contrast: false,
+            hud: false,
+            changes: false,
+            timing_graph: false,
+            outline_sprites: false,
+            ticks_per_frame: 10,
+            frame_skip: 0,
+            interpolate: false,
+            power_saving: false,
+            quirks: "modern".to_string(),
+            ram_init: RamInit::Zero,
+            ram_seed: None,
+            volume: 50,
+            audio_buffer: 512,
+            rumble: 0,
+            rumble_ms: 100,
+            keys: [
+                "X", "1", "2", "3", "Q", "W", "E", "A", "S", "D", "Z", "C", "4", "R", "F", "V",
+            ]
+            .map(String::from),
+            scancodes: false,
+            turbo: Vec::new(),
+            hotkeys: "default".to_string(),
+            hotkey_profiles: BTreeMap::new(),
+            window: WindowState::default(),
+            bezel: None,
+            effects: Vec::new(),
+            shader: None,
+        }
+    }
+}
+
+impl Config {
+    /// Read the config file, falling back to defaults when it is missing or broken.
+    pub fn load() -> Config {
+        let Some(file) = config_file() else {
+            return Config::default();
+        };
+        let Ok(contents) = fs::read_to_string(&file) else {
+            return Config::default();
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(err) => {
+                warn!(file = %file.display(), %err, "Ignoring invalid config");
+                Config::default()
+            }
+        }
+    }
+
+    pub fn save(&self) {
+        let Some(file) = config_file() else {
+            return;
+        };
+        let contents = toml::to_string(self).expect("Config should serialize");
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(err) = fs::write(&file, contents) {
+            warn!(file = %file.display(), %err, "Could not save config");
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        Quirks::preset(&self.quirks).unwrap_or_default()
+    }
+
+    /// CHIP-8 key bound to the host key with `keycode` and `scancode`.
+    pub fn chip8_key(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<usize> {
+        let name = self.key_name(keycode, scancode)?;
+        self.keys.iter().position(|bound| *bound == name)
+    }
+
+    /// Turbo button bound to the host key with `keycode` and `scancode`.
+    pub fn turbo_key(
+        &self,
+        keycode: Option<Keycode>,
+        scancode: Option<Scancode>,
+    ) -> Option<&TurboKey> {
+        let name = self.key_name(keycode, scancode)?;
+        self.turbo
+            .iter()
+            .find(|turbo| turbo.key == name && turbo.chip8 <= 0xF)
+    }
+
+    /// Name a host key is stored under in `keys`.
+    pub fn key_name(&self, keycode: Option<Keycode>, scancode: Option<Scancode>) -> Option<String> {
+        if self.scancodes {
+            scancode.map(|scancode| scancode.name().to_string())
+        } else {
+            keycode.map(|keycode| keycode.name())
+        }
+        .filter(|name| !name.is_empty())
+    }
+}
+
+fn config_file() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("config.toml"))
+}
@@ -0,0 +1,38 @@
+use chip_8::SaveState;
+use clap::Args;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::error;
+
+#[derive(Args)]
+pub struct DiffArgs {
+    /// First save state
+    first: PathBuf,
+
+    /// Second save state
+    second: PathBuf,
+}
+
+/// `chip-8 diff`: report what differs between two save states.
+///
+/// Exits with 0 when they are identical, 1 when they differ and 2 when one could not be read.
+pub fn diff(args: DiffArgs) -> ExitCode {
+    let mut states = Vec::with_capacity(2);
+    for path in [&args.first, &args.second] {
+        match SaveState::load(path) {
+            Ok(state) => states.push(state),
+            Err(err) => {
+                error!(file = %path.display(), %err, "Could not read save state");
+                return ExitCode::from(2);
+            }
+        }
+    }
+
+    let diff = states[0].diff(&states[1]);
+    print!("{}", diff);
+    if diff.is_empty() {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::from(1)
+    }
+}
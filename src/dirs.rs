@@ -0,0 +1,127 @@
+//! Where settings, per-game data and downloads are kept.
+//!
+//! | | Linux and other Unix | macOS | Windows |
+//! |-|-|-|-|
+//! | config | `$XDG_CONFIG_HOME/chip-8`, `~/.config/chip-8` | `~/Library/Application Support/chip-8` | `%APPDATA%\chip-8` |
+//! | data | `$XDG_DATA_HOME/chip-8`, `~/.local/share/chip-8` | `~/Library/Application Support/chip-8` | `%APPDATA%\chip-8` |
+//! | cache | `$XDG_CACHE_HOME/chip-8`, `~/.cache/chip-8` | `~/Library/Caches/chip-8` | `%LOCALAPPDATA%\chip-8` |
+//!
+//! A `~/.chip-8` directory left by older versions is used for all three instead.
+
+use std::env;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use tracing::debug;
+
+const APP: &str = "chip-8";
+
+struct Dirs {
+    config: Option<PathBuf>,
+    data: Option<PathBuf>,
+    cache: Option<PathBuf>,
+}
+
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+/// Use the given directories over the platform ones, before any of them is looked up.
+pub fn init(config: Option<PathBuf>, data: Option<PathBuf>, cache: Option<PathBuf>) {
+    let _ = DIRS.set(resolve(config, data, cache));
+}
+
+fn dirs() -> &'static Dirs {
+    DIRS.get_or_init(|| resolve(None, None, None))
+}
+
+fn resolve(config: Option<PathBuf>, data: Option<PathBuf>, cache: Option<PathBuf>) -> Dirs {
+    let legacy = home()
+        .map(|home| home.join(".chip-8"))
+        .filter(|dir| dir.is_dir());
+    let dirs = Dirs {
+        config: config.or_else(|| legacy.clone()).or_else(platform_config),
+        data: data.or_else(|| legacy.clone()).or_else(platform_data),
+        cache: cache.or(legacy).or_else(platform_cache),
+    };
+    debug!(config = ?dirs.config, data = ?dirs.data, cache = ?dirs.cache, "Directories");
+    dirs
+}
+
+/// Settings, `config.toml`.
+pub fn config_dir() -> Option<PathBuf> {
+    dirs().config.clone()
+}
+
+/// Per-game settings, scores and macros in `games`, the recently played list.
+pub fn data_dir() -> Option<PathBuf> {
+    dirs().data.clone()
+}
+
+/// Downloaded ROMs, safe to delete.
+pub fn cache_dir() -> Option<PathBuf> {
+    dirs().cache.clone()
+}
+
+fn home() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .or_else(|| env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+#[cfg(not(target_os = "macos"))]
+fn env_dir(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+}
+
+#[cfg(windows)]
+fn platform_config() -> Option<PathBuf> {
+    env_dir("APPDATA").map(|dir| dir.join(APP))
+}
+
+#[cfg(windows)]
+fn platform_data() -> Option<PathBuf> {
+    platform_config()
+}
+
+#[cfg(windows)]
+fn platform_cache() -> Option<PathBuf> {
+    env_dir("LOCALAPPDATA").map(|dir| dir.join(APP))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_config() -> Option<PathBuf> {
+    home().map(|home| home.join("Library/Application Support").join(APP))
+}
+
+#[cfg(target_os = "macos")]
+fn platform_data() -> Option<PathBuf> {
+    platform_config()
+}
+
+#[cfg(target_os = "macos")]
+fn platform_cache() -> Option<PathBuf> {
+    home().map(|home| home.join("Library/Caches").join(APP))
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_config() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_data() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share")
+}
+
+#[cfg(not(any(windows, target_os = "macos")))]
+fn platform_cache() -> Option<PathBuf> {
+    xdg_dir("XDG_CACHE_HOME", ".cache")
+}
+
+/// `$var/chip-8`, or `~/default/chip-8` when it is unset, see the XDG Base Directory Specification.
+#[cfg(not(any(windows, target_os = "macos")))]
+fn xdg_dir(var: &str, default: &str) -> Option<PathBuf> {
+    env_dir(var)
+        .or_else(|| home().map(|home| home.join(default)))
+        .map(|dir| dir.join(APP))
+}
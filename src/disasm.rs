@@ -0,0 +1,43 @@
+use crate::instruction::Instruction;
+
+/// Assembly mnemonic of `opcode`, in the notation used by the opcode docs.
+/// Anything that is not an instruction is shown as a `DW` data word.
+pub fn disassemble(opcode: u16) -> String {
+    match Instruction::decode(opcode) {
+        Instruction::Cls => "CLS".to_string(),
+        Instruction::Ret => "RET".to_string(),
+        Instruction::Jump { nnn } => format!("JMP {:#05X}", nnn),
+        Instruction::Call { nnn } => format!("CALL {:#05X}", nnn),
+        Instruction::SkipEqImm { x, nn } => format!("SE V{:X}, {:#04X}", x, nn),
+        Instruction::SkipNeImm { x, nn } => format!("SNE V{:X}, {:#04X}", x, nn),
+        Instruction::SkipEq { x, y } => format!("SE V{:X}, V{:X}", x, y),
+        Instruction::LoadImm { x, nn } => format!("LD V{:X}, {:#04X}", x, nn),
+        Instruction::AddImm { x, nn } => format!("ADD V{:X}, {:#04X}", x, nn),
+        Instruction::Load { x, y } => format!("LD V{:X}, V{:X}", x, y),
+        Instruction::Or { x, y } => format!("OR V{:X}, V{:X}", x, y),
+        Instruction::And { x, y } => format!("AND V{:X}, V{:X}", x, y),
+        Instruction::Xor { x, y } => format!("XOR V{:X}, V{:X}", x, y),
+        Instruction::Add { x, y } => format!("ADD V{:X}, V{:X}", x, y),
+        Instruction::Sub { x, y } => format!("SUB V{:X}, V{:X}", x, y),
+        Instruction::ShiftRight { x, y } => format!("SHR V{:X}, V{:X}", x, y),
+        Instruction::SubN { x, y } => format!("SUBN V{:X}, V{:X}", x, y),
+        Instruction::ShiftLeft { x, y } => format!("SHL V{:X}, V{:X}", x, y),
+        Instruction::SkipNe { x, y } => format!("SNE V{:X}, V{:X}", x, y),
+        Instruction::LoadI { nnn } => format!("LD I, {:#05X}", nnn),
+        Instruction::JumpOffset { nnn, .. } => format!("JMP V0, {:#05X}", nnn),
+        Instruction::Random { x, nn } => format!("RND V{:X}, {:#04X}", x, nn),
+        Instruction::Draw { x, y, n } => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+        Instruction::SkipKey { x } => format!("SKP V{:X}", x),
+        Instruction::SkipNoKey { x } => format!("SKNP V{:X}", x),
+        Instruction::LoadDelay { x } => format!("LD V{:X}, DT", x),
+        Instruction::WaitKey { x } => format!("LD V{:X}, KEY", x),
+        Instruction::SetDelay { x } => format!("LD DT, V{:X}", x),
+        Instruction::SetSound { x } => format!("LD ST, V{:X}", x),
+        Instruction::AddI { x } => format!("ADD I, V{:X}", x),
+        Instruction::LoadFont { x } => format!("LD I, FONT(V{:X})", x),
+        Instruction::Bcd { x } => format!("BCD V{:X}", x),
+        Instruction::Store { x } => format!("LD [I], V{:X}", x),
+        Instruction::LoadMem { x } => format!("LD V{:X}, [I]", x),
+        Instruction::Data(opcode) => format!("DW {:#06X}", opcode),
+    }
+}
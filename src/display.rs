@@ -0,0 +1,95 @@
+/// What happens to the parts of a sprite that reach past an edge of the display.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Edges {
+    /// They come back in on the opposite side
+    Wrap,
+    /// They are cut off
+    Clip,
+}
+
+/// Size of a display in pixels and how sprites are drawn at its edges.
+///
+/// A sprite's starting position always wraps onto the display, whatever the edges do:
+/// drawing at (70, 40) on a 64x32 display starts at (6, 8).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Display {
+    pub width: usize,
+    pub height: usize,
+    pub edges: Edges,
+}
+
+impl Display {
+    pub const fn new(width: usize, height: usize, edges: Edges) -> Display {
+        Display {
+            width,
+            height,
+            edges,
+        }
+    }
+
+    /// Where a sprite drawn at (`x`, `y`) starts.
+    pub fn origin(&self, x: u8, y: u8) -> (usize, usize) {
+        (x as usize % self.width, y as usize % self.height)
+    }
+
+    /// Column of the pixel `offset` to the right of column `x`, `None` when it is clipped.
+    pub fn column(&self, x: usize, offset: usize) -> Option<usize> {
+        self.place(x + offset, self.width)
+    }
+
+    /// Row of the pixel `offset` below row `y`, `None` when it is clipped.
+    pub fn row(&self, y: usize, offset: usize) -> Option<usize> {
+        self.place(y + offset, self.height)
+    }
+
+    /// Index of the pixel at (`x`, `y`) in row-major video memory.
+    pub fn index(&self, x: usize, y: usize) -> usize {
+        y * self.width + x
+    }
+
+    fn place(&self, at: usize, size: usize) -> Option<usize> {
+        match self.edges {
+            Edges::Clip if at >= size => None,
+            _ => Some(at % size),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WRAP: Display = Display::new(64, 32, Edges::Wrap);
+    const CLIP: Display = Display::new(64, 32, Edges::Clip);
+
+    #[test]
+    fn origin_wraps_each_coordinate_by_its_own_size() {
+        for display in [WRAP, CLIP] {
+            assert_eq!(display.origin(0, 0), (0, 0));
+            assert_eq!(display.origin(63, 31), (63, 31));
+            assert_eq!(display.origin(64, 32), (0, 0));
+            assert_eq!(display.origin(70, 40), (6, 8));
+            assert_eq!(display.origin(255, 255), (63, 31));
+        }
+    }
+
+    #[test]
+    fn pixels_past_an_edge_wrap_or_are_clipped() {
+        assert_eq!(WRAP.column(62, 1), Some(63));
+        assert_eq!(WRAP.column(62, 2), Some(0));
+        assert_eq!(WRAP.row(31, 1), Some(0));
+        assert_eq!(WRAP.row(30, 15), Some(13));
+        assert_eq!(CLIP.column(62, 1), Some(63));
+        assert_eq!(CLIP.column(62, 2), None);
+        assert_eq!(CLIP.row(31, 0), Some(31));
+        assert_eq!(CLIP.row(31, 1), None);
+    }
+
+    #[test]
+    fn index_is_row_major() {
+        assert_eq!(WRAP.index(0, 0), 0);
+        assert_eq!(WRAP.index(63, 0), 63);
+        assert_eq!(WRAP.index(0, 1), 64);
+        assert_eq!(WRAP.index(63, 31), 64 * 32 - 1);
+    }
+}
@@ -0,0 +1,75 @@
+use crate::i18n::tr;
+use crate::menu::draw_footer;
+use crate::text::{GREY, LINE_HEIGHT, MARGIN, TEXT_SCALE, WHITE, dim, draw_text};
+use chip_8::{Chip8, Chip8Error};
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+
+const RED: Color = Color::RGB(230, 40, 40);
+
+pub enum ErrorAction {
+    None,
+    /// Load the game again from the start
+    Reset,
+    Quit,
+}
+
+/// Diagnostic shown over the last frame when the machine stops on a fatal error.
+pub struct ErrorScreen {
+    error: Chip8Error,
+    registers: [u8; 16],
+    pc: u16,
+    i: u16,
+    sp: u8,
+    opcode: u16,
+}
+
+impl ErrorScreen {
+    pub fn new(error: Chip8Error, chip8: &Chip8) -> ErrorScreen {
+        ErrorScreen {
+            error,
+            registers: chip8.registers,
+            pc: chip8.pc,
+            i: chip8.i,
+            sp: chip8.sp,
+            opcode: chip8.opcode,
+        }
+    }
+
+    pub fn handle_key(&self, key: Keycode) -> ErrorAction {
+        match key {
+            Keycode::R => ErrorAction::Reset,
+            Keycode::Escape | Keycode::Q => ErrorAction::Quit,
+            _ => ErrorAction::None,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        dim(canvas);
+        draw_text(canvas, tr("error.title"), MARGIN, MARGIN, TEXT_SCALE, RED);
+
+        let mut y = MARGIN + 2 * LINE_HEIGHT;
+        let message = self.error.to_string().to_uppercase();
+        draw_text(canvas, &message, MARGIN, y, TEXT_SCALE, WHITE);
+        y += 2 * LINE_HEIGHT;
+
+        let state = format!(
+            "PC {:03X}  I {:03X}  SP {:X}  OP {:04X}",
+            self.pc, self.i, self.sp, self.opcode
+        );
+        draw_text(canvas, &state, MARGIN, y, TEXT_SCALE, GREY);
+        y += LINE_HEIGHT;
+        for (row, values) in self.registers.chunks(8).enumerate() {
+            let line: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(n, value)| format!("V{:X} {:02X}", row * 8 + n, value))
+                .collect();
+            draw_text(canvas, &line.join(" "), MARGIN, y, TEXT_SCALE, GREY);
+            y += LINE_HEIGHT;
+        }
+
+        draw_footer(canvas, tr("error.footer"));
+    }
+}
@@ -0,0 +1,198 @@
+use crate::achievements::Condition;
+use crate::dirs::data_dir;
+use crate::remote;
+use chip_8::hash::fnv1a;
+use chip_8::{CartridgeQuirks, Chip8, InputScript};
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::warn;
+
+/// Entries kept in a game's leaderboard.
+const MAX_HIGH_SCORES: usize = 10;
+
+/// Settings for a single game, stored in `games/<hash>.toml` in the data directory.
+/// Games are told apart by a hash of the ROM contents, so renamed copies share them.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameConfig {
+    /// Quirks the game needs, over the ones of its cartridge and the configured preset:
+    /// a preset name or flags, `quirks = { clip_sprites = true }`
+    pub quirks: Option<CartridgeQuirks>,
+
+    /// Show the display as a CRT beam scans it out while the game runs, for the sprite
+    /// tearing and flicker of the original hardware, see `Scanout`
+    pub scanout: bool,
+
+    /// Recorded keypad sequences, replayed by pressing their host key
+    pub macros: Vec<Macro>,
+
+    /// Where the game keeps its score, for the high-score watcher
+    pub score: Option<ScoreSource>,
+
+    /// Best scores across sessions, highest first
+    pub high_scores: Vec<HighScore>,
+
+    /// Goals checked every frame, unlocked once
+    pub achievements: Vec<Achievement>,
+
+    /// What the monitor was set to watch, restored when the game loads with `--monitor`
+    pub monitor: MonitorSession,
+
+    #[serde(skip)]
+    file: Option<PathBuf>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Macro {
+    /// Host key name, read like `Config::keys`
+    pub key: String,
+
+    /// Keypad changes, frames counted from the host key press
+    pub input: InputScript,
+}
+
+/// Location and encoding of a game's score, e.g. `score = { format = "bcd", address = 0x3F0, digits = 3 }`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "format", rename_all = "lowercase")]
+pub enum ScoreSource {
+    /// `digits` bytes from `address`, one decimal digit each, most significant first (as FX33 stores them)
+    Bcd { address: u16, digits: u8 },
+
+    /// The byte at `address`
+    Byte { address: u16 },
+
+    /// Register V`register`
+    Register { register: u8 },
+}
+
+impl ScoreSource {
+    /// Current score, `None` while the memory holds something that is not a valid score.
+    pub fn read(&self, chip8: &Chip8) -> Option<u32> {
+        match *self {
+            ScoreSource::Bcd { address, digits } => {
+                let start = address as usize;
+                let digits = chip8.bus.ram.get(start..start + digits as usize)?;
+                digits.iter().try_fold(0u32, |score, &digit| {
+                    (digit <= 9).then(|| score * 10 + digit as u32)
+                })
+            }
+            ScoreSource::Byte { address } => chip8.bus.ram.get(address as usize).map(|&b| b as u32),
+            ScoreSource::Register { register } => {
+                chip8.registers.get(register as usize).map(|&v| v as u32)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct HighScore {
+    pub score: u32,
+
+    /// Unix time the session ended
+    pub at: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Achievement {
+    pub name: String,
+
+    /// When it is reached, e.g. `"score >= 100"`
+    pub when: Condition,
+
+    /// Unix time it was first reached
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unlocked: Option<u64>,
+}
+
+/// Breakpoints, tracepoints and watches of the monitor, kept between sessions.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MonitorSession {
+    pub breakpoints: Vec<u16>,
+    pub tracepoints: Vec<Tracepoint>,
+
+    /// Expressions as typed, like `[I + V1] * 2`
+    pub watches: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Tracepoint {
+    pub address: u16,
+
+    /// Message with placeholders like `{V3}`
+    pub message: String,
+}
+
+impl GameConfig {
+    /// Read the settings of the ROM at `rom`, falling back to defaults.
+    pub fn load(rom: &Path) -> GameConfig {
+        let file = remote::local_file(rom)
+            .and_then(fs::read)
+            .ok()
+            .and_then(|bytes| game_file(&bytes));
+        let mut config: GameConfig = file
+            .as_ref()
+            .and_then(|file| {
+                let contents = fs::read_to_string(file).ok()?;
+                toml::from_str(&contents)
+                    .inspect_err(
+                        |err| warn!(file = %file.display(), %err, "Ignoring invalid game config"),
+                    )
+                    .ok()
+            })
+            .unwrap_or_default();
+        config.file = file;
+        config
+    }
+
+    pub fn save(&self) {
+        let Some(file) = &self.file else {
+            return;
+        };
+        let contents = toml::to_string(self).expect("Game config should serialize");
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(err) = fs::write(file, contents) {
+            warn!(file = %file.display(), %err, "Could not save game config");
+        }
+    }
+
+    /// Quick save slot of the game, next to its settings.
+    pub fn state_file(&self) -> Option<PathBuf> {
+        self.file.as_ref().map(|file| file.with_extension("c8s"))
+    }
+
+    pub fn macro_for(&self, key: &str) -> Option<&Macro> {
+        self.macros.iter().find(|m| m.key == key)
+    }
+
+    /// Add `score` to the leaderboard, keeping the `MAX_HIGH_SCORES` best.
+    /// Returns the previous best when `score` beats it.
+    pub fn add_high_score(&mut self, score: u32) -> Option<u32> {
+        let previous = self.high_scores.first().map_or(0, |best| best.score);
+        let at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let idx = self
+            .high_scores
+            .partition_point(|entry| entry.score >= score);
+        self.high_scores.insert(idx, HighScore { score, at });
+        self.high_scores.truncate(MAX_HIGH_SCORES);
+        (score > previous).then_some(previous)
+    }
+
+    /// Bind `input` to host `key`, replacing what was bound to it.
+    pub fn set_macro(&mut self, key: String, input: InputScript) {
+        self.macros.retain(|m| m.key != key);
+        self.macros.push(Macro { key, input });
+    }
+}
+
+/// FNV-1a over the ROM contents.
+fn game_file(rom: &[u8]) -> Option<PathBuf> {
+    let hash = fnv1a(rom.iter().copied());
+    data_dir().map(|dir| dir.join("games").join(format!("{:016x}.toml", hash)))
+}
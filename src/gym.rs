@@ -0,0 +1,191 @@
+//! Reinforcement learning environment over an `EmulatorPool`, in the style of Gym:
+//! agents see the display and act by holding keys.
+//!
+//! ```ignore
+//! let config = GymConfig { reward_addr: Some(0x3F0), ..GymConfig::default() };
+//! let mut env = GymEnv::new(machines, config);
+//! let mut observations = env.reset_all();
+//! loop {
+//!     let actions: Vec<Action> = observations.iter().map(|obs| agent.act(obs)).collect();
+//!     for (index, step) in env.step(&actions).into_iter().enumerate() {
+//!         agent.learn(index, &step);
+//!         observations[index] = if step.done { env.reset(index) } else { step.observation };
+//!     }
+//! }
+//! ```
+
+use crate::chip8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH};
+use crate::pool::EmulatorPool;
+use crate::state::SaveState;
+
+/// What an agent sees: the display, row by row like `Chip8::video`.
+pub type Observation = [bool; DISP_WIDTH * DISP_HEIGHT];
+
+/// What an agent does: the keys 0x0-0xF held down for the next step.
+pub type Action = [bool; 16];
+
+/// How the environment steps and rewards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct GymConfig {
+    /// Instructions per 60 Hz frame
+    pub ticks_per_frame: u32,
+
+    /// Frames an action is held for, one step of the environment
+    pub frame_skip: u32,
+
+    /// RAM byte holding the score; the reward of a step is how much it grew.
+    /// `None` rewards nothing.
+    pub reward_addr: Option<u16>,
+}
+
+impl Default for GymConfig {
+    fn default() -> GymConfig {
+        GymConfig {
+            ticks_per_frame: 10,
+            frame_skip: 4,
+            reward_addr: None,
+        }
+    }
+}
+
+/// Outcome of one step of one machine.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GymStep {
+    pub observation: Observation,
+
+    /// Change of the byte at `GymConfig::reward_addr`
+    pub reward: i32,
+
+    /// The machine halted, reset it to go on
+    pub done: bool,
+
+    /// What halted it
+    pub error: Option<Chip8Error>,
+}
+
+/// Machines run as independent episodes, stepped together with one action each.
+///
+/// Every machine resets to the state it was handed over in, so give them their ROM,
+/// quirks and seed first.
+pub struct GymEnv {
+    pool: EmulatorPool,
+    start: Vec<SaveState>,
+    config: GymConfig,
+}
+
+impl GymEnv {
+    pub fn new(machines: Vec<Chip8>, config: GymConfig) -> GymEnv {
+        let start = machines.iter().map(Chip8::save_state).collect();
+        GymEnv {
+            pool: EmulatorPool::new(machines, config.ticks_per_frame),
+            start,
+            config,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pool.is_empty()
+    }
+
+    pub fn config(&self) -> GymConfig {
+        self.config
+    }
+
+    /// The machines, e.g. to change their thread count or look at more than the display.
+    pub fn pool(&mut self) -> &mut EmulatorPool {
+        &mut self.pool
+    }
+
+    /// Start a new episode of machine `index` and return its first observation.
+    pub fn reset(&mut self, index: usize) -> Observation {
+        let chip8 = self.pool.machine_mut(index);
+        chip8.load_state(&self.start[index]);
+        chip8.take_sound_events();
+        self.pool.resume(index);
+        self.pool.machine(index).video
+    }
+
+    pub fn reset_all(&mut self) -> Vec<Observation> {
+        (0..self.len()).map(|index| self.reset(index)).collect()
+    }
+
+    /// Hold down the keys of `actions[index]` on every machine for `frame_skip` frames.
+    ///
+    /// # Panics
+    ///
+    /// When there is not one action per machine.
+    pub fn step(&mut self, actions: &[Action]) -> Vec<GymStep> {
+        assert_eq!(actions.len(), self.len(), "one action per machine");
+        let before: Vec<u8> = (0..self.len()).map(|index| self.score(index)).collect();
+        for (index, action) in actions.iter().enumerate() {
+            let chip8 = self.pool.machine_mut(index);
+            for (key, &held) in action.iter().enumerate() {
+                chip8.set_key_value(key, held as u8);
+            }
+        }
+        let results = self.pool.step(self.config.frame_skip);
+        results
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| GymStep {
+                observation: self.pool.machine(index).video,
+                reward: self.score(index) as i32 - before[index] as i32,
+                done: result.is_err(),
+                error: result.err(),
+            })
+            .collect()
+    }
+
+    fn score(&self, index: usize) -> u8 {
+        self.config
+            .reward_addr
+            .and_then(|addr| self.pool.machine(index).bus.ram.get(addr as usize).copied())
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Counts up the byte at 0x301 while key 5 is held.
+    #[rustfmt::skip]
+    const PRESS_FOR_POINTS: [u8; 12] = [
+        0x60, 0x05, // V0 := 5
+        0xE0, 0xA1, // loop: if key V0 is held
+        0x71, 0x01, //   V1 += 1
+        0xA3, 0x00, // I := 0x300
+        0xF1, 0x55, // save V0-V1
+        0x12, 0x02, // jump loop
+    ];
+
+    fn env() -> GymEnv {
+        let mut chip8 = Chip8::new();
+        chip8.seed(0);
+        chip8.load_rom_bytes(&PRESS_FOR_POINTS).unwrap();
+        let config = GymConfig {
+            ticks_per_frame: 10,
+            frame_skip: 2,
+            reward_addr: Some(0x301),
+        };
+        GymEnv::new(vec![chip8], config)
+    }
+
+    #[test]
+    fn holding_keys_earns_the_score_growth() {
+        let mut env = env();
+        let mut press = [false; 16];
+        press[5] = true;
+        assert_eq!(env.step(&[press])[0].reward, 4);
+        assert_eq!(env.step(&[[false; 16]])[0].reward, 0);
+        assert!(!env.step(&[press])[0].done);
+
+        env.reset(0);
+        assert_eq!(env.pool().machine(0).bus.ram[0x301], 0);
+        assert_eq!(env.step(&[press])[0].reward, 4);
+    }
+}
@@ -0,0 +1,11 @@
+//! Hashes for telling ROMs, states and frames apart, the same on every platform.
+
+const OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const PRIME: u64 = 0x100000001b3;
+
+/// 64-bit FNV-1a of `bytes`. Not for anything where collisions could be forced on purpose.
+pub fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    bytes.into_iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(PRIME)
+    })
+}
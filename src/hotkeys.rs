@@ -0,0 +1,176 @@
+use crate::config::Config;
+use sdl2::event::Event;
+use sdl2::keyboard::Mod;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use tracing::warn;
+
+/// Emulator action bound to a host key combination.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Hotkey {
+    Pause,
+    Fullscreen,
+    NextDisplay,
+    Switcher,
+    RecordMacro,
+    /// Load the game again from the start
+    Reset,
+    SaveState,
+    LoadState,
+    /// Run faster while held
+    FastForward,
+    Screenshot,
+    /// Show or hide the register and keypad HUD
+    Hud,
+    /// Highlight the pixels changed since the last drawn frame
+    Changes,
+    /// Show or hide the frame timing graph
+    TimingGraph,
+}
+
+/// Bindings of the `default` profile.
+const DEFAULT: [(Hotkey, &str); 13] = [
+    (Hotkey::Pause, "Escape"),
+    (Hotkey::Fullscreen, "F11"),
+    (Hotkey::NextDisplay, "Shift+F11"),
+    (Hotkey::Switcher, "Ctrl+R"),
+    (Hotkey::RecordMacro, "Ctrl+M"),
+    (Hotkey::Reset, "Ctrl+Backspace"),
+    (Hotkey::SaveState, "F5"),
+    (Hotkey::LoadState, "F9"),
+    (Hotkey::FastForward, "Tab"),
+    (Hotkey::Screenshot, "F12"),
+    (Hotkey::Hud, "F3"),
+    (Hotkey::Changes, "F4"),
+    (Hotkey::TimingGraph, "F6"),
+];
+
+/// Bindings of the `left-handed` profile, on the navigation keys right of the letters
+/// so the left hand can stay on the keypad keys.
+const LEFT_HANDED: [(Hotkey, &str); 13] = [
+    (Hotkey::Pause, "Backspace"),
+    (Hotkey::Fullscreen, "Home"),
+    (Hotkey::NextDisplay, "Shift+Home"),
+    (Hotkey::Switcher, "Insert"),
+    (Hotkey::RecordMacro, "End"),
+    (Hotkey::Reset, "Delete"),
+    (Hotkey::SaveState, "PageUp"),
+    (Hotkey::LoadState, "PageDown"),
+    (Hotkey::FastForward, "Return"),
+    (Hotkey::Screenshot, "PrintScreen"),
+    (Hotkey::Hud, "Shift+Insert"),
+    (Hotkey::Changes, "Shift+End"),
+    (Hotkey::TimingGraph, "Shift+Delete"),
+];
+
+/// Host key combination like `Ctrl+Shift+S`.
+struct Binding {
+    ctrl: bool,
+    shift: bool,
+    alt: bool,
+    /// Host key name, read like `Config::keys`
+    key: String,
+}
+
+impl Binding {
+    fn parse(text: &str) -> Option<Binding> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty())?;
+        let mut binding = Binding {
+            ctrl: false,
+            shift: false,
+            alt: false,
+            key: key.to_string(),
+        };
+        for modifier in parts {
+            match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" => binding.ctrl = true,
+                "shift" => binding.shift = true,
+                "alt" => binding.alt = true,
+                _ => return None,
+            }
+        }
+        Some(binding)
+    }
+}
+
+/// Hotkeys of the profile chosen in `Config::hotkeys`.
+pub struct Hotkeys {
+    bindings: Vec<(Hotkey, Binding)>,
+}
+
+impl Hotkeys {
+    /// Bindings of the configured profile. Actions a custom profile leaves out keep
+    /// their `default` binding; unknown profiles and invalid bindings are warned about.
+    pub fn new(config: &Config) -> Hotkeys {
+        let mut bindings: BTreeMap<Hotkey, String> = DEFAULT
+            .iter()
+            .map(|&(hotkey, binding)| (hotkey, binding.to_string()))
+            .collect();
+        match config.hotkeys.as_str() {
+            "default" => {}
+            "left-handed" => bindings.extend(
+                LEFT_HANDED
+                    .iter()
+                    .map(|&(hotkey, binding)| (hotkey, binding.to_string())),
+            ),
+            name => match config.hotkey_profiles.get(name) {
+                Some(profile) => bindings.extend(profile.clone()),
+                None => warn!(profile = name, "No such hotkey profile, using the default"),
+            },
+        }
+        let bindings = bindings
+            .into_iter()
+            .filter_map(|(hotkey, text)| match Binding::parse(&text) {
+                Some(binding) => Some((hotkey, binding)),
+                None => {
+                    warn!(binding = %text, "Ignoring invalid hotkey");
+                    None
+                }
+            })
+            .collect();
+        Hotkeys { bindings }
+    }
+
+    /// Action of a key press, if it is bound to one. Held keys repeating do not count.
+    pub fn pressed(&self, event: &Event, config: &Config) -> Option<Hotkey> {
+        let Event::KeyDown {
+            keycode,
+            scancode,
+            keymod,
+            repeat: false,
+            ..
+        } = *event
+        else {
+            return None;
+        };
+        let name = config.key_name(keycode, scancode)?;
+        let ctrl = keymod.intersects(Mod::LCTRLMOD | Mod::RCTRLMOD);
+        let shift = keymod.intersects(Mod::LSHIFTMOD | Mod::RSHIFTMOD);
+        let alt = keymod.intersects(Mod::LALTMOD | Mod::RALTMOD);
+        self.bindings
+            .iter()
+            .find(|(_, binding)| {
+                binding.key.eq_ignore_ascii_case(&name)
+                    && (binding.ctrl, binding.shift, binding.alt) == (ctrl, shift, alt)
+            })
+            .map(|&(hotkey, _)| hotkey)
+    }
+
+    /// Whether `event` releases the key of `hotkey`, whatever the modifiers.
+    pub fn released(&self, hotkey: Hotkey, event: &Event, config: &Config) -> bool {
+        let Event::KeyUp {
+            keycode, scancode, ..
+        } = *event
+        else {
+            return false;
+        };
+        let Some(name) = config.key_name(keycode, scancode) else {
+            return false;
+        };
+        self.bindings
+            .iter()
+            .any(|(bound, binding)| *bound == hotkey && binding.key.eq_ignore_ascii_case(&name))
+    }
+}
@@ -0,0 +1,59 @@
+use crate::i18n::tr;
+use crate::text::{Anchor, GREY, draw_label_scaled};
+use chip_8::Chip8;
+use sdl2::render::WindowCanvas;
+use std::fmt::Write;
+
+/// Font pixel size of the HUD, smaller than other overlays to keep the game visible.
+const HUD_SCALE: u32 = 2;
+
+/// Keys of the COSMAC VIP keypad, row by row as laid out on it.
+pub const KEYPAD: [[usize; 4]; 4] = [
+    [1, 2, 3, 0xC],
+    [4, 5, 6, 0xD],
+    [7, 8, 9, 0xE],
+    [0xA, 0, 0xB, 0xF],
+];
+
+/// Registers, I, PC, SP, the timers and the keypad in the top-right corner,
+/// for debugging at a glance.
+pub fn draw(canvas: &mut WindowCanvas, chip8: &Chip8) {
+    let mut text = String::new();
+    for (row, values) in chip8.registers.chunks(4).enumerate() {
+        for (n, value) in values.iter().enumerate() {
+            let separator = if n == 0 { "" } else { " " };
+            write!(text, "{}V{:X} {:02X}", separator, row * 4 + n, value).unwrap();
+        }
+        text.push('\n');
+    }
+    writeln!(
+        text,
+        "I {:03X}  PC {:03X}  SP {:X}",
+        chip8.i, chip8.pc, chip8.sp
+    )
+    .unwrap();
+    writeln!(text, "DT {:02X}  ST {:02X}", chip8.dt, chip8.st).unwrap();
+    text += &keypad(chip8);
+    draw_label_scaled(canvas, &text, Anchor::TopRight, HUD_SCALE, GREY);
+}
+
+/// The keypad as four lines of text, held keys by their digit and the others as `.`,
+/// and a fifth line while FX0A waits for a key.
+pub fn keypad(chip8: &Chip8) -> String {
+    let mut text = String::new();
+    for row in KEYPAD {
+        for key in row {
+            if chip8.keypad[key] > 0 {
+                write!(text, " {:X} ", key).unwrap();
+            } else {
+                text += " . ";
+            }
+        }
+        text.push('\n');
+    }
+    if chip8.waiting_for_key() {
+        text += tr("hud.waiting_for_key");
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,132 @@
+use clap::Command;
+use std::collections::HashMap;
+use std::env;
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// Translations built in, by language code, see `lang/en.toml`.
+const CATALOGS: &[(&str, &str)] = &[("en", include_str!("lang/en.toml"))];
+
+/// Texts of the chosen language with English filling the gaps, by dotted key like `menu.paused`.
+static TEXTS: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Choose the language: `lang` if given, else the one of the `LC_ALL`, `LC_MESSAGES`
+/// or `LANG` locale, else English. Does nothing once a text was looked up.
+pub fn init(lang: Option<&str>) {
+    let _ = TEXTS.set(load(lang));
+}
+
+/// Whether there is a translation for `lang`, like `de` or `pt_BR`.
+pub fn available(lang: &str) -> bool {
+    catalog(lang).is_some()
+}
+
+/// Value of `--lang` on the command line, needed before the command line is parsed
+/// so its help can be translated.
+pub fn lang_arg() -> Option<String> {
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        } else if arg == "--lang" {
+            return args.next();
+        } else if let Some(lang) = arg.strip_prefix("--lang=") {
+            return Some(lang.to_string());
+        }
+    }
+    None
+}
+
+/// Text of `key` in the chosen language, or the key itself when no catalog has it.
+pub fn tr(key: &'static str) -> &'static str {
+    TEXTS
+        .get_or_init(|| load(None))
+        .get(key)
+        .map_or(key, String::as_str)
+}
+
+/// Text of `key` with its `{name}` placeholders filled in.
+pub fn tr_with(key: &'static str, args: &[(&str, &dyn Display)]) -> String {
+    args.iter()
+        .fold(tr(key).to_string(), |text, (name, value)| {
+            text.replace(&format!("{{{}}}", name), &value.to_string())
+        })
+}
+
+/// Replace the help of `command` and its subcommands with the translated one, where there is one.
+pub fn localize(command: Command) -> Command {
+    localize_at(command, "cli")
+}
+
+fn localize_at(mut command: Command, path: &str) -> Command {
+    let texts = TEXTS.get_or_init(|| load(None));
+    if let Some(about) = texts.get(&format!("{}.about", path)) {
+        command = command.about(about.as_str());
+    }
+    let args: Vec<String> = command
+        .get_arguments()
+        .map(|arg| arg.get_id().to_string())
+        .collect();
+    for id in args {
+        if let Some(help) = texts.get(&format!("{}.{}", path, id)) {
+            command = command.mut_arg(id, |arg| arg.help(help.as_str()));
+        }
+    }
+    let subcommands: Vec<String> = command
+        .get_subcommands()
+        .map(|subcommand| subcommand.get_name().to_string())
+        .collect();
+    for name in subcommands {
+        let path = format!("{}.{}", path, name);
+        command = command.mut_subcommand(name, |subcommand| localize_at(subcommand, &path));
+    }
+    command
+}
+
+fn load(lang: Option<&str>) -> HashMap<String, String> {
+    let mut texts = HashMap::new();
+    flatten(&parse(CATALOGS[0].1), "", &mut texts);
+    let lang = lang.map(str::to_string).or_else(|| {
+        ["LC_ALL", "LC_MESSAGES", "LANG"]
+            .iter()
+            .find_map(|var| env::var(var).ok().filter(|value| !value.is_empty()))
+    });
+    if let Some(catalog) = lang.as_deref().and_then(catalog) {
+        flatten(&parse(catalog), "", &mut texts);
+    }
+    texts
+}
+
+/// Catalog for a language or locale name, e.g. `de_DE.UTF-8` falls back to `de`.
+fn catalog(lang: &str) -> Option<&'static str> {
+    let lang = lang
+        .split(['.', '@'])
+        .next()
+        .unwrap_or(lang)
+        .replace('-', "_");
+    let language = lang.split('_').next().unwrap_or(&lang);
+    [lang.as_str(), language].into_iter().find_map(|code| {
+        CATALOGS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(code))
+            .map(|(_, catalog)| *catalog)
+    })
+}
+
+fn parse(catalog: &str) -> toml::Table {
+    toml::from_str(catalog).expect("Built-in catalogs should be valid TOML")
+}
+
+/// Add the texts of `table` under dotted keys starting with `prefix`.
+fn flatten(table: &toml::Table, prefix: &str, texts: &mut HashMap<String, String>) {
+    for (name, value) in table {
+        let key = format!("{}{}", prefix, name);
+        match value {
+            toml::Value::String(text) => {
+                texts.insert(key, text.clone());
+            }
+            toml::Value::Table(table) => flatten(table, &format!("{}.", key), texts),
+            _ => {}
+        }
+    }
+}
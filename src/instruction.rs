@@ -0,0 +1,295 @@
+/// Decoded CHIP-8 instruction with its operands extracted.
+///
+/// `x` and `y` are register numbers, `nn` an 8-bit immediate,
+/// `nnn` a 12-bit address and `n` a 4-bit sprite height.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    /// `00E0`
+    Cls,
+    /// `00EE`
+    Ret,
+    /// `1NNN`
+    Jump { nnn: u16 },
+    /// `2NNN`
+    Call { nnn: u16 },
+    /// `3XNN`
+    SkipEqImm { x: u8, nn: u8 },
+    /// `4XNN`
+    SkipNeImm { x: u8, nn: u8 },
+    /// `5XY0`
+    SkipEq { x: u8, y: u8 },
+    /// `6XNN`
+    LoadImm { x: u8, nn: u8 },
+    /// `7XNN`
+    AddImm { x: u8, nn: u8 },
+    /// `8XY0`
+    Load { x: u8, y: u8 },
+    /// `8XY1`
+    Or { x: u8, y: u8 },
+    /// `8XY2`
+    And { x: u8, y: u8 },
+    /// `8XY3`
+    Xor { x: u8, y: u8 },
+    /// `8XY4`
+    Add { x: u8, y: u8 },
+    /// `8XY5`
+    Sub { x: u8, y: u8 },
+    /// `8XY6`
+    ShiftRight { x: u8, y: u8 },
+    /// `8XY7`
+    SubN { x: u8, y: u8 },
+    /// `8XYE`
+    ShiftLeft { x: u8, y: u8 },
+    /// `9XY0`
+    SkipNe { x: u8, y: u8 },
+    /// `ANNN`
+    LoadI { nnn: u16 },
+    /// `BNNN`, `x` is only used with the `jump_uses_vx` quirk
+    JumpOffset { x: u8, nnn: u16 },
+    /// `CXNN`
+    Random { x: u8, nn: u8 },
+    /// `DXYN`
+    Draw { x: u8, y: u8, n: u8 },
+    /// `EX9E`
+    SkipKey { x: u8 },
+    /// `EXA1`
+    SkipNoKey { x: u8 },
+    /// `FX07`
+    LoadDelay { x: u8 },
+    /// `FX0A`
+    WaitKey { x: u8 },
+    /// `FX15`
+    SetDelay { x: u8 },
+    /// `FX18`
+    SetSound { x: u8 },
+    /// `FX1E`
+    AddI { x: u8 },
+    /// `FX29`
+    LoadFont { x: u8 },
+    /// `FX33`
+    Bcd { x: u8 },
+    /// `FX55`
+    Store { x: u8 },
+    /// `FX65`
+    LoadMem { x: u8 },
+    /// Anything that is not an instruction
+    Data(u16),
+}
+
+/// What an instruction does, for references like `chip-8 ops`.
+pub struct Reference {
+    /// Opcode with its operands as letters, e.g. `8XY6`
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub semantics: &'static str,
+    /// Quirks that change the instruction, with what they change it to
+    pub quirks: &'static [(&'static str, &'static str)],
+}
+
+impl Instruction {
+    /// An opcode of every instruction the interpreter runs, in opcode order.
+    pub const EXAMPLES: [u16; 34] = [
+        0x00E0, 0x00EE, 0x1000, 0x2000, 0x3000, 0x4000, 0x5000, 0x6000, 0x7000, 0x8000, 0x8001,
+        0x8002, 0x8003, 0x8004, 0x8005, 0x8006, 0x8007, 0x800E, 0x9000, 0xA000, 0xB000, 0xC000,
+        0xD000, 0xE09E, 0xE0A1, 0xF007, 0xF00A, 0xF015, 0xF018, 0xF01E, 0xF029, 0xF033, 0xF055,
+        0xF065,
+    ];
+
+    /// Reference of the instruction, `None` for `Data`.
+    pub fn reference(&self) -> Option<Reference> {
+        let (pattern, mnemonic, semantics, quirks): (_, _, _, &[(&str, &str)]) = match self {
+            Instruction::Cls => ("00E0", "CLS", "Clear the display", &[]),
+            Instruction::Ret => ("00EE", "RET", "Return from a subroutine: PC = pop()", &[]),
+            Instruction::Jump { .. } => ("1NNN", "JMP NNN", "PC = NNN", &[]),
+            Instruction::Call { .. } => ("2NNN", "CALL NNN", "push(PC), PC = NNN", &[]),
+            Instruction::SkipEqImm { .. } => ("3XNN", "SE Vx, NN", "Skip if Vx == NN", &[]),
+            Instruction::SkipNeImm { .. } => ("4XNN", "SNE Vx, NN", "Skip if Vx != NN", &[]),
+            Instruction::SkipEq { .. } => ("5XY0", "SE Vx, Vy", "Skip if Vx == Vy", &[]),
+            Instruction::LoadImm { .. } => ("6XNN", "LD Vx, NN", "Vx = NN", &[]),
+            Instruction::AddImm { .. } => ("7XNN", "ADD Vx, NN", "Vx = Vx + NN, VF unchanged", &[]),
+            Instruction::Load { .. } => ("8XY0", "LD Vx, Vy", "Vx = Vy", &[]),
+            Instruction::Or { .. } => (
+                "8XY1",
+                "OR Vx, Vy",
+                "Vx = Vx | Vy",
+                &[("vf_reset", "VF = 0 afterwards")],
+            ),
+            Instruction::And { .. } => (
+                "8XY2",
+                "AND Vx, Vy",
+                "Vx = Vx & Vy",
+                &[("vf_reset", "VF = 0 afterwards")],
+            ),
+            Instruction::Xor { .. } => (
+                "8XY3",
+                "XOR Vx, Vy",
+                "Vx = Vx ^ Vy",
+                &[("vf_reset", "VF = 0 afterwards")],
+            ),
+            Instruction::Add { .. } => ("8XY4", "ADD Vx, Vy", "Vx = Vx + Vy, VF = carry", &[]),
+            Instruction::Sub { .. } => ("8XY5", "SUB Vx, Vy", "Vx = Vx - Vy, VF = no borrow", &[]),
+            Instruction::ShiftRight { .. } => (
+                "8XY6",
+                "SHR Vx, Vy",
+                "Vx = Vx >> 1, VF = bit shifted out",
+                &[("shift_uses_vy", "Vx = Vy >> 1")],
+            ),
+            Instruction::SubN { .. } => {
+                ("8XY7", "SUBN Vx, Vy", "Vx = Vy - Vx, VF = no borrow", &[])
+            }
+            Instruction::ShiftLeft { .. } => (
+                "8XYE",
+                "SHL Vx, Vy",
+                "Vx = Vx << 1, VF = bit shifted out",
+                &[("shift_uses_vy", "Vx = Vy << 1")],
+            ),
+            Instruction::SkipNe { .. } => ("9XY0", "SNE Vx, Vy", "Skip if Vx != Vy", &[]),
+            Instruction::LoadI { .. } => ("ANNN", "LD I, NNN", "I = NNN", &[]),
+            Instruction::JumpOffset { .. } => (
+                "BNNN",
+                "JMP V0, NNN",
+                "PC = V0 + NNN",
+                &[("jump_uses_vx", "PC = Vx + NNN")],
+            ),
+            Instruction::Random { .. } => (
+                "CXNN",
+                "RND Vx, NN",
+                "Vx = random byte & NN",
+                &[(
+                    "vip_random",
+                    "byte from a frame-dependent shift register, VIP-style",
+                )],
+            ),
+            Instruction::Draw { .. } => (
+                "DXYN",
+                "DRW Vx, Vy, N",
+                "XOR the N-byte sprite at I onto the display at (Vx, Vy), wrapping around; \
+                 VF = 1 if a pixel was turned off",
+                &[
+                    (
+                        "clip_sprites",
+                        "cut off at the display edges instead of wrapping",
+                    ),
+                    (
+                        "wrap_memory",
+                        "addresses past 0xFFF wrap to 0 instead of faulting",
+                    ),
+                ],
+            ),
+            Instruction::SkipKey { .. } => ("EX9E", "SKP Vx", "Skip if key Vx is down", &[]),
+            Instruction::SkipNoKey { .. } => ("EXA1", "SKNP Vx", "Skip if key Vx is up", &[]),
+            Instruction::LoadDelay { .. } => ("FX07", "LD Vx, DT", "Vx = DT", &[]),
+            Instruction::WaitKey { .. } => (
+                "FX0A",
+                "LD Vx, KEY",
+                "Wait for a key press and release, Vx = key",
+                &[],
+            ),
+            Instruction::SetDelay { .. } => ("FX15", "LD DT, Vx", "DT = Vx", &[]),
+            Instruction::SetSound { .. } => (
+                "FX18",
+                "LD ST, Vx",
+                "ST = Vx, beep until ST is 0; below 2 (MIN_BEEP_TICKS) stays silent",
+                &[],
+            ),
+            Instruction::AddI { .. } => (
+                "FX1E",
+                "ADD I, Vx",
+                "I = I + Vx, VF unchanged",
+                &[("add_i_sets_vf", "VF = 1 when I + Vx passes 0xFFF, else 0")],
+            ),
+            Instruction::LoadFont { .. } => (
+                "FX29",
+                "LD I, FONT(Vx)",
+                "I = address of the font sprite of digit Vx",
+                &[],
+            ),
+            Instruction::Bcd { .. } => (
+                "FX33",
+                "BCD Vx",
+                "RAM[I..I+3] = hundreds, tens and ones of Vx",
+                &[(
+                    "wrap_memory",
+                    "addresses past 0xFFF wrap to 0 instead of faulting",
+                )],
+            ),
+            Instruction::Store { .. } => (
+                "FX55",
+                "LD [I], Vx",
+                "RAM[I..=I+X] = V0..=Vx",
+                &[
+                    ("memory_increments_i", "then I = I + X + 1"),
+                    (
+                        "wrap_memory",
+                        "addresses past 0xFFF wrap to 0 instead of faulting",
+                    ),
+                ],
+            ),
+            Instruction::LoadMem { .. } => (
+                "FX65",
+                "LD Vx, [I]",
+                "V0..=Vx = RAM[I..=I+X]",
+                &[
+                    ("memory_increments_i", "then I = I + X + 1"),
+                    (
+                        "wrap_memory",
+                        "addresses past 0xFFF wrap to 0 instead of faulting",
+                    ),
+                ],
+            ),
+            Instruction::Data(_) => return None,
+        };
+        Some(Reference {
+            pattern,
+            mnemonic,
+            semantics,
+            quirks,
+        })
+    }
+
+    pub fn decode(opcode: u16) -> Instruction {
+        let x = ((opcode & 0x0F00) >> 8) as u8;
+        let y = ((opcode & 0x00F0) >> 4) as u8;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+
+        match ((opcode & 0xF000) >> 12, x, y, n) {
+            (0, 0, 0xE, 0) => Instruction::Cls,
+            (0, 0, 0xE, 0xE) => Instruction::Ret,
+            (1, _, _, _) => Instruction::Jump { nnn },
+            (2, _, _, _) => Instruction::Call { nnn },
+            (3, _, _, _) => Instruction::SkipEqImm { x, nn },
+            (4, _, _, _) => Instruction::SkipNeImm { x, nn },
+            (5, _, _, 0) => Instruction::SkipEq { x, y },
+            (6, _, _, _) => Instruction::LoadImm { x, nn },
+            (7, _, _, _) => Instruction::AddImm { x, nn },
+            (8, _, _, 0) => Instruction::Load { x, y },
+            (8, _, _, 1) => Instruction::Or { x, y },
+            (8, _, _, 2) => Instruction::And { x, y },
+            (8, _, _, 3) => Instruction::Xor { x, y },
+            (8, _, _, 4) => Instruction::Add { x, y },
+            (8, _, _, 5) => Instruction::Sub { x, y },
+            (8, _, _, 6) => Instruction::ShiftRight { x, y },
+            (8, _, _, 7) => Instruction::SubN { x, y },
+            (8, _, _, 0xE) => Instruction::ShiftLeft { x, y },
+            (9, _, _, 0) => Instruction::SkipNe { x, y },
+            (0xA, _, _, _) => Instruction::LoadI { nnn },
+            (0xB, _, _, _) => Instruction::JumpOffset { x, nnn },
+            (0xC, _, _, _) => Instruction::Random { x, nn },
+            (0xD, _, _, _) => Instruction::Draw { x, y, n },
+            (0xE, _, 9, 0xE) => Instruction::SkipKey { x },
+            (0xE, _, 0xA, 1) => Instruction::SkipNoKey { x },
+            (0xF, _, 0, 7) => Instruction::LoadDelay { x },
+            (0xF, _, 0, 0xA) => Instruction::WaitKey { x },
+            (0xF, _, 1, 5) => Instruction::SetDelay { x },
+            (0xF, _, 1, 8) => Instruction::SetSound { x },
+            (0xF, _, 1, 0xE) => Instruction::AddI { x },
+            (0xF, _, 2, 9) => Instruction::LoadFont { x },
+            (0xF, _, 3, 3) => Instruction::Bcd { x },
+            (0xF, _, 5, 5) => Instruction::Store { x },
+            (0xF, _, 6, 5) => Instruction::LoadMem { x },
+            _ => Instruction::Data(opcode),
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use crate::cartridge::invalid;
+use std::io;
+
+/// Patches start with this.
+const HEADER: &[u8] = b"PATCH";
+
+/// Patches end with this, where the next record's offset would be.
+const FOOTER: &[u8] = b"EOF";
+
+/// Apply an IPS patch to `rom`, the format ROM hacking communities share fixes and
+/// translations in without handing out the ROM itself.
+///
+/// A patch is `PATCH`, then records of a 3-byte offset into the ROM and a 2-byte length,
+/// both big endian, followed by that many bytes to write there. A length of 0 is a run
+/// instead: a 2-byte count and the byte to repeat. `EOF` ends the records, optionally
+/// followed by a 3-byte length to truncate the ROM to. Records past the end grow the ROM,
+/// zero-filling any gap.
+pub fn apply(rom: &mut Vec<u8>, patch: &[u8]) -> io::Result<()> {
+    let mut rest = patch
+        .strip_prefix(HEADER)
+        .ok_or_else(|| invalid("not an IPS patch"))?;
+    loop {
+        if let Some(footer) = rest.strip_prefix(FOOTER) {
+            match *footer {
+                [] => return Ok(()),
+                [_, _, _] => {
+                    rom.truncate(number(&mut &footer[..], 3)?);
+                    return Ok(());
+                }
+                _ => return Err(invalid("unexpected bytes after EOF")),
+            }
+        }
+        let offset = number(&mut rest, 3)?;
+        let bytes = match number(&mut rest, 2)? {
+            0 => {
+                let count = number(&mut rest, 2)?;
+                vec![take(&mut rest, 1)?[0]; count]
+            }
+            len => take(&mut rest, len)?.to_vec(),
+        };
+        if rom.len() < offset + bytes.len() {
+            rom.resize(offset + bytes.len(), 0);
+        }
+        rom[offset..offset + bytes.len()].copy_from_slice(&bytes);
+    }
+}
+
+/// Split `len` bytes off the front of `rest`.
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> io::Result<&'a [u8]> {
+    if rest.len() < len {
+        return Err(invalid("truncated IPS patch"));
+    }
+    let (taken, remaining) = rest.split_at(len);
+    *rest = remaining;
+    Ok(taken)
+}
+
+/// Big endian number of `len` bytes split off the front of `rest`.
+fn number(rest: &mut &[u8], len: usize) -> io::Result<usize> {
+    Ok(take(rest, len)?
+        .iter()
+        .fold(0, |number, &byte| number << 8 | byte as usize))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `PATCH`, `records` and `EOF`.
+    fn patch(records: &[&[u8]]) -> Vec<u8> {
+        [HEADER, &records.concat(), FOOTER].concat()
+    }
+
+    fn patched(rom: &[u8], patch: &[u8]) -> io::Result<Vec<u8>> {
+        let mut rom = rom.to_vec();
+        apply(&mut rom, patch).map(|()| rom)
+    }
+
+    #[test]
+    fn records_write_their_bytes_at_their_offset() {
+        let patch = patch(&[&[0x00, 0x00, 0x01, 0x00, 0x02, 0xAA, 0xBB]]);
+        assert_eq!(patched(&[1, 2, 3, 4], &patch).unwrap(), [1, 0xAA, 0xBB, 4]);
+    }
+
+    #[test]
+    fn runs_repeat_one_byte() {
+        let patch = patch(&[&[0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03, 0xEE]]);
+        assert_eq!(
+            patched(&[1, 2, 3, 4, 5], &patch).unwrap(),
+            [1, 0xEE, 0xEE, 0xEE, 5]
+        );
+    }
+
+    #[test]
+    fn records_past_the_end_grow_the_rom() {
+        let patch = patch(&[&[0x00, 0x00, 0x04, 0x00, 0x01, 0x77]]);
+        assert_eq!(patched(&[1, 2], &patch).unwrap(), [1, 2, 0, 0, 0x77]);
+    }
+
+    #[test]
+    fn a_length_after_eof_truncates_the_rom() {
+        let mut patch = patch(&[&[0x00, 0x00, 0x00, 0x00, 0x01, 0x09]]);
+        patch.extend_from_slice(&[0x00, 0x00, 0x02]);
+        assert_eq!(patched(&[1, 2, 3, 4], &patch).unwrap(), [9, 2]);
+    }
+
+    #[test]
+    fn broken_patches_are_refused_as_invalid_data() {
+        let truncated = [HEADER, &[0x00, 0x00, 0x01, 0x00, 0x04, 0xAA]].concat();
+        for (patch, message) in [
+            (&truncated[..], "truncated IPS patch"),
+            (&b"IPS\x00\x00\x00\x00\x01\x01EOF"[..], "not an IPS patch"),
+            (&b"PATCHEOF\x01"[..], "unexpected bytes after EOF"),
+        ] {
+            let err = patched(&[0; 4], patch).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+            assert_eq!(err.to_string(), message);
+        }
+    }
+}
@@ -0,0 +1,601 @@
+//! Experimental native code backend, built with the `jit` feature.
+//!
+//! Runs of register-only instructions (loads, ALU ops, timers and `I` arithmetic)
+//! are compiled with Cranelift into one native function per basic block.
+//! Control flow, skips, drawing, RND, keys and memory access stay with the
+//! interpreter, which also runs the tail of a block that does not fit the cycle budget.
+
+use crate::chip8::{Chip8, Chip8Error, Quirks, StepResult};
+use crate::instruction::Instruction;
+use cranelift_codegen::ir::{AbiParam, InstBuilder, MemFlagsData, Value, condcodes::IntCC, types};
+use cranelift_codegen::isa::OwnedTargetIsa;
+use cranelift_codegen::settings::{self, Configurable};
+use cranelift_frontend::{FuncInstBuilder, FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Module, default_libcall_names};
+use std::collections::HashMap;
+use std::mem::{self, ManuallyDrop, offset_of};
+use tracing::debug;
+
+/// Longest block compiled, in instructions.
+const MAX_BLOCK: usize = 64;
+
+/// Functions compiled before all of them are thrown away and their memory freed,
+/// so self-modifying programs cannot grow the code without limit.
+const MAX_COMPILED: usize = 1024;
+
+/// Machine state seen by compiled code.
+#[repr(C)]
+struct JitState {
+    v: [u8; 16],
+    i: u16,
+    dt: u8,
+    st: u8,
+}
+
+type BlockFn = unsafe extern "C" fn(*mut JitState);
+
+/// Compiled code for the instructions starting at one address.
+struct Block {
+    /// RAM contents the block was compiled from, checked before every run
+    /// so self-modifying writes invalidate it
+    source: Vec<u8>,
+
+    /// Instructions covered, `0` when the first one is left to the interpreter
+    len: u32,
+
+    func: Option<BlockFn>,
+}
+
+pub struct Jit {
+    isa: OwnedTargetIsa,
+
+    /// Holds the code of `compiled`, freed on `flush` and drop
+    module: ManuallyDrop<JITModule>,
+    builder_context: FunctionBuilderContext,
+
+    /// Block starting at every address
+    blocks: Vec<Option<Block>>,
+
+    /// Function compiled for every instruction sequence, by its bytes,
+    /// shared by blocks holding the same code
+    compiled: HashMap<Vec<u8>, BlockFn>,
+
+    /// Quirks the blocks were compiled with
+    quirks: Quirks,
+}
+
+impl Jit {
+    pub fn new() -> Result<Jit, String> {
+        let mut flags = settings::builder();
+        flags
+            .set("use_colocated_libcalls", "false")
+            .map_err(|err| err.to_string())?;
+        flags
+            .set("is_pic", "false")
+            .map_err(|err| err.to_string())?;
+        let isa = cranelift_native::builder()?
+            .finish(settings::Flags::new(flags))
+            .map_err(|err| err.to_string())?;
+        Ok(Jit {
+            module: ManuallyDrop::new(module(&isa)),
+            isa,
+            builder_context: FunctionBuilderContext::new(),
+            blocks: (0..4096).map(|_| None).collect(),
+            compiled: HashMap::new(),
+            quirks: Quirks::default(),
+        })
+    }
+
+    /// Forget every block and free the code compiled for them.
+    fn flush(&mut self) {
+        self.blocks.iter_mut().for_each(|block| *block = None);
+        self.compiled.clear();
+        let old = mem::replace(&mut *self.module, module(&self.isa));
+        // SAFETY: no compiled code is running and no pointer into it is left.
+        unsafe { old.free_memory() };
+        debug!("Compiled code freed");
+    }
+
+    /// Execute `cycles` instructions, like as many `Chip8::cycle` calls.
+    /// Returns the result of the last one.
+    pub fn run(&mut self, chip8: &mut Chip8, cycles: u32) -> Result<StepResult, Chip8Error> {
+        if chip8.quirks != self.quirks {
+            self.flush();
+            self.quirks = chip8.quirks;
+        }
+
+        let mut result = StepResult::Executed;
+        let mut left = cycles;
+        while left > 0 {
+            let pc = chip8.pc as usize;
+            let block = self.block(chip8, pc);
+            match block.func {
+                Some(func) if block.len <= left => {
+                    let mut state = JitState {
+                        v: chip8.registers,
+                        i: chip8.i,
+                        dt: chip8.dt,
+                        st: chip8.st,
+                    };
+                    // SAFETY: compiled from `compile` for exactly this signature,
+                    // the code only touches the `JitState` it is given.
+                    unsafe { func(&mut state) };
+                    chip8.registers = state.v;
+                    chip8.i = state.i;
+                    chip8.dt = state.dt;
+                    chip8.set_sound_timer(state.st);
+
+                    let last = pc + 2 * (block.len as usize - 1);
+                    chip8.opcode =
+                        (chip8.bus.ram[last] as u16) << 8 | chip8.bus.ram[last + 1] as u16;
+                    chip8.pc += 2 * block.len as u16;
+                    left -= block.len;
+                    result = StepResult::Executed;
+                }
+                _ => {
+                    result = chip8.cycle()?;
+                    left -= 1;
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Block at `pc`, compiled now when missing or stale.
+    fn block(&mut self, chip8: &Chip8, pc: usize) -> &Block {
+        let stale = match &self.blocks[pc] {
+            Some(block) => {
+                chip8.bus.ram.get(pc..pc + block.source.len()) != Some(&block.source[..])
+            }
+            None => true,
+        };
+        if stale {
+            let block = self.compile(chip8, pc);
+            self.blocks[pc] = Some(block);
+        }
+        self.blocks[pc].as_ref().unwrap()
+    }
+
+    fn compile(&mut self, chip8: &Chip8, pc: usize) -> Block {
+        let mut instructions = Vec::new();
+        let mut addr = pc;
+        while instructions.len() < MAX_BLOCK && addr + 1 < chip8.bus.ram.len() {
+            let opcode = (chip8.bus.ram[addr] as u16) << 8 | chip8.bus.ram[addr + 1] as u16;
+            let instruction = Instruction::decode(opcode);
+            if !compilable(instruction) {
+                break;
+            }
+            instructions.push(instruction);
+            addr += 2;
+            // ST reaches the machine after the block, so one FX18 per block keeps every beep.
+            if matches!(instruction, Instruction::SetSound { .. }) {
+                break;
+            }
+        }
+        if instructions.is_empty() {
+            return Block {
+                source: chip8.bus.ram[pc..(pc + 2).min(chip8.bus.ram.len())].to_vec(),
+                len: 0,
+                func: None,
+            };
+        }
+
+        let source = chip8.bus.ram[pc..addr].to_vec();
+        let func = match self.compiled.get(&source) {
+            Some(&func) => Some(func),
+            None => {
+                if self.compiled.len() >= MAX_COMPILED {
+                    self.flush();
+                }
+                match self.emit(&instructions) {
+                    Ok(func) => {
+                        debug!(pc, len = instructions.len(), "Block compiled");
+                        self.compiled.insert(source.clone(), func);
+                        Some(func)
+                    }
+                    Err(err) => {
+                        debug!(pc, %err, "Block left to the interpreter");
+                        None
+                    }
+                }
+            }
+        };
+        Block {
+            source,
+            len: instructions.len() as u32,
+            func,
+        }
+    }
+
+    fn emit(&mut self, instructions: &[Instruction]) -> Result<BlockFn, String> {
+        let pointer = self.module.target_config().pointer_type();
+        let mut ctx = self.module.make_context();
+        ctx.func.signature.params.push(AbiParam::new(pointer));
+
+        let mut builder = FunctionBuilder::new(&mut ctx.func, &mut self.builder_context);
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        builder.switch_to_block(entry);
+        let mut emitter = Emitter {
+            state: builder.block_params(entry)[0],
+            builder,
+            quirks: self.quirks,
+        };
+        for &instruction in instructions {
+            emitter.instruction(instruction);
+        }
+        let mut builder = emitter.builder;
+        builder.ins().return_(&[]);
+        builder.seal_all_blocks();
+        builder.finalize(self.module.target_config());
+
+        let id = self
+            .module
+            .declare_anonymous_function(&ctx.func.signature)
+            .map_err(|err| err.to_string())?;
+        self.module
+            .define_function(id, &mut ctx)
+            .map_err(|err| err.to_string())?;
+        self.module.clear_context(&mut ctx);
+        self.module
+            .finalize_definitions()
+            .map_err(|err| err.to_string())?;
+        let code = self.module.get_finalized_function(id);
+        // SAFETY: the function was declared with a single pointer parameter and no return value.
+        Ok(unsafe { mem::transmute::<*const u8, BlockFn>(code) })
+    }
+}
+
+impl Drop for Jit {
+    fn drop(&mut self) {
+        // SAFETY: the module is not used again, and no compiled code runs past `run`.
+        unsafe { ManuallyDrop::take(&mut self.module).free_memory() };
+    }
+}
+
+/// Module compiling for `isa`.
+fn module(isa: &OwnedTargetIsa) -> JITModule {
+    JITModule::new(JITBuilder::with_isa(isa.clone(), default_libcall_names()))
+}
+
+/// Instructions that only touch registers, `I` and the timers, and never change PC.
+fn compilable(instruction: Instruction) -> bool {
+    matches!(
+        instruction,
+        Instruction::LoadImm { .. }
+            | Instruction::AddImm { .. }
+            | Instruction::Load { .. }
+            | Instruction::Or { .. }
+            | Instruction::And { .. }
+            | Instruction::Xor { .. }
+            | Instruction::Add { .. }
+            | Instruction::Sub { .. }
+            | Instruction::ShiftRight { .. }
+            | Instruction::SubN { .. }
+            | Instruction::ShiftLeft { .. }
+            | Instruction::LoadI { .. }
+            | Instruction::AddI { .. }
+            | Instruction::LoadFont { .. }
+            | Instruction::LoadDelay { .. }
+            | Instruction::SetDelay { .. }
+            | Instruction::SetSound { .. }
+    )
+}
+
+/// Translates instructions into Cranelift IR working on a `JitState`.
+///
+/// Every instruction loads its operands and stores its results in the same
+/// order as the interpreter, so aliasing cases like `x == 0xF` behave alike.
+struct Emitter<'a> {
+    builder: FunctionBuilder<'a>,
+    state: Value,
+    quirks: Quirks,
+}
+
+impl Emitter<'_> {
+    fn instruction(&mut self, instruction: Instruction) {
+        match instruction {
+            Instruction::LoadImm { x, nn } => {
+                let nn = self.imm8(nn);
+                self.store_v(x, nn);
+            }
+            Instruction::AddImm { x, nn } => {
+                let vx = self.load_v(x);
+                let sum = self.builder.ins().iadd_imm_u(vx, nn as i64);
+                self.store_v(x, sum);
+            }
+            Instruction::Load { x, y } => {
+                let vy = self.load_v(y);
+                self.store_v(x, vy);
+            }
+            Instruction::Or { x, y } => self.logic(x, y, |ins, a, b| ins.bor(a, b)),
+            Instruction::And { x, y } => self.logic(x, y, |ins, a, b| ins.band(a, b)),
+            Instruction::Xor { x, y } => self.logic(x, y, |ins, a, b| ins.bxor(a, b)),
+            Instruction::Add { x, y } => {
+                let vx = self.load_v(x);
+                let vy = self.load_v(y);
+                let sum = self.builder.ins().iadd(vx, vy);
+                let carry = self.builder.ins().icmp(IntCC::UnsignedLessThan, sum, vx);
+                self.store_v(x, sum);
+                self.store_v(0xF, carry);
+            }
+            Instruction::Sub { x, y } => self.sub(x, x, y),
+            Instruction::SubN { x, y } => self.sub(x, y, x),
+            Instruction::ShiftRight { x, y } => {
+                self.shift_source(x, y);
+                let vx = self.load_v(x);
+                let bit = self.builder.ins().band_imm_u(vx, 1);
+                let shifted = self.builder.ins().ushr_imm_u(vx, 1);
+                self.store_v(x, shifted);
+                self.store_v(0xF, bit);
+            }
+            Instruction::ShiftLeft { x, y } => {
+                self.shift_source(x, y);
+                let vx = self.load_v(x);
+                let bit = self.builder.ins().ushr_imm_u(vx, 7);
+                let shifted = self.builder.ins().ishl_imm_u(vx, 1);
+                self.store_v(x, shifted);
+                self.store_v(0xF, bit);
+            }
+            Instruction::LoadI { nnn } => {
+                let nnn = self.builder.ins().iconst(types::I16, nnn as i64);
+                self.store_i(nnn);
+            }
+            Instruction::AddI { x } => {
+                // Summed in 32 bits so that the carry out of I shows.
+                let i = self.load_i();
+                let i = self.builder.ins().uextend(types::I32, i);
+                let vx = self.load_v(x);
+                let vx = self.builder.ins().uextend(types::I32, vx);
+                let sum = self.builder.ins().iadd(i, vx);
+                let i = self.builder.ins().ireduce(types::I16, sum);
+                self.store_i(i);
+                if self.quirks.add_i_sets_vf {
+                    let overflow =
+                        self.builder
+                            .ins()
+                            .icmp_imm_u(IntCC::UnsignedGreaterThan, sum, 0xFFF);
+                    self.store_v(0xF, overflow);
+                }
+            }
+            Instruction::LoadFont { x } => {
+                let vx = self.load_v(x);
+                let vx = self.builder.ins().uextend(types::I16, vx);
+                let addr = self.builder.ins().imul_imm_u(vx, 5);
+                self.store_i(addr);
+            }
+            Instruction::LoadDelay { x } => {
+                let dt = self.load(offset_of!(JitState, dt), types::I8);
+                self.store_v(x, dt);
+            }
+            Instruction::SetDelay { x } => {
+                let vx = self.load_v(x);
+                self.store(offset_of!(JitState, dt), vx);
+            }
+            Instruction::SetSound { x } => {
+                let vx = self.load_v(x);
+                self.store(offset_of!(JitState, st), vx);
+            }
+            _ => unreachable!("not compilable: {:?}", instruction),
+        }
+    }
+
+    /// `8XY1`, `8XY2` and `8XY3`
+    fn logic(
+        &mut self,
+        x: u8,
+        y: u8,
+        op: impl FnOnce(FuncInstBuilder<'_, '_>, Value, Value) -> Value,
+    ) {
+        let vx = self.load_v(x);
+        let vy = self.load_v(y);
+        let result = op(self.builder.ins(), vx, vy);
+        self.store_v(x, result);
+        if self.quirks.vf_reset {
+            let zero = self.imm8(0);
+            self.store_v(0xF, zero);
+        }
+    }
+
+    /// `Vx = Va - Vb`, VF set when nothing was borrowed.
+    fn sub(&mut self, x: u8, a: u8, b: u8) {
+        let va = self.load_v(a);
+        let vb = self.load_v(b);
+        let difference = self.builder.ins().isub(va, vb);
+        let no_borrow = self
+            .builder
+            .ins()
+            .icmp(IntCC::UnsignedGreaterThanOrEqual, va, vb);
+        self.store_v(x, difference);
+        self.store_v(0xF, no_borrow);
+    }
+
+    /// With the `shift_uses_vy` quirk, shifts start by copying Vy into Vx.
+    fn shift_source(&mut self, x: u8, y: u8) {
+        if self.quirks.shift_uses_vy {
+            let vy = self.load_v(y);
+            self.store_v(x, vy);
+        }
+    }
+
+    fn imm8(&mut self, value: u8) -> Value {
+        self.builder.ins().iconst(types::I8, value as i64)
+    }
+
+    fn load_v(&mut self, register: u8) -> Value {
+        self.load(offset_of!(JitState, v) + register as usize, types::I8)
+    }
+
+    fn store_v(&mut self, register: u8, value: Value) {
+        self.store(offset_of!(JitState, v) + register as usize, value);
+    }
+
+    fn load_i(&mut self) -> Value {
+        self.load(offset_of!(JitState, i), types::I16)
+    }
+
+    fn store_i(&mut self, value: Value) {
+        self.store(offset_of!(JitState, i), value);
+    }
+
+    fn load(&mut self, offset: usize, ty: types::Type) -> Value {
+        self.builder
+            .ins()
+            .load(ty, MemFlagsData::trusted(), self.state, offset as i32)
+    }
+
+    fn store(&mut self, offset: usize, value: Value) {
+        self.builder
+            .ins()
+            .store(MemFlagsData::trusted(), value, self.state, offset as i32);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::SoundEvent;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    /// Opcode templates, operands are filled in at random.
+    const TEMPLATES: [u16; 34] = [
+        0x00E0, 0x00EE, 0x1000, 0x2000, 0x3000, 0x4000, 0x5000, 0x6000, 0x7000, 0x8000, 0x8001,
+        0x8002, 0x8003, 0x8004, 0x8005, 0x8006, 0x8007, 0x800E, 0x9000, 0xA000, 0xB000, 0xC000,
+        0xD000, 0xE09E, 0xE0A1, 0xF007, 0xF00A, 0xF015, 0xF018, 0xF01E, 0xF029, 0xF033, 0xF055,
+        0xF065,
+    ];
+
+    /// Random program biased towards compilable instructions, jumps stay inside it.
+    fn program(rng: &mut ChaCha8Rng, len: u16) -> Vec<u8> {
+        let mut rom = Vec::new();
+        for _ in 0..len {
+            let template = if rng.gen_range(0..3) == 0 {
+                TEMPLATES[rng.gen_range(0..TEMPLATES.len())]
+            } else {
+                TEMPLATES[rng.gen_range(7..19)]
+            };
+            let opcode = match template & 0xF000 {
+                0x0000 => template,
+                0x1000 | 0x2000 | 0xB000 => template | (0x200 + rng.gen_range(0..len) * 2),
+                0x5000 | 0x8000 | 0x9000 => template | (rng.r#gen::<u16>() & 0x0FF0),
+                0xE000 | 0xF000 => template | (rng.gen_range(0..16) << 8),
+                // FX29, FX33, FX55 and FX65 address RAM through I, keep it low.
+                0xA000 => template | rng.gen_range(0..0x100),
+                _ => template | (rng.r#gen::<u16>() & 0x0FFF),
+            };
+            rom.extend_from_slice(&opcode.to_be_bytes());
+        }
+        rom
+    }
+
+    fn machine(rom: &[u8], seed: u64, quirks: Quirks) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.seed(seed);
+        chip8.quirks = quirks;
+        chip8.bus.ram[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        chip8
+    }
+
+    fn assert_same(jit: &mut Chip8, interpreter: &mut Chip8, context: &str) {
+        assert_eq!(
+            jit.registers, interpreter.registers,
+            "registers, {}",
+            context
+        );
+        assert_eq!(jit.pc, interpreter.pc, "pc, {}", context);
+        assert_eq!(jit.i, interpreter.i, "I, {}", context);
+        assert_eq!(jit.dt, interpreter.dt, "DT, {}", context);
+        assert_eq!(jit.st, interpreter.st, "ST, {}", context);
+        assert_eq!(jit.opcode, interpreter.opcode, "opcode, {}", context);
+        assert_eq!(jit.bus.ram, interpreter.bus.ram, "RAM, {}", context);
+        assert_eq!(jit.video, interpreter.video, "video, {}", context);
+        assert_eq!(
+            jit.take_sound_events(),
+            interpreter.take_sound_events(),
+            "sound events, {}",
+            context
+        );
+    }
+
+    #[test]
+    fn every_sound_timer_write_reaches_the_machine() {
+        // LD V0, 5; LD ST, V0; LD V1, 0; LD ST, V1
+        let rom = [0x60, 0x05, 0xF0, 0x18, 0x61, 0x00, 0xF1, 0x18];
+        let mut interpreter = machine(&rom, 0, Quirks::default());
+        let mut compiled = machine(&rom, 0, Quirks::default());
+        for _ in 0..4 {
+            interpreter.cycle().unwrap();
+        }
+        Jit::new().unwrap().run(&mut compiled, 4).unwrap();
+        let events = [SoundEvent::Started { ticks: 5 }, SoundEvent::Stopped];
+        assert_eq!(interpreter.take_sound_events(), events);
+        assert_eq!(compiled.take_sound_events(), events);
+    }
+
+    #[test]
+    fn identical_code_is_compiled_once_and_rewritten_code_is_capped() {
+        let block = [0x60, 0x01, 0x61, 0x02, 0x00, 0xE0];
+        let mut chip8 = machine(&block, 0, Quirks::default());
+        chip8.bus.ram[0x300..0x306].copy_from_slice(&block);
+        let mut jit = Jit::new().unwrap();
+        jit.run(&mut chip8, 2).unwrap();
+        chip8.pc = 0x300;
+        jit.run(&mut chip8, 2).unwrap();
+        assert_eq!(jit.compiled.len(), 1);
+
+        for n in 0..MAX_COMPILED as u16 + 10 {
+            chip8.bus.ram[0x200..0x202].copy_from_slice(&(0x6000 | n & 0xFF).to_be_bytes());
+            chip8.bus.ram[0x203] = (n >> 8) as u8;
+            chip8.pc = 0x200;
+            jit.run(&mut chip8, 2).unwrap();
+            assert_eq!(chip8.registers[0], n as u8);
+            assert_eq!(chip8.registers[1], (n >> 8) as u8);
+        }
+        assert!(jit.compiled.len() <= MAX_COMPILED);
+    }
+
+    /// Differential test: random programs must leave the JIT and the interpreter
+    /// in the same state after every batch of cycles, and halt on the same error.
+    #[test]
+    fn matches_interpreter() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0x8);
+        for program_seed in 0..300 {
+            let rom = program(&mut rng, 100);
+            let quirks = Quirks {
+                add_i_sets_vf: program_seed % 2 == 1,
+                ..Quirks::PRESETS[program_seed as usize % Quirks::PRESETS.len()].1
+            };
+            let mut interpreter = machine(&rom, program_seed, quirks);
+            let mut compiled = machine(&rom, program_seed, quirks);
+            let mut jit = Jit::new().unwrap();
+
+            for batch in 0..100 {
+                let key = rng.gen_range(0..16);
+                let pressed = rng.gen_range(0..2);
+                interpreter.set_key_value(key, pressed);
+                compiled.set_key_value(key, pressed);
+
+                let cycles = rng.gen_range(1..40);
+                let mut expected = Ok(StepResult::Executed);
+                for _ in 0..cycles {
+                    expected = interpreter.cycle();
+                    if expected.is_err() {
+                        break;
+                    }
+                }
+                let actual = jit.run(&mut compiled, cycles);
+                let context = format!("program {} batch {}", program_seed, batch);
+                assert_eq!(actual, expected, "{}", context);
+                assert_same(&mut compiled, &mut interpreter, &context);
+                if expected.is_err() {
+                    break;
+                }
+
+                interpreter.tick_timers();
+                compiled.tick_timers();
+            }
+        }
+    }
+}
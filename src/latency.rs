@@ -0,0 +1,83 @@
+use std::time::{Duration, Instant};
+use tracing::info;
+
+/// Samples between two interim reports.
+const REPORT_EVERY: usize = 20;
+
+/// Measures how long a host key press takes to reach the game and the screen.
+///
+/// Every press is timed from the host key event to the end of the first frame
+/// in which the program saw the key pressed, and from there to the next present.
+#[derive(Default)]
+pub struct LatencyMeter {
+    /// Host event time of keys not yet seen by the program
+    pressed: [Option<Instant>; 16],
+
+    /// Host event times of keys seen by the program, not yet presented
+    observed: Vec<Instant>,
+
+    to_observe: Vec<Duration>,
+    to_present: Vec<Duration>,
+}
+
+impl LatencyMeter {
+    pub fn key_down(&mut self, key: usize) {
+        self.pressed[key].get_or_insert_with(Instant::now);
+    }
+
+    /// Presses released before the program looked at them are not counted.
+    pub fn key_up(&mut self, key: usize) {
+        self.pressed[key] = None;
+    }
+
+    /// End of an emulated frame, `observed_keys` as from `Chip8::take_observed_keys`.
+    pub fn frame_emulated(&mut self, observed_keys: u16) {
+        let now = Instant::now();
+        for key in 0..16 {
+            if observed_keys & (1 << key) != 0
+                && let Some(pressed) = self.pressed[key].take()
+            {
+                self.to_observe.push(now - pressed);
+                self.observed.push(pressed);
+            }
+        }
+    }
+
+    pub fn presented(&mut self) {
+        let now = Instant::now();
+        for pressed in std::mem::take(&mut self.observed) {
+            self.to_present.push(now - pressed);
+            if self.to_present.len().is_multiple_of(REPORT_EVERY) {
+                self.report();
+            }
+        }
+    }
+
+    pub fn report(&self) {
+        if self.to_present.is_empty() {
+            info!("No input latency samples, press keys the game reacts to");
+            return;
+        }
+        let (observe_avg, observe_min, observe_max) = summary(&self.to_observe);
+        let (present_avg, present_min, present_max) = summary(&self.to_present);
+        info!(
+            samples = self.to_present.len(),
+            observe_avg_ms = observe_avg,
+            observe_min_ms = observe_min,
+            observe_max_ms = observe_max,
+            present_avg_ms = present_avg,
+            present_min_ms = present_min,
+            present_max_ms = present_max,
+            "Input latency"
+        );
+    }
+}
+
+/// Average, minimum and maximum in milliseconds.
+fn summary(samples: &[Duration]) -> (f64, f64, f64) {
+    let ms: Vec<f64> = samples.iter().map(|d| d.as_secs_f64() * 1000.0).collect();
+    let avg = ms.iter().sum::<f64>() / ms.len() as f64;
+    let min = ms.iter().copied().fold(f64::INFINITY, f64::min);
+    let max = ms.iter().copied().fold(0.0, f64::max);
+    (avg, min, max)
+}
@@ -0,0 +1,71 @@
+//! CHIP-8 interpreter core, independent of any frontend.
+//!
+//! Diagnostics are emitted through [`tracing`], install a subscriber to see them.
+
+pub mod assembler;
+#[cfg(feature = "bevy")]
+pub mod bevy_chip8;
+pub mod bus;
+pub mod cartridge;
+pub mod chip8;
+pub mod clock;
+pub mod disasm;
+pub mod display;
+pub mod gym;
+pub mod hash;
+pub mod instruction;
+pub mod ips;
+#[cfg(feature = "jit")]
+pub mod jit;
+#[cfg(feature = "octo")]
+mod octo;
+pub mod pacer;
+pub mod palette;
+pub mod peripheral;
+pub mod pool;
+pub mod postprocess;
+pub mod profile;
+pub mod random;
+pub mod recompiler;
+#[cfg(feature = "async")]
+pub mod runner;
+pub mod script;
+pub mod serial;
+pub mod state;
+pub mod symbols;
+pub mod trace;
+#[cfg(feature = "egui")]
+pub mod widget;
+
+pub use assembler::{AsmError, AsmOptions, Assembly, Variant, assemble, assemble_files};
+#[cfg(feature = "bevy")]
+pub use bevy_chip8::{Chip8Halted, Chip8Input, Chip8Machine, Chip8Plugin};
+pub use bus::{Bus, MAX_BANKED_ROM_LEN};
+pub use cartridge::{Cartridge, CartridgeQuirks, MAX_ROM_LEN, Metadata};
+pub use chip8::{
+    CaptureInfo, Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH, FRAMEBUFFER_BYTES, MIN_BEEP_TICKS,
+    Quirks, RamInit, SoundEvent, SpriteDraw, StepResult, Trap,
+};
+pub use clock::{Clock, TIMER_HZ};
+pub use disasm::disassemble;
+pub use display::{Display, Edges};
+pub use gym::{Action, GymConfig, GymEnv, GymStep, Observation};
+pub use instruction::Instruction;
+pub use pacer::{FramePacer, PacerStats};
+pub use palette::{PALETTES, Palette};
+pub use peripheral::Peripheral;
+pub use pool::EmulatorPool;
+pub use postprocess::{
+    ColorGrade, Decay, Effect, Grid, PostChain, PostProcessor, RgbaFrame, Scanlines,
+};
+pub use profile::Profile;
+pub use random::{RandomSource, VipRandom};
+#[cfg(feature = "async")]
+pub use runner::{Chip8Runner, Frame, KeyEvent, RunnerHandle};
+pub use script::InputScript;
+pub use serial::Serial;
+pub use state::{SaveState, StateDiff};
+pub use symbols::Symbols;
+pub use trace::JsonTrace;
+#[cfg(feature = "egui")]
+pub use widget::Chip8Widget;
@@ -0,0 +1,144 @@
+use crate::i18n::tr;
+use crate::text::{Anchor, draw_label};
+use chip_8::script::InputEvent;
+use chip_8::{Chip8, InputScript};
+use sdl2::pixels::Color;
+use sdl2::render::WindowCanvas;
+
+const RED: Color = Color::RGB(230, 40, 40);
+
+enum State {
+    Idle,
+    /// Collecting keypad changes since emulated frame `start`
+    Recording {
+        start: u64,
+        input: InputScript,
+    },
+    /// Waiting for the host key to bind the finished recording to
+    Binding(InputScript),
+}
+
+/// Records keypad sequences for binding to a host key, see `GameConfig::macros`.
+pub struct MacroRecorder {
+    state: State,
+    /// Keys held during recording, to skip host key repeat
+    held: u16,
+}
+
+impl MacroRecorder {
+    pub fn new() -> MacroRecorder {
+        MacroRecorder {
+            state: State::Idle,
+            held: 0,
+        }
+    }
+
+    /// Start recording, or finish it and ask for a key to bind.
+    pub fn toggle(&mut self, frame: u64) {
+        self.state = match std::mem::replace(&mut self.state, State::Idle) {
+            State::Idle => {
+                self.held = 0;
+                State::Recording {
+                    start: frame,
+                    input: InputScript::default(),
+                }
+            }
+            State::Recording { start, mut input } if !input.events.is_empty() => {
+                // Release whatever is still held, so replays leave the keypad clean.
+                let held: Vec<u8> = (0..16).filter(|key| self.held & (1 << key) != 0).collect();
+                if !held.is_empty() {
+                    push(&mut input, frame - start, None, &held);
+                }
+                State::Binding(input)
+            }
+            _ => State::Idle,
+        }
+    }
+
+    pub fn is_binding(&self) -> bool {
+        matches!(self.state, State::Binding(_))
+    }
+
+    pub fn cancel(&mut self) {
+        self.state = State::Idle;
+    }
+
+    /// The finished recording, once the host key to bind it to was pressed.
+    pub fn bind(&mut self) -> Option<InputScript> {
+        match std::mem::replace(&mut self.state, State::Idle) {
+            State::Binding(input) => Some(input),
+            state => {
+                self.state = state;
+                None
+            }
+        }
+    }
+
+    /// Note a CHIP-8 key change made by the player at emulated `frame`.
+    pub fn key(&mut self, key: usize, pressed: bool, frame: u64) {
+        let State::Recording { start, input } = &mut self.state else {
+            return;
+        };
+        let bit = 1 << key;
+        if pressed == (self.held & bit != 0) {
+            return;
+        }
+        self.held ^= bit;
+        let key = [key as u8];
+        if pressed {
+            push(input, frame - *start, Some(&key), &[]);
+        } else {
+            push(input, frame - *start, None, &key);
+        }
+    }
+
+    /// Status line in the top-left corner while recording or binding.
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        let status = match self.state {
+            State::Idle => return,
+            State::Recording { .. } => tr("macro.recording"),
+            State::Binding(_) => tr("macro.binding"),
+        };
+        draw_label(canvas, status, Anchor::TopLeft, RED);
+    }
+}
+
+/// Add key changes at `frame`, merging with the last event when it is the same frame.
+fn push(input: &mut InputScript, frame: u64, press: Option<&[u8]>, release: &[u8]) {
+    if input.events.last().is_none_or(|event| event.frame != frame) {
+        input.events.push(InputEvent {
+            frame,
+            hash: None,
+            press: Vec::new(),
+            release: Vec::new(),
+        });
+    }
+    let event = input.events.last_mut().unwrap();
+    event.press.extend(press.unwrap_or_default());
+    event.release.extend(release);
+}
+
+/// Macros being replayed, each with the emulated frame it started in.
+#[derive(Default)]
+pub struct MacroPlayer {
+    playing: Vec<(u64, InputScript)>,
+}
+
+impl MacroPlayer {
+    pub fn start(&mut self, input: &InputScript, frame: u64) {
+        self.playing.push((frame, input.clone()));
+    }
+
+    pub fn stop_all(&mut self) {
+        self.playing.clear();
+    }
+
+    /// Apply the keypad changes for emulated `frame`, called before running it.
+    pub fn update(&mut self, frame: u64, chip8: &mut Chip8) {
+        for (start, input) in &self.playing {
+            input.apply(frame - start, chip8);
+        }
+        self.playing
+            .retain(|(start, input)| frame - start < input.len_frames());
+    }
+}
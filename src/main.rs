@@ -1,117 +1,1230 @@
-use chip8::{Chip8, DISP_HEIGHT, DISP_WIDTH};
+use achievements::AchievementWatcher;
+use audio::Beeper;
+use backend::{Backend, BackendKind};
+use bezel::Bezel;
+#[cfg(feature = "bundled")]
+use bundled::{Picker, PickerAction};
+use changes::Changes;
+use chip_8::palette::palette;
+use chip_8::{Cartridge, Metadata, Palette, PostChain, SaveState};
+use chip_8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH, FramePacer, JsonTrace};
+use chip_8::{SoundEvent, SpriteDraw, StepResult, Symbols};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use config::Config;
+use error_screen::{ErrorAction, ErrorScreen};
+use game_config::GameConfig;
+use hotkeys::{Hotkey, Hotkeys};
+use latency::LatencyMeter;
+use macros::{MacroPlayer, MacroRecorder};
+use menu::{MenuAction, PauseMenu};
+use monitor::Monitor;
+use playlist::Playlist;
+use postfx::PostScreen;
+use power::PowerStats;
+use recent::RecentRoms;
+use rumble::Rumble;
+use scanout::Scanout;
+use score::ScoreWatcher;
 use sdl2::event::Event;
 use sdl2::keyboard::Keycode;
 use sdl2::pixels::Color;
 use sdl2::rect::Rect;
 use sdl2::render::WindowCanvas;
-use std::env;
+use sdl2::{EventPump, EventSubsystem};
+use shader::Shader;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(feature = "png")]
+use std::time::{SystemTime, UNIX_EPOCH};
+use switcher::{Switcher, SwitcherAction};
+use timing::TimingGraph;
+use tracing::{error, info, info_span, trace, warn};
+use tracing_subscriber::EnvFilter;
+use turbo::Turbo;
+use window::Scaling;
 
-mod chip8;
+mod achievements;
+mod asm;
+mod audio;
+mod backend;
+mod bench;
+mod bezel;
+#[cfg(feature = "bundled")]
+mod bundled;
+mod changes;
+mod config;
+mod diff;
+mod dirs;
+mod error_screen;
+mod game_config;
+mod hotkeys;
+mod hud;
+mod i18n;
+mod latency;
+mod macros;
+mod menu;
+mod monitor;
+mod ops;
+mod pack;
+mod playlist;
+mod postfx;
+mod power;
+mod recent;
+mod remote;
+mod rom;
+mod rumble;
+mod run;
+mod scanout;
+mod score;
+mod shader;
+mod snapshot;
+mod soak;
+mod states;
+mod switcher;
+mod text;
+#[cfg(feature = "png")]
+mod thumbs;
+mod timing;
+mod transpile;
+mod turbo;
+mod watch;
+mod window;
 
 extern crate sdl2;
-const SCALE: u32 = 15;
-const TICKS_PER_FRAME: u32 = 10;
+/// Timer frequency, every emulated frame ticks DT and ST once.
+const TIMER_HZ: u64 = 60;
+/// Longest sleep while idle, in milliseconds.
+const IDLE_WAIT_MS: u32 = 1000;
+/// Frames emulated per frame while fast-forwarding.
+const FAST_FORWARD: u32 = 4;
+/// Frame around the sprite drawn last, see `Config::outline_sprites`.
+const SPRITE_OUTLINE: Color = Color::RGB(0xF0, 0xD0, 0x20);
 
-fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() != 2 {
-        println!("Usage: Missing parameter - path/to/game");
-        return;
+#[derive(Parser)]
+#[command(
+    version,
+    about = "CHIP-8 emulator",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Path to the ROM to play; a menu of the built-in demos when left out of `bundled` builds
+    #[cfg_attr(not(feature = "bundled"), arg(required_unless_present = "playlist"))]
+    rom: Option<PathBuf>,
+
+    /// Log verbosity (error, warn, info, debug, trace) or a filter like `chip_8=debug`;
+    /// overrides `RUST_LOG`
+    #[arg(long, global = true)]
+    log_level: Option<String>,
+
+    /// Language of menus and messages, like `de` or `pt_BR`; the system locale when unset
+    #[arg(long, global = true, value_name = "LANG")]
+    lang: Option<String>,
+
+    /// Instruction execution backend
+    #[arg(long, global = true, value_enum, default_value_t)]
+    backend: BackendKind,
+
+    /// Directory for config.toml, instead of the platform's config directory
+    #[arg(long, global = true, value_name = "DIR")]
+    config_dir: Option<PathBuf>,
+
+    /// Directory for per-game data and the recently played list,
+    /// instead of the platform's data directory
+    #[arg(long, global = true, value_name = "DIR")]
+    data_dir: Option<PathBuf>,
+
+    /// Directory for downloaded ROMs, instead of the platform's cache directory
+    #[arg(long, global = true, value_name = "DIR")]
+    cache_dir: Option<PathBuf>,
+
+    /// IPS patch to apply to the ROM, like a fan translation or bug fix; repeatable,
+    /// applied in order
+    #[arg(long, value_name = "FILE", conflicts_with = "playlist")]
+    patch: Vec<PathBuf>,
+
+    /// Write every executed instruction to FILE as JSON Lines
+    #[arg(long, value_name = "FILE")]
+    trace_json: Option<PathBuf>,
+
+    /// Log how long key presses take to reach the game and the screen
+    #[arg(long)]
+    measure_latency: bool,
+
+    /// Read monitor commands on stdin while playing, like `b 2A4` for a breakpoint;
+    /// `?` lists them
+    #[arg(long)]
+    monitor: bool,
+
+    /// Label addresses for the monitor, as `asm --symbols` writes them
+    #[arg(long, value_name = "FILE", requires = "monitor")]
+    symbols: Option<PathBuf>,
+
+    /// Display to go fullscreen on, 0 being the primary one; remembered for later runs
+    #[arg(long, value_name = "N")]
+    display: Option<i32>,
+
+    /// Seed for random RAM (`ram_init = "random"`), so it holds the same bytes every run;
+    /// remembered for later runs
+    #[arg(long, value_name = "SEED")]
+    ram_seed: Option<u64>,
+
+    /// Attract mode: cycle through the ROMs listed in FILE, one path per line
+    #[arg(long, value_name = "FILE", conflicts_with = "rom")]
+    playlist: Option<PathBuf>,
+
+    /// Seconds each playlist ROM runs before the next; a key press skips ahead sooner
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 60,
+        requires = "playlist"
+    )]
+    attract_seconds: u64,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run a ROM headless with scripted input and check the final framebuffer
+    Run(Box<run::RunArgs>),
+    /// Run a ROM headless as fast as possible and report core throughput
+    Bench(bench::BenchArgs),
+    /// Run every ROM in a directory headless with random input and report the ones that broke
+    Soak(soak::SoakArgs),
+    /// Run every ROM in a directory headless and save a thumbnail of each
+    #[cfg(feature = "png")]
+    Thumbs(thumbs::ThumbsArgs),
+    /// Translate a ROM into a standalone Rust program
+    Transpile(transpile::TranspileArgs),
+    /// Report the differences between two save states
+    Diff(diff::DiffArgs),
+    /// Print save states or convert them to and from JSON
+    State(states::StateArgs),
+    /// Assemble an Octo source file into a ROM
+    Asm(asm::AsmArgs),
+    /// Trim or pad ROM files and report how much of RAM they take
+    Rom(rom::RomArgs),
+    /// Bundle a ROM and its metadata into a .c8x cartridge
+    Pack(pack::PackArgs),
+    /// List every instruction with what it does and the quirks that change it
+    Ops(ops::OpsArgs),
+}
+
+/// Screen shown over the paused game.
+enum Overlay {
+    Menu(PauseMenu),
+    Switcher(Switcher),
+    Error(ErrorScreen),
+    #[cfg(feature = "bundled")]
+    Picker(Picker),
+}
+
+fn main() -> ExitCode {
+    i18n::init(i18n::lang_arg().as_deref());
+    let matches = i18n::localize(Cli::command()).get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|err| err.exit());
+    init_logging(cli.log_level.as_deref());
+    if let Some(lang) = &cli.lang
+        && !i18n::available(lang)
+    {
+        warn!(lang = %lang, "No translation for this language, using English");
+    }
+    dirs::init(cli.config_dir, cli.data_dir, cli.cache_dir);
+
+    match cli.command {
+        Some(Command::Run(args)) => run::run(*args, cli.backend),
+        Some(Command::Bench(args)) => bench::bench(args, cli.backend),
+        Some(Command::Soak(args)) => soak::soak(args),
+        #[cfg(feature = "png")]
+        Some(Command::Thumbs(args)) => thumbs::thumbs(args),
+        Some(Command::Transpile(args)) => transpile::transpile(args),
+        Some(Command::Diff(args)) => diff::diff(args),
+        Some(Command::State(args)) => states::state(args),
+        Some(Command::Asm(args)) => asm::asm(args),
+        Some(Command::Rom(args)) => rom::rom(args),
+        Some(Command::Pack(args)) => pack::pack(args),
+        Some(Command::Ops(args)) => ops::ops(args),
+        None => {
+            let playlist = match &cli.playlist {
+                Some(file) => {
+                    match Playlist::load(file, Duration::from_secs(cli.attract_seconds)) {
+                        Ok(playlist) => Some(playlist),
+                        Err(err) => {
+                            error!(file = %file.display(), %err, "Could not load playlist");
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+                None => None,
+            };
+            let (rom, startup) = match (&playlist, cli.rom) {
+                (Some(playlist), _) => (playlist.current().to_path_buf(), None),
+                (None, Some(rom)) => (rom, None),
+                #[cfg(feature = "bundled")]
+                (None, None) => match bundled::extract(0) {
+                    Ok(rom) => (rom, Some(Overlay::Picker(Picker::new()))),
+                    Err(err) => {
+                        error!(%err, "Could not extract the built-in demos");
+                        return ExitCode::FAILURE;
+                    }
+                },
+                #[cfg(not(feature = "bundled"))]
+                (None, None) => unreachable!("ROM is required without subcommand or playlist"),
+            };
+            play(
+                &rom,
+                &cli.patch,
+                cli.backend,
+                Debugging {
+                    trace_json: cli.trace_json.as_deref(),
+                    measure_latency: cli.measure_latency,
+                    monitor: cli.monitor,
+                    symbols: cli.symbols.as_deref(),
+                },
+                Overrides {
+                    display: cli.display,
+                    ram_seed: cli.ram_seed,
+                },
+                playlist,
+                startup,
+            )
+        }
+    }
+}
+
+/// Debugging aids of the window, from the command line.
+struct Debugging<'a> {
+    trace_json: Option<&'a Path>,
+    measure_latency: bool,
+    monitor: bool,
+    symbols: Option<&'a Path>,
+}
+
+/// Settings from the command line that replace the ones in `config.toml`.
+struct Overrides {
+    display: Option<i32>,
+    ram_seed: Option<u64>,
+}
+
+/// Play `rom_path` in a window with `patches` applied, paused under `startup` if given.
+/// With a `playlist`, `rom_path` is its current entry and the others follow.
+fn play(
+    rom_path: &Path,
+    patches: &[PathBuf],
+    backend: BackendKind,
+    debugging: Debugging,
+    overrides: Overrides,
+    mut playlist: Option<Playlist>,
+    startup: Option<Overlay>,
+) -> ExitCode {
+    let mut config = Config::load();
+    if overrides.display.is_some() {
+        config.window.fullscreen_display = overrides.display;
+    }
+    if overrides.ram_seed.is_some() {
+        config.ram_seed = overrides.ram_seed;
+    }
+    let (mut chip8, metadata, mut game, mut rom) = match load_game(rom_path, patches, &config) {
+        Ok(game) => game,
+        Err(err) => {
+            error!(rom = %rom_path.display(), %err, "Could not load ROM");
+            return ExitCode::FAILURE;
+        }
+    };
+    if debugging.trace_json.is_some() && backend != BackendKind::Interpreter {
+        error!("Tracing needs the interpreter backend");
+        return ExitCode::FAILURE;
+    }
+    if debugging.monitor && backend != BackendKind::Interpreter {
+        error!("The monitor needs the interpreter backend");
+        return ExitCode::FAILURE;
+    }
+    let mut backend = match Backend::new(backend) {
+        Ok(backend) => backend,
+        Err(err) => {
+            error!(%err, "Could not start backend");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut trace = match debugging.trace_json {
+        Some(path) => match File::create(path) {
+            Ok(file) => Some(JsonTrace::new(BufWriter::new(file))),
+            Err(err) => {
+                error!(file = %path.display(), %err, "Could not create trace file");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => None,
+    };
+    let mut latency = debugging.measure_latency.then(LatencyMeter::default);
+    let symbols = match debugging.symbols {
+        Some(path) => match Symbols::load(path) {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                error!(file = %path.display(), %err, "Could not load symbols");
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Symbols::default(),
+    };
+    let mut monitor = debugging.monitor.then(|| {
+        let mut monitor = Monitor::spawn(false, symbols);
+        monitor.restore(&game.monitor);
+        monitor
+    });
+    let mut turbo = Turbo::default();
+    let mut score = ScoreWatcher::default();
+    let mut achievements = AchievementWatcher::default();
+    let mut recorder = MacroRecorder::new();
+    let mut player = MacroPlayer::default();
+    let mut recent = RecentRoms::load();
+    let hotkeys = Hotkeys::new(&config);
+    if playlist.is_none() {
+        recent.push(rom_path);
     }
-    let mut chip8 = Chip8::new();
-    chip8.load_rom(&args[1]);
 
+    config.window.set_hints();
     let sdl_context = sdl2::init().unwrap();
     let video_subsystem = sdl_context.video().unwrap();
-    let window = video_subsystem
-        .window(
-            "Chip-8",
-            DISP_WIDTH as u32 * SCALE,
-            DISP_HEIGHT as u32 * SCALE,
-        )
-        .position_centered()
-        .opengl()
-        .build()
-        .unwrap();
+    let mut beeper = match sdl_context
+        .audio()
+        .and_then(|audio| Beeper::new(&audio, config.volume, config.audio_buffer))
+    {
+        Ok(beeper) => Some(beeper),
+        Err(err) => {
+            warn!(%err, "Sound disabled");
+            None
+        }
+    };
+    let mut rumble = match sdl_context.game_controller() {
+        Ok(subsystem) => Some(Rumble::new(subsystem)),
+        Err(err) => {
+            warn!(%err, "Rumble disabled");
+            None
+        }
+    };
+    let window = config
+        .window
+        .open(&video_subsystem, &window_title(rom_path, &metadata));
 
+    if config.shader.is_some() {
+        // Shaders run in the context of SDL's OpenGL renderer.
+        sdl2::hint::set("SDL_RENDER_DRIVER", "opengl");
+    }
     let mut canvas = window.into_canvas().present_vsync().build().unwrap();
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
     canvas.present();
+    let textures = canvas.texture_creator();
+    let bezel = config
+        .bezel
+        .as_deref()
+        .and_then(|path| match Bezel::load(path, &textures) {
+            Ok(bezel) => Some(bezel),
+            Err(err) => {
+                warn!(file = %path.display(), %err, "Could not load bezel");
+                None
+            }
+        });
+    let shader = config.shader.as_deref().and_then(|path| {
+        let shader = match canvas.info().name {
+            "opengl" => Shader::load(path, &video_subsystem),
+            renderer => Err(format!("needs the opengl renderer, not {}", renderer)),
+        };
+        shader
+            .inspect_err(|err| warn!(file = %path.display(), %err, "Could not load shader"))
+            .ok()
+    });
+    let mut post =
+        (!config.effects.is_empty() || shader.is_some() || config.interpolate).then(|| {
+            let chain = PostChain::from_effects(&config.effects);
+            PostScreen::new(chain, shader, config.interpolate, &textures)
+        });
 
+    let event_subsystem = sdl_context.event().unwrap();
     let mut event_pump = sdl_context.event_pump().unwrap();
+    let mut overlay = startup;
+    let mut pacer = FramePacer::new(TIMER_HZ);
+    let mut frame: u64 = 0;
+    // Frames since the game or a state was loaded, what screenshots record
+    let mut game_frame: u64 = 0;
+    let mut drawn_frame: u64 = 0;
+    let mut drawn_video = chip8.video;
+    // The window was resized or uncovered and needs drawing even if nothing changed
+    let mut redraw = false;
+    let mut waiting_for_key = false;
+    let mut fast_forward = false;
+    // The tone was switched off for an overlay and has to follow `st` again
+    let mut tone_muted = false;
+    let mut exit = ExitCode::SUCCESS;
+    let mut next_rom: Option<PathBuf> = None;
+    let mut current_rom = rom_path.to_path_buf();
+    // Replaced by the palette and speed chosen in the menu
+    let mut cartridge_palette = metadata.palette();
+    let mut cartridge_speed = metadata.ticks_per_frame;
+    let mut scanout = game.scanout.then(Scanout::new);
+    let mut changes = config.changes.then(|| Changes::new(&chip8.video));
+    let mut timing = config.timing_graph.then(TimingGraph::default);
+    let mut power = PowerStats::default();
     'running: loop {
         for event in event_pump.poll_iter() {
+            if let Some(rumble) = &mut rumble {
+                rumble.handle_event(&event);
+            }
+            let hotkey = hotkeys.pressed(&event, &config);
             match event {
                 Event::Quit { .. } => break 'running,
+                Event::Window { win_event, .. } => {
+                    config.window.track(canvas.window(), &win_event);
+                    redraw = true;
+                }
+                _ if hotkey == Some(Hotkey::Fullscreen) => {
+                    config.window.toggle_fullscreen(canvas.window_mut());
+                }
+                _ if hotkey == Some(Hotkey::NextDisplay) => {
+                    config.window.next_display(canvas.window_mut());
+                }
+                _ if hotkey == Some(Hotkey::Switcher) => {
+                    overlay = match overlay {
+                        Some(Overlay::Switcher(_)) => None,
+                        _ => Some(Overlay::Switcher(Switcher::new())),
+                    };
+                    chip8.keypad = [0; 16];
+                    turbo.release_all();
+                    player.stop_all();
+                    recorder.cancel();
+                }
                 Event::KeyDown {
-                    keycode: Some(key), ..
+                    keycode: Some(key),
+                    scancode,
+                    ..
+                } if overlay.is_some() => match overlay.as_mut().unwrap() {
+                    Overlay::Menu(menu) => {
+                        let (quirks, palette_name, speed) = (
+                            config.quirks.clone(),
+                            config.palette.clone(),
+                            config.ticks_per_frame,
+                        );
+                        match menu.handle_key(key, scancode, &mut config) {
+                            MenuAction::None => {}
+                            MenuAction::Changed => {
+                                if config.quirks != quirks {
+                                    info!(preset = %config.quirks, "Quirks selected");
+                                    chip8.quirks = config.quirks();
+                                }
+                                if config.palette != palette_name {
+                                    cartridge_palette = None;
+                                }
+                                if config.ticks_per_frame != speed {
+                                    cartridge_speed = None;
+                                }
+                                if let Some(beeper) = &mut beeper {
+                                    beeper.set_volume(config.volume);
+                                }
+                                config.save();
+                            }
+                            MenuAction::Resume => overlay = None,
+                            MenuAction::OpenSwitcher => {
+                                overlay = Some(Overlay::Switcher(Switcher::new()))
+                            }
+                            MenuAction::Quit => break 'running,
+                        }
+                    }
+                    Overlay::Switcher(switcher) => match switcher.handle_key(key, &recent) {
+                        SwitcherAction::None => {}
+                        SwitcherAction::Close => overlay = None,
+                        SwitcherAction::Load(path) => next_rom = Some(path),
+                    },
+                    Overlay::Error(screen) => match screen.handle_key(key) {
+                        ErrorAction::None => {}
+                        ErrorAction::Reset => next_rom = Some(current_rom.clone()),
+                        ErrorAction::Quit => break 'running,
+                    },
+                    #[cfg(feature = "bundled")]
+                    Overlay::Picker(picker) => match picker.handle_key(key) {
+                        PickerAction::None => {}
+                        PickerAction::Close => overlay = None,
+                        PickerAction::Load(path) => next_rom = Some(path),
+                    },
+                },
+                _ if hotkey == Some(Hotkey::RecordMacro) => {
+                    recorder.toggle(frame);
+                    redraw = true;
+                    if recorder.is_binding() {
+                        chip8.keypad = [0; 16];
+                    }
+                }
+                Event::KeyDown {
+                    keycode, scancode, ..
+                } if recorder.is_binding() => {
+                    redraw = true;
+                    if keycode == Some(Keycode::Escape) {
+                        recorder.cancel();
+                    } else if let Some(name) = config.key_name(keycode, scancode)
+                        && config.chip8_key(keycode, scancode).is_none()
+                        && let Some(input) = recorder.bind()
+                    {
+                        info!(key = %name, "Macro recorded");
+                        game.set_macro(name, input);
+                        game.save();
+                    }
+                }
+                _ if hotkey == Some(Hotkey::Pause) => {
+                    overlay = Some(Overlay::Menu(PauseMenu::new()));
+                    chip8.keypad = [0; 16];
+                    turbo.release_all();
+                    player.stop_all();
+                    recorder.cancel();
+                    fast_forward = false;
+                }
+                _ if hotkey == Some(Hotkey::Reset) => next_rom = Some(current_rom.clone()),
+                _ if hotkey == Some(Hotkey::SaveState) => save_state(&chip8, &game),
+                _ if hotkey == Some(Hotkey::LoadState) => {
+                    let loaded = load_state(&mut chip8, &game);
+                    if loaded {
+                        game_frame = 0;
+                    }
+                }
+                _ if hotkey == Some(Hotkey::FastForward) => fast_forward = true,
+                _ if hotkey == Some(Hotkey::Hud) => {
+                    config.hud = !config.hud;
+                    redraw = true;
+                }
+                _ if hotkey == Some(Hotkey::Changes) => {
+                    config.changes = !config.changes;
+                    changes = config.changes.then(|| Changes::new(&chip8.video));
+                    redraw = true;
+                }
+                _ if hotkey == Some(Hotkey::TimingGraph) => {
+                    config.timing_graph = !config.timing_graph;
+                    timing = config.timing_graph.then(TimingGraph::default);
+                    redraw = true;
+                }
+                _ if hotkey == Some(Hotkey::Screenshot) => {
+                    screenshot(
+                        &chip8,
+                        &display_palette(cartridge_palette.as_ref(), &config),
+                        &current_rom,
+                        &rom,
+                        game_frame,
+                    );
+                }
+                _ if hotkeys.released(Hotkey::FastForward, &event, &config) => {
+                    fast_forward = false;
+                }
+                Event::KeyDown {
+                    keycode,
+                    scancode,
+                    repeat,
+                    ..
                 } => {
-                    if let Some(k) = match_key(key) {
-                        chip8.set_key_value(k, 1)
+                    if let Some(playlist) = &mut playlist {
+                        // In attract mode any key moves on to the next game.
+                        if !repeat {
+                            next_rom = Some(playlist.next().to_path_buf());
+                        }
+                        continue;
+                    }
+                    if let Some(m) = config
+                        .key_name(keycode, scancode)
+                        .and_then(|name| game.macro_for(&name))
+                    {
+                        if !repeat {
+                            player.start(&m.input, frame);
+                        }
+                    } else if let Some(turbo_key) = config.turbo_key(keycode, scancode) {
+                        turbo.key_down(turbo_key, frame);
+                    } else if let Some(k) = config.chip8_key(keycode, scancode) {
+                        chip8.set_key_value(k, 1);
+                        recorder.key(k, true, frame);
+                        if let Some(latency) = &mut latency {
+                            latency.key_down(k);
+                        }
                     }
                 }
                 Event::KeyUp {
-                    keycode: Some(key), ..
+                    keycode, scancode, ..
                 } => {
-                    if let Some(k) = match_key(key) {
-                        chip8.set_key_value(k, 0)
+                    if let Some(turbo_key) = config.turbo_key(keycode, scancode) {
+                        turbo.key_up(turbo_key, &mut chip8);
+                    } else if let Some(k) = config.chip8_key(keycode, scancode) {
+                        chip8.set_key_value(k, 0);
+                        recorder.key(k, false, frame);
+                        if let Some(latency) = &mut latency {
+                            latency.key_up(k);
+                        }
                     }
                 }
                 _ => {}
             }
         }
 
-        for _ in 0..TICKS_PER_FRAME {
-            chip8.cycle();
+        if let Some(playlist) = &mut playlist
+            && next_rom.is_none()
+            && overlay.is_none()
+            && playlist.expired()
+        {
+            next_rom = Some(playlist.next().to_path_buf());
+        }
+        if let Some(path) = next_rom.take() {
+            // Patches are for the ROM they were given with, not the ones switched to.
+            let patches = if path == rom_path { patches } else { &[] };
+            match load_game(&path, patches, &config) {
+                Ok((new_chip8, metadata, new_game, new_rom)) => {
+                    exit = ExitCode::SUCCESS;
+                    current_rom = path.clone();
+                    score.finish(&mut game);
+                    achievements.clear();
+                    chip8 = new_chip8;
+                    rom = new_rom;
+                    game_frame = 0;
+                    if let Some(beeper) = &mut beeper {
+                        beeper.set_playing(false);
+                    }
+                    cartridge_palette = metadata.palette();
+                    cartridge_speed = metadata.ticks_per_frame;
+                    game = new_game;
+                    if let Some(monitor) = &mut monitor {
+                        monitor.restore(&game.monitor);
+                    }
+                    scanout = game.scanout.then(Scanout::new);
+                    changes = config.changes.then(|| Changes::new(&chip8.video));
+                    if playlist.is_none() {
+                        recent.push(&path);
+                    }
+                    canvas
+                        .window_mut()
+                        .set_title(&window_title(&path, &metadata))
+                        .expect("Error when setting title");
+                    overlay = None;
+                    turbo.release_all();
+                    player.stop_all();
+                    recorder.cancel();
+                }
+                Err(err) => {
+                    error!(rom = %path.display(), %err, "Could not load ROM");
+                    match &mut playlist {
+                        Some(playlist) if playlist.current() == path => {
+                            if !playlist.remove_current() {
+                                exit = ExitCode::FAILURE;
+                                break 'running;
+                            }
+                            next_rom = Some(playlist.next().to_path_buf());
+                        }
+                        _ => recent.remove(&path),
+                    }
+                }
+            }
+        }
+
+        if let Some(overlay) = &overlay {
+            pacer.reset();
+            if let Some(beeper) = &mut beeper {
+                beeper.set_playing(false);
+                tone_muted = true;
+            }
+            if let Some(rumble) = &mut rumble {
+                rumble.update(0, config.rumble, config.rumble_ms);
+            }
+            let colors = display_palette(cartridge_palette.as_ref(), &config);
+            draw(
+                scanout.as_ref().map_or(&chip8.video, Scanout::video),
+                &mut canvas,
+                &colors,
+                config.window.scaling,
+                bezel.as_ref(),
+                &[],
+                post.as_mut(),
+            );
+            match overlay {
+                Overlay::Menu(menu) => menu.draw(&mut canvas, &config),
+                Overlay::Switcher(switcher) => switcher.draw(&mut canvas, &recent),
+                Overlay::Error(screen) => screen.draw(&mut canvas),
+                #[cfg(feature = "bundled")]
+                Overlay::Picker(picker) => picker.draw(&mut canvas),
+            }
+            canvas.present();
+            continue;
+        }
+
+        let frame_start = Instant::now();
+        let due = if monitor.as_mut().is_some_and(|monitor| {
+            let stopped = monitor.poll(&mut chip8);
+            monitor.persist(&mut game);
+            stopped
+        }) {
+            pacer.reset();
+            0
+        } else {
+            pacer.frames_due() * if fast_forward { FAST_FORWARD } else { 1 }
+        };
+        let ticks_per_frame = cartridge_speed.unwrap_or(config.ticks_per_frame);
+        for _ in 0..due {
+            if monitor.as_ref().is_some_and(Monitor::stopped) {
+                break;
+            }
+            turbo.update(frame, &mut chip8);
+            player.update(frame, &mut chip8);
+            if let Some(post) = &mut post {
+                post.frame_starting(scanout.as_ref().map_or(&chip8.video, Scanout::video));
+            }
+            let mut run = |chip8: &mut Chip8, cycles| match (&trace, &monitor) {
+                (None, None) => backend.run(chip8, cycles),
+                _ => run_stepwise(chip8, cycles, &mut trace, &mut monitor),
+            };
+            let result = match &mut scanout {
+                Some(scanout) => scanout.run_frame(&mut chip8, ticks_per_frame, run),
+                None => run(&mut chip8, ticks_per_frame),
+            };
+            match result {
+                Ok(result) => waiting_for_key = result == StepResult::WaitingForKey,
+                Err(err) => {
+                    error!(%err, "Machine stopped");
+                    match &mut playlist {
+                        Some(playlist) => next_rom = Some(playlist.next().to_path_buf()),
+                        None => {
+                            exit = ExitCode::FAILURE;
+                            overlay = Some(Overlay::Error(ErrorScreen::new(err, &chip8)));
+                        }
+                    }
+                    break;
+                }
+            }
+            chip8.tick_timers();
+            frame += 1;
+            game_frame += 1;
+            if let Some(changes) = &mut changes {
+                changes.update(&chip8.video);
+            }
+            achievements.update(&chip8, &mut game);
+            if let Some(latency) = &mut latency {
+                latency.frame_emulated(chip8.take_observed_keys());
+            }
+        }
+        score.update(&chip8, &game);
+        let mut xruns = 0;
+        let sound_events = chip8.take_sound_events();
+        if let Some(beeper) = &mut beeper {
+            if tone_muted {
+                beeper.set_playing(chip8.beeping());
+                tone_muted = false;
+            } else {
+                for event in sound_events {
+                    match event {
+                        SoundEvent::Started { ticks } => beeper.beep(ticks),
+                        SoundEvent::Stopped => beeper.stop(),
+                    }
+                }
+            }
+            xruns = beeper.stats().xruns;
+        }
+        if let Some(rumble) = &mut rumble {
+            let st = if chip8.beeping() { chip8.st } else { 0 };
+            rumble.update(st, config.rumble, config.rumble_ms);
+        }
+        let emulation_time = frame_start.elapsed();
+        let mut rendering_time = Duration::ZERO;
+        // Blended frames are presented at the display's rate, as often as vsync allows.
+        let draw_due =
+            config.interpolate || due > 0 && frame - drawn_frame > config.frame_skip as u64;
+        // Overlays that change every frame always count as a change.
+        let unchanged = config.power_saving
+            && !config.interpolate
+            && chip8.video == drawn_video
+            && !config.hud
+            && changes.is_none()
+            && timing.is_none()
+            && !achievements.showing();
+
+        if waiting_for_key
+            && monitor.is_none()
+            && !config.interpolate
+            && chip8.dt == 0
+            && chip8.st == 0
+            && chip8.video == drawn_video
+            && !redraw
+        {
+            // Nothing can change until a key is pressed or released, sleep until the next event.
+            let idle_start = Instant::now();
+            wait_event(&mut event_pump, &event_subsystem, IDLE_WAIT_MS);
+            power.idle(idle_start.elapsed(), ticks_per_frame);
+            pacer.reset();
+        } else if redraw || draw_due && !unchanged {
+            let colors = display_palette(cartridge_palette.as_ref(), &config);
+            if let Some(post) = &mut post {
+                post.interpolate(pacer.fraction() as f32);
+            }
+            let viewport = draw(
+                scanout.as_ref().map_or(&chip8.video, Scanout::video),
+                &mut canvas,
+                &colors,
+                config.window.scaling,
+                bezel.as_ref(),
+                &changes.as_mut().map_or_else(Vec::new, Changes::take),
+                post.as_mut(),
+            );
+            if config.outline_sprites
+                && let Some(sprite) = chip8.last_draw()
+            {
+                outline_sprite(&mut canvas, viewport, sprite);
+            }
+            if config.hud {
+                hud::draw(&mut canvas, &chip8);
+            }
+            recorder.draw(&mut canvas);
+            achievements.draw(&mut canvas);
+            if let Some(timing) = &timing {
+                timing.draw(&mut canvas);
+            }
+            rendering_time = frame_start.elapsed() - emulation_time;
+            canvas.present();
+            power.presented();
+            if let Some(latency) = &mut latency {
+                latency.presented();
+            }
+            drawn_frame = frame;
+            drawn_video = chip8.video;
+            redraw = false;
+        } else if config.power_saving {
+            if draw_due {
+                power.unchanged();
+            }
+            // Nothing is presented, so vsync does not pace this iteration; input wakes it early.
+            let wait = pacer.until_next_frame().as_millis() as u32;
+            wait_event(&mut event_pump, &event_subsystem, wait);
+        } else {
+            // Nothing is presented, so vsync does not pace this iteration.
+            thread::sleep(pacer.until_next_frame());
+        }
+        if let Some(timing) = &mut timing {
+            timing.record(
+                frame_start.elapsed(),
+                emulation_time,
+                rendering_time,
+                (due * ticks_per_frame) as u64,
+            );
+        }
+        let stats = pacer.stats();
+        trace!(
+            frame,
+            frames = due,
+            cycles = due * ticks_per_frame,
+            pc = chip8.pc,
+            emulation_us = emulation_time.as_micros() as u64,
+            frame_us = frame_start.elapsed().as_micros() as u64,
+            speed = stats.speed,
+            dropped = stats.dropped_frames,
+            xruns,
+            "Frame"
+        );
+    }
+
+    if let Some(json) = &mut trace
+        && let Err(err) = json.flush()
+    {
+        warn!(%err, "Could not write trace");
+    }
+    if let Some(latency) = &latency {
+        latency.report();
+    }
+    if config.power_saving {
+        power.report();
+    }
+    score.finish(&mut game);
+    config.window.capture(canvas.window());
+    config.save();
+    info!("Finito.");
+    exit
+}
+
+/// Wait up to `timeout_ms` for an event and queue it again for the loop to handle.
+fn wait_event(event_pump: &mut EventPump, event_subsystem: &EventSubsystem, timeout_ms: u32) {
+    if let Some(event) = event_pump.wait_event_timeout(timeout_ms)
+        && let Err(err) = event_subsystem.push_event(event)
+    {
+        // Only event kinds the loop ignores cannot be queued again.
+        trace!(%err, "Dropped event while waiting");
+    }
+}
+
+/// Interpret `cycles` instructions, recording each one in `trace` until writing it fails,
+/// and ending early when `monitor` stops at a breakpoint.
+fn run_stepwise(
+    chip8: &mut Chip8,
+    cycles: u32,
+    trace: &mut Option<JsonTrace<BufWriter<File>>>,
+    monitor: &mut Option<Monitor>,
+) -> Result<StepResult, Chip8Error> {
+    let mut result = StepResult::Executed;
+    for _ in 0..cycles {
+        if let Some(monitor) = monitor
+            && !monitor.check(chip8)
+        {
+            break;
+        }
+        let (pc, registers) = (chip8.pc, chip8.registers);
+        result = chip8.cycle()?;
+        if let Some(json) = trace
+            && let Err(err) = json.record(pc, &registers, chip8)
+        {
+            warn!(%err, "Trace stopped");
+            *trace = None;
+        }
+    }
+    Ok(result)
+}
+
+/// Log to stderr, filtered by `level` or else `RUST_LOG`, defaulting to `info`.
+fn init_logging(level: Option<&str>) {
+    let filter = match level {
+        Some(level) => EnvFilter::new(level),
+        None => EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")),
+    };
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(io::stderr)
+        .init();
+}
+
+/// Create a fresh machine with the ROM or `.c8x` cartridge at `path` loaded and `patches`
+/// applied to it, and return it with the ROM as loaded.
+/// Quirks come from the cartridge when it names a preset, else from `config`.
+fn load_game(
+    path: &Path,
+    patches: &[PathBuf],
+    config: &Config,
+) -> io::Result<(Chip8, Metadata, GameConfig, Vec<u8>)> {
+    let _span = info_span!("load_game", rom = %path.display()).entered();
+    let mut cartridge = Cartridge::load(remote::local_file(path)?)?;
+    for patch in patches {
+        cartridge.patch(patch).map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!("could not apply patch {}: {}", patch.display(), err),
+            )
+        })?;
+        info!(patch = %patch.display(), "Patch applied");
+    }
+    let metadata = cartridge.metadata;
+    let game = GameConfig::load(path);
+    let mut chip8 = Chip8::new();
+    let selected = [
+        (&game.quirks, "game settings"),
+        (&metadata.quirks, "cartridge"),
+    ]
+    .into_iter()
+    .find_map(|(setting, source)| {
+        let setting = setting.as_ref()?;
+        let quirks = setting.resolve();
+        match quirks {
+            Some(_) => info!(preset = %setting, source, "Quirks selected"),
+            None => warn!(preset = %setting, source, "Ignoring unknown quirks preset"),
         }
-        chip8.tick_timers();
+        quirks
+    });
+    chip8.quirks = selected.unwrap_or_else(|| {
+        info!(preset = %config.quirks, "Quirks selected");
+        config.quirks()
+    });
+    if let Some(variant) = &metadata.variant
+        && !variant.eq_ignore_ascii_case("chip-8")
+    {
+        warn!(%variant, "Game is made for another CHIP-8 variant and may not run correctly");
+    }
+    for (key, label) in &metadata.keys {
+        info!(key = %key.to_uppercase(), %label, "Control");
+    }
+    if let Some(seed) = config.ram_seed {
+        chip8.seed(seed);
+    }
+    chip8.init_ram(config.ram_init);
+    chip8.load_rom_bytes(&cartridge.rom)?;
+    Ok((chip8, metadata, game, cartridge.rom))
+}
 
-        draw(&chip8, &mut canvas);
+/// Write the machine to the game's quick save slot.
+fn save_state(chip8: &Chip8, game: &GameConfig) {
+    let Some(file) = game.state_file() else {
+        return;
+    };
+    if let Some(dir) = file.parent() {
+        let _ = fs::create_dir_all(dir);
+    }
+    match chip8.save_state().save(&file) {
+        Ok(()) => info!(file = %file.display(), "State saved"),
+        Err(err) => warn!(file = %file.display(), %err, "Could not save state"),
     }
+}
 
-    println!("Finito.")
+/// Resume from the game's quick save slot, false when there is none to load.
+fn load_state(chip8: &mut Chip8, game: &GameConfig) -> bool {
+    let Some(file) = game.state_file() else {
+        return false;
+    };
+    match SaveState::load(&file) {
+        Ok(state) => {
+            chip8.load_state(&state);
+            info!(file = %file.display(), "State loaded");
+            true
+        }
+        Err(err) => {
+            warn!(file = %file.display(), %err, "Could not load state");
+            false
+        }
+    }
 }
 
-fn draw(chip8: &Chip8, canvas: &mut WindowCanvas) {
+/// Save the display as `screenshots/<rom>-<unix time>.png` in the data directory,
+/// noting the `rom` loaded from `rom_path` and the frames since.
+fn screenshot(chip8: &Chip8, palette: &Palette, rom_path: &Path, rom: &[u8], frame: u64) {
+    #[cfg(feature = "png")]
+    {
+        let Some(dir) = dirs::data_dir().map(|dir| dir.join("screenshots")) else {
+            return;
+        };
+        let _ = fs::create_dir_all(&dir);
+        let stem = rom_path
+            .file_stem()
+            .map_or("screenshot".into(), |stem| stem.to_string_lossy());
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |since| since.as_secs());
+        let file = dir.join(format!("{}-{}.png", stem, time));
+        let info = chip_8::CaptureInfo::new(rom_path, rom, frame);
+        match chip8.save_screenshot(&file, palette, 10, &info) {
+            Ok(()) => info!(file = %file.display(), "Screenshot saved"),
+            Err(err) => warn!(file = %file.display(), %err, "Could not save screenshot"),
+        }
+    }
+    #[cfg(not(feature = "png"))]
+    {
+        let _ = (chip8, palette, rom_path, rom, frame);
+        warn!("Screenshots need the `png` feature");
+    }
+}
+
+fn window_title(rom_path: &Path, metadata: &Metadata) -> String {
+    match (&metadata.title, &metadata.author, rom_path.file_name()) {
+        (Some(title), Some(author), _) => format!("Chip-8 - {} by {}", title, author),
+        (Some(title), None, _) => format!("Chip-8 - {}", title),
+        (None, _, Some(name)) => format!("Chip-8 - {}", name.to_string_lossy()),
+        (None, _, None) => "Chip-8".to_string(),
+    }
+}
+
+/// Palette the display is drawn in: the game's unless one was chosen in the menu.
+fn display_palette(cartridge: Option<&Palette>, config: &Config) -> Palette {
+    let palette = cartridge.unwrap_or_else(|| palette(&config.palette));
+    if config.high_contrast {
+        palette.high_contrast()
+    } else {
+        palette.clone()
+    }
+}
+
+fn draw(
+    video: &[bool; DISP_WIDTH * DISP_HEIGHT],
+    canvas: &mut WindowCanvas,
+    palette: &Palette,
+    scaling: Scaling,
+    bezel: Option<&Bezel>,
+    changes: &[(usize, Color)],
+    post: Option<&mut PostScreen>,
+) -> Rect {
+    // The display is drawn in output pixels, overlays afterwards in window points.
+    canvas.set_scale(1.0, 1.0).expect("Error when drawing");
     canvas.set_draw_color(Color::RGB(0, 0, 0));
     canvas.clear();
-    canvas.set_draw_color(Color::RGB(255, 255, 255));
+    let screen = match bezel {
+        Some(bezel) => bezel.draw(canvas),
+        None => {
+            let (width, height) = canvas.output_size().expect("Error when drawing");
+            Rect::new(0, 0, width, height)
+        }
+    };
+    let mut viewport = scaling.viewport(screen.size());
+    viewport.offset(screen.x(), screen.y());
+    match post {
+        Some(post) => post.draw(canvas, video, palette, viewport),
+        None => draw_pixels(video, canvas, palette, viewport),
+    }
+    for &(idx, color) in changes {
+        canvas.set_draw_color(color);
+        canvas
+            .fill_rect(pixel_rect(
+                viewport,
+                idx % DISP_WIDTH,
+                idx / DISP_WIDTH,
+                1,
+                1,
+            ))
+            .expect("Error when drawing");
+    }
+    let scale = window::ui_scale(canvas.window());
+    canvas.set_scale(scale, scale).expect("Error when drawing");
+    viewport
+}
+
+/// Display drawn straight into `viewport`, a rectangle per lit pixel.
+fn draw_pixels(
+    video: &[bool; DISP_WIDTH * DISP_HEIGHT],
+    canvas: &mut WindowCanvas,
+    palette: &Palette,
+    viewport: Rect,
+) {
+    let [r, g, b] = palette.background;
+    canvas.set_draw_color(Color::RGB(r, g, b));
+    canvas.fill_rect(viewport).expect("Error when drawing");
+    let [r, g, b] = palette.foreground;
+    canvas.set_draw_color(Color::RGB(r, g, b));
     for x in 0..DISP_WIDTH {
         for y in 0..DISP_HEIGHT {
-            if chip8.video[y * DISP_WIDTH + x] {
+            if video[y * DISP_WIDTH + x] {
                 canvas
-                    .fill_rect(Rect::new(
-                        (x * SCALE as usize) as i32,
-                        (y * SCALE as usize) as i32,
-                        SCALE,
-                        SCALE,
-                    ))
+                    .fill_rect(pixel_rect(viewport, x, y, 1, 1))
                     .expect("Error when drawing");
             }
         }
     }
-    canvas.present();
 }
 
-fn match_key(key: Keycode) -> Option<usize> {
-    match key {
-        Keycode::Num1 => Some(0x1),
-        Keycode::Num2 => Some(0x2),
-        Keycode::Num3 => Some(0x3),
-        Keycode::Num4 => Some(0xC),
-        Keycode::Q => Some(0x4),
-        Keycode::W => Some(0x5),
-        Keycode::E => Some(0x6),
-        Keycode::R => Some(0xD),
-        Keycode::A => Some(0x7),
-        Keycode::S => Some(0x8),
-        Keycode::D => Some(0x9),
-        Keycode::F => Some(0xE),
-        Keycode::Z => Some(0xA),
-        Keycode::X => Some(0x0),
-        Keycode::C => Some(0xB),
-        Keycode::V => Some(0xF),
-        _ => None,
+/// Output pixels covered by the `width` x `height` CHIP-8 pixels from (`x`, `y`) on.
+/// Edges are rounded separately so pixels tile the viewport without gaps at any size.
+fn pixel_rect(viewport: Rect, x: usize, y: usize, width: usize, height: usize) -> Rect {
+    let column = |x: usize| viewport.x() + (x as u32 * viewport.width() / DISP_WIDTH as u32) as i32;
+    let row = |y: usize| viewport.y() + (y as u32 * viewport.height() / DISP_HEIGHT as u32) as i32;
+    let (left, top) = (column(x), row(y));
+    Rect::new(
+        left,
+        top,
+        (column(x + width) - left) as u32,
+        (row(y + height) - top) as u32,
+    )
+}
+
+/// Frame the sprite `draw` covers on the display drawn into `viewport`, the part of it
+/// that fits before the edges.
+fn outline_sprite(canvas: &mut WindowCanvas, viewport: Rect, draw: SpriteDraw) {
+    let (x, y) = (draw.x as usize, draw.y as usize);
+    let width = 8.min(DISP_WIDTH - x);
+    let height = (draw.height as usize).min(DISP_HEIGHT - y);
+    if height == 0 {
+        return;
     }
+    let scale = canvas.scale();
+    canvas.set_scale(1.0, 1.0).expect("Error when drawing");
+    canvas.set_draw_color(SPRITE_OUTLINE);
+    canvas
+        .draw_rect(pixel_rect(viewport, x, y, width, height))
+        .expect("Error when drawing");
+    canvas
+        .set_scale(scale.0, scale.1)
+        .expect("Error when drawing");
 }
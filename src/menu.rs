@@ -0,0 +1,354 @@
+use crate::config::Config;
+use crate::i18n::{tr, tr_with};
+use crate::text::{GREY, LINE_HEIGHT, MARGIN, TEXT_SCALE, WHITE, dim, draw_text, overlay_size};
+use crate::window::Scaling;
+use chip_8::{PALETTES, Quirks};
+use sdl2::keyboard::{Keycode, Scancode};
+use sdl2::render::WindowCanvas;
+
+const MAX_TICKS_PER_FRAME: u32 = 1000;
+const MAX_FRAME_SKIP: u32 = 9;
+
+#[derive(Clone, Copy, PartialEq)]
+enum Item {
+    Resume,
+    Palette,
+    HighContrast,
+    Scaling,
+    Speed,
+    FrameSkip,
+    Quirks,
+    Volume,
+    KeyBindings,
+    RecentRoms,
+    Quit,
+}
+
+const ITEMS: [Item; 11] = [
+    Item::Resume,
+    Item::Palette,
+    Item::HighContrast,
+    Item::Scaling,
+    Item::Speed,
+    Item::FrameSkip,
+    Item::Quirks,
+    Item::Volume,
+    Item::KeyBindings,
+    Item::RecentRoms,
+    Item::Quit,
+];
+
+impl Item {
+    fn name(self) -> &'static str {
+        tr(match self {
+            Item::Resume => "menu.resume",
+            Item::Palette => "menu.palette",
+            Item::HighContrast => "menu.contrast",
+            Item::Scaling => "menu.scaling",
+            Item::Speed => "menu.speed",
+            Item::FrameSkip => "menu.frame_skip",
+            Item::Quirks => "menu.quirks",
+            Item::Volume => "menu.volume",
+            Item::KeyBindings => "menu.key_bindings",
+            Item::RecentRoms => "menu.recent_roms",
+            Item::Quit => "menu.quit",
+        })
+    }
+
+    /// Current value of a setting, `None` for entries that are not settings.
+    fn value(self, config: &Config) -> Option<String> {
+        Some(match self {
+            Item::Palette => config.palette.clone(),
+            Item::HighContrast if config.high_contrast => tr("menu.contrast_high").to_string(),
+            Item::HighContrast => tr("menu.contrast_normal").to_string(),
+            Item::Scaling => config.window.scaling.name().to_string(),
+            Item::Speed => config.ticks_per_frame.to_string(),
+            Item::FrameSkip => config.frame_skip.to_string(),
+            Item::Quirks => config.quirks.clone(),
+            Item::Volume => config.volume.to_string(),
+            Item::Resume | Item::KeyBindings | Item::RecentRoms | Item::Quit => return None,
+        })
+    }
+}
+
+pub enum MenuAction {
+    None,
+    /// A setting in the config was changed
+    Changed,
+    Resume,
+    OpenSwitcher,
+    Quit,
+}
+
+enum Page {
+    Settings,
+    /// Key binding list, `waiting` for the new host key of the selected one
+    Keys {
+        selected: usize,
+        waiting: bool,
+    },
+}
+
+/// Pause menu drawn over the game for changing settings at runtime.
+pub struct PauseMenu {
+    selected: usize,
+    page: Page,
+}
+
+impl PauseMenu {
+    pub fn new() -> PauseMenu {
+        PauseMenu {
+            selected: 0,
+            page: Page::Settings,
+        }
+    }
+
+    /// `scancode` is only used when rebinding keys in scancode mode.
+    pub fn handle_key(
+        &mut self,
+        key: Keycode,
+        scancode: Option<Scancode>,
+        config: &mut Config,
+    ) -> MenuAction {
+        match self.page {
+            Page::Settings => self.handle_settings_key(key, config),
+            Page::Keys { selected, waiting } => {
+                self.handle_keys_key(key, scancode, selected, waiting, config)
+            }
+        }
+    }
+
+    fn handle_settings_key(&mut self, key: Keycode, config: &mut Config) -> MenuAction {
+        let item = ITEMS[self.selected];
+        match key {
+            Keycode::Escape => MenuAction::Resume,
+            Keycode::Up => {
+                self.selected = (self.selected + ITEMS.len() - 1) % ITEMS.len();
+                MenuAction::None
+            }
+            Keycode::Down => {
+                self.selected = (self.selected + 1) % ITEMS.len();
+                MenuAction::None
+            }
+            Keycode::Left => change(item, config, -1),
+            Keycode::Right => change(item, config, 1),
+            Keycode::Return | Keycode::KpEnter => match item {
+                Item::Resume => MenuAction::Resume,
+                Item::KeyBindings => {
+                    self.page = Page::Keys {
+                        selected: 0,
+                        waiting: false,
+                    };
+                    MenuAction::None
+                }
+                Item::RecentRoms => MenuAction::OpenSwitcher,
+                Item::Quit => MenuAction::Quit,
+                _ => change(item, config, 1),
+            },
+            _ => MenuAction::None,
+        }
+    }
+
+    fn handle_keys_key(
+        &mut self,
+        key: Keycode,
+        scancode: Option<Scancode>,
+        selected: usize,
+        waiting: bool,
+        config: &mut Config,
+    ) -> MenuAction {
+        if waiting {
+            self.page = Page::Keys {
+                selected,
+                waiting: false,
+            };
+            if key == Keycode::Escape {
+                return MenuAction::None;
+            }
+            let Some(name) = config.key_name(Some(key), scancode) else {
+                return MenuAction::None;
+            };
+            // A host key drives only one CHIP-8 key, the previous owner loses it.
+            for bound in config.keys.iter_mut() {
+                if *bound == name {
+                    bound.clear();
+                }
+            }
+            config.keys[selected] = name;
+            return MenuAction::Changed;
+        }
+
+        let selected = match key {
+            Keycode::Escape => {
+                self.page = Page::Settings;
+                return MenuAction::None;
+            }
+            Keycode::Return | Keycode::KpEnter => {
+                self.page = Page::Keys {
+                    selected,
+                    waiting: true,
+                };
+                return MenuAction::None;
+            }
+            Keycode::Up => (selected + 15) % 16,
+            Keycode::Down => (selected + 1) % 16,
+            Keycode::Left | Keycode::Right => (selected + 8) % 16,
+            _ => selected,
+        };
+        self.page = Page::Keys {
+            selected,
+            waiting: false,
+        };
+        MenuAction::None
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas, config: &Config) {
+        dim(canvas);
+        match self.page {
+            Page::Settings => self.draw_settings(canvas, config),
+            Page::Keys { selected, waiting } => draw_keys(canvas, config, selected, waiting),
+        }
+    }
+
+    fn draw_settings(&self, canvas: &mut WindowCanvas, config: &Config) {
+        draw_text(canvas, tr("menu.paused"), MARGIN, MARGIN, TEXT_SCALE, WHITE);
+
+        let mut y = MARGIN + 2 * LINE_HEIGHT;
+        // Values line up after the longest setting name.
+        let width = ITEMS
+            .iter()
+            .filter(|item| item.value(config).is_some())
+            .map(|item| item.name().chars().count())
+            .max()
+            .unwrap_or(0)
+            + 2;
+        for (n, item) in ITEMS.iter().enumerate() {
+            let label = match item.value(config) {
+                Some(value) => format!("{:width$}< {} >", item.name(), value, width = width),
+                None => item.name().to_string(),
+            };
+            draw_entry(canvas, &label, MARGIN, y, n == self.selected);
+            y += LINE_HEIGHT;
+        }
+
+        draw_footer(canvas, tr("menu.footer"));
+    }
+}
+
+fn draw_keys(canvas: &mut WindowCanvas, config: &Config, selected: usize, waiting: bool) {
+    draw_text(
+        canvas,
+        tr("menu.key_bindings"),
+        MARGIN,
+        MARGIN,
+        TEXT_SCALE,
+        WHITE,
+    );
+
+    let (width, _) = overlay_size(canvas);
+    for (key, name) in config.keys.iter().enumerate() {
+        let x = MARGIN + (key / 8) as i32 * (width as i32 / 2);
+        let y = MARGIN + (2 + (key % 8) as i32) * LINE_HEIGHT;
+        let name = if waiting && key == selected {
+            "..."
+        } else if name.is_empty() {
+            "-"
+        } else {
+            name
+        };
+        draw_entry(
+            canvas,
+            &format!("{:X}  {}", key, name),
+            x,
+            y,
+            key == selected,
+        );
+    }
+
+    if waiting {
+        draw_footer(
+            canvas,
+            &tr_with(
+                "menu.press_key",
+                &[("key", &format_args!("{:X}", selected))],
+            ),
+        );
+    } else {
+        draw_footer(canvas, tr("menu.keys_footer"));
+    }
+}
+
+/// Step setting `item` by `delta`.
+fn change(item: Item, config: &mut Config, delta: i32) -> MenuAction {
+    match item {
+        Item::Palette => {
+            let names: Vec<&str> = PALETTES.iter().map(|palette| palette.name).collect();
+            config.palette = cycle(&names, &config.palette, delta).to_string();
+        }
+        Item::HighContrast => config.high_contrast = !config.high_contrast,
+        Item::Scaling => {
+            let names = Scaling::ALL.map(Scaling::name);
+            let name = cycle(&names, config.window.scaling.name(), delta);
+            config.window.scaling = Scaling::ALL[names.iter().position(|n| *n == name).unwrap()];
+        }
+        Item::Speed => {
+            config.ticks_per_frame = config
+                .ticks_per_frame
+                .saturating_add_signed(delta)
+                .clamp(1, MAX_TICKS_PER_FRAME);
+        }
+        Item::FrameSkip => {
+            config.frame_skip = config
+                .frame_skip
+                .saturating_add_signed(delta)
+                .min(MAX_FRAME_SKIP);
+        }
+        Item::Quirks => {
+            let names: Vec<&str> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+            config.quirks = cycle(&names, &config.quirks, delta).to_string();
+        }
+        Item::Volume => {
+            config.volume = (config.volume as i32 + delta * 10).clamp(0, 100) as u8;
+        }
+        _ => return MenuAction::None,
+    }
+    MenuAction::Changed
+}
+
+/// Name `delta` steps away from `current` in `names`, wrapping around.
+fn cycle<'a>(names: &[&'a str], current: &str, delta: i32) -> &'a str {
+    let idx = names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(current))
+        .unwrap_or(0);
+    names[(idx as i32 + delta).rem_euclid(names.len() as i32) as usize]
+}
+
+/// Menu line, highlighted with a `>` marker when selected.
+pub fn draw_entry(canvas: &mut WindowCanvas, label: &str, x: i32, y: i32, selected: bool) {
+    let (marker, color) = if selected {
+        ("> ", WHITE)
+    } else {
+        ("  ", GREY)
+    };
+    draw_text(
+        canvas,
+        &format!("{}{}", marker, label),
+        x,
+        y,
+        TEXT_SCALE,
+        color,
+    );
+}
+
+pub fn draw_footer(canvas: &mut WindowCanvas, hint: &str) {
+    let (_, height) = overlay_size(canvas);
+    draw_text(
+        canvas,
+        hint,
+        MARGIN,
+        height as i32 - MARGIN - LINE_HEIGHT,
+        TEXT_SCALE,
+        GREY,
+    );
+}
@@ -0,0 +1,531 @@
+use crate::game_config::{GameConfig, MonitorSession, Tracepoint};
+use crate::hud;
+use crate::rom::{START_ADDR, footprint};
+use crate::run::{hex_dump, parse_addr};
+use crate::watch::Watch;
+use chip_8::{Chip8, Symbols, disassemble};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io::{self, BufRead};
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread;
+
+/// Bytes `m` dumps when no length is given.
+const DUMP_LEN: u16 = 0x40;
+
+/// Instructions `d` disassembles when no count is given.
+const DISASSEMBLE_COUNT: u16 = 10;
+
+const HELP: &str = "\
+m ADDR [LEN]   dump LEN bytes of memory, 0x40 by default
+e ADDR BYTE..  write bytes to memory from ADDR on
+w FILE         save memory from 0x200 to the last non-zero byte as a ROM
+r              show registers, timers and the stack
+g [ADDR]       run, from ADDR if given
+c ADDR         run until ADDR is about to execute
+u EXPR         run until EXPR, like V3 == 0, is non-zero
+s              execute one instruction
+h              stop running
+b [ADDR]       set or clear a breakpoint at ADDR, list them without
+t ADDR [TEXT]  print TEXT whenever ADDR executes, with {V0}..{VF}, {I}, {PC},
+               {SP}, {DT} and {ST} filled in; clear it without TEXT, list them without ADDR
+k [KEY]        show the keypad and whether FX0A waits for a key; KEY toggles holding it
+d [ADDR] [N]   disassemble N instructions, 10 from PC by default
+x [EXPR]       show EXPR, like [I + V1] * 2, at every stop and with r; clear it when
+               given again, show them all without EXPR
+";
+
+/// Machine monitor reading commands from stdin, in the manner of the ROM monitors of
+/// 8-bit computers: `m 200 20` dumps memory, `r` shows registers, `b 2A4` sets a breakpoint,
+/// `g` runs on and `d 200` disassembles. Addresses and lengths are in hex, or label names
+/// when given the symbols of the program.
+///
+/// Tracepoints print a message with register values each time their address executes and
+/// let the machine run on, for watching a hot path without tracing every instruction.
+/// Watches are expressions printed with their value whenever the machine stops.
+///
+/// Breakpoints, tracepoints and watches are kept in the game's settings, so debugging the
+/// same ROM again picks up where the last session left off.
+///
+/// Stdin is read on a thread, so the frontend stays responsive while no command comes in.
+/// Frontends call `poll` once per frame and `check` before every instruction.
+pub struct Monitor {
+    commands: Receiver<String>,
+    breakpoints: BTreeSet<u16>,
+
+    /// Messages by address, see `expand` for the placeholders
+    tracepoints: BTreeMap<u16, String>,
+
+    /// Labels of the program, shown next to their address and taken for it in commands
+    symbols: Symbols,
+
+    watches: Vec<Watch>,
+
+    /// Address `c` runs to, cleared at the next stop
+    run_to: Option<u16>,
+
+    /// Condition `u` runs until, cleared at the next stop
+    until: Option<Watch>,
+
+    /// Whether breakpoints, tracepoints or watches changed since the last `persist`
+    changed: bool,
+
+    mode: Mode,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Running,
+    Stopped,
+    /// Running again from a breakpoint, which must not stop the first instruction
+    Resuming,
+    /// One instruction to execute before stopping again
+    Stepping,
+    Stepped,
+}
+
+impl Monitor {
+    /// Start reading commands, with the machine stopped until `g` if `stopped`.
+    pub fn spawn(stopped: bool, symbols: Symbols) -> Monitor {
+        let (sender, commands) = mpsc::channel();
+        thread::spawn(move || {
+            for line in io::stdin().lock().lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Monitor {
+            commands,
+            breakpoints: BTreeSet::new(),
+            tracepoints: BTreeMap::new(),
+            symbols,
+            watches: Vec::new(),
+            run_to: None,
+            until: None,
+            changed: false,
+            mode: if stopped {
+                Mode::Stopped
+            } else {
+                Mode::Running
+            },
+        }
+    }
+
+    /// Take over the breakpoints, tracepoints and watches of an earlier session,
+    /// replacing the current ones.
+    pub fn restore(&mut self, session: &MonitorSession) {
+        self.breakpoints = session.breakpoints.iter().copied().collect();
+        self.tracepoints = session
+            .tracepoints
+            .iter()
+            .map(|trace| (trace.address, trace.message.clone()))
+            .collect();
+        self.watches = session
+            .watches
+            .iter()
+            .filter_map(|text| {
+                Watch::parse(text, &self.symbols)
+                    .inspect_err(|err| println!("Dropping watch {}: {}", text, err))
+                    .ok()
+            })
+            .collect();
+        self.changed = false;
+        if !(self.breakpoints.is_empty() && self.tracepoints.is_empty() && self.watches.is_empty())
+        {
+            println!(
+                "Restored {} breakpoints, {} tracepoints and {} watches",
+                self.breakpoints.len(),
+                self.tracepoints.len(),
+                self.watches.len()
+            );
+        }
+    }
+
+    /// Save breakpoints, tracepoints and watches to `game` when they changed.
+    pub fn persist(&mut self, game: &mut GameConfig) {
+        if !self.changed {
+            return;
+        }
+        self.changed = false;
+        game.monitor = MonitorSession {
+            breakpoints: self.breakpoints.iter().copied().collect(),
+            tracepoints: self
+                .tracepoints
+                .iter()
+                .map(|(&address, message)| Tracepoint {
+                    address,
+                    message: message.clone(),
+                })
+                .collect(),
+            watches: self.watches.iter().map(Watch::to_string).collect(),
+        };
+        game.save();
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.mode == Mode::Stopped
+    }
+
+    /// Execute the commands typed since the last call, returning whether the machine
+    /// is stopped.
+    pub fn poll(&mut self, chip8: &mut Chip8) -> bool {
+        loop {
+            match self.commands.try_recv() {
+                Ok(line) => self.execute(&line, chip8),
+                // Without stdin nobody could ever resume, so run on.
+                Err(TryRecvError::Disconnected) if self.stopped() => self.mode = Mode::Running,
+                Err(_) => return self.stopped(),
+            }
+        }
+    }
+
+    /// Block until a command resumes the machine, for frontends with nothing else to do.
+    pub fn wait(&mut self, chip8: &mut Chip8) {
+        while self.stopped() {
+            match self.commands.recv() {
+                Ok(line) => self.execute(&line, chip8),
+                Err(_) => self.mode = Mode::Running,
+            }
+        }
+    }
+
+    /// Whether the instruction at PC may execute, stopping at breakpoints, after a step and
+    /// where `c` or `u` run to.
+    /// Tracepoints print their message when it may.
+    pub fn check(&mut self, chip8: &Chip8) -> bool {
+        let execute = self.advance(chip8);
+        if execute && let Some(message) = self.tracepoints.get(&chip8.pc) {
+            println!("{:#05x}: {}", chip8.pc, expand(message, chip8));
+        }
+        execute
+    }
+
+    fn advance(&mut self, chip8: &Chip8) -> bool {
+        match self.mode {
+            Mode::Running if self.breakpoints.contains(&chip8.pc) => {
+                self.stop("Breakpoint", chip8);
+                false
+            }
+            Mode::Running if self.run_to == Some(chip8.pc) => {
+                self.stop("Reached", chip8);
+                false
+            }
+            Mode::Running => {
+                let Some(until) = &self.until else {
+                    return true;
+                };
+                let reason = match until.eval(chip8) {
+                    Ok(0) => return true,
+                    Ok(_) => format!("{} holds", until),
+                    Err(err) => format!("{}: {}", until, err),
+                };
+                self.stop(&reason, chip8);
+                false
+            }
+            Mode::Stopped => false,
+            Mode::Resuming => {
+                self.mode = Mode::Running;
+                true
+            }
+            Mode::Stepping => {
+                self.mode = Mode::Stepped;
+                true
+            }
+            Mode::Stepped => {
+                self.stop("Stepped", chip8);
+                false
+            }
+        }
+    }
+
+    fn stop(&mut self, reason: &str, chip8: &Chip8) {
+        self.mode = Mode::Stopped;
+        self.run_to = None;
+        self.until = None;
+        println!("{} at {}", reason, self.instruction_line(chip8, chip8.pc));
+        self.show_watches(chip8);
+    }
+
+    fn show_watches(&self, chip8: &Chip8) {
+        for watch in &self.watches {
+            println!("{}", watch_line(watch, chip8));
+        }
+    }
+
+    /// `x` with `expr` after it: add or clear a watch, or show them all.
+    fn watch(&mut self, expr: &str, chip8: &Chip8) {
+        if expr.is_empty() {
+            self.show_watches(chip8);
+            return;
+        }
+        let watch = match Watch::parse(expr, &self.symbols) {
+            Ok(watch) => watch,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let text = watch.to_string();
+        if let Some(index) = self.watches.iter().position(|w| w.to_string() == text) {
+            self.watches.remove(index);
+            self.changed = true;
+            println!("Watch {} cleared", text);
+            return;
+        }
+        println!("{}", watch_line(&watch, chip8));
+        self.watches.push(watch);
+        self.changed = true;
+    }
+
+    fn execute(&mut self, line: &str, chip8: &mut Chip8) {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return;
+        };
+        match command {
+            "t" => return self.tracepoint(line.trim_start()[1..].trim()),
+            "w" => return export(line.trim_start()[1..].trim(), chip8),
+            "x" => return self.watch(line.trim_start()[1..].trim(), chip8),
+            "u" => return self.run_until(line.trim_start()[1..].trim()),
+            _ => {}
+        }
+        let args: Result<Vec<u16>, String> = words.map(|word| self.address(word)).collect();
+        let args = match args {
+            Ok(args) => args,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        match (command, args.as_slice()) {
+            ("m", [addr] | [addr, _]) => {
+                let len = args.get(1).copied().unwrap_or(DUMP_LEN);
+                let end = addr.saturating_add(len).min(chip8.bus.ram.len() as u16);
+                print!("{}", hex_dump(&chip8.bus.ram, *addr..end));
+            }
+            ("e", [addr, bytes @ ..]) if !bytes.is_empty() => {
+                let start = *addr as usize;
+                let end = start + bytes.len();
+                if let Some(byte) = bytes.iter().find(|&&byte| byte > 0xFF) {
+                    println!("{:#x} is not a byte", byte);
+                } else if end > chip8.bus.ram.len() {
+                    println!(
+                        "{} bytes from {:#05x} go past the end of RAM",
+                        bytes.len(),
+                        addr
+                    );
+                } else {
+                    for (cell, &byte) in chip8.bus.ram[start..end].iter_mut().zip(bytes) {
+                        *cell = byte as u8;
+                    }
+                    print!("{}", hex_dump(&chip8.bus.ram, *addr..end as u16));
+                }
+            }
+            ("r", []) => {
+                print!("{}", registers(chip8));
+                self.show_watches(chip8);
+            }
+            ("g", [] | [_]) => {
+                if let Some(&addr) = args.first() {
+                    chip8.pc = addr;
+                }
+                self.mode = Mode::Resuming;
+            }
+            ("c", [addr]) => {
+                self.run_to = Some(*addr);
+                self.mode = Mode::Resuming;
+            }
+            ("s", []) => self.mode = Mode::Stepping,
+            ("h", []) if !self.stopped() => self.stop("Stopped", chip8),
+            ("h", []) => {}
+            ("b", []) => {
+                let list: Vec<String> = self
+                    .breakpoints
+                    .iter()
+                    .map(|&addr| self.located(addr))
+                    .collect();
+                println!("Breakpoints: [{}]", list.join(", "));
+            }
+            ("b", [addr]) => {
+                self.changed = true;
+                if self.breakpoints.remove(addr) {
+                    println!("Breakpoint at {} cleared", self.located(*addr));
+                } else {
+                    self.breakpoints.insert(*addr);
+                    println!("Breakpoint at {} set", self.located(*addr));
+                }
+            }
+            ("k", []) => print!("{}", hud::keypad(chip8)),
+            ("k", [key]) if *key < 16 => {
+                let key = *key as usize;
+                chip8.keypad[key] = u8::from(chip8.keypad[key] == 0);
+                print!("{}", hud::keypad(chip8));
+            }
+            ("d", [] | [_] | [_, _]) => {
+                let start = args.first().copied().unwrap_or(chip8.pc);
+                let count = args.get(1).copied().unwrap_or(DISASSEMBLE_COUNT);
+                for addr in (start..0xFFF).step_by(2).take(count as usize) {
+                    println!("{}", self.instruction_line(chip8, addr));
+                }
+            }
+            ("?" | "help", _) => print!("{}", HELP),
+            _ => println!("Unknown command {:?}, ? lists them", line.trim()),
+        }
+    }
+
+    /// `u` with `expr` after it: run until the expression is non-zero.
+    fn run_until(&mut self, expr: &str) {
+        if expr.is_empty() {
+            println!("u needs an expression");
+            return;
+        }
+        match Watch::parse(expr, &self.symbols) {
+            Ok(until) => {
+                self.until = Some(until);
+                self.mode = Mode::Resuming;
+            }
+            Err(err) => println!("{}", err),
+        }
+    }
+
+    /// `t` with `args` after it: set, clear or list tracepoints.
+    fn tracepoint(&mut self, args: &str) {
+        if args.is_empty() {
+            for (&addr, message) in &self.tracepoints {
+                println!("{}: {}", self.located(addr), message);
+            }
+            return;
+        }
+        let (addr, message) = args.split_once(char::is_whitespace).unwrap_or((args, ""));
+        let addr = match self.address(addr) {
+            Ok(addr) => addr,
+            Err(err) => {
+                println!("{}", err);
+                return;
+            }
+        };
+        let message = message.trim();
+        if message.is_empty() {
+            if self.tracepoints.remove(&addr).is_some() {
+                self.changed = true;
+                println!("Tracepoint at {} cleared", self.located(addr));
+            }
+        } else {
+            println!("Tracepoint at {} set", self.located(addr));
+            self.tracepoints.insert(addr, message.to_string());
+            self.changed = true;
+        }
+    }
+
+    /// Address of the label `word`, or the hex number it is.
+    fn address(&self, word: &str) -> Result<u16, String> {
+        match self.symbols.address(word) {
+            Some(addr) => Ok(addr),
+            None => parse_addr(word, 0xFFF),
+        }
+    }
+
+    /// `addr` in hex, followed by the name of a label there like `0x2a4 <draw-ship>`.
+    fn located(&self, addr: u16) -> String {
+        match self.symbols.name(addr) {
+            Some(name) => format!("{:#05x} <{}>", addr, name),
+            None => format!("{:#05x}", addr),
+        }
+    }
+
+    /// `addr` with the instruction there, like `0x2a4: 6a02  LD VA, 0x02`.
+    fn instruction_line(&self, chip8: &Chip8, addr: u16) -> String {
+        match chip8.bus.ram.get(addr as usize..addr as usize + 2) {
+            Some(&[high, low]) => {
+                let opcode = u16::from_be_bytes([high, low]);
+                format!(
+                    "{}: {:04x}  {}",
+                    self.located(addr),
+                    opcode,
+                    disassemble(opcode)
+                )
+            }
+            _ => format!("{:#05x}: outside of RAM", addr),
+        }
+    }
+}
+
+/// `message` with `{V0}` to `{VF}`, `{I}`, `{PC}`, `{SP}`, `{DT}` and `{ST}` replaced by the
+/// values in `chip8`, registers in hex. Anything else in braces is left alone.
+fn expand(message: &str, chip8: &Chip8) -> String {
+    let mut text = String::new();
+    let mut rest = message;
+    while let Some(start) = rest.find('{') {
+        text += &rest[..start];
+        rest = &rest[start..];
+        let Some(end) = rest.find('}') else { break };
+        let name = rest[1..end].to_ascii_uppercase();
+        let value = match name.as_str() {
+            "I" => Some(format!("{:#05x}", chip8.i)),
+            "PC" => Some(format!("{:#05x}", chip8.pc)),
+            "SP" => Some(chip8.sp.to_string()),
+            "DT" => Some(chip8.dt.to_string()),
+            "ST" => Some(chip8.st.to_string()),
+            _ => name
+                .strip_prefix('V')
+                .filter(|reg| reg.len() == 1)
+                .and_then(|reg| u8::from_str_radix(reg, 16).ok())
+                .map(|reg| format!("{:#04x}", chip8.registers[reg as usize])),
+        };
+        match value {
+            Some(value) => text += &value,
+            None => text += &rest[..=end],
+        }
+        rest = &rest[end + 1..];
+    }
+    text + rest
+}
+
+/// `w` with `path` after it: save the program area of RAM as a ROM file. It holds the
+/// edits made with `e`, and whatever the program itself stored there when it already ran.
+fn export(path: &str, chip8: &Chip8) {
+    if path.is_empty() {
+        println!("w needs a file name");
+        return;
+    }
+    let program = &chip8.bus.ram[START_ADDR..];
+    let rom = &program[..footprint(program)];
+    match fs::write(path, rom) {
+        Ok(()) => println!(
+            "Saved {} bytes, {:#05x}..{:#05x}, to {}",
+            rom.len(),
+            START_ADDR,
+            START_ADDR + rom.len(),
+            path
+        ),
+        Err(err) => println!("Could not save {}: {}", path, err),
+    }
+}
+
+/// `watch` with its value in `chip8`, in hex and decimal.
+fn watch_line(watch: &Watch, chip8: &Chip8) -> String {
+    match watch.eval(chip8) {
+        Ok(value) => format!("{} = {:#x} ({})", watch, value, value),
+        Err(err) => format!("{}: {}", watch, err),
+    }
+}
+
+/// Registers, timers and stack of `chip8`, a few lines of text.
+fn registers(chip8: &Chip8) -> String {
+    let mut text = format!(
+        "PC {:#05x}  I {:#05x}  SP {}  DT {}  ST {}\n",
+        chip8.pc, chip8.i, chip8.sp, chip8.dt, chip8.st
+    );
+    for (reg, value) in chip8.registers.iter().enumerate() {
+        let separator = if reg % 8 == 7 { "\n" } else { "  " };
+        text += &format!("V{:X} {:#04x}{}", reg, value, separator);
+    }
+    let stack: Vec<String> = chip8.stack[..(chip8.sp as usize).min(chip8.stack.len())]
+        .iter()
+        .map(|addr| format!("{:#05x}", addr))
+        .collect();
+    text += &format!("stack [{}]\n", stack.join(", "));
+    text
+}
@@ -0,0 +1,99 @@
+//! Octo `.gif` cartridges.
+//!
+//! The label image hides the payload in the low nybble of every pixel's palette index,
+//! two pixels per byte, high nybble first, over all frames in order. The payload is a big endian
+//! `u32` length followed by that many bytes of JSON, `{"options": {...}, "program": "..."}`,
+//! where `program` is Octo source.
+
+use crate::assembler::assemble;
+use crate::cartridge::{Cartridge, CartridgeQuirks, Metadata, invalid};
+use crate::chip8::Quirks;
+use serde::Deserialize;
+use std::io;
+
+/// Largest program Octo lets a CHIP-8 or SUPER-CHIP game use.
+const MAX_SIZE_SCHIP: u32 = 3584;
+
+#[derive(Deserialize)]
+struct Payload {
+    #[serde(default)]
+    options: Options,
+    program: String,
+}
+
+/// Options saved by Octo, only those this interpreter has a use for.
+#[derive(Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+struct Options {
+    tickrate: Option<u32>,
+    fill_color: Option<String>,
+    background_color: Option<String>,
+    shift_quirks: Option<bool>,
+    jump_quirks: Option<bool>,
+    logic_quirks: Option<bool>,
+    clip_quirks: Option<bool>,
+    load_store_quirks: Option<bool>,
+    max_size: Option<u32>,
+}
+
+pub(crate) fn read_cart(bytes: &[u8]) -> io::Result<Cartridge> {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let mut decoder = options.read_info(bytes).map_err(invalid)?;
+    let mut nybbles = Vec::new();
+    while let Some(frame) = decoder.read_next_frame().map_err(invalid)? {
+        nybbles.extend(frame.buffer.iter().map(|index| index & 0xF));
+    }
+    let payload: Vec<u8> = nybbles
+        .chunks_exact(2)
+        .map(|pair| pair[0] << 4 | pair[1])
+        .collect();
+
+    let len = payload
+        .get(..4)
+        .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize)
+        .ok_or_else(|| invalid("no Octo cartridge data"))?;
+    let json = payload
+        .get(4..4 + len)
+        .ok_or_else(|| invalid("truncated Octo cartridge data"))?;
+    let payload: Payload = serde_json::from_slice(json).map_err(invalid)?;
+    let rom = assemble(&payload.program)
+        .map_err(|err| invalid(format!("Octo cartridge program, {}", err)))?;
+
+    let options = payload.options;
+    let quirks = Quirks {
+        shift_uses_vy: !options.shift_quirks.unwrap_or(false),
+        jump_uses_vx: options.jump_quirks.unwrap_or(false),
+        vf_reset: options.logic_quirks.unwrap_or(false),
+        clip_sprites: options.clip_quirks.unwrap_or(false),
+        vip_random: false,
+        add_i_sets_vf: false,
+        memory_increments_i: !options.load_store_quirks.unwrap_or(false),
+        wrap_memory: false,
+    };
+    let variant = match options.max_size {
+        Some(size) if size > MAX_SIZE_SCHIP => "xo-chip",
+        _ => "chip-8",
+    };
+    Ok(Cartridge {
+        metadata: Metadata {
+            variant: Some(variant.to_string()),
+            quirks: Some(CartridgeQuirks::Custom(quirks)),
+            foreground: options.fill_color.as_deref().and_then(parse_color),
+            background: options.background_color.as_deref().and_then(parse_color),
+            ticks_per_frame: options.tickrate,
+            ..Metadata::default()
+        },
+        rom,
+    })
+}
+
+/// `#RRGGBB` as used by Octo.
+fn parse_color(text: &str) -> Option<[u8; 3]> {
+    let hex = text.strip_prefix('#')?;
+    if hex.len() != 6 {
+        return None;
+    }
+    let rgb = u32::from_str_radix(hex, 16).ok()?;
+    Some([(rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8])
+}
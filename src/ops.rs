@@ -0,0 +1,39 @@
+use chip_8::{Instruction, Quirks};
+use clap::Args;
+use std::process::ExitCode;
+use tracing::error;
+
+#[derive(Args)]
+pub struct OpsArgs {
+    /// Only list the instructions this quirk changes, e.g. `shift_uses_vy`
+    #[arg(long)]
+    quirk: Option<String>,
+}
+
+/// `chip-8 ops`: list the instructions the interpreter runs, with the quirks that change them.
+pub fn ops(args: OpsArgs) -> ExitCode {
+    if let Some(quirk) = &args.quirk
+        && !Quirks::NAMES.contains(&quirk.as_str())
+    {
+        error!(quirk = %quirk, known = %Quirks::NAMES.join(", "), "No such quirk");
+        return ExitCode::FAILURE;
+    }
+    for opcode in Instruction::EXAMPLES {
+        let Some(reference) = Instruction::decode(opcode).reference() else {
+            continue;
+        };
+        if let Some(quirk) = &args.quirk
+            && !reference.quirks.iter().any(|(name, _)| name == quirk)
+        {
+            continue;
+        }
+        println!(
+            "{}  {:<15} {}",
+            reference.pattern, reference.mnemonic, reference.semantics
+        );
+        for (quirk, effect) in reference.quirks {
+            println!("{:22}with {}: {}", "", quirk, effect);
+        }
+    }
+    ExitCode::SUCCESS
+}
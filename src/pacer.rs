@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+/// Frames run at once to catch up after a stall, anything beyond is dropped.
+const MAX_CATCH_UP: u64 = 5;
+
+/// Measured timing of a `FramePacer`.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PacerStats {
+    /// Frames emulated since start
+    pub emulated_frames: u64,
+
+    /// Frames given up on because the host fell too far behind
+    pub dropped_frames: u64,
+
+    /// Emulated frames relative to wall-clock time over the last second, `1.0` is full speed
+    pub speed: f64,
+}
+
+/// Keeps emulation locked to a fixed frame rate (the 60 Hz timer clock) from wall-clock time,
+/// whatever rate the host loop runs at.
+///
+/// Frames are scheduled at exact multiples of the frame period from a base instant,
+/// so rounding never accumulates into drift over long sessions.
+pub struct FramePacer {
+    hz: u64,
+    base: Instant,
+    /// Frames scheduled since `base`
+    scheduled: u64,
+    stats: PacerStats,
+    window_start: Instant,
+    window_frames: u64,
+}
+
+impl FramePacer {
+    pub fn new(hz: u64) -> FramePacer {
+        let now = Instant::now();
+        FramePacer {
+            hz,
+            base: now,
+            scheduled: 0,
+            stats: PacerStats {
+                speed: 1.0,
+                ..PacerStats::default()
+            },
+            window_start: now,
+            window_frames: 0,
+        }
+    }
+
+    /// Number of frames to emulate now: `0` when the host runs faster than `hz`,
+    /// more than one when it fell behind.
+    pub fn frames_due(&mut self) -> u32 {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.base).as_nanos() as u64;
+        let behind = (elapsed * self.hz / 1_000_000_000).saturating_sub(self.scheduled);
+
+        let due = behind.min(MAX_CATCH_UP);
+        if behind > MAX_CATCH_UP {
+            self.stats.dropped_frames += behind - MAX_CATCH_UP;
+            self.rebase(now);
+        } else {
+            self.scheduled += due;
+        }
+        self.stats.emulated_frames += due;
+        self.window_frames += due;
+
+        let window = now.duration_since(self.window_start);
+        if window >= Duration::from_secs(1) {
+            self.stats.speed = self.window_frames as f64 / (window.as_secs_f64() * self.hz as f64);
+            self.window_start = now;
+            self.window_frames = 0;
+        }
+
+        due as u32
+    }
+
+    /// Time left until the next frame is due.
+    pub fn until_next_frame(&self) -> Duration {
+        let next = self.base + Duration::from_nanos((self.scheduled + 1) * 1_000_000_000 / self.hz);
+        next.saturating_duration_since(Instant::now())
+    }
+
+    /// How far the host is into the period of the last emulated frame, from `0.0` just as
+    /// it was due towards `1.0` when the next one is, for blending frames in between.
+    pub fn fraction(&self) -> f64 {
+        let elapsed = Instant::now().duration_since(self.base).as_secs_f64();
+        (elapsed * self.hz as f64 - self.scheduled as f64).clamp(0.0, 1.0)
+    }
+
+    /// Restart the schedule from now, without counting the gap as dropped frames.
+    /// Used after the emulation was paused on purpose.
+    pub fn reset(&mut self) {
+        let now = Instant::now();
+        self.rebase(now);
+        self.window_start = now;
+        self.window_frames = 0;
+    }
+
+    pub fn stats(&self) -> PacerStats {
+        self.stats
+    }
+
+    fn rebase(&mut self, now: Instant) {
+        self.base = now;
+        self.scheduled = 0;
+    }
+}
@@ -0,0 +1,98 @@
+use crate::run::parse_quirks;
+use chip_8::{Cartridge, CartridgeQuirks, Metadata, PALETTES};
+use clap::Args;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::{error, info};
+
+#[derive(Args)]
+pub struct PackArgs {
+    /// Path to the ROM to pack
+    rom: PathBuf,
+
+    /// Path of the `.c8x` file, defaults to the ROM path with a `.c8x` extension
+    #[arg(long, short, value_name = "FILE")]
+    output: Option<PathBuf>,
+
+    #[arg(long)]
+    title: Option<String>,
+
+    #[arg(long)]
+    author: Option<String>,
+
+    /// Platform the game was written for, e.g. chip-8, superchip or xo-chip
+    #[arg(long)]
+    variant: Option<String>,
+
+    /// Quirks preset the game needs (modern, cosmac, superchip)
+    #[arg(long, value_parser = check_quirks)]
+    quirks: Option<String>,
+
+    /// Palette the game looks best in
+    #[arg(long, value_parser = check_palette)]
+    palette: Option<String>,
+
+    /// What a key is used for, e.g. `--key 5=FIRE`, repeatable
+    #[arg(long = "key", value_name = "KEY=LABEL", value_parser = parse_key)]
+    keys: Vec<(String, String)>,
+}
+
+/// `chip-8 pack`: bundle a ROM and its metadata into a `.c8x` cartridge.
+pub fn pack(args: PackArgs) -> ExitCode {
+    let rom = match fs::read(&args.rom) {
+        Ok(rom) => rom,
+        Err(err) => {
+            error!(rom = %args.rom.display(), %err, "Could not load ROM");
+            return ExitCode::FAILURE;
+        }
+    };
+    let cartridge = Cartridge {
+        metadata: Metadata {
+            title: args.title,
+            author: args.author,
+            variant: args.variant,
+            quirks: args.quirks.map(CartridgeQuirks::Preset),
+            palette: args.palette,
+            keys: args.keys.into_iter().collect::<BTreeMap<_, _>>(),
+            ..Metadata::default()
+        },
+        rom,
+    };
+
+    let output = args
+        .output
+        .unwrap_or_else(|| args.rom.with_extension("c8x"));
+    if let Err(err) = cartridge.save(&output) {
+        error!(file = %output.display(), %err, "Could not write cartridge");
+        return ExitCode::FAILURE;
+    }
+    info!(file = %output.display(), "Cartridge written");
+    ExitCode::SUCCESS
+}
+
+fn check_quirks(name: &str) -> Result<String, String> {
+    parse_quirks(name).map(|_| name.to_string())
+}
+
+fn check_palette(name: &str) -> Result<String, String> {
+    if PALETTES
+        .iter()
+        .any(|palette| palette.name.eq_ignore_ascii_case(name))
+    {
+        return Ok(name.to_string());
+    }
+    let names: Vec<&str> = PALETTES.iter().map(|palette| palette.name).collect();
+    Err(format!("expected one of {}", names.join(", ")))
+}
+
+fn parse_key(text: &str) -> Result<(String, String), String> {
+    let (key, label) = text
+        .split_once('=')
+        .ok_or_else(|| "expected KEY=LABEL".to_string())?;
+    match u8::from_str_radix(key, 16) {
+        Ok(0..=0xF) if key.len() == 1 => Ok((key.to_uppercase(), label.to_string())),
+        _ => Err(format!("no such key {}", key)),
+    }
+}
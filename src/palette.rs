@@ -0,0 +1,117 @@
+/// Colors used to draw the display, as RGB.
+#[derive(Clone)]
+pub struct Palette {
+    pub name: &'static str,
+    pub foreground: [u8; 3],
+    pub background: [u8; 3],
+
+    /// XO-CHIP four-color mode: pixels set only in the second plane
+    pub plane2: [u8; 3],
+
+    /// XO-CHIP four-color mode: pixels set in both planes
+    pub blend: [u8; 3],
+}
+
+pub const PALETTES: [Palette; 8] = [
+    Palette {
+        name: "classic",
+        foreground: [255, 255, 255],
+        background: [0, 0, 0],
+        plane2: [170, 170, 170],
+        blend: [85, 85, 85],
+    },
+    Palette {
+        name: "amber",
+        foreground: [255, 176, 0],
+        background: [40, 20, 0],
+        plane2: [170, 100, 0],
+        blend: [255, 220, 120],
+    },
+    Palette {
+        name: "phosphor",
+        foreground: [51, 255, 102],
+        background: [0, 26, 8],
+        plane2: [20, 150, 60],
+        blend: [170, 255, 190],
+    },
+    Palette {
+        name: "lcd",
+        foreground: [15, 56, 15],
+        background: [155, 188, 15],
+        plane2: [48, 98, 48],
+        blend: [139, 172, 15],
+    },
+    Palette {
+        name: "cosmac",
+        foreground: [230, 230, 210],
+        background: [40, 40, 50],
+        plane2: [150, 150, 140],
+        blend: [90, 90, 100],
+    },
+    // Okabe-Ito colors, told apart with red-green color blindness
+    Palette {
+        name: "deuteranopia",
+        foreground: [230, 159, 0],
+        background: [0, 0, 0],
+        plane2: [86, 180, 233],
+        blend: [255, 255, 255],
+    },
+    // Red against cyan, told apart with blue-yellow color blindness
+    Palette {
+        name: "tritanopia",
+        foreground: [255, 90, 90],
+        background: [0, 0, 0],
+        plane2: [0, 200, 200],
+        blend: [255, 255, 255],
+    },
+    // Luminance alone tells the colors apart, for any kind of color blindness
+    Palette {
+        name: "monochrome",
+        foreground: [255, 255, 255],
+        background: [0, 0, 0],
+        plane2: [150, 150, 150],
+        blend: [70, 70, 70],
+    },
+];
+
+/// Gray levels `high_contrast` spreads the colors over, darkest first.
+const CONTRAST_LEVELS: [[u8; 3]; 4] = [[0, 0, 0], [85, 85, 85], [170, 170, 170], [255, 255, 255]];
+
+impl Palette {
+    /// The palette with luminance differences as large as they get: foreground and background
+    /// become black and white, keeping which one is lighter, and the XO-CHIP colors the grays
+    /// in between.
+    pub fn high_contrast(&self) -> Palette {
+        let (dark, light) = (CONTRAST_LEVELS[0], CONTRAST_LEVELS[3]);
+        let (foreground, background) = if luminance(self.foreground) >= luminance(self.background) {
+            (light, dark)
+        } else {
+            (dark, light)
+        };
+        let (plane2, blend) = if luminance(self.plane2) >= luminance(self.blend) {
+            (CONTRAST_LEVELS[2], CONTRAST_LEVELS[1])
+        } else {
+            (CONTRAST_LEVELS[1], CONTRAST_LEVELS[2])
+        };
+        Palette {
+            name: self.name,
+            foreground,
+            background,
+            plane2,
+            blend,
+        }
+    }
+}
+
+/// Relative luminance, unnormalized, with the Rec. 709 weights.
+fn luminance([r, g, b]: [u8; 3]) -> u32 {
+    2126 * r as u32 + 7152 * g as u32 + 722 * b as u32
+}
+
+/// Palette called `name`, falls back to the first one.
+pub fn palette(name: &str) -> &'static Palette {
+    PALETTES
+        .iter()
+        .find(|palette| palette.name.eq_ignore_ascii_case(name))
+        .unwrap_or(&PALETTES[0])
+}
@@ -0,0 +1,14 @@
+//! Devices mapped into the address space, for experimenting with CHIP-8 hardware extensions.
+
+/// Device answering the memory accesses of I-based instructions (`DXYN`, `FX33`, `FX55`, `FX65`)
+/// in the address range it is mapped to with `Chip8::map_peripheral`, in place of RAM.
+///
+/// Opcodes are always fetched from RAM, and save states hold RAM only, so a device keeps
+/// its own state across loading one.
+pub trait Peripheral: Send + Sync {
+    /// Byte an instruction reads at `addr`.
+    fn read(&mut self, addr: u16) -> u8;
+
+    /// Byte an instruction writes to `addr`.
+    fn write(&mut self, addr: u16, value: u8);
+}
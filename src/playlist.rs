@@ -0,0 +1,69 @@
+use crate::remote;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// ROMs shown one after another in attract mode.
+pub struct Playlist {
+    roms: Vec<PathBuf>,
+    current: usize,
+    /// How long each game runs before the next one starts
+    duration: Duration,
+    started: Instant,
+}
+
+impl Playlist {
+    /// Read one ROM path per line from `file`, skipping blank lines and `#` comments.
+    /// Relative paths are taken from the directory of `file`, URLs are kept as they are.
+    pub fn load(file: &Path, duration: Duration) -> io::Result<Playlist> {
+        let dir = file.parent().unwrap_or(Path::new(""));
+        let roms: Vec<PathBuf> = fs::read_to_string(file)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| match remote::is_url(Path::new(line)) {
+                true => PathBuf::from(line),
+                false => dir.join(line),
+            })
+            .collect();
+        if roms.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "playlist is empty",
+            ));
+        }
+        Ok(Playlist {
+            roms,
+            current: 0,
+            duration,
+            started: Instant::now(),
+        })
+    }
+
+    pub fn current(&self) -> &Path {
+        &self.roms[self.current]
+    }
+
+    /// Move on to the next ROM, wrapping around at the end.
+    pub fn next(&mut self) -> &Path {
+        self.current = (self.current + 1) % self.roms.len();
+        self.started = Instant::now();
+        self.current()
+    }
+
+    /// Drop the current ROM, used when it cannot be loaded.
+    /// Returns `false` when nothing is left to play.
+    pub fn remove_current(&mut self) -> bool {
+        self.roms.remove(self.current);
+        self.current = self
+            .current
+            .checked_sub(1)
+            .unwrap_or(self.roms.len().saturating_sub(1));
+        !self.roms.is_empty()
+    }
+
+    pub fn expired(&self) -> bool {
+        self.started.elapsed() >= self.duration
+    }
+}
@@ -0,0 +1,258 @@
+//! Many machines stepped in parallel, for reinforcement learning and fuzzing.
+//!
+//! ```ignore
+//! let machines = (0..1000).map(|seed| machine_for(seed)).collect();
+//! let mut pool = EmulatorPool::new(machines, 10);
+//! for _ in 0..600 {
+//!     pool.machine_mut(0).set_key_value(5, 1);
+//!     let results = pool.step(1);
+//!     // look at results, pool.machine(i).video, ...
+//! }
+//! ```
+
+use crate::chip8::{Chip8, Chip8Error, FRAMEBUFFER_BYTES, StepResult};
+use std::panic;
+use std::thread;
+
+/// Machine of a pool and the error it stopped on, if it did.
+struct Slot {
+    chip8: Chip8,
+    halted: Option<Chip8Error>,
+}
+
+impl Slot {
+    /// Run `frames` frames unless halted, keeping the error that stops the machine.
+    fn step(&mut self, frames: u32, ticks_per_frame: u32) -> Result<StepResult, Chip8Error> {
+        if let Some(error) = self.halted {
+            return Err(error);
+        }
+        let mut result = Ok(StepResult::Executed);
+        for _ in 0..frames {
+            result = run_frame(&mut self.chip8, ticks_per_frame);
+            if let Err(error) = result {
+                self.halted = Some(error);
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// Independent `Chip8` instances stepped a batch of frames at a time on all cores.
+///
+/// A frame is `ticks_per_frame` instructions and one `tick_timers`, like in the frontend.
+/// The machines are split into one chunk per thread for every `step`, so the threads
+/// are only worth it with many machines or several frames per batch.
+/// Nothing is shared between machines, and each runs the same as it would alone.
+pub struct EmulatorPool {
+    slots: Vec<Slot>,
+    ticks_per_frame: u32,
+    threads: usize,
+}
+
+impl EmulatorPool {
+    /// Pool of `machines`, executing `ticks_per_frame` instructions per frame on as many
+    /// threads as the system has cores.
+    pub fn new(machines: Vec<Chip8>, ticks_per_frame: u32) -> EmulatorPool {
+        let threads = thread::available_parallelism().map_or(1, |threads| threads.get());
+        EmulatorPool {
+            slots: machines
+                .into_iter()
+                .map(|chip8| Slot {
+                    chip8,
+                    halted: None,
+                })
+                .collect(),
+            ticks_per_frame,
+            threads,
+        }
+    }
+
+    /// Use at most `threads` threads, 1 runs every machine on the calling thread.
+    pub fn set_threads(&mut self, threads: usize) {
+        self.threads = threads.max(1);
+    }
+
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn machine(&self, index: usize) -> &Chip8 {
+        &self.slots[index].chip8
+    }
+
+    /// Direct access between batches, e.g. to press keys or load a state.
+    pub fn machine_mut(&mut self, index: usize) -> &mut Chip8 {
+        &mut self.slots[index].chip8
+    }
+
+    pub fn machines(&self) -> impl Iterator<Item = &Chip8> {
+        self.slots.iter().map(|slot| &slot.chip8)
+    }
+
+    /// Error machine `index` stopped on, it is skipped by `step` until `resume`.
+    pub fn halted(&self, index: usize) -> Option<Chip8Error> {
+        self.slots[index].halted
+    }
+
+    /// Step machine `index` again after it halted, e.g. once it was reset.
+    pub fn resume(&mut self, index: usize) {
+        self.slots[index].halted = None;
+    }
+
+    /// Run every machine for `frames` frames and collect the result of each, in pool order:
+    /// the last instruction's, or the error that halted the machine, now or in an earlier batch.
+    pub fn step(&mut self, frames: u32) -> Vec<Result<StepResult, Chip8Error>> {
+        let ticks_per_frame = self.ticks_per_frame;
+        let chunk = self.slots.len().div_ceil(self.threads).max(1);
+        if chunk >= self.slots.len() {
+            return self
+                .slots
+                .iter_mut()
+                .map(|slot| slot.step(frames, ticks_per_frame))
+                .collect();
+        }
+        thread::scope(|scope| {
+            let workers: Vec<_> = self
+                .slots
+                .chunks_mut(chunk)
+                .map(|slots| {
+                    scope.spawn(move || {
+                        slots
+                            .iter_mut()
+                            .map(|slot| slot.step(frames, ticks_per_frame))
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                // ROMs halt with an error, a panic is a bug of the emulator or a
+                // peripheral, passed on like it would be without threads.
+                .flat_map(|worker| {
+                    worker
+                        .join()
+                        .unwrap_or_else(|err| panic::resume_unwind(err))
+                })
+                .collect()
+        })
+    }
+
+    /// Append the display of every machine to `frames`, packed like `Chip8::framebuffer_bits`,
+    /// `FRAMEBUFFER_BYTES` each in pool order. Appending after every batch stacks them over time.
+    pub fn append_framebuffers(&self, frames: &mut Vec<u8>) {
+        frames.reserve(self.slots.len() * FRAMEBUFFER_BYTES);
+        for slot in &self.slots {
+            frames.extend_from_slice(&slot.chip8.framebuffer_bits());
+        }
+    }
+
+    pub fn into_machines(self) -> Vec<Chip8> {
+        self.slots.into_iter().map(|slot| slot.chip8).collect()
+    }
+}
+
+fn run_frame(chip8: &mut Chip8, ticks_per_frame: u32) -> Result<StepResult, Chip8Error> {
+    let mut result = StepResult::Executed;
+    for _ in 0..ticks_per_frame {
+        result = chip8.cycle()?;
+    }
+    chip8.tick_timers();
+    Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Machine running `rom`, the same on every run.
+    fn machine(rom: &[u8]) -> Chip8 {
+        let mut chip8 = Chip8::new();
+        chip8.seed(0);
+        chip8.load_rom_bytes(rom).unwrap();
+        chip8
+    }
+
+    /// Machine adding `step` to V0 forever.
+    fn counter(step: u8) -> Chip8 {
+        machine(&[0x70, step, 0x12, 0x00])
+    }
+
+    #[test]
+    fn threads_run_every_machine_like_alone() {
+        let machines = (1..=40).map(counter).collect();
+        let mut pool = EmulatorPool::new(machines, 9);
+        pool.set_threads(4);
+        assert!(pool.step(5).iter().all(|result| result.is_ok()));
+        pool.step(2);
+
+        for (index, chip8) in pool.machines().enumerate() {
+            let mut alone = counter(index as u8 + 1);
+            for _ in 0..7 {
+                run_frame(&mut alone, 9).unwrap();
+            }
+            assert_eq!(chip8.registers, alone.registers, "machine {}", index);
+            assert_eq!(chip8.pc, alone.pc, "machine {}", index);
+        }
+    }
+
+    #[test]
+    fn halted_machines_stay_halted_until_resumed() {
+        let mut pool = EmulatorPool::new(vec![machine(&[0xFF, 0xFF]), counter(1)], 10);
+        pool.set_threads(2);
+        let results = pool.step(1);
+        let error = results[0].unwrap_err();
+        assert!(matches!(
+            error,
+            Chip8Error::IllegalOpcode { opcode: 0xFFFF, .. }
+        ));
+        assert!(results[1].is_ok());
+        assert_eq!(pool.halted(0), Some(error));
+
+        let pc = pool.machine(0).pc;
+        assert_eq!(pool.step(1)[0], Err(error));
+        assert_eq!(pool.machine(0).pc, pc);
+        assert_eq!(pool.machine(1).registers[0], 10);
+
+        pool.machine_mut(0).load_rom_bytes(&[0x12, 0x00]).unwrap();
+        pool.machine_mut(0).pc = 0x200;
+        pool.resume(0);
+        assert!(pool.step(1)[0].is_ok());
+    }
+
+    #[test]
+    fn stack_misuse_halts_only_its_machine() {
+        let machines = vec![machine(&[0x00, 0xEE]), machine(&[0x22, 0x00]), counter(1)];
+        let mut pool = EmulatorPool::new(machines, 20);
+        pool.set_threads(3);
+        let results = pool.step(1);
+        assert_eq!(results[0], Err(Chip8Error::StackUnderflow { pc: 0x200 }));
+        assert_eq!(results[1], Err(Chip8Error::StackOverflow { pc: 0x200 }));
+        assert!(results[2].is_ok());
+    }
+
+    #[test]
+    fn framebuffers_stack_in_pool_order() {
+        // Draw the font sprite of V0, which differs between the machines.
+        let machines = (0..3)
+            .map(|digit| machine(&[0x60, digit, 0xF0, 0x29, 0xD0, 0x05]))
+            .collect();
+        let mut pool = EmulatorPool::new(machines, 3);
+        pool.step(1);
+        let mut frames = Vec::new();
+        pool.append_framebuffers(&mut frames);
+        pool.append_framebuffers(&mut frames);
+        assert_eq!(frames.len(), 6 * FRAMEBUFFER_BYTES);
+        for (index, frame) in frames.chunks(FRAMEBUFFER_BYTES).enumerate() {
+            assert_eq!(frame, pool.machine(index % 3).framebuffer_bits());
+        }
+        assert_ne!(
+            frames[..FRAMEBUFFER_BYTES],
+            frames[FRAMEBUFFER_BYTES..][..FRAMEBUFFER_BYTES]
+        );
+    }
+}
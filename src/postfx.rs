@@ -0,0 +1,86 @@
+use crate::shader::Shader;
+use chip_8::{DISP_HEIGHT, DISP_WIDTH, Palette, PostChain, PostProcessor, RgbaFrame};
+use sdl2::pixels::PixelFormatEnum;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, TextureCreator, WindowCanvas};
+use sdl2::video::WindowContext;
+
+/// Display drawn through the effects of `Config::effects` instead of pixel by pixel:
+/// the frame they return is uploaded to a texture and stretched over the viewport,
+/// or drawn there by the `Config::shader`.
+pub struct PostScreen<'a> {
+    chain: PostChain,
+    shader: Option<Shader>,
+    /// Display before the last emulated frame, kept with `Config::interpolate`
+    previous: Option<Box<[bool; DISP_WIDTH * DISP_HEIGHT]>>,
+    /// How far to blend from `previous` to the display, see `interpolate`
+    amount: f32,
+    textures: &'a TextureCreator<WindowContext>,
+    /// Sized to the last frame, made again when the effects change its size
+    texture: Option<Texture<'a>>,
+}
+
+impl<'a> PostScreen<'a> {
+    pub fn new(
+        chain: PostChain,
+        shader: Option<Shader>,
+        interpolate: bool,
+        textures: &'a TextureCreator<WindowContext>,
+    ) -> PostScreen<'a> {
+        PostScreen {
+            chain,
+            shader,
+            previous: interpolate.then(|| Box::new([false; DISP_WIDTH * DISP_HEIGHT])),
+            amount: 1.0,
+            textures,
+            texture: None,
+        }
+    }
+
+    /// Note the display `video` shows just before a frame is emulated, to blend from.
+    pub fn frame_starting(&mut self, video: &[bool; DISP_WIDTH * DISP_HEIGHT]) {
+        if let Some(previous) = &mut self.previous {
+            **previous = *video;
+        }
+    }
+
+    /// Draw the display `amount` of the way from the one before the last emulated frame
+    /// to the current one, `FramePacer::fraction` for an even fade between 60 Hz frames.
+    pub fn interpolate(&mut self, amount: f32) {
+        self.amount = amount;
+    }
+
+    pub fn draw(
+        &mut self,
+        canvas: &mut WindowCanvas,
+        video: &[bool; DISP_WIDTH * DISP_HEIGHT],
+        palette: &Palette,
+        viewport: Rect,
+    ) {
+        let mut frame = RgbaFrame::from_video(video, palette);
+        if let Some(previous) = &self.previous {
+            frame = RgbaFrame::from_video(previous, palette).blend(&frame, self.amount);
+        }
+        let frame = self.chain.process(frame);
+        let (width, height) = (frame.width as u32, frame.height as u32);
+        let texture = match self.texture.take() {
+            Some(texture) if (texture.query().width, texture.query().height) == (width, height) => {
+                texture
+            }
+            _ => self
+                .textures
+                .create_texture_streaming(PixelFormatEnum::RGBA32, width, height)
+                .expect("Error when drawing"),
+        };
+        let texture = self.texture.insert(texture);
+        texture
+            .update(None, &frame.pixels, frame.width * 4)
+            .expect("Error when drawing");
+        match &mut self.shader {
+            Some(shader) => shader.draw(canvas, texture, (width, height), viewport),
+            None => canvas
+                .copy(texture, None, viewport)
+                .expect("Error when drawing"),
+        }
+    }
+}
@@ -0,0 +1,374 @@
+//! Visual effects applied to the display before it is shown, each a `PostProcessor`
+//! turning one RGBA frame into another, chained in a `PostChain`.
+
+use crate::chip8::{DISP_HEIGHT, DISP_WIDTH};
+use crate::palette::Palette;
+use serde::{Deserialize, Serialize};
+
+/// Image of `width` x `height` pixels, 4 bytes each in R, G, B, A order, row by row.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RgbaFrame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaFrame {
+    /// `video` in `palette` colors, one pixel per CHIP-8 pixel.
+    pub fn from_video(video: &[bool; DISP_WIDTH * DISP_HEIGHT], palette: &Palette) -> RgbaFrame {
+        let pixels = video
+            .iter()
+            .flat_map(|&pixel| {
+                let [r, g, b] = if pixel {
+                    palette.foreground
+                } else {
+                    palette.background
+                };
+                [r, g, b, 0xFF]
+            })
+            .collect();
+        RgbaFrame {
+            width: DISP_WIDTH,
+            height: DISP_HEIGHT,
+            pixels,
+        }
+    }
+
+    /// This frame moved `amount` of the way to `next`, from `0.0` (this one) to `1.0` (`next`),
+    /// every channel blended on its own. Frames of different sizes give `next`.
+    pub fn blend(&self, next: &RgbaFrame, amount: f32) -> RgbaFrame {
+        if (self.width, self.height) != (next.width, next.height) {
+            return next.clone();
+        }
+        let amount = amount.clamp(0.0, 1.0);
+        RgbaFrame {
+            width: next.width,
+            height: next.height,
+            pixels: self
+                .pixels
+                .iter()
+                .zip(&next.pixels)
+                .map(|(&from, &to)| mix(from, to, amount))
+                .collect(),
+        }
+    }
+}
+
+/// Effect on the frames shown, e.g. a filter imitating some display hardware.
+///
+/// Frames go through it in the order they are shown, so an effect may keep earlier
+/// ones, and it may return a frame of another size; the result is scaled to the viewport.
+pub trait PostProcessor: Send {
+    fn process(&mut self, frame: RgbaFrame) -> RgbaFrame;
+}
+
+/// Effects applied one after the other, the output of each going into the next.
+#[derive(Default)]
+pub struct PostChain {
+    processors: Vec<Box<dyn PostProcessor>>,
+}
+
+impl PostChain {
+    /// Chain of the built-in `effects`, in order.
+    pub fn from_effects(effects: &[Effect]) -> PostChain {
+        let mut chain = PostChain::default();
+        for effect in effects {
+            chain.processors.push(effect.processor());
+        }
+        chain
+    }
+
+    /// Add `processor` at the end of the chain.
+    pub fn push(&mut self, processor: impl PostProcessor + 'static) {
+        self.processors.push(Box::new(processor));
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.processors.is_empty()
+    }
+}
+
+impl PostProcessor for PostChain {
+    fn process(&mut self, frame: RgbaFrame) -> RgbaFrame {
+        self.processors
+            .iter_mut()
+            .fold(frame, |frame, processor| processor.process(frame))
+    }
+}
+
+/// Built-in effect and its settings, as listed in the config file,
+/// e.g. `{ effect = "scanlines", scale = 3, darkness = 0.5 }`.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "effect", rename_all = "lowercase")]
+pub enum Effect {
+    /// See `Decay`
+    Decay { persistence: f32 },
+
+    /// See `Scanlines`
+    Scanlines { scale: usize, darkness: f32 },
+
+    /// See `ColorGrade`
+    Grade {
+        brightness: f32,
+        contrast: f32,
+        saturation: f32,
+    },
+
+    /// See `Grid`
+    Grid {
+        scale: usize,
+        color: [u8; 3],
+        alpha: f32,
+    },
+}
+
+impl Effect {
+    pub fn processor(&self) -> Box<dyn PostProcessor> {
+        match *self {
+            Effect::Decay { persistence } => Box::new(Decay::new(persistence)),
+            Effect::Scanlines { scale, darkness } => Box::new(Scanlines { scale, darkness }),
+            Effect::Grade {
+                brightness,
+                contrast,
+                saturation,
+            } => Box::new(ColorGrade {
+                brightness,
+                contrast,
+                saturation,
+            }),
+            Effect::Grid {
+                scale,
+                color,
+                alpha,
+            } => Box::new(Grid {
+                scale,
+                color,
+                alpha,
+            }),
+        }
+    }
+}
+
+/// Pixels fade between colors over several frames like on a slow phosphor or LCD,
+/// which also smooths the flicker of sprites drawn every other frame.
+pub struct Decay {
+    /// Share of the previous frame kept in every frame, from 0 (no effect) to below 1
+    persistence: f32,
+    previous: Option<RgbaFrame>,
+}
+
+impl Decay {
+    pub fn new(persistence: f32) -> Decay {
+        Decay {
+            persistence: persistence.clamp(0.0, 0.99),
+            previous: None,
+        }
+    }
+}
+
+impl PostProcessor for Decay {
+    fn process(&mut self, mut frame: RgbaFrame) -> RgbaFrame {
+        if let Some(previous) = &self.previous
+            && (previous.width, previous.height) == (frame.width, frame.height)
+        {
+            for (pixel, &before) in frame.pixels.iter_mut().zip(&previous.pixels) {
+                *pixel = mix(*pixel, before, self.persistence);
+            }
+        }
+        self.previous = Some(frame.clone());
+        frame
+    }
+}
+
+/// Every row of pixels becomes `scale` rows, the last of them darker, like the gaps
+/// between the lines of a CRT.
+pub struct Scanlines {
+    pub scale: usize,
+
+    /// How much darker the gap is, from 0 to 1 (black)
+    pub darkness: f32,
+}
+
+impl PostProcessor for Scanlines {
+    fn process(&mut self, frame: RgbaFrame) -> RgbaFrame {
+        let scale = self.scale.max(2);
+        let row_len = frame.width * 4;
+        let mut pixels = Vec::with_capacity(frame.pixels.len() * scale);
+        for row in frame.pixels.chunks(row_len) {
+            for _ in 1..scale {
+                pixels.extend_from_slice(row);
+            }
+            pixels.extend(row.chunks(4).flat_map(|pixel| {
+                let dim = |channel: u8| mix(channel, 0, self.darkness.clamp(0.0, 1.0));
+                [dim(pixel[0]), dim(pixel[1]), dim(pixel[2]), pixel[3]]
+            }));
+        }
+        RgbaFrame {
+            width: frame.width,
+            height: frame.height * scale,
+            pixels,
+        }
+    }
+}
+
+/// Color adjustment, every setting 1 for no change.
+pub struct ColorGrade {
+    pub brightness: f32,
+
+    /// Spread of the colors around the middle gray
+    pub contrast: f32,
+
+    /// 0 for grays only
+    pub saturation: f32,
+}
+
+impl PostProcessor for ColorGrade {
+    fn process(&mut self, mut frame: RgbaFrame) -> RgbaFrame {
+        for pixel in frame.pixels.chunks_mut(4) {
+            let rgb = [pixel[0], pixel[1], pixel[2]].map(|channel| channel as f32 / 255.0);
+            let luma = 0.299 * rgb[0] + 0.587 * rgb[1] + 0.114 * rgb[2];
+            for (channel, value) in pixel.iter_mut().zip(rgb) {
+                let value = luma + (value - luma) * self.saturation;
+                let value = ((value - 0.5) * self.contrast + 0.5) * self.brightness;
+                *channel = (value.clamp(0.0, 1.0) * 255.0).round() as u8;
+            }
+        }
+        frame
+    }
+}
+
+/// Lines between the pixels, so sprites read clearly at large scales: every pixel becomes
+/// a `scale` x `scale` cell with a one-texel line shared with its neighbors, none at the edges.
+/// Put it before effects that change the size, like `Scanlines`.
+pub struct Grid {
+    pub scale: usize,
+    pub color: [u8; 3],
+
+    /// How much the line covers the pixels under it, from 0 (invisible) to 1 (solid `color`)
+    pub alpha: f32,
+}
+
+impl PostProcessor for Grid {
+    fn process(&mut self, frame: RgbaFrame) -> RgbaFrame {
+        let scale = self.scale.max(2);
+        let (width, height) = (frame.width * scale, frame.height * scale);
+        let alpha = self.alpha.clamp(0.0, 1.0);
+        let mut pixels = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let at = (y / scale * frame.width + x / scale) * 4;
+                let pixel = &frame.pixels[at..at + 4];
+                let line = x % scale == 0 && x > 0 || y % scale == 0 && y > 0;
+                if line {
+                    for (&channel, &color) in pixel.iter().zip(&self.color) {
+                        pixels.push(mix(channel, color, alpha));
+                    }
+                    pixels.push(pixel[3]);
+                } else {
+                    pixels.extend_from_slice(pixel);
+                }
+            }
+        }
+        RgbaFrame {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// `value` moved `amount` of the way to `to`.
+fn mix(value: u8, to: u8, amount: f32) -> u8 {
+    (value as f32 + (to as f32 - value as f32) * amount).round() as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(width: usize, height: usize, rgb: [u8; 3]) -> RgbaFrame {
+        RgbaFrame {
+            width,
+            height,
+            pixels: [rgb[0], rgb[1], rgb[2], 0xFF].repeat(width * height),
+        }
+    }
+
+    #[test]
+    fn blend_moves_each_channel_towards_the_next_frame() {
+        let (from, to) = (frame(2, 1, [0, 100, 200]), frame(2, 1, [200, 100, 0]));
+        assert_eq!(from.blend(&to, 0.0), from);
+        assert_eq!(from.blend(&to, 0.25), frame(2, 1, [50, 100, 150]));
+        assert_eq!(from.blend(&to, 1.0), to);
+        assert_eq!(from.blend(&frame(1, 1, [9; 3]), 0.5), frame(1, 1, [9; 3]));
+    }
+
+    #[test]
+    fn decay_fades_to_the_new_frame() {
+        let mut decay = Decay::new(0.5);
+        assert_eq!(decay.process(frame(1, 1, [200; 3])), frame(1, 1, [200; 3]));
+        assert_eq!(decay.process(frame(1, 1, [0; 3])), frame(1, 1, [100; 3]));
+        assert_eq!(decay.process(frame(1, 1, [0; 3])), frame(1, 1, [50; 3]));
+        // A frame of another size starts over.
+        assert_eq!(decay.process(frame(2, 1, [0; 3])), frame(2, 1, [0; 3]));
+    }
+
+    #[test]
+    fn scanlines_repeat_rows_and_darken_the_last() {
+        let mut scanlines = Scanlines {
+            scale: 3,
+            darkness: 0.5,
+        };
+        let out = scanlines.process(frame(2, 2, [100, 50, 10]));
+        assert_eq!((out.width, out.height), (2, 6));
+        let rows: Vec<&[u8]> = out.pixels.chunks(8).collect();
+        assert_eq!(rows[0], rows[1]);
+        assert_eq!(rows[2], [50, 25, 5, 0xFF].repeat(2));
+        assert_eq!(rows[3..], rows[..3]);
+    }
+
+    #[test]
+    fn grid_lines_go_between_pixels_only() {
+        let mut grid = Grid {
+            scale: 3,
+            color: [0, 0, 200],
+            alpha: 0.5,
+        };
+        let out = grid.process(frame(2, 1, [100; 3]));
+        assert_eq!((out.width, out.height), (6, 3));
+        let line = [50, 50, 150, 0xFF];
+        let plain = [100, 100, 100, 0xFF];
+        for (x, pixel) in out.pixels.chunks(4).take(out.width).enumerate() {
+            assert_eq!(pixel, if x == 3 { line } else { plain }, "column {}", x);
+        }
+    }
+
+    #[test]
+    fn chain_runs_effects_in_order() {
+        let effects = [
+            Effect::Grade {
+                brightness: 1.0,
+                contrast: 1.0,
+                saturation: 0.0,
+            },
+            Effect::Scanlines {
+                scale: 2,
+                darkness: 1.0,
+            },
+        ];
+        let mut chain = PostChain::from_effects(&effects);
+        let out = chain.process(frame(1, 1, [255, 0, 0]));
+        assert_eq!(out.pixels, [76, 76, 76, 0xFF, 0, 0, 0, 0xFF]);
+
+        let mut identity = PostChain::default();
+        identity.push(ColorGrade {
+            brightness: 1.0,
+            contrast: 1.0,
+            saturation: 1.0,
+        });
+        assert_eq!(
+            identity.process(frame(1, 1, [1, 2, 3])),
+            frame(1, 1, [1, 2, 3])
+        );
+    }
+}
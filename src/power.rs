@@ -0,0 +1,44 @@
+use std::time::Duration;
+use tracing::info;
+
+/// Work left out by `Config::power_saving`, logged on exit so the savings can be checked.
+#[derive(Default)]
+pub struct PowerStats {
+    /// Frames drawn and presented
+    presented: u64,
+
+    /// Frames due for drawing but not presented, as they looked like the last one
+    unchanged: u64,
+
+    /// Time spent waiting for a key instead of running the game
+    idle: Duration,
+
+    /// Instructions the game would have run while idle
+    idle_cycles: u64,
+}
+
+impl PowerStats {
+    pub fn presented(&mut self) {
+        self.presented += 1;
+    }
+
+    pub fn unchanged(&mut self) {
+        self.unchanged += 1;
+    }
+
+    /// Count `time` spent waiting for a key, at `ticks_per_frame` instructions per 60 Hz frame.
+    pub fn idle(&mut self, time: Duration, ticks_per_frame: u32) {
+        self.idle += time;
+        self.idle_cycles += (time.as_secs_f64() * 60.0 * ticks_per_frame as f64) as u64;
+    }
+
+    pub fn report(&self) {
+        info!(
+            presented = self.presented,
+            unchanged = self.unchanged,
+            idle_ms = self.idle.as_millis() as u64,
+            idle_cycles = self.idle_cycles,
+            "Power saving"
+        );
+    }
+}
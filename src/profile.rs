@@ -0,0 +1,278 @@
+use crate::chip8::Chip8;
+use crate::disasm::disassemble;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+#[cfg(feature = "png")]
+use std::fs::File;
+#[cfg(feature = "png")]
+use std::io::BufWriter;
+use std::io::{self, Write};
+#[cfg(feature = "png")]
+use std::path::Path;
+
+/// Instructions per row of the heatmap, 64 bytes of RAM.
+pub const HEATMAP_COLUMNS: usize = 32;
+
+/// Rows of the heatmap, covering all of RAM.
+pub const HEATMAP_ROWS: usize = 4096 / (HEATMAP_COLUMNS * 2);
+
+/// Color of instructions that never ran.
+const COLD: [u8; 3] = [0x10, 0x10, 0x10];
+
+/// Where programs start, what instructions outside of any subroutine are counted under.
+const ENTRY: u16 = 0x200;
+
+/// Where a run spent its instructions: how often every address was executed,
+/// and which subroutine it was in at the time.
+///
+/// Call `record` before every `Chip8::cycle`, then export the counts with
+/// `write_csv` or `save_heatmap` to spot hot loops and code that never ran,
+/// or see `subroutines` for the time spent per subroutine.
+pub struct Profile {
+    counts: Vec<u64>,
+
+    /// Instructions recorded so far
+    total: u64,
+
+    /// Subroutines entered and not yet returned from, innermost last
+    calls: Vec<Call>,
+
+    /// Figures per subroutine by address, the program itself under `ENTRY`
+    subroutines: BTreeMap<u16, Subroutine>,
+}
+
+/// Subroutine entered, see `Profile::calls`.
+#[derive(Clone, Copy)]
+struct Call {
+    address: u16,
+
+    /// `Profile::total` when it was entered
+    entered: u64,
+}
+
+/// Time spent in one subroutine, see `Profile::subroutines`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Subroutine {
+    /// Address CALL jumped to
+    pub address: u16,
+
+    /// Times it was called
+    pub calls: u64,
+
+    /// Instructions executed until it returned, those of the subroutines it called included
+    pub inclusive: u64,
+
+    /// Instructions executed in its own code
+    pub exclusive: u64,
+}
+
+impl Default for Profile {
+    fn default() -> Profile {
+        Profile::new()
+    }
+}
+
+impl Profile {
+    pub fn new() -> Profile {
+        Profile {
+            counts: vec![0; 4096],
+            total: 0,
+            calls: Vec::new(),
+            subroutines: BTreeMap::new(),
+        }
+    }
+
+    /// Count the instruction `chip8` is about to execute, following CALL and RET
+    /// to know which subroutine it belongs to.
+    pub fn record(&mut self, chip8: &Chip8) {
+        let pc = chip8.pc;
+        let Some(count) = self.counts.get_mut(pc as usize) else {
+            return;
+        };
+        *count += 1;
+        self.total += 1;
+        let current = self.calls.last().map_or(ENTRY, |call| call.address);
+        self.subroutine(current).exclusive += 1;
+
+        let opcode = match chip8.bus.ram.get(pc as usize..pc as usize + 2) {
+            Some(&[high, low]) => u16::from_be_bytes([high, low]),
+            _ => return,
+        };
+        if opcode & 0xF000 == 0x2000 {
+            let address = opcode & 0x0FFF;
+            self.subroutine(address).calls += 1;
+            self.calls.push(Call {
+                address,
+                entered: self.total,
+            });
+        } else if opcode == 0x00EE
+            && let Some(call) = self.calls.pop()
+        {
+            self.returned(&call);
+        }
+    }
+
+    fn subroutine(&mut self, address: u16) -> &mut Subroutine {
+        self.subroutines
+            .entry(address)
+            .or_insert_with(|| Subroutine {
+                address,
+                ..Subroutine::default()
+            })
+    }
+
+    /// Add the time spent in `call` to its subroutine, unless it is still running further up
+    /// the stack: a recursive subroutine's time counts once, from its outermost call.
+    fn returned(&mut self, call: &Call) {
+        if self.calls.iter().all(|outer| outer.address != call.address) {
+            let spent = self.total - call.entered;
+            self.subroutine(call.address).inclusive += spent;
+        }
+    }
+
+    /// Executions of the instruction at `addr`.
+    pub fn count(&self, addr: u16) -> u64 {
+        self.counts.get(addr as usize).copied().unwrap_or(0)
+    }
+
+    /// Instructions executed in all.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Time spent per subroutine, the most inclusive first, counting subroutines still
+    /// running as if they returned now. The program outside of any subroutine is listed
+    /// under its entry point 0x200, with everything in its inclusive count.
+    pub fn subroutines(&self) -> Vec<Subroutine> {
+        let mut profile = Profile {
+            counts: Vec::new(),
+            total: self.total,
+            calls: self.calls.clone(),
+            subroutines: self.subroutines.clone(),
+        };
+        while let Some(call) = profile.calls.pop() {
+            profile.returned(&call);
+        }
+        profile.subroutine(ENTRY).inclusive = self.total;
+
+        let mut subroutines: Vec<Subroutine> = profile.subroutines.into_values().collect();
+        subroutines.sort_by_key(|subroutine| {
+            (std::cmp::Reverse(subroutine.inclusive), subroutine.address)
+        });
+        subroutines
+    }
+
+    /// Write the counts as CSV lined up with the disassembly of `ram`: one row per instruction
+    /// from 0x200 to the end of the program, `address,opcode,instruction,count`.
+    /// Odd addresses only get a row when something ran there.
+    pub fn write_csv(&self, ram: &[u8; 4096], mut out: impl Write) -> io::Result<()> {
+        let last_executed = self.counts.iter().rposition(|&count| count > 0);
+        let last_byte = ram.iter().rposition(|&byte| byte != 0);
+        let end = last_executed.max(last_byte).unwrap_or(0).max(0x200) + 1;
+
+        writeln!(out, "address,opcode,instruction,count")?;
+        for addr in 0x200..end.min(ram.len() - 1) {
+            let count = self.counts[addr];
+            if addr % 2 == 1 && count == 0 {
+                continue;
+            }
+            let opcode = u16::from_be_bytes([ram[addr], ram[addr + 1]]);
+            writeln!(
+                out,
+                "{:#05x},{:#06x},\"{}\",{}",
+                addr,
+                opcode,
+                disassemble(opcode),
+                count
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Heatmap as RGBA bytes, `HEATMAP_COLUMNS` instructions per row from address 0 on,
+    /// so row `r` shows addresses `r * 64` to `r * 64 + 63` like a hex dump. Colors go
+    /// from dark red for rarely run code to white for the hottest, on a log scale.
+    pub fn heatmap_rgba(&self) -> Vec<u8> {
+        let cells: Vec<u64> = self
+            .counts
+            .chunks(2)
+            .map(|pair| pair.iter().sum())
+            .collect();
+        let hottest = cells.iter().copied().max().unwrap_or(0);
+        cells
+            .iter()
+            .flat_map(|&count| {
+                let [r, g, b] = heat(count, hottest);
+                [r, g, b, 0xFF]
+            })
+            .collect()
+    }
+
+    /// Save the heatmap as PNG, every instruction drawn as a `scale` x `scale` square.
+    #[cfg(feature = "png")]
+    pub fn save_heatmap<P: AsRef<Path>>(&self, path: P, scale: usize) -> io::Result<()> {
+        let scale = scale.max(1);
+        let (width, height) = (HEATMAP_COLUMNS * scale, HEATMAP_ROWS * scale);
+        let rgba = self.heatmap_rgba();
+        let mut image = Vec::with_capacity(width * height * 4);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = ((y / scale) * HEATMAP_COLUMNS + x / scale) * 4;
+                image.extend_from_slice(&rgba[idx..idx + 4]);
+            }
+        }
+
+        let file = BufWriter::new(File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(io::Error::other)?;
+        writer.write_image_data(&image).map_err(io::Error::other)
+    }
+}
+
+/// Table of `subroutines`, one line each, with the share of all instructions in percent.
+pub struct SubroutineTable<'a>(pub &'a [Subroutine]);
+
+impl fmt::Display for SubroutineTable<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let total = self
+            .0
+            .iter()
+            .map(|subroutine| subroutine.exclusive)
+            .sum::<u64>()
+            .max(1);
+        writeln!(
+            f,
+            "{:<7}  {:>10}  {:>12}  {:>6}  {:>12}  {:>6}",
+            "ADDRESS", "CALLS", "INCLUSIVE", "%", "EXCLUSIVE", "%"
+        )?;
+        for subroutine in self.0 {
+            writeln!(
+                f,
+                "{:<#7x}  {:>10}  {:>12}  {:>6.2}  {:>12}  {:>6.2}",
+                subroutine.address,
+                subroutine.calls,
+                subroutine.inclusive,
+                subroutine.inclusive as f64 * 100.0 / total as f64,
+                subroutine.exclusive,
+                subroutine.exclusive as f64 * 100.0 / total as f64
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn heat(count: u64, hottest: u64) -> [u8; 3] {
+    if count == 0 {
+        return COLD;
+    }
+    let level = if hottest > 1 {
+        0.15 + 0.85 * (count as f64).ln() / (hottest as f64).ln()
+    } else {
+        1.0
+    };
+    let channel = |offset: f64| ((level * 3.0 - offset).clamp(0.0, 1.0) * 255.0) as u8;
+    [channel(0.0), channel(1.0), channel(2.0)]
+}
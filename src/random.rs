@@ -0,0 +1,64 @@
+use rand::RngCore;
+use rand_chacha::ChaCha8Rng;
+
+/// Generator behind CXNN, see `Quirks::vip_random` for choosing one.
+pub trait RandomSource {
+    /// Next random byte, before CXNN masks it with NN.
+    fn next_byte(&mut self) -> u8;
+}
+
+/// Default source: uniform bytes that do not depend on timing at all.
+impl RandomSource for ChaCha8Rng {
+    fn next_byte(&mut self) -> u8 {
+        self.next_u32() as u8
+    }
+}
+
+/// VIP-style source: a 16-bit Galois shift register offset by a counter of 60 Hz frames,
+/// so the byte a ROM gets depends on the frame it asks in and bytes drawn in quick
+/// succession are strongly related, as with the COSMAC VIP interpreter's RND.
+/// It is not a port of that routine and gives different numbers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VipRandom {
+    /// 16-bit Galois shift register, never zero
+    pub(crate) lfsr: u16,
+
+    /// Frames counted by `tick`, wrapping
+    pub(crate) frame: u8,
+}
+
+/// Feedback taps of the shift register, x^16 + x^14 + x^13 + x^11 + 1.
+const TAPS: u16 = 0xB400;
+
+impl Default for VipRandom {
+    fn default() -> VipRandom {
+        VipRandom::new(0)
+    }
+}
+
+impl VipRandom {
+    pub fn new(seed: u16) -> VipRandom {
+        VipRandom {
+            lfsr: seed.max(1),
+            frame: 0,
+        }
+    }
+
+    /// Count a 60 Hz frame.
+    pub fn tick(&mut self) {
+        self.frame = self.frame.wrapping_add(1);
+    }
+}
+
+impl RandomSource for VipRandom {
+    fn next_byte(&mut self) -> u8 {
+        for _ in 0..8 {
+            let carry = self.lfsr & 1;
+            self.lfsr >>= 1;
+            if carry != 0 {
+                self.lfsr ^= TAPS;
+            }
+        }
+        (self.lfsr as u8).wrapping_add(self.frame)
+    }
+}
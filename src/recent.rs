@@ -0,0 +1,78 @@
+use crate::dirs::data_dir;
+use crate::remote;
+use std::fs;
+use std::path::{Path, PathBuf};
+use tracing::warn;
+
+/// How many ROMs are remembered.
+const MAX_RECENT: usize = 10;
+
+/// Most recently played ROMs, newest first.
+/// Stored as one path per line in `recent` in the data directory.
+pub struct RecentRoms {
+    paths: Vec<PathBuf>,
+}
+
+impl RecentRoms {
+    pub fn load() -> RecentRoms {
+        let paths = recent_file()
+            .and_then(|file| fs::read_to_string(file).ok())
+            .map(|contents| {
+                contents
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(PathBuf::from)
+                    .take(MAX_RECENT)
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        RecentRoms { paths }
+    }
+
+    pub fn paths(&self) -> &[PathBuf] {
+        &self.paths
+    }
+
+    /// Move `rom` to the front of the list and persist it.
+    /// A ROM piped in on stdin cannot be loaded again and is left out.
+    pub fn push(&mut self, rom: &Path) {
+        if remote::is_stdin(rom) {
+            return;
+        }
+        let rom = fs::canonicalize(rom).unwrap_or_else(|_| rom.to_path_buf());
+        self.paths.retain(|path| *path != rom);
+        self.paths.insert(0, rom);
+        self.paths.truncate(MAX_RECENT);
+        self.save();
+    }
+
+    /// Drop `rom` from the list, used when it can no longer be loaded.
+    pub fn remove(&mut self, rom: &Path) {
+        self.paths.retain(|path| path != rom);
+        self.save();
+    }
+
+    fn save(&self) {
+        let Some(file) = recent_file() else {
+            return;
+        };
+        let contents: String = self
+            .paths
+            .iter()
+            .map(|path| format!("{}\n", path.display()))
+            .collect();
+
+        // Losing the recent list is not worth interrupting the game for.
+        if let Some(dir) = file.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        if let Err(err) = fs::write(&file, contents) {
+            warn!(file = %file.display(), %err, "Could not save recent ROMs");
+        }
+    }
+}
+
+fn recent_file() -> Option<PathBuf> {
+    data_dir().map(|dir| dir.join("recent"))
+}
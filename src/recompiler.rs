@@ -0,0 +1,264 @@
+use crate::cartridge::MAX_ROM_LEN;
+use crate::chip8::Quirks;
+use crate::instruction::Instruction;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const START_ADDR: usize = 0x200;
+
+/// Machine state, instruction helpers and window shared by every translated program.
+pub const RUNTIME: &str = include_str!("recompiler/runtime.rs");
+
+/// Static recompiler output for one ROM: the files of a standalone Cargo project.
+pub struct Transpiled {
+    pub cargo_toml: String,
+    pub main_rs: String,
+
+    /// Addresses translated to Rust, everything else runs on the runtime's interpreter
+    pub translated: usize,
+}
+
+/// Translate the code in `rom` reachable from the entry point into Rust.
+///
+/// Every instruction becomes one arm of a `match` on PC, with its operands and
+/// `quirks` baked in. Code only reached through `BNNN`, outside the ROM, or
+/// overwritten by the program at runtime falls back to the runtime's interpreter.
+///
+/// `main.rs` expects the ROM next to it as `rom.ch8` and the runtime as `runtime.rs`.
+/// ROMs too large for RAM are refused, the runtime has no banks.
+pub fn transpile(
+    rom: &[u8],
+    name: &str,
+    quirks: Quirks,
+    ticks_per_frame: u32,
+) -> Result<Transpiled, String> {
+    if rom.len() > MAX_ROM_LEN {
+        return Err(format!(
+            "ROM of {} bytes needs banking, transpiled programs only have the {} bytes of RAM",
+            rom.len(),
+            MAX_ROM_LEN
+        ));
+    }
+    let end = START_ADDR + rom.len();
+    let opcode = |addr: usize| {
+        let offset = addr - START_ADDR;
+        (rom[offset] as u16) << 8 | rom[offset + 1] as u16
+    };
+
+    let mut reached = BTreeSet::new();
+    let mut pending = vec![START_ADDR];
+    while let Some(addr) = pending.pop() {
+        if addr < START_ADDR || addr + 1 >= end || !reached.insert(addr) {
+            continue;
+        }
+        let next = addr + 2;
+        match Instruction::decode(opcode(addr)) {
+            Instruction::Jump { nnn } => pending.push(nnn as usize),
+            Instruction::Call { nnn } => pending.extend([nnn as usize, next]),
+            Instruction::Ret | Instruction::JumpOffset { .. } | Instruction::Data(_) => {}
+            Instruction::SkipEqImm { .. }
+            | Instruction::SkipNeImm { .. }
+            | Instruction::SkipEq { .. }
+            | Instruction::SkipNe { .. }
+            | Instruction::SkipKey { .. }
+            | Instruction::SkipNoKey { .. } => pending.extend([next, next + 2]),
+            _ => pending.push(next),
+        }
+    }
+
+    let mut arms = String::new();
+    let mut translated = 0;
+    for &addr in &reached {
+        let opcode = opcode(addr);
+        if let Some(body) = translate(Instruction::decode(opcode), addr as u16 + 2, quirks) {
+            writeln!(arms, "        // {}", crate::disassemble(opcode)).unwrap();
+            writeln!(
+                arms,
+                "        {:#05X} if m.holds({:#06X}) => {{ {} }}",
+                addr, opcode, body
+            )
+            .unwrap();
+            translated += 1;
+        }
+    }
+
+    let package = package_name(name);
+    let cargo_toml = format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2024\"\n\n[dependencies]\nsdl2 = \"0.38\"\n",
+        package
+    );
+    let main_rs = format!(
+        r#"//! {name} translated to Rust by `chip-8 transpile`.
+
+// Generated code mirrors the ROM, not idiomatic Rust.
+#![allow(clippy::all)]
+
+mod runtime;
+
+use runtime::{{Machine, Quirks}};
+
+const ROM: &[u8] = include_bytes!("rom.ch8");
+
+fn step(m: &mut Machine) -> Result<(), String> {{
+    match m.pc {{
+{arms}        _ => m.interpret()?,
+    }}
+    Ok(())
+}}
+
+fn main() {{
+    let quirks = Quirks {{
+        shift_uses_vy: {shift_uses_vy},
+        jump_uses_vx: {jump_uses_vx},
+        vf_reset: {vf_reset},
+        clip_sprites: {clip_sprites},
+        vip_random: {vip_random},
+        add_i_sets_vf: {add_i_sets_vf},
+        memory_increments_i: {memory_increments_i},
+        wrap_memory: {wrap_memory},
+    }};
+    runtime::run("{title}", Machine::new(ROM, quirks), {ticks_per_frame}, step);
+}}
+"#,
+        name = name,
+        arms = arms,
+        shift_uses_vy = quirks.shift_uses_vy,
+        jump_uses_vx = quirks.jump_uses_vx,
+        vf_reset = quirks.vf_reset,
+        clip_sprites = quirks.clip_sprites,
+        vip_random = quirks.vip_random,
+        add_i_sets_vf = quirks.add_i_sets_vf,
+        memory_increments_i = quirks.memory_increments_i,
+        wrap_memory = quirks.wrap_memory,
+        title = format!("Chip-8 - {}", name).escape_default(),
+        ticks_per_frame = ticks_per_frame,
+    );
+
+    Ok(Transpiled {
+        cargo_toml,
+        main_rs,
+        translated,
+    })
+}
+
+/// Rust statements for `instruction`, the one before `next`.
+/// `None` for data, which is left to the interpreter to report.
+fn translate(instruction: Instruction, next: u16, quirks: Quirks) -> Option<String> {
+    let skip = |condition: String| {
+        format!(
+            "m.pc = if {} {{ {:#05X} }} else {{ {:#05X} }};",
+            condition,
+            next + 2,
+            next
+        )
+    };
+    let vf_reset = if quirks.vf_reset { " m.v[15] = 0;" } else { "" };
+    let shift_source = |x: u8, y: u8| {
+        if quirks.shift_uses_vy && x != y {
+            format!("m.v[{}] = m.v[{}]; ", x, y)
+        } else {
+            String::new()
+        }
+    };
+
+    let body = match instruction {
+        Instruction::Cls => "m.video = [false; runtime::WIDTH * runtime::HEIGHT];".to_string(),
+        Instruction::Ret => return Some(format!("m.ret({:#05X})?;", next - 2)),
+        Instruction::Jump { nnn } => return Some(format!("m.pc = {:#05X};", nnn)),
+        Instruction::Call { nnn } => return Some(format!("m.call({:#05X}, {:#05X})?;", nnn, next)),
+        Instruction::SkipEqImm { x, nn } => {
+            return Some(skip(format!("m.v[{}] == {:#04X}", x, nn)));
+        }
+        Instruction::SkipNeImm { x, nn } => {
+            return Some(skip(format!("m.v[{}] != {:#04X}", x, nn)));
+        }
+        Instruction::SkipEq { x, y } if x == y => {
+            return Some(format!("m.pc = {:#05X};", next + 2));
+        }
+        Instruction::SkipNe { x, y } if x == y => return Some(format!("m.pc = {:#05X};", next)),
+        Instruction::SkipEq { x, y } => return Some(skip(format!("m.v[{}] == m.v[{}]", x, y))),
+        Instruction::SkipNe { x, y } => return Some(skip(format!("m.v[{}] != m.v[{}]", x, y))),
+        Instruction::SkipKey { x } => {
+            return Some(skip(format!("m.keys[(m.v[{}] & 0xF) as usize]", x)));
+        }
+        Instruction::SkipNoKey { x } => {
+            return Some(skip(format!("!m.keys[(m.v[{}] & 0xF) as usize]", x)));
+        }
+        Instruction::LoadImm { x, nn } => format!("m.v[{}] = {:#04X};", x, nn),
+        Instruction::AddImm { x, nn } => {
+            format!("m.v[{0}] = m.v[{0}].wrapping_add({1:#04X});", x, nn)
+        }
+        Instruction::Load { x, y } if x == y => String::new(),
+        Instruction::Load { x, y } => format!("m.v[{}] = m.v[{}];", x, y),
+        Instruction::Or { x, y } => format!("m.v[{}] |= m.v[{}];{}", x, y, vf_reset),
+        Instruction::And { x, y } => format!("m.v[{}] &= m.v[{}];{}", x, y, vf_reset),
+        Instruction::Xor { x, y } => format!("m.v[{}] ^= m.v[{}];{}", x, y, vf_reset),
+        Instruction::Add { x, y } => format!(
+            "let (sum, carry) = m.v[{0}].overflowing_add(m.v[{1}]); m.v[{0}] = sum; m.v[15] = carry as u8;",
+            x, y
+        ),
+        Instruction::Sub { x, y } => format!(
+            "let (diff, borrow) = m.v[{0}].overflowing_sub(m.v[{1}]); m.v[{0}] = diff; m.v[15] = !borrow as u8;",
+            x, y
+        ),
+        Instruction::SubN { x, y } => format!(
+            "let (diff, borrow) = m.v[{1}].overflowing_sub(m.v[{0}]); m.v[{0}] = diff; m.v[15] = !borrow as u8;",
+            x, y
+        ),
+        Instruction::ShiftRight { x, y } => format!(
+            "{}let bit = m.v[{1}] & 1; m.v[{1}] >>= 1; m.v[15] = bit;",
+            shift_source(x, y),
+            x
+        ),
+        Instruction::ShiftLeft { x, y } => format!(
+            "{}let bit = m.v[{1}] >> 7; m.v[{1}] <<= 1; m.v[15] = bit;",
+            shift_source(x, y),
+            x
+        ),
+        Instruction::LoadI { nnn } => format!("m.i = {:#05X};", nnn),
+        Instruction::JumpOffset { x, nnn } => {
+            let x = if quirks.jump_uses_vx { x } else { 0 };
+            return Some(format!("m.pc = m.v[{}] as u16 + {:#05X};", x, nnn));
+        }
+        Instruction::Random { x, nn } => format!("m.v[{}] = m.random() & {:#04X};", x, nn),
+        Instruction::Draw { x, y, n } => format!("m.draw({}, {}, {});", x, y, n),
+        Instruction::LoadDelay { x } => format!("m.v[{}] = m.dt;", x),
+        Instruction::WaitKey { x } => {
+            return Some(format!("if m.wait_key({}) {{ m.pc = {:#05X}; }}", x, next));
+        }
+        Instruction::SetDelay { x } => format!("m.dt = m.v[{}];", x),
+        Instruction::SetSound { x } => format!("m.st = m.v[{}];", x),
+        Instruction::AddI { x } => format!("m.add_i({});", x),
+        Instruction::LoadFont { x } => format!("m.i = m.v[{}] as u16 * 5;", x),
+        Instruction::Bcd { x } => format!("m.bcd({});", x),
+        Instruction::Store { x } => format!("m.store({});", x),
+        Instruction::LoadMem { x } => format!("m.load({});", x),
+        Instruction::Data(_) => return None,
+    };
+    let advance = format!("m.pc = {:#05X};", next);
+    if body.is_empty() {
+        Some(advance)
+    } else {
+        Some(format!("{} {}", body, advance))
+    }
+}
+
+/// Cargo package name derived from the ROM name.
+fn package_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let name = name.trim_matches('-');
+    if name.is_empty() || name.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("chip8-{}", name)
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,436 @@
+//! Runtime for CHIP-8 programs translated to Rust by `chip-8 transpile`.
+//!
+//! Holds the machine state, the instructions too involved to inline,
+//! an interpreter for code the translator could not reach, and an SDL window.
+
+use sdl2::event::Event;
+use sdl2::keyboard::Keycode;
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub const WIDTH: usize = 64;
+pub const HEIGHT: usize = 32;
+const SCALE: u32 = 15;
+const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+const FONTS: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// Host keys for CHIP-8 keys 0 to F.
+const KEYS: [Keycode; 16] = [
+    Keycode::X,
+    Keycode::Num1,
+    Keycode::Num2,
+    Keycode::Num3,
+    Keycode::Q,
+    Keycode::W,
+    Keycode::E,
+    Keycode::A,
+    Keycode::S,
+    Keycode::D,
+    Keycode::Z,
+    Keycode::C,
+    Keycode::Num4,
+    Keycode::R,
+    Keycode::F,
+    Keycode::V,
+];
+
+pub struct Quirks {
+    pub shift_uses_vy: bool,
+    pub jump_uses_vx: bool,
+    pub vf_reset: bool,
+    pub clip_sprites: bool,
+    pub vip_random: bool,
+    pub add_i_sets_vf: bool,
+    pub memory_increments_i: bool,
+    pub wrap_memory: bool,
+}
+
+pub struct Machine {
+    pub ram: [u8; 4096],
+    pub v: [u8; 16],
+    pub stack: [u16; 16],
+    pub sp: usize,
+    pub pc: u16,
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub video: [bool; WIDTH * HEIGHT],
+    pub keys: [bool; 16],
+    pub quirks: Quirks,
+    rng: u64,
+
+    /// Shift register and frame counter of the VIP-style RND, with the `vip_random` quirk
+    vip_lfsr: u16,
+    vip_frame: u8,
+
+    /// Key `FX0A` saw pressed, it finishes when the key is released
+    key_awaited_release: Option<usize>,
+}
+
+impl Machine {
+    pub fn new(rom: &[u8], quirks: Quirks) -> Machine {
+        let mut ram = [0; 4096];
+        ram[..FONTS.len()].copy_from_slice(&FONTS);
+        ram[0x200..0x200 + rom.len()].copy_from_slice(rom);
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(1, |time| time.as_nanos() as u64);
+        Machine {
+            ram,
+            v: [0; 16],
+            stack: [0; 16],
+            sp: 0,
+            pc: 0x200,
+            i: 0,
+            dt: 0,
+            st: 0,
+            video: [false; WIDTH * HEIGHT],
+            keys: [false; 16],
+            quirks,
+            rng: seed | 1,
+            vip_lfsr: (seed as u16).max(1),
+            vip_frame: 0,
+            key_awaited_release: None,
+        }
+    }
+
+    /// Whether RAM at PC still holds `opcode`, the instruction translated for it.
+    pub fn holds(&self, opcode: u16) -> bool {
+        let pc = self.pc as usize;
+        (self.ram[pc] as u16) << 8 | self.ram[pc + 1] as u16 == opcode
+    }
+
+    /// `2NNN` at `ret - 2`, an error when the stack is full.
+    pub fn call(&mut self, addr: u16, ret: u16) -> Result<(), String> {
+        let slot = self
+            .stack
+            .get_mut(self.sp)
+            .ok_or_else(|| format!("Call with a full stack at {:#05x}", ret - 2))?;
+        *slot = ret;
+        self.sp += 1;
+        self.pc = addr;
+        Ok(())
+    }
+
+    /// `00EE` at `at`, an error when the stack is empty.
+    pub fn ret(&mut self, at: u16) -> Result<(), String> {
+        self.sp = self
+            .sp
+            .checked_sub(1)
+            .ok_or_else(|| format!("Return with an empty stack at {:#05x}", at))?;
+        self.pc = self.stack[self.sp];
+        Ok(())
+    }
+
+    /// xorshift64, good enough for games; with the `vip_random` quirk a shift register
+    /// offset by the frame count, VIP-style but not the VIP's own routine.
+    pub fn random(&mut self) -> u8 {
+        if self.quirks.vip_random {
+            for _ in 0..8 {
+                let carry = self.vip_lfsr & 1;
+                self.vip_lfsr >>= 1;
+                if carry != 0 {
+                    self.vip_lfsr ^= 0xB400;
+                }
+            }
+            return (self.vip_lfsr as u8).wrapping_add(self.vip_frame);
+        }
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng as u8
+    }
+
+    pub fn draw(&mut self, x: usize, y: usize, n: u8) {
+        let x_coord = self.v[x] % WIDTH as u8;
+        let y_coord = self.v[y] % HEIGHT as u8;
+        let mut collision = false;
+        for row in 0..n {
+            let byte = self.ram[self.at(self.i as usize + row as usize)];
+            let curr_y = y_coord as usize + row as usize;
+            if self.quirks.clip_sprites && curr_y >= HEIGHT {
+                break;
+            }
+            let curr_y = curr_y % HEIGHT;
+            for col in 0..8 {
+                let curr_x = x_coord as usize + col;
+                if self.quirks.clip_sprites && curr_x >= WIDTH {
+                    break;
+                }
+                let idx = curr_y * WIDTH + curr_x % WIDTH;
+                if byte & (0x80 >> col) > 0 {
+                    collision |= self.video[idx];
+                    self.video[idx] ^= true;
+                }
+            }
+        }
+        self.v[0xF] = collision as u8;
+    }
+
+    /// `FX0A`, storing a key in Vx once it was pressed and released, the lowest one
+    /// when several are pressed together; false until then.
+    pub fn wait_key(&mut self, x: usize) -> bool {
+        match self.key_awaited_release {
+            Some(key) if !self.keys[key] => {
+                self.key_awaited_release = None;
+                self.v[x] = key as u8;
+                return true;
+            }
+            Some(_) => {}
+            None => self.key_awaited_release = self.keys.iter().position(|&held| held),
+        }
+        false
+    }
+
+    /// Count down DT and ST, once per 60 Hz frame.
+    pub fn tick_timers(&mut self) {
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
+        self.vip_frame = self.vip_frame.wrapping_add(1);
+    }
+
+    /// `FX1E`, flagging a sum past 0xFFF in VF with the `add_i_sets_vf` quirk.
+    pub fn add_i(&mut self, x: usize) {
+        let sum = self.i as u32 + self.v[x] as u32;
+        self.i = sum as u16;
+        if self.quirks.add_i_sets_vf {
+            self.v[0xF] = (sum > 0xFFF) as u8;
+        }
+    }
+
+    pub fn bcd(&mut self, x: usize) {
+        let value = self.v[x];
+        let i = self.i as usize;
+        self.ram[self.at(i)] = value / 100;
+        self.ram[self.at(i + 1)] = value / 10 % 10;
+        self.ram[self.at(i + 2)] = value % 10;
+    }
+
+    pub fn store(&mut self, x: usize) {
+        for n in 0..=x {
+            let addr = self.at(self.i as usize + n);
+            self.ram[addr] = self.v[n];
+        }
+        self.advance_i(x);
+    }
+
+    pub fn load(&mut self, x: usize) {
+        for n in 0..=x {
+            self.v[n] = self.ram[self.at(self.i as usize + n)];
+        }
+        self.advance_i(x);
+    }
+
+    /// RAM index of an I-relative address, wrapped around with the `wrap_memory` quirk.
+    fn at(&self, addr: usize) -> usize {
+        if self.quirks.wrap_memory {
+            addr % self.ram.len()
+        } else {
+            addr
+        }
+    }
+
+    fn advance_i(&mut self, x: usize) {
+        if self.quirks.memory_increments_i {
+            self.i = self.i.wrapping_add((x + 1) as u16);
+        }
+    }
+
+    /// Decode and execute the instruction at PC, for code that was not translated.
+    /// An error for illegal opcodes and stack misuse, leaving PC on the instruction.
+    pub fn interpret(&mut self) -> Result<(), String> {
+        let pc = self.pc as usize;
+        let opcode = (self.ram[pc] as u16) << 8 | self.ram[pc + 1] as u16;
+        let x = ((opcode & 0x0F00) >> 8) as usize;
+        let y = ((opcode & 0x00F0) >> 4) as usize;
+        let n = (opcode & 0x000F) as u8;
+        let nn = (opcode & 0x00FF) as u8;
+        let nnn = opcode & 0x0FFF;
+        let next = self.pc + 2;
+        self.pc = next;
+        match (opcode >> 12, x, y, n) {
+            (0, 0, 0xE, 0) => self.video = [false; WIDTH * HEIGHT],
+            (0, 0, 0xE, 0xE) => self.ret(pc as u16).inspect_err(|_| self.pc = pc as u16)?,
+            (1, ..) => self.pc = nnn,
+            (2, ..) => self.call(nnn, next).inspect_err(|_| self.pc = pc as u16)?,
+            (3, ..) => self.skip_if(self.v[x] == nn),
+            (4, ..) => self.skip_if(self.v[x] != nn),
+            (5, _, _, 0) => self.skip_if(self.v[x] == self.v[y]),
+            (6, ..) => self.v[x] = nn,
+            (7, ..) => self.v[x] = self.v[x].wrapping_add(nn),
+            (8, _, _, 0) => self.v[x] = self.v[y],
+            (8, _, _, 1) => self.logic(x, self.v[x] | self.v[y]),
+            (8, _, _, 2) => self.logic(x, self.v[x] & self.v[y]),
+            (8, _, _, 3) => self.logic(x, self.v[x] ^ self.v[y]),
+            (8, _, _, 4) => {
+                let (sum, carry) = self.v[x].overflowing_add(self.v[y]);
+                self.v[x] = sum;
+                self.v[0xF] = carry as u8;
+            }
+            (8, _, _, 5) => self.sub(x, x, y),
+            (8, _, _, 7) => self.sub(x, y, x),
+            (8, _, _, 6) => {
+                if self.quirks.shift_uses_vy {
+                    self.v[x] = self.v[y];
+                }
+                let bit = self.v[x] & 1;
+                self.v[x] >>= 1;
+                self.v[0xF] = bit;
+            }
+            (8, _, _, 0xE) => {
+                if self.quirks.shift_uses_vy {
+                    self.v[x] = self.v[y];
+                }
+                let bit = self.v[x] >> 7;
+                self.v[x] <<= 1;
+                self.v[0xF] = bit;
+            }
+            (9, _, _, 0) => self.skip_if(self.v[x] != self.v[y]),
+            (0xA, ..) => self.i = nnn,
+            (0xB, ..) => {
+                let offset = if self.quirks.jump_uses_vx {
+                    self.v[x]
+                } else {
+                    self.v[0]
+                };
+                self.pc = offset as u16 + nnn;
+            }
+            (0xC, ..) => self.v[x] = self.random() & nn,
+            (0xD, ..) => self.draw(x, y, n),
+            (0xE, _, 9, 0xE) => self.skip_if(self.keys[(self.v[x] & 0xF) as usize]),
+            (0xE, _, 0xA, 1) => self.skip_if(!self.keys[(self.v[x] & 0xF) as usize]),
+            (0xF, _, 0, 7) => self.v[x] = self.dt,
+            (0xF, _, 0, 0xA) => {
+                if !self.wait_key(x) {
+                    self.pc -= 2;
+                }
+            }
+            (0xF, _, 1, 5) => self.dt = self.v[x],
+            (0xF, _, 1, 8) => self.st = self.v[x],
+            (0xF, _, 1, 0xE) => self.add_i(x),
+            (0xF, _, 2, 9) => self.i = self.v[x] as u16 * 5,
+            (0xF, _, 3, 3) => self.bcd(x),
+            (0xF, _, 5, 5) => self.store(x),
+            (0xF, _, 6, 5) => self.load(x),
+            _ => {
+                self.pc = pc as u16;
+                return Err(format!("Illegal OP {:#06x} at {:#05x}", opcode, pc));
+            }
+        }
+        Ok(())
+    }
+
+    fn skip_if(&mut self, condition: bool) {
+        if condition {
+            self.pc += 2;
+        }
+    }
+
+    fn logic(&mut self, x: usize, value: u8) {
+        self.v[x] = value;
+        if self.quirks.vf_reset {
+            self.v[0xF] = 0;
+        }
+    }
+
+    fn sub(&mut self, x: usize, a: usize, b: usize) {
+        let (difference, borrow) = self.v[a].overflowing_sub(self.v[b]);
+        self.v[x] = difference;
+        self.v[0xF] = !borrow as u8;
+    }
+}
+
+/// Open a window and run `step`, one instruction per call, `ticks_per_frame` times per 60 Hz frame.
+/// An error from `step` stops the machine, the window keeps showing where it stopped.
+pub fn run(
+    title: &str,
+    mut machine: Machine,
+    ticks_per_frame: u32,
+    step: fn(&mut Machine) -> Result<(), String>,
+) {
+    let sdl = sdl2::init().unwrap();
+    let window = sdl
+        .video()
+        .unwrap()
+        .window(title, WIDTH as u32 * SCALE, HEIGHT as u32 * SCALE)
+        .position_centered()
+        .build()
+        .unwrap();
+    let mut canvas = window.into_canvas().build().unwrap();
+    let mut events = sdl.event_pump().unwrap();
+    let mut halted = false;
+
+    let mut next_frame = Instant::now();
+    'running: loop {
+        for event in events.poll_iter() {
+            match event {
+                Event::Quit { .. }
+                | Event::KeyDown {
+                    keycode: Some(Keycode::Escape),
+                    ..
+                } => break 'running,
+                Event::KeyDown {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(k) = KEYS.iter().position(|&bound| bound == key) {
+                        machine.keys[k] = true;
+                    }
+                }
+                Event::KeyUp {
+                    keycode: Some(key), ..
+                } => {
+                    if let Some(k) = KEYS.iter().position(|&bound| bound == key) {
+                        machine.keys[k] = false;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if !halted {
+            for _ in 0..ticks_per_frame {
+                if let Err(err) = step(&mut machine) {
+                    eprintln!("Machine stopped: {}", err);
+                    let _ = canvas
+                        .window_mut()
+                        .set_title(&format!("{} - {}", title, err));
+                    halted = true;
+                    break;
+                }
+            }
+            machine.tick_timers();
+        }
+
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        canvas.clear();
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        for (idx, _) in machine.video.iter().enumerate().filter(|(_, on)| **on) {
+            let (x, y) = ((idx % WIDTH) as u32, (idx / WIDTH) as u32);
+            canvas
+                .fill_rect(Rect::new(
+                    (x * SCALE) as i32,
+                    (y * SCALE) as i32,
+                    SCALE,
+                    SCALE,
+                ))
+                .unwrap();
+        }
+        canvas.present();
+
+        next_frame += FRAME;
+        if let Some(wait) = next_frame.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        } else {
+            next_frame = Instant::now();
+        }
+    }
+}
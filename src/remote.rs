@@ -0,0 +1,124 @@
+use crate::dirs::cache_dir;
+use chip_8::hash::fnv1a;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+/// Largest download or piped in ROM accepted, room for Octo cartridges which are much larger than their ROM.
+const MAX_DOWNLOAD: u64 = 1024 * 1024;
+
+/// ROM path standing for the standard input, as in `chip-8 -`.
+const STDIN: &str = "-";
+
+/// Copy of the ROM piped in, read once so it can be loaded again on reset.
+static STDIN_COPY: OnceLock<Result<PathBuf, String>> = OnceLock::new();
+
+pub fn is_url(path: &Path) -> bool {
+    path.to_str()
+        .is_some_and(|path| path.starts_with("http://") || path.starts_with("https://"))
+}
+
+/// Whether `path` is `-`, the ROM piped in on stdin.
+pub fn is_stdin(path: &Path) -> bool {
+    path == Path::new(STDIN)
+}
+
+/// File to read the ROM at `path` from: `path` itself, for an http(s) URL its copy
+/// in the download cache, fetched first if it is not there yet, and for `-` a copy
+/// of what was piped in on stdin.
+pub fn local_file(path: &Path) -> io::Result<PathBuf> {
+    if is_stdin(path) {
+        return STDIN_COPY
+            .get_or_init(|| copy_stdin().map_err(|err| err.to_string()))
+            .clone()
+            .map_err(io::Error::other);
+    }
+    if !is_url(path) {
+        return Ok(path.to_path_buf());
+    }
+    let url = path.to_string_lossy();
+    let cached = cache_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?
+        .join("downloads")
+        .join(cache_name(&url));
+    if !cached.exists() {
+        download(&url, &cached)?;
+    }
+    Ok(cached)
+}
+
+/// FNV-1a of the URL, keeping the extension for people browsing the cache.
+fn cache_name(url: &str) -> String {
+    let hash = fnv1a(url.bytes());
+    match Path::new(url).extension() {
+        Some(extension) => format!("{:016x}.{}", hash, extension.to_string_lossy()),
+        None => format!("{:016x}", hash),
+    }
+}
+
+/// Read stdin to its end into `stdin` in the cache directory, named after the contents.
+fn copy_stdin() -> io::Result<PathBuf> {
+    let mut bytes = Vec::new();
+    io::stdin()
+        .lock()
+        .take(MAX_DOWNLOAD + 1)
+        .read_to_end(&mut bytes)?;
+    if bytes.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "nothing piped in on stdin",
+        ));
+    }
+    if bytes.len() as u64 > MAX_DOWNLOAD {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("more than {} bytes on stdin", MAX_DOWNLOAD),
+        ));
+    }
+    let dir = cache_dir()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no cache directory"))?
+        .join("stdin");
+    let file = dir.join(format!("{:016x}.ch8", fnv1a(bytes.iter().copied())));
+    fs::create_dir_all(&dir)?;
+    fs::write(&file, bytes)?;
+    Ok(file)
+}
+
+#[cfg(feature = "http")]
+fn download(url: &str, file: &Path) -> io::Result<()> {
+    use tracing::info;
+
+    let mut response = ureq::get(url).call().map_err(io::Error::other)?;
+    let body = response.body_mut();
+    if let Some(len) = body.content_length()
+        && len > MAX_DOWNLOAD
+    {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("download too large: {} bytes", len),
+        ));
+    }
+    let bytes = body
+        .with_config()
+        .limit(MAX_DOWNLOAD)
+        .read_to_vec()
+        .map_err(io::Error::other)?;
+    info!(url, bytes = bytes.len(), "ROM downloaded");
+
+    if let Some(dir) = file.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    // Written under another name first so an interrupted write is never taken for a cached ROM.
+    let partial = file.with_extension("part");
+    fs::write(&partial, bytes)?;
+    fs::rename(partial, file)
+}
+
+#[cfg(not(feature = "http"))]
+fn download(_url: &str, _file: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "loading ROMs from URLs needs the `http` feature",
+    ))
+}
@@ -0,0 +1,144 @@
+use crate::config::Config;
+use chip_8::{Cartridge, MAX_ROM_LEN, RamInit};
+use clap::{Args, Subcommand};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, warn};
+
+/// Where ROMs are loaded.
+pub const START_ADDR: usize = 0x200;
+
+#[derive(Args)]
+pub struct RomArgs {
+    #[command(subcommand)]
+    command: RomCommand,
+}
+
+#[derive(Subcommand)]
+enum RomCommand {
+    /// Strip the zero bytes at the end of a ROM; the program sees them again only while
+    /// RAM starts out zero
+    Trim {
+        /// Plain ROM file
+        file: PathBuf,
+
+        /// Where to write the result, instead of over FILE
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+    /// Fill a ROM up with zero bytes to a size
+    Pad {
+        /// Plain ROM file
+        file: PathBuf,
+
+        /// Size in bytes, decimal or hex with `0x`; all of the 3584 bytes above 0x200 by default
+        #[arg(long, value_parser = parse_size)]
+        size: Option<usize>,
+
+        /// Where to write the result, instead of over FILE
+        #[arg(long, short, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// `chip-8 rom`: trim or pad plain ROM files, reporting how much of RAM they take.
+///
+/// Exits with 0 on success, 1 when the result would not fit in RAM
+/// and 2 when a file could not be read or written.
+pub fn rom(args: RomArgs) -> ExitCode {
+    let (file, output) = match &args.command {
+        RomCommand::Trim { file, output } | RomCommand::Pad { file, output, .. } => {
+            (file, output.as_ref().unwrap_or(file))
+        }
+    };
+    let mut rom = match read_plain(file) {
+        Ok(rom) => rom,
+        Err(err) => {
+            error!(rom = %file.display(), %err, "Could not load ROM");
+            return ExitCode::from(2);
+        }
+    };
+    match &args.command {
+        RomCommand::Trim { .. } => {
+            let used = footprint(&rom);
+            let ram_init = Config::load().ram_init;
+            if used < rom.len() && ram_init != RamInit::Zero {
+                warn!(
+                    bytes = rom.len() - used,
+                    ?ram_init,
+                    "Trimmed bytes will not read as zero with this ram_init setting"
+                );
+            }
+            rom.truncate(used);
+        }
+        RomCommand::Pad { size, .. } => {
+            let size = size.unwrap_or(MAX_ROM_LEN);
+            if size < rom.len() {
+                error!(
+                    bytes = rom.len(),
+                    size, "ROM is already larger, trim it first"
+                );
+                return ExitCode::FAILURE;
+            }
+            rom.resize(size, 0);
+        }
+    }
+    if rom.len() > MAX_ROM_LEN {
+        error!(
+            bytes = rom.len(),
+            max = MAX_ROM_LEN,
+            "ROM would not fit below 0x1000"
+        );
+        return ExitCode::FAILURE;
+    }
+    if let Err(err) = fs::write(output, &rom) {
+        error!(file = %output.display(), %err, "Could not write ROM");
+        return ExitCode::from(2);
+    }
+    println!("{}", report(output, &rom));
+    ExitCode::SUCCESS
+}
+
+/// Bytes of a plain ROM; containers like `.c8x` are refused, as rewriting them would lose
+/// what they hold besides the ROM.
+fn read_plain(file: &Path) -> Result<Vec<u8>, String> {
+    let bytes = fs::read(file).map_err(|err| err.to_string())?;
+    let cartridge = Cartridge::from_bytes(bytes.clone()).map_err(|err| err.to_string())?;
+    if cartridge.rom.len() != bytes.len() {
+        return Err("not a plain ROM but a cartridge with metadata".to_string());
+    }
+    Ok(bytes)
+}
+
+/// Bytes up to the last non-zero one: what has to be loaded for the program to see
+/// the same memory, as long as RAM starts out zero. With `RamInit::Ones` or `Random`,
+/// the zeros past it are not there anymore once trimmed.
+pub fn footprint(rom: &[u8]) -> usize {
+    rom.iter()
+        .rposition(|&byte| byte != 0)
+        .map_or(0, |last| last + 1)
+}
+
+/// Size, address range and room left of `rom`, one line.
+fn report(file: &Path, rom: &[u8]) -> String {
+    let used = footprint(rom);
+    format!(
+        "{}: {} bytes at {:#05x}..{:#05x}, {} bytes used up to {:#05x}, {} bytes free",
+        file.display(),
+        rom.len(),
+        START_ADDR,
+        START_ADDR + rom.len(),
+        used,
+        START_ADDR + used,
+        MAX_ROM_LEN - rom.len()
+    )
+}
+
+fn parse_size(size: &str) -> Result<usize, String> {
+    match size.strip_prefix("0x") {
+        Some(digits) => usize::from_str_radix(digits, 16),
+        None => size.parse(),
+    }
+    .map_err(|err| format!("invalid size {:?}: {}", size, err))
+}
@@ -0,0 +1,63 @@
+use sdl2::GameControllerSubsystem;
+use sdl2::controller::GameController;
+use sdl2::event::Event;
+use tracing::{info, warn};
+
+/// Pulses the rumble motors of connected game controllers while the sound timer runs.
+pub struct Rumble {
+    subsystem: GameControllerSubsystem,
+    controllers: Vec<GameController>,
+    last_st: u8,
+}
+
+impl Rumble {
+    pub fn new(subsystem: GameControllerSubsystem) -> Rumble {
+        Rumble {
+            subsystem,
+            controllers: Vec::new(),
+            last_st: 0,
+        }
+    }
+
+    /// Track controllers being plugged in and out.
+    /// Already connected controllers are reported as added at startup.
+    pub fn handle_event(&mut self, event: &Event) {
+        match *event {
+            Event::ControllerDeviceAdded { which, .. } => match self.subsystem.open(which) {
+                Ok(controller) if controller.has_rumble() => {
+                    info!(name = controller.name(), "Rumble controller connected");
+                    self.controllers.push(controller);
+                }
+                Ok(_) => {}
+                Err(err) => warn!(%err, "Could not open controller"),
+            },
+            Event::ControllerDeviceRemoved { which, .. } => {
+                self.controllers
+                    .retain(|controller| controller.instance_id() != which);
+            }
+            _ => {}
+        }
+    }
+
+    /// Start a pulse when ST is set, lasting until it runs out but at least `min_ms`.
+    /// `strength` is in percent, `0` turns rumble off.
+    pub fn update(&mut self, st: u8, strength: u8, min_ms: u32) {
+        let started = st > self.last_st;
+        let stopped = st == 0 && self.last_st > 0;
+        self.last_st = st;
+        if strength == 0 || !(started || stopped) {
+            return;
+        }
+
+        let (motor, duration_ms) = if started {
+            let motor = (u16::MAX as u32 * strength.min(100) as u32 / 100) as u16;
+            (motor, (st as u32 * 1000 / 60).max(min_ms))
+        } else {
+            (0, 0)
+        };
+        for controller in &mut self.controllers {
+            // Losing a pulse is harmless, the controller may just have been unplugged.
+            let _ = controller.set_rumble(motor, motor, duration_ms);
+        }
+    }
+}
@@ -0,0 +1,513 @@
+use crate::backend::{Backend, BackendKind};
+use crate::game_config::GameConfig;
+use crate::monitor::Monitor;
+use crate::remote;
+use crate::snapshot;
+#[cfg(feature = "png")]
+use chip_8::PALETTES;
+use chip_8::profile::SubroutineTable;
+use chip_8::{
+    CaptureInfo, Cartridge, Chip8, InputScript, Profile, Quirks, RamInit, SaveState, Serial,
+    Symbols,
+};
+use clap::Args;
+use std::fs;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, info, warn};
+
+#[derive(Args)]
+pub struct RunArgs {
+    /// Path to the ROM to run
+    rom: PathBuf,
+
+    /// IPS patch to apply to the ROM before running it; repeatable, applied in order
+    #[arg(long, value_name = "FILE")]
+    patch: Vec<PathBuf>,
+
+    /// Number of instructions to execute
+    #[arg(long)]
+    cycles: u64,
+
+    /// Seed for RND results
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// JSON file with scripted key presses, `[{"frame": 60, "press": [5]}, ...]`
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
+
+    /// Write the input script back with a state hash every `--hash-interval` frames,
+    /// to replay later and catch the frame where a run stops matching
+    #[arg(long, value_name = "FILE")]
+    record_replay: Option<PathBuf>,
+
+    /// Frames between the state hashes written by `--record-replay`
+    #[arg(long, default_value_t = 60, value_name = "FRAMES")]
+    hash_interval: u64,
+
+    /// Save states for every hashed frame: written by `--record-replay`
+    /// and compared against when a replay desyncs
+    #[arg(long, value_name = "DIR")]
+    checkpoints: Option<PathBuf>,
+
+    /// Expected framebuffer hash after the run, exits with 1 on mismatch
+    #[arg(long, value_name = "HASH", value_parser = parse_hash)]
+    expect_hash: Option<u64>,
+
+    /// Quirks preset (modern, cosmac, superchip), defaults to the cartridge's preset or modern
+    #[arg(long, value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+
+    /// Instructions per 60 Hz frame
+    #[arg(long, default_value_t = 10)]
+    ticks_per_frame: u32,
+
+    /// What RAM holds before the ROM is loaded (zero, ones, random); random follows `--seed`
+    #[arg(long, default_value = "zero", value_parser = parse_ram_init)]
+    ram_init: RamInit,
+
+    /// Write a byte to RAM after the ROM is loaded, like `0x3F0=0xFF`; repeatable
+    #[arg(long, value_name = "ADDR=VALUE", value_parser = parse_poke)]
+    poke: Vec<(u16, u8)>,
+
+    /// Print a RAM range at the end before the hash, like `0x3F0..0x400` or `0x3F0`; repeatable
+    #[arg(long, value_name = "ADDR..ADDR", value_parser = parse_peek)]
+    peek: Vec<Range<u16>>,
+
+    /// Print the final display as text before the hash
+    #[arg(long)]
+    dump_screen: bool,
+
+    /// Save the final machine state, for comparing runs with `diff`
+    #[arg(long, value_name = "FILE")]
+    save_state: Option<PathBuf>,
+
+    /// Map the serial console at 0x1F0: bytes the ROM stores there go to stdout,
+    /// and it reads what comes in on stdin
+    #[arg(long)]
+    serial: bool,
+
+    /// Log the debugging opcodes 0FF0 (every register) and 0FEX (VX) instead of stopping on them
+    #[arg(long)]
+    host_calls: bool,
+
+    /// Read monitor commands on stdin, stopped at the first instruction until `g`;
+    /// `?` lists the commands
+    #[arg(long, conflicts_with = "serial")]
+    monitor: bool,
+
+    /// Label addresses for the monitor, as `asm --symbols` writes them
+    #[arg(long, value_name = "FILE", requires = "monitor")]
+    symbols: Option<PathBuf>,
+
+    /// On SIGUSR1, write a save state and a screenshot of the moment to DIR and carry on,
+    /// for looking into long runs (Unix only)
+    #[arg(long, value_name = "DIR")]
+    snapshots: Option<PathBuf>,
+
+    /// Save the final display as PNG
+    #[cfg(feature = "png")]
+    #[arg(long, value_name = "FILE")]
+    screenshot: Option<PathBuf>,
+
+    /// Count how often every instruction ran and write the counts as CSV,
+    /// next to the disassembly
+    #[arg(long, value_name = "FILE")]
+    profile: Option<PathBuf>,
+
+    /// Save the instruction counts as a PNG heatmap, 32 instructions per row
+    #[cfg(feature = "png")]
+    #[arg(long, value_name = "FILE")]
+    heatmap: Option<PathBuf>,
+
+    /// Print the instructions spent per subroutine, with and without the subroutines
+    /// it calls, before the hash
+    #[arg(long)]
+    subroutines: bool,
+
+    /// Write the instructions spent per subroutine as JSON
+    #[arg(long, value_name = "FILE")]
+    subroutines_json: Option<PathBuf>,
+}
+
+impl RunArgs {
+    fn profiling(&self) -> bool {
+        #[cfg(feature = "png")]
+        if self.heatmap.is_some() {
+            return true;
+        }
+        self.profile.is_some() || self.subroutines || self.subroutines_json.is_some()
+    }
+}
+
+/// `chip-8 run`: execute a ROM headless and print the final framebuffer hash.
+///
+/// Exits with 0 on success, 1 on hash mismatch and 2 when the run could not complete.
+pub fn run(args: RunArgs, backend: BackendKind) -> ExitCode {
+    let script = match &args.input {
+        Some(path) => match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|json| InputScript::from_json(&json).map_err(|err| err.to_string()))
+        {
+            Ok(script) => script,
+            Err(err) => {
+                error!(file = %path.display(), %err, "Could not read input script");
+                return ExitCode::from(2);
+            }
+        },
+        None => InputScript::default(),
+    };
+
+    let mut cartridge = match remote::local_file(&args.rom).and_then(Cartridge::load) {
+        Ok(cartridge) => cartridge,
+        Err(err) => {
+            error!(rom = %args.rom.display(), %err, "Could not load ROM");
+            return ExitCode::from(2);
+        }
+    };
+    for patch in &args.patch {
+        if let Err(err) = cartridge.patch(patch) {
+            error!(file = %patch.display(), %err, "Could not apply patch");
+            return ExitCode::from(2);
+        }
+    }
+    let mut chip8 = Chip8::new();
+    chip8.quirks = args
+        .quirks
+        .or_else(|| cartridge.metadata.quirks.as_ref()?.resolve())
+        .unwrap_or_default();
+    chip8.seed(args.seed);
+    chip8.init_ram(args.ram_init);
+    if args.host_calls {
+        chip8.enable_host_calls();
+    }
+    if args.serial {
+        chip8.map_peripheral(Serial::ADDR..Serial::ADDR + Serial::LEN, Serial::stdio());
+    }
+    if let Err(err) = chip8.load_rom_bytes(&cartridge.rom) {
+        error!(rom = %args.rom.display(), %err, "Could not load ROM");
+        return ExitCode::from(2);
+    }
+    for &(addr, value) in &args.poke {
+        chip8.bus.ram[addr as usize] = value;
+    }
+
+    if args.profiling() && backend != BackendKind::Interpreter {
+        error!("Profiling needs the interpreter backend");
+        return ExitCode::from(2);
+    }
+    if args.monitor && backend != BackendKind::Interpreter {
+        error!("The monitor needs the interpreter backend");
+        return ExitCode::from(2);
+    }
+    let mut backend = match Backend::new(backend) {
+        Ok(backend) => backend,
+        Err(err) => {
+            error!(%err, "Could not start backend");
+            return ExitCode::from(2);
+        }
+    };
+
+    let mut replay = args.record_replay.as_ref().map(|_| {
+        let mut replay = script.clone();
+        replay.clear_hashes();
+        replay
+    });
+    if let Some(dir) = args.checkpoints.as_ref().filter(|_| replay.is_some())
+        && let Err(err) = fs::create_dir_all(dir)
+    {
+        error!(dir = %dir.display(), %err, "Could not create checkpoint directory");
+        return ExitCode::from(2);
+    }
+    if let Some(dir) = &args.snapshots {
+        if let Err(err) = fs::create_dir_all(dir) {
+            error!(dir = %dir.display(), %err, "Could not create snapshot directory");
+            return ExitCode::from(2);
+        }
+        if !snapshot::listen() {
+            warn!("Snapshots on SIGUSR1 need Unix");
+        }
+    }
+
+    let mut profile = args.profiling().then(Profile::new);
+    let symbols = match &args.symbols {
+        Some(path) => match Symbols::load(path) {
+            Ok(symbols) => symbols,
+            Err(err) => {
+                error!(file = %path.display(), %err, "Could not load symbols");
+                return ExitCode::from(2);
+            }
+        },
+        None => Symbols::default(),
+    };
+    let mut game = GameConfig::load(&args.rom);
+    let mut monitor = args.monitor.then(|| {
+        let mut monitor = Monitor::spawn(true, symbols);
+        monitor.restore(&game.monitor);
+        monitor
+    });
+    if monitor.is_some() {
+        println!("Stopped at {:#05x}, ? lists the commands", chip8.pc);
+    }
+    let ticks_per_frame = args.ticks_per_frame.max(1) as u64;
+    let hash_interval = args.hash_interval.max(1);
+    let mut frame = 0;
+    let mut executed = 0;
+    while executed < args.cycles {
+        if let Some(replay) = &mut replay {
+            if frame > 0 && frame % hash_interval == 0 {
+                let state = chip8.save_state();
+                replay.set_hash(frame, state.hash());
+                if let Some(dir) = &args.checkpoints
+                    && let Err(err) = state.save(checkpoint_file(dir, frame))
+                {
+                    error!(dir = %dir.display(), %err, "Could not save checkpoint");
+                    return ExitCode::from(2);
+                }
+            }
+        } else if let Some(expected) = script.hash_at(frame) {
+            let state = chip8.save_state();
+            if state.hash() != expected {
+                report_desync(frame, &state, expected, args.checkpoints.as_deref());
+                return ExitCode::from(1);
+            }
+        }
+
+        if let Some(dir) = &args.snapshots
+            && snapshot::requested()
+        {
+            write_snapshot(
+                &chip8,
+                dir,
+                &CaptureInfo::new(&args.rom, &cartridge.rom, frame),
+            );
+        }
+
+        script.apply(frame, &mut chip8);
+        let cycles = ticks_per_frame.min(args.cycles - executed);
+        if let Some(monitor) = &mut monitor {
+            monitor.poll(&mut chip8);
+            monitor.persist(&mut game);
+        }
+        let result = if profile.is_some() || monitor.is_some() {
+            (0..cycles).try_for_each(|_| {
+                if let Some(monitor) = &mut monitor {
+                    while !monitor.check(&chip8) {
+                        monitor.wait(&mut chip8);
+                        monitor.persist(&mut game);
+                    }
+                }
+                if let Some(profile) = &mut profile {
+                    profile.record(&chip8);
+                }
+                chip8.cycle().map(|_| ())
+            })
+        } else {
+            backend.run(&mut chip8, cycles as u32).map(|_| ())
+        };
+        if let Err(err) = result {
+            error!(frame, %err, "Machine stopped");
+            return ExitCode::from(2);
+        }
+        executed += cycles;
+        if cycles == ticks_per_frame {
+            chip8.tick_timers();
+            frame += 1;
+        }
+    }
+
+    if let (Some(path), Some(replay)) = (&args.record_replay, &replay) {
+        let json = serde_json::to_string_pretty(replay).expect("Replay should serialize");
+        if let Err(err) = fs::write(path, json) {
+            error!(file = %path.display(), %err, "Could not write replay");
+            return ExitCode::from(2);
+        }
+    }
+
+    for range in &args.peek {
+        print!("{}", hex_dump(&chip8.bus.ram, range.clone()));
+    }
+    if args.dump_screen {
+        print!("{}", chip8.render_ascii('#', '.'));
+    }
+    #[cfg(feature = "png")]
+    if let Some(path) = &args.screenshot
+        && let Err(err) = chip8.save_screenshot(
+            path,
+            &PALETTES[0],
+            10,
+            &CaptureInfo::new(&args.rom, &cartridge.rom, frame),
+        )
+    {
+        error!(file = %path.display(), %err, "Could not save screenshot");
+        return ExitCode::from(2);
+    }
+    if let Some(profile) = &profile
+        && !write_profile(profile, &chip8, &args)
+    {
+        return ExitCode::from(2);
+    }
+    if let Some(path) = &args.save_state
+        && let Err(err) = chip8.save_state().save(path)
+    {
+        error!(file = %path.display(), %err, "Could not save state");
+        return ExitCode::from(2);
+    }
+    let hash = chip8.frame_hash();
+    println!("{:016x}", hash);
+    match args.expect_hash {
+        Some(expected) if expected != hash => {
+            error!(
+                expected = format!("{:016x}", expected),
+                actual = format!("{:016x}", hash),
+                "Framebuffer hash mismatch"
+            );
+            ExitCode::from(1)
+        }
+        _ => ExitCode::SUCCESS,
+    }
+}
+
+/// Write the outputs of `--profile`, `--heatmap` and `--subroutines`, returning whether
+/// all could be written.
+fn write_profile(profile: &Profile, chip8: &Chip8, args: &RunArgs) -> bool {
+    if let Some(path) = &args.profile {
+        let mut csv = Vec::new();
+        if let Err(err) = profile
+            .write_csv(&chip8.bus.ram, &mut csv)
+            .and_then(|()| fs::write(path, csv))
+        {
+            error!(file = %path.display(), %err, "Could not write profile");
+            return false;
+        }
+    }
+    #[cfg(feature = "png")]
+    if let Some(path) = &args.heatmap
+        && let Err(err) = profile.save_heatmap(path, 8)
+    {
+        error!(file = %path.display(), %err, "Could not save heatmap");
+        return false;
+    }
+    let subroutines = profile.subroutines();
+    if args.subroutines {
+        print!("{}", SubroutineTable(&subroutines));
+    }
+    if let Some(path) = &args.subroutines_json {
+        let json = serde_json::to_string_pretty(&subroutines).expect("Profile should serialize");
+        if let Err(err) = fs::write(path, json) {
+            error!(file = %path.display(), %err, "Could not write subroutine profile");
+            return false;
+        }
+    }
+    true
+}
+
+/// Save state and screenshot of the moment, failures logged but not stopping the run.
+fn write_snapshot(chip8: &Chip8, dir: &Path, info: &CaptureInfo) {
+    let state = dir.join(format!("{:08}.c8s", info.frame));
+    match chip8.save_state().save(&state) {
+        Ok(()) => info!(file = %state.display(), "Snapshot saved"),
+        Err(err) => error!(file = %state.display(), %err, "Could not save snapshot"),
+    }
+    #[cfg(feature = "png")]
+    {
+        let screenshot = dir.join(format!("{:08}.png", info.frame));
+        if let Err(err) = chip8.save_screenshot(&screenshot, &PALETTES[0], 10, info) {
+            error!(file = %screenshot.display(), %err, "Could not save snapshot screenshot");
+        }
+    }
+}
+
+fn checkpoint_file(dir: &Path, frame: u64) -> PathBuf {
+    dir.join(format!("{:08}.c8s", frame))
+}
+
+/// Log where a replay went out of sync and print how the state differs from the recording,
+/// when its checkpoint is available.
+fn report_desync(frame: u64, state: &SaveState, expected: u64, checkpoints: Option<&Path>) {
+    error!(
+        frame,
+        expected = format!("{:016x}", expected),
+        actual = format!("{:016x}", state.hash()),
+        "Replay desync"
+    );
+    let Some(dir) = checkpoints else {
+        return;
+    };
+    match SaveState::load(checkpoint_file(dir, frame)) {
+        Ok(recorded) => print!("{}", recorded.diff(state)),
+        Err(err) => error!(dir = %dir.display(), frame, %err, "No checkpoint to compare with"),
+    }
+}
+
+/// `range` of `ram` as lines of up to 16 bytes, each starting with its address.
+pub fn hex_dump(ram: &[u8], range: Range<u16>) -> String {
+    let mut text = String::new();
+    for start in range.clone().step_by(16) {
+        let end = range.end.min(start.saturating_add(16));
+        let bytes: Vec<String> = ram[start as usize..end as usize]
+            .iter()
+            .map(|byte| format!("{:02x}", byte))
+            .collect();
+        text += &format!("{:#05x}: {}\n", start, bytes.join(" "));
+    }
+    text
+}
+
+/// RAM address in hex, with or without `0x`, at most `last`.
+pub fn parse_addr(addr: &str, last: u16) -> Result<u16, String> {
+    let digits = addr.trim().trim_start_matches("0x");
+    match u16::from_str_radix(digits, 16) {
+        Ok(value) if value <= last => Ok(value),
+        Ok(value) => Err(format!("address {:#x} is outside of RAM", value)),
+        Err(err) => Err(format!("invalid address {:?}: {}", addr, err)),
+    }
+}
+
+fn parse_poke(poke: &str) -> Result<(u16, u8), String> {
+    let (addr, value) = poke
+        .split_once('=')
+        .ok_or("expected ADDR=VALUE, like 0x3F0=0xFF")?;
+    let digits = value.trim().trim_start_matches("0x");
+    let value = u8::from_str_radix(digits, 16)
+        .map_err(|err| format!("invalid byte {:?}: {}", value, err))?;
+    Ok((parse_addr(addr, 0xFFF)?, value))
+}
+
+/// `start..end` with `end` exclusive, or a single address.
+fn parse_peek(peek: &str) -> Result<Range<u16>, String> {
+    match peek.split_once("..") {
+        Some((start, end)) => {
+            let (start, end) = (parse_addr(start, 0xFFF)?, parse_addr(end, 0x1000)?);
+            if start >= end {
+                return Err(format!("empty range {}", peek));
+            }
+            Ok(start..end)
+        }
+        None => {
+            let addr = parse_addr(peek, 0xFFF)?;
+            Ok(addr..addr + 1)
+        }
+    }
+}
+
+fn parse_hash(hash: &str) -> Result<u64, String> {
+    let digits = hash.strip_prefix("0x").unwrap_or(hash);
+    u64::from_str_radix(digits, 16).map_err(|err| err.to_string())
+}
+
+fn parse_ram_init(name: &str) -> Result<RamInit, String> {
+    RamInit::from_name(name).ok_or_else(|| {
+        let names: Vec<&str> = RamInit::NAMES.iter().map(|(name, _)| *name).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
+
+pub fn parse_quirks(name: &str) -> Result<Quirks, String> {
+    Quirks::preset(name).ok_or_else(|| {
+        let names: Vec<&str> = Quirks::PRESETS.iter().map(|(name, _)| *name).collect();
+        format!("expected one of {}", names.join(", "))
+    })
+}
@@ -0,0 +1,155 @@
+//! Async driver for embedding the core in tokio applications.
+//!
+//! ```ignore
+//! let (mut runner, handle) = Chip8Runner::new(chip8, 10);
+//! let mut frames = handle.frames;
+//! tokio::spawn(async move { runner.run().await });
+//! handle.input.send(KeyEvent::Press(5))?;
+//! while frames.changed().await.is_ok() {
+//!     let frame = frames.borrow_and_update().clone();
+//!     // send frame.video to spectators
+//! }
+//! ```
+
+use crate::chip8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH, StepResult};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::{mpsc, watch};
+use tokio::time::{Interval, MissedTickBehavior};
+
+/// Rate of the timer clock, one `step_frame` per tick.
+const FRAME_HZ: u64 = 60;
+
+/// Keypad change sent to a running `Chip8Runner`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum KeyEvent {
+    /// Key 0x0..=0xF went down
+    Press(u8),
+    /// Key 0x0..=0xF went up
+    Release(u8),
+}
+
+/// Display published after a frame that changed it.
+#[derive(Clone, Debug)]
+pub struct Frame {
+    /// Frames stepped since the runner was created
+    pub number: u64,
+
+    /// Same layout as `Chip8::video`
+    pub video: Arc<[bool; DISP_WIDTH * DISP_HEIGHT]>,
+}
+
+/// Channel ends for talking to a `Chip8Runner` from other tasks.
+pub struct RunnerHandle {
+    /// Keypad input, applied at the start of the next frame
+    pub input: mpsc::UnboundedSender<KeyEvent>,
+
+    /// Latest display, see `watch::Receiver::changed`
+    pub frames: watch::Receiver<Frame>,
+}
+
+/// Steps a `Chip8` at 60 frames per second on the tokio timer.
+///
+/// Frames that fall behind are run late rather than bunched up,
+/// so a stalled executor slows the game down instead of fast-forwarding it.
+pub struct Chip8Runner {
+    chip8: Chip8,
+    ticks_per_frame: u32,
+    interval: Interval,
+    frame: u64,
+    input: mpsc::UnboundedReceiver<KeyEvent>,
+    input_tx: mpsc::UnboundedSender<KeyEvent>,
+    frames: watch::Sender<Frame>,
+}
+
+impl Chip8Runner {
+    /// Runner executing `ticks_per_frame` instructions per frame.
+    /// Must be called from within a tokio runtime.
+    pub fn new(chip8: Chip8, ticks_per_frame: u32) -> (Chip8Runner, RunnerHandle) {
+        let (input_tx, input) = mpsc::unbounded_channel();
+        let (frames, frames_rx) = watch::channel(Frame {
+            number: 0,
+            video: Arc::new(chip8.video),
+        });
+        let mut interval = tokio::time::interval(Duration::from_nanos(1_000_000_000 / FRAME_HZ));
+        interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        let handle = RunnerHandle {
+            input: input_tx.clone(),
+            frames: frames_rx,
+        };
+        let runner = Chip8Runner {
+            chip8,
+            ticks_per_frame,
+            interval,
+            frame: 0,
+            input,
+            input_tx,
+            frames,
+        };
+        (runner, handle)
+    }
+
+    /// Another sender for keypad input.
+    pub fn input(&self) -> mpsc::UnboundedSender<KeyEvent> {
+        self.input_tx.clone()
+    }
+
+    /// Another receiver of the display.
+    pub fn subscribe(&self) -> watch::Receiver<Frame> {
+        self.frames.subscribe()
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    /// Direct access between frames, e.g. to load a ROM or change quirks.
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    /// Wait for the next frame on the timer, then apply pending input, execute
+    /// one frame worth of instructions, tick the timers and publish the display
+    /// if it changed. Returns the result of the last instruction.
+    pub async fn step_frame(&mut self) -> Result<StepResult, Chip8Error> {
+        self.interval.tick().await;
+
+        while let Ok(event) = self.input.try_recv() {
+            match event {
+                KeyEvent::Press(key) => self.chip8.set_key_value(key as usize & 0xF, 1),
+                KeyEvent::Release(key) => self.chip8.set_key_value(key as usize & 0xF, 0),
+            }
+        }
+
+        let mut result = StepResult::Executed;
+        for _ in 0..self.ticks_per_frame {
+            result = self.chip8.cycle()?;
+        }
+        self.chip8.tick_timers();
+        self.frame += 1;
+
+        let (number, video) = (self.frame, &self.chip8.video);
+        self.frames.send_if_modified(|frame| {
+            if *frame.video == *video {
+                return false;
+            }
+            *frame = Frame {
+                number,
+                video: Arc::new(*video),
+            };
+            true
+        });
+        Ok(result)
+    }
+
+    /// Step frames until the program hits a fatal error.
+    /// Drop the future (e.g. abort its task) to stop earlier.
+    pub async fn run(&mut self) -> Chip8Error {
+        loop {
+            if let Err(err) = self.step_frame().await {
+                return err;
+            }
+        }
+    }
+}
@@ -0,0 +1,44 @@
+use chip_8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH, StepResult};
+
+/// Display as a CRT beam scans it out while the game runs, like the COSMAC VIP's display DMA
+/// interleaved with the interpreter: each row shows the pixels as they were when the beam
+/// passed it, so sprites redrawn mid-frame tear and flicker instead of appearing all at once.
+pub struct Scanout {
+    video: [bool; DISP_WIDTH * DISP_HEIGHT],
+}
+
+impl Scanout {
+    pub fn new() -> Scanout {
+        Scanout {
+            video: [false; DISP_WIDTH * DISP_HEIGHT],
+        }
+    }
+
+    /// Run a frame of `cycles` instructions with `run`, in slices between the rows.
+    pub fn run_frame(
+        &mut self,
+        chip8: &mut Chip8,
+        cycles: u32,
+        mut run: impl FnMut(&mut Chip8, u32) -> Result<StepResult, Chip8Error>,
+    ) -> Result<StepResult, Chip8Error> {
+        let mut result = StepResult::Executed;
+        let mut done = 0;
+        for (row, pixels) in self.video.chunks_mut(DISP_WIDTH).enumerate() {
+            let due = (row as u64 * cycles as u64 / DISP_HEIGHT as u64) as u32;
+            if due > done {
+                result = run(chip8, due - done)?;
+                done = due;
+            }
+            pixels.copy_from_slice(&chip8.video[row * DISP_WIDTH..(row + 1) * DISP_WIDTH]);
+        }
+        if cycles > done {
+            result = run(chip8, cycles - done)?;
+        }
+        Ok(result)
+    }
+
+    /// The display as last scanned out.
+    pub fn video(&self) -> &[bool; DISP_WIDTH * DISP_HEIGHT] {
+        &self.video
+    }
+}
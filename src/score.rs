@@ -0,0 +1,30 @@
+use crate::game_config::GameConfig;
+use chip_8::Chip8;
+use tracing::info;
+
+/// Follows the score of the running game, see `GameConfig::score`,
+/// and enters the best one of the session into the game's leaderboard.
+#[derive(Default)]
+pub struct ScoreWatcher {
+    best: Option<u32>,
+}
+
+impl ScoreWatcher {
+    pub fn update(&mut self, chip8: &Chip8, game: &GameConfig) {
+        if let Some(score) = game.score.and_then(|source| source.read(chip8)) {
+            self.best = self.best.max(Some(score));
+        }
+    }
+
+    /// End the session: record its best score and announce a new high score.
+    pub fn finish(&mut self, game: &mut GameConfig) {
+        let Some(score) = self.best.take().filter(|&score| score > 0) else {
+            return;
+        };
+        match game.add_high_score(score) {
+            Some(previous) => info!(score, previous, "New high score!"),
+            None => info!(score, best = game.high_scores[0].score, "Score"),
+        }
+        game.save();
+    }
+}
@@ -0,0 +1,127 @@
+use crate::chip8::Chip8;
+use serde::de::Error;
+use serde::{Deserialize, Serialize};
+
+/// Key changes applied at the start of `frame`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct InputEvent {
+    pub frame: u64,
+
+    /// `SaveState::hash` at the start of `frame`, before its key changes,
+    /// for checking that a replay still runs the way it was recorded
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "hex_hash")]
+    pub hash: Option<u64>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub press: Vec<u8>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub release: Vec<u8>,
+}
+
+/// Scripted keypad input for headless runs.
+///
+/// ```json
+/// [
+///     {"frame": 60, "press": [5]},
+///     {"frame": 64, "release": [5]},
+///     {"frame": 120, "hash": "3c7f0e1d9a2b4c55"}
+/// ]
+/// ```
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct InputScript {
+    pub events: Vec<InputEvent>,
+}
+
+impl InputScript {
+    pub fn from_json(json: &str) -> serde_json::Result<InputScript> {
+        let script: InputScript = serde_json::from_str(json)?;
+        for event in &script.events {
+            if let Some(key) = event.press.iter().chain(&event.release).find(|&&k| k > 0xF) {
+                return Err(serde_json::Error::custom(format!(
+                    "frame {}: no such key {}",
+                    event.frame, key
+                )));
+            }
+        }
+        Ok(script)
+    }
+
+    /// Frame of the last event, after which the script has no further effect.
+    pub fn len_frames(&self) -> u64 {
+        self.events
+            .iter()
+            .map(|event| event.frame)
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// State hash expected at the start of `frame`, if the script has one.
+    pub fn hash_at(&self, frame: u64) -> Option<u64> {
+        self.events
+            .iter()
+            .filter(|event| event.frame == frame)
+            .find_map(|event| event.hash)
+    }
+
+    /// Record `hash` as the expected state hash at the start of `frame`.
+    pub fn set_hash(&mut self, frame: u64, hash: u64) {
+        match self.events.iter_mut().find(|event| event.frame == frame) {
+            Some(event) => event.hash = Some(hash),
+            None => {
+                let at = self.events.partition_point(|event| event.frame < frame);
+                self.events.insert(
+                    at,
+                    InputEvent {
+                        frame,
+                        hash: Some(hash),
+                        press: Vec::new(),
+                        release: Vec::new(),
+                    },
+                );
+            }
+        }
+    }
+
+    /// Drop all state hashes, keeping the key changes.
+    pub fn clear_hashes(&mut self) {
+        self.events.retain_mut(|event| {
+            event.hash = None;
+            !event.press.is_empty() || !event.release.is_empty()
+        });
+    }
+
+    /// Apply all events scheduled for `frame`.
+    pub fn apply(&self, frame: u64, chip8: &mut Chip8) {
+        for event in self.events.iter().filter(|event| event.frame == frame) {
+            for &key in &event.release {
+                chip8.set_key_value(key as usize, 0);
+            }
+            for &key in &event.press {
+                chip8.set_key_value(key as usize, 1);
+            }
+        }
+    }
+}
+
+/// Hashes as 16 hex digits, JSON numbers lose precision beyond 2^53 in most tools.
+mod hex_hash {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(hash: &Option<u64>, serializer: S) -> Result<S::Ok, S::Error> {
+        match hash {
+            Some(hash) => serializer.serialize_str(&format!("{:016x}", hash)),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<u64>, D::Error> {
+        Option::<String>::deserialize(deserializer)?
+            .map(|hash| u64::from_str_radix(&hash, 16).map_err(D::Error::custom))
+            .transpose()
+    }
+}
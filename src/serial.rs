@@ -0,0 +1,88 @@
+//! Serial console for homebrew debug output, see `Serial`.
+
+use crate::peripheral::Peripheral;
+use std::collections::VecDeque;
+use std::io::{self, Read, Write};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+use tracing::warn;
+
+/// Serial port writing the bytes a program stores to the host and reading host input,
+/// for `printf`-style debugging of homebrew.
+///
+/// Every address of its range is the data port, so `save vX` with I on it sends V0 to VX
+/// in order and `load vX` receives as many bytes, `0` once the input ran dry. The assembler's
+/// `:print` emits the code to send a line of text.
+pub struct Serial {
+    output: Box<dyn Write + Send + Sync>,
+    /// Input read so far by the reader thread and not yet received
+    input: Arc<Mutex<VecDeque<u8>>>,
+}
+
+impl Serial {
+    /// Where frontends map the port, in the interpreter area below programs.
+    pub const ADDR: u16 = 0x1F0;
+
+    /// Addresses the port takes up from `ADDR`.
+    pub const LEN: u16 = 16;
+
+    /// Port writing to `output` and reading `input` on a thread of its own,
+    /// so waiting for input never blocks the machine.
+    pub fn new(
+        output: impl Write + Send + Sync + 'static,
+        input: impl Read + Send + 'static,
+    ) -> Serial {
+        Serial {
+            output: Box::new(output),
+            input: spawn_reader(input),
+        }
+    }
+
+    /// Port on the host terminal, stdout and stdin. Ports made like this share one reader
+    /// of stdin, so a machine started again does not lose input to the old one.
+    pub fn stdio() -> Serial {
+        static STDIN: OnceLock<Arc<Mutex<VecDeque<u8>>>> = OnceLock::new();
+        Serial {
+            output: Box::new(io::stdout()),
+            input: Arc::clone(STDIN.get_or_init(|| spawn_reader(io::stdin()))),
+        }
+    }
+}
+
+/// Buffer a thread fills with what it reads from `input`, until it ends.
+fn spawn_reader(mut input: impl Read + Send + 'static) -> Arc<Mutex<VecDeque<u8>>> {
+    let buffer = Arc::new(Mutex::new(VecDeque::new()));
+    let received = Arc::clone(&buffer);
+    thread::spawn(move || {
+        let mut chunk = [0; 256];
+        loop {
+            match input.read(&mut chunk) {
+                Ok(0) => break,
+                Ok(len) => received.lock().unwrap().extend(&chunk[..len]),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => {
+                    warn!(%err, "Serial input failed");
+                    break;
+                }
+            }
+        }
+    });
+    buffer
+}
+
+impl Peripheral for Serial {
+    fn read(&mut self, _addr: u16) -> u8 {
+        self.input.lock().unwrap().pop_front().unwrap_or(0)
+    }
+
+    fn write(&mut self, _addr: u16, value: u8) {
+        // Flushed every byte, output is for people watching it live.
+        if let Err(err) = self
+            .output
+            .write_all(&[value])
+            .and_then(|_| self.output.flush())
+        {
+            warn!(%err, "Serial output failed");
+        }
+    }
+}
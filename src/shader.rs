@@ -0,0 +1,277 @@
+use sdl2::VideoSubsystem;
+use sdl2::rect::Rect;
+use sdl2::render::{Texture, WindowCanvas};
+use std::ffi::{CString, c_char};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::time::{Instant, SystemTime};
+use tracing::{info, warn};
+
+const GL_FRAGMENT_SHADER: u32 = 0x8B30;
+const GL_VERTEX_SHADER: u32 = 0x8B31;
+const GL_COMPILE_STATUS: u32 = 0x8B81;
+const GL_LINK_STATUS: u32 = 0x8B82;
+const GL_INFO_LOG_LENGTH: u32 = 0x8B84;
+const GL_QUADS: u32 = 0x0007;
+
+/// Passes the quad through in normalized device coordinates, leaving SDL's matrices alone.
+const VERTEX_SHADER: &str = "#version 120
+void main() {
+    gl_Position = gl_Vertex;
+    gl_TexCoord[0] = gl_MultiTexCoord0;
+}
+";
+
+/// OpenGL entry points of SDL's context, for the little shaders need.
+struct Gl {
+    create_shader: unsafe extern "system" fn(u32) -> u32,
+    shader_source: unsafe extern "system" fn(u32, i32, *const *const c_char, *const i32),
+    compile_shader: unsafe extern "system" fn(u32),
+    get_shader_iv: unsafe extern "system" fn(u32, u32, *mut i32),
+    get_shader_info_log: unsafe extern "system" fn(u32, i32, *mut i32, *mut c_char),
+    delete_shader: unsafe extern "system" fn(u32),
+    create_program: unsafe extern "system" fn() -> u32,
+    attach_shader: unsafe extern "system" fn(u32, u32),
+    link_program: unsafe extern "system" fn(u32),
+    get_program_iv: unsafe extern "system" fn(u32, u32, *mut i32),
+    get_program_info_log: unsafe extern "system" fn(u32, i32, *mut i32, *mut c_char),
+    delete_program: unsafe extern "system" fn(u32),
+    use_program: unsafe extern "system" fn(u32),
+    get_uniform_location: unsafe extern "system" fn(u32, *const c_char) -> i32,
+    uniform_1i: unsafe extern "system" fn(i32, i32),
+    uniform_1f: unsafe extern "system" fn(i32, f32),
+    uniform_2f: unsafe extern "system" fn(i32, f32, f32),
+    begin: unsafe extern "system" fn(u32),
+    end: unsafe extern "system" fn(),
+    tex_coord_2f: unsafe extern "system" fn(f32, f32),
+    vertex_2f: unsafe extern "system" fn(f32, f32),
+}
+
+/// Look `name` up in the current OpenGL context as a function of the type the field asks for.
+macro_rules! gl_fn {
+    ($video:expr, $name:literal) => {{
+        let address = $video.gl_get_proc_address($name);
+        if address.is_null() {
+            return Err(format!("OpenGL has no {}", $name));
+        }
+        // SAFETY: the field's type is the signature of the OpenGL function of that name.
+        #[allow(clippy::missing_transmute_annotations)]
+        let function = unsafe { std::mem::transmute::<*const (), _>(address) };
+        function
+    }};
+}
+
+impl Gl {
+    fn load(video: &VideoSubsystem) -> Result<Gl, String> {
+        Ok(Gl {
+            create_shader: gl_fn!(video, "glCreateShader"),
+            shader_source: gl_fn!(video, "glShaderSource"),
+            compile_shader: gl_fn!(video, "glCompileShader"),
+            get_shader_iv: gl_fn!(video, "glGetShaderiv"),
+            get_shader_info_log: gl_fn!(video, "glGetShaderInfoLog"),
+            delete_shader: gl_fn!(video, "glDeleteShader"),
+            create_program: gl_fn!(video, "glCreateProgram"),
+            attach_shader: gl_fn!(video, "glAttachShader"),
+            link_program: gl_fn!(video, "glLinkProgram"),
+            get_program_iv: gl_fn!(video, "glGetProgramiv"),
+            get_program_info_log: gl_fn!(video, "glGetProgramInfoLog"),
+            delete_program: gl_fn!(video, "glDeleteProgram"),
+            use_program: gl_fn!(video, "glUseProgram"),
+            get_uniform_location: gl_fn!(video, "glGetUniformLocation"),
+            uniform_1i: gl_fn!(video, "glUniform1i"),
+            uniform_1f: gl_fn!(video, "glUniform1f"),
+            uniform_2f: gl_fn!(video, "glUniform2f"),
+            begin: gl_fn!(video, "glBegin"),
+            end: gl_fn!(video, "glEnd"),
+            tex_coord_2f: gl_fn!(video, "glTexCoord2f"),
+            vertex_2f: gl_fn!(video, "glVertex2f"),
+        })
+    }
+
+    /// Compiled shader of `kind`, or the compiler's log.
+    unsafe fn compile(&self, kind: u32, source: &str) -> Result<u32, String> {
+        let source = CString::new(source).map_err(|_| "shader contains a zero byte")?;
+        unsafe {
+            let shader = (self.create_shader)(kind);
+            (self.shader_source)(shader, 1, &source.as_ptr(), ptr::null());
+            (self.compile_shader)(shader);
+            let mut compiled = 0;
+            (self.get_shader_iv)(shader, GL_COMPILE_STATUS, &mut compiled);
+            if compiled == 0 {
+                let log = self.log(shader, self.get_shader_iv, self.get_shader_info_log);
+                (self.delete_shader)(shader);
+                return Err(log);
+            }
+            Ok(shader)
+        }
+    }
+
+    /// Program of the pass-through vertex shader and `fragment`, or what went wrong.
+    unsafe fn link(&self, fragment: &str) -> Result<u32, String> {
+        unsafe {
+            let vertex = self.compile(GL_VERTEX_SHADER, VERTEX_SHADER)?;
+            let fragment = match self.compile(GL_FRAGMENT_SHADER, fragment) {
+                Ok(fragment) => fragment,
+                Err(err) => {
+                    (self.delete_shader)(vertex);
+                    return Err(err);
+                }
+            };
+            let program = (self.create_program)();
+            (self.attach_shader)(program, vertex);
+            (self.attach_shader)(program, fragment);
+            (self.link_program)(program);
+            // The program keeps them as long as it needs them.
+            (self.delete_shader)(vertex);
+            (self.delete_shader)(fragment);
+            let mut linked = 0;
+            (self.get_program_iv)(program, GL_LINK_STATUS, &mut linked);
+            if linked == 0 {
+                let log = self.log(program, self.get_program_iv, self.get_program_info_log);
+                (self.delete_program)(program);
+                return Err(log);
+            }
+            Ok(program)
+        }
+    }
+
+    unsafe fn log(
+        &self,
+        object: u32,
+        get_iv: unsafe extern "system" fn(u32, u32, *mut i32),
+        get_log: unsafe extern "system" fn(u32, i32, *mut i32, *mut c_char),
+    ) -> String {
+        let mut len = 0;
+        unsafe {
+            get_iv(object, GL_INFO_LOG_LENGTH, &mut len);
+            let mut log = vec![0u8; len.max(1) as usize];
+            get_log(
+                object,
+                len,
+                ptr::null_mut(),
+                log.as_mut_ptr() as *mut c_char,
+            );
+            String::from_utf8_lossy(&log)
+                .trim_end_matches('\0')
+                .trim()
+                .to_string()
+        }
+    }
+}
+
+/// GLSL fragment shader from a file that draws the display instead of a plain copy,
+/// see `Config::shader`. It is compiled again whenever the file changes.
+///
+/// The shader is GLSL 1.20 and gets the display as `uniform sampler2D frame` at
+/// `gl_TexCoord[0]`, its size in pixels as `uniform vec2 frame_size`, the size it is drawn at
+/// in output pixels as `uniform vec2 output_size` and the seconds since loading as
+/// `uniform float time`. Needs SDL's OpenGL renderer.
+pub struct Shader {
+    gl: Gl,
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    program: u32,
+    start: Instant,
+}
+
+impl Shader {
+    /// Compile the shader at `path` for the OpenGL context current on `video`.
+    pub fn load(path: &Path, video: &VideoSubsystem) -> Result<Shader, String> {
+        let gl = Gl::load(video)?;
+        let modified = modified(path);
+        let source = fs::read_to_string(path).map_err(|err| err.to_string())?;
+        let program = unsafe { gl.link(&source)? };
+        Ok(Shader {
+            gl,
+            path: path.to_path_buf(),
+            modified,
+            program,
+            start: Instant::now(),
+        })
+    }
+
+    /// Compile the file again when it changed since, keeping the running program
+    /// when the new one does not compile.
+    fn reload_if_changed(&mut self) {
+        let modified = modified(&self.path);
+        if modified == self.modified {
+            return;
+        }
+        self.modified = modified;
+        let program = fs::read_to_string(&self.path)
+            .map_err(|err| err.to_string())
+            .and_then(|source| unsafe { self.gl.link(&source) });
+        match program {
+            Ok(program) => {
+                unsafe { (self.gl.delete_program)(self.program) };
+                self.program = program;
+                info!(file = %self.path.display(), "Shader reloaded");
+            }
+            Err(err) => warn!(file = %self.path.display(), %err, "Could not compile shader"),
+        }
+    }
+
+    /// Draw `texture`, `frame_size` pixels of it, into `viewport` through the shader.
+    pub fn draw(
+        &mut self,
+        canvas: &mut WindowCanvas,
+        texture: &mut Texture,
+        frame_size: (u32, u32),
+        viewport: Rect,
+    ) {
+        self.reload_if_changed();
+        // SDL batches its drawing, what it has queued belongs below the display.
+        unsafe { canvas.render_flush() };
+        let (output_width, output_height) = canvas.output_size().expect("Error when drawing");
+        let ndc_x = |x: i32| x as f32 / output_width as f32 * 2.0 - 1.0;
+        let ndc_y = |y: i32| 1.0 - y as f32 / output_height as f32 * 2.0;
+        let (left, right) = (ndc_x(viewport.left()), ndc_x(viewport.right()));
+        let (top, bottom) = (ndc_y(viewport.top()), ndc_y(viewport.bottom()));
+        let gl = &self.gl;
+        let program = self.program;
+        let time = self.start.elapsed().as_secs_f32();
+        texture.gl_with_bind(|texture_width, texture_height| unsafe {
+            (gl.use_program)(program);
+            let uniform = |name: &str| {
+                let name = CString::new(name).expect("uniform names have no zero bytes");
+                (gl.get_uniform_location)(program, name.as_ptr())
+            };
+            (gl.uniform_1i)(uniform("frame"), 0);
+            (gl.uniform_2f)(
+                uniform("frame_size"),
+                frame_size.0 as f32,
+                frame_size.1 as f32,
+            );
+            (gl.uniform_2f)(
+                uniform("output_size"),
+                viewport.width() as f32,
+                viewport.height() as f32,
+            );
+            (gl.uniform_1f)(uniform("time"), time);
+            (gl.begin)(GL_QUADS);
+            for (u, v, x, y) in [
+                (0.0, 0.0, left, top),
+                (texture_width, 0.0, right, top),
+                (texture_width, texture_height, right, bottom),
+                (0.0, texture_height, left, bottom),
+            ] {
+                (gl.tex_coord_2f)(u, v);
+                (gl.vertex_2f)(x, y);
+            }
+            (gl.end)();
+            // SDL's renderer keeps track of its own program, and draws right without one.
+            (gl.use_program)(0);
+        });
+    }
+}
+
+impl Drop for Shader {
+    fn drop(&mut self) {
+        unsafe { (self.gl.delete_program)(self.program) };
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|meta| meta.modified()).ok()
+}
@@ -0,0 +1,27 @@
+//! SIGUSR1 as a request for a snapshot of a long headless run, see `run --snapshots`.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+
+static REQUESTED: AtomicBool = AtomicBool::new(false);
+
+/// Take SIGUSR1 as a snapshot request from now on, instead of being killed by it.
+/// Returns whether that worked; there are no such signals outside of Unix.
+#[cfg(unix)]
+pub fn listen() -> bool {
+    extern "C" fn on_signal(_: libc::c_int) {
+        REQUESTED.store(true, Ordering::Relaxed);
+    }
+    let handler = on_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+    // SAFETY: the handler only stores to an atomic, which is safe in a signal handler.
+    unsafe { libc::signal(libc::SIGUSR1, handler) != libc::SIG_ERR }
+}
+
+#[cfg(not(unix))]
+pub fn listen() -> bool {
+    false
+}
+
+/// Whether a snapshot was requested since the last call.
+pub fn requested() -> bool {
+    REQUESTED.swap(false, Ordering::Relaxed)
+}
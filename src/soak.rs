@@ -0,0 +1,277 @@
+use crate::remote;
+use crate::run::parse_quirks;
+use chip_8::{Cartridge, Chip8, Instruction, Quirks};
+use clap::Args;
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::any::Any;
+use std::fs;
+use std::io;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::sync::mpsc::{self, Receiver};
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{error, info};
+
+/// Frames run between clock checks.
+const FRAMES_PER_CHECK: u64 = 60;
+
+/// Chance per frame that a random key changes, so games see presses held for a while.
+const KEY_CHANGE_CHANCE: f64 = 0.1;
+
+/// Extensions of the files `rom_files` takes as ROMs: plain ROMs, `.c8x` and Octo cartridges.
+const ROM_EXTENSIONS: [&str; 3] = ["ch8", "c8x", "gif"];
+
+#[derive(Args)]
+pub struct SoakArgs {
+    /// Directory with the ROMs to run, every file in it
+    dir: PathBuf,
+
+    /// Time for the whole directory, split evenly between the ROMs
+    #[arg(long, default_value_t = 1.0)]
+    minutes: f64,
+
+    /// Quirks preset (modern, cosmac, superchip), instead of each ROM's own
+    #[arg(long, value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+
+    /// Seed for RND results and the random key presses
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Instructions per 60 Hz frame
+    #[arg(long, default_value_t = 10)]
+    ticks_per_frame: u32,
+
+    /// Run a twin of every machine on another thread with the same seed and input,
+    /// and fail the ROM when their state hashes differ after any frame
+    #[arg(long)]
+    lockstep: bool,
+}
+
+/// How a ROM's soak run ended.
+enum Outcome {
+    /// Ran for its whole time
+    Survived,
+
+    /// Ended up jumping to itself, how many programs stop for good
+    Looped { pc: u16 },
+
+    /// Stopped on an illegal opcode, address or stack use
+    Halted(String),
+
+    /// Broke an invariant of the machine or panicked
+    Failed(String),
+}
+
+/// `chip-8 soak`: run every ROM in a directory headless with random input, checking the
+/// machine after every instruction, and report which ones did not survive.
+///
+/// Exits with 0 when every ROM survived or looped, 1 when one halted or failed
+/// and 2 when the directory could not be read.
+pub fn soak(args: SoakArgs) -> ExitCode {
+    let roms = match rom_files(&args.dir) {
+        Ok(roms) => roms,
+        Err(err) => {
+            error!(dir = %args.dir.display(), %err, "Could not read ROM directory");
+            return ExitCode::from(2);
+        }
+    };
+    if roms.is_empty() {
+        error!(dir = %args.dir.display(), "No ROMs to soak");
+        return ExitCode::from(2);
+    }
+    let budget = Duration::from_secs_f64(args.minutes.max(0.0) * 60.0 / roms.len() as f64);
+
+    // Panics are reported per ROM, not as they happen.
+    let hook = panic::take_hook();
+    panic::set_hook(Box::new(|_| {}));
+    let results: Vec<(String, u64, Outcome)> = roms
+        .iter()
+        .map(|rom| {
+            let name = rom.file_name().map_or_else(
+                || rom.display().to_string(),
+                |name| name.to_string_lossy().into_owned(),
+            );
+            info!(rom = %name, "Soaking");
+            let (frames, outcome) = soak_rom(rom, &args, budget);
+            (name, frames, outcome)
+        })
+        .collect();
+    panic::set_hook(hook);
+
+    let width = results
+        .iter()
+        .map(|(name, ..)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(3);
+    println!("{:width$}  {:8}  {:>8}  DETAIL", "ROM", "RESULT", "FRAMES");
+    let mut failed = false;
+    for (name, frames, outcome) in &results {
+        let (result, detail) = match outcome {
+            Outcome::Survived => ("ok", String::new()),
+            Outcome::Looped { pc } => ("looped", format!("jumps to itself at {:#05x}", pc)),
+            Outcome::Halted(err) => ("halted", err.clone()),
+            Outcome::Failed(err) => ("failed", err.clone()),
+        };
+        failed |= matches!(outcome, Outcome::Halted(_) | Outcome::Failed(_));
+        let line = format!("{:width$}  {:8}  {:>8}  {}", name, result, frames, detail);
+        println!("{}", line.trim_end());
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// ROMs in `dir` by name, the files with one of `ROM_EXTENSIONS`;
+/// sources, notes and subdirectories are left out.
+pub fn rom_files(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut roms: Vec<PathBuf> = fs::read_dir(dir)?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && is_rom(path))
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+fn is_rom(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| {
+        ROM_EXTENSIONS
+            .iter()
+            .any(|rom| extension.eq_ignore_ascii_case(rom))
+    })
+}
+
+/// Run `rom` for `budget` with random key presses, returning the frames it got through.
+fn soak_rom(rom: &Path, args: &SoakArgs, budget: Duration) -> (u64, Outcome) {
+    let cartridge = match remote::local_file(rom).and_then(Cartridge::load) {
+        Ok(cartridge) => cartridge,
+        Err(err) => return (0, Outcome::Failed(format!("could not load: {}", err))),
+    };
+    let mut chip8 = match machine(&cartridge, args) {
+        Ok(chip8) => chip8,
+        Err(err) => return (0, Outcome::Failed(format!("could not load: {}", err))),
+    };
+    let (hashes, twin) = if args.lockstep {
+        let (hashes, received) = mpsc::channel();
+        let twin = machine(&cartridge, args).expect("loaded once already");
+        let (seed, ticks_per_frame) = (args.seed, args.ticks_per_frame);
+        let twin = thread::spawn(move || follow(twin, seed, ticks_per_frame, received));
+        (Some(hashes), Some(twin))
+    } else {
+        (None, None)
+    };
+
+    let mut rng = ChaCha8Rng::seed_from_u64(args.seed);
+    let mut frames = 0;
+    let start = Instant::now();
+    let run = panic::catch_unwind(AssertUnwindSafe(|| {
+        loop {
+            if frames % FRAMES_PER_CHECK == 0 && start.elapsed() >= budget {
+                return Outcome::Survived;
+            }
+            press_random_key(&mut rng, &mut chip8);
+            for _ in 0..args.ticks_per_frame.max(1) {
+                let pc = chip8.pc;
+                if let Err(err) = chip8.cycle() {
+                    return Outcome::Halted(err.to_string());
+                }
+                if let Err(broken) = check_invariants(&chip8) {
+                    return Outcome::Failed(broken);
+                }
+                if chip8.pc == pc
+                    && Instruction::decode(chip8.opcode) == (Instruction::Jump { nnn: pc })
+                {
+                    return Outcome::Looped { pc };
+                }
+            }
+            chip8.tick_timers();
+            frames += 1;
+            if let Some(hashes) = &hashes {
+                // A twin that stopped listening already diverged, which is reported below.
+                let _ = hashes.send(chip8.state_hash());
+            }
+        }
+    }));
+    let mut outcome = run.unwrap_or_else(|payload| {
+        Outcome::Failed(format!("panicked: {}", panic_message(&*payload)))
+    });
+    drop(hashes);
+    if let Some(twin) = twin {
+        let diverged = twin
+            .join()
+            .unwrap_or_else(|payload| Some(format!("panicked: {}", panic_message(&*payload))));
+        if let Some(diverged) = diverged {
+            outcome = Outcome::Failed(format!("twin {}", diverged));
+        }
+    }
+    (frames, outcome)
+}
+
+/// Machine set up for soaking `cartridge`, the same every time for the same arguments.
+fn machine(cartridge: &Cartridge, args: &SoakArgs) -> io::Result<Chip8> {
+    let mut chip8 = Chip8::new();
+    chip8.quirks = args
+        .quirks
+        .or_else(|| cartridge.metadata.quirks.as_ref()?.resolve())
+        .unwrap_or_default();
+    chip8.seed(args.seed);
+    chip8.load_rom_bytes(&cartridge.rom)?;
+    Ok(chip8)
+}
+
+/// Now and then flip a random key, from `rng` alone so a twin presses the same ones.
+fn press_random_key(rng: &mut ChaCha8Rng, chip8: &mut Chip8) {
+    if rng.gen_bool(KEY_CHANGE_CHANCE) {
+        let key = rng.gen_range(0..16);
+        chip8.keypad[key] ^= 1;
+    }
+}
+
+/// Run `twin` a frame for every state hash `received` from the soaked machine, with the
+/// same input, returning how it diverged if it did.
+fn follow(
+    mut twin: Chip8,
+    seed: u64,
+    ticks_per_frame: u32,
+    received: Receiver<u64>,
+) -> Option<String> {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    for (frame, hash) in received.into_iter().enumerate() {
+        press_random_key(&mut rng, &mut twin);
+        for _ in 0..ticks_per_frame.max(1) {
+            if let Err(err) = twin.cycle() {
+                return Some(format!("halted alone in frame {}: {}", frame, err));
+            }
+        }
+        twin.tick_timers();
+        if twin.state_hash() != hash {
+            return Some(format!("diverged in frame {}", frame));
+        }
+    }
+    None
+}
+
+/// What must hold after every instruction whatever the program does.
+fn check_invariants(chip8: &Chip8) -> Result<(), String> {
+    if chip8.pc as usize >= chip8.bus.ram.len() {
+        return Err(format!("PC {:#x} outside of memory", chip8.pc));
+    }
+    if chip8.sp as usize > chip8.stack.len() {
+        return Err(format!("SP {} beyond the stack", chip8.sp));
+    }
+    Ok(())
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic")
+}
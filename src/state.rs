@@ -0,0 +1,708 @@
+use crate::bus::{BANK_WINDOW, MAX_BANKS};
+use crate::chip8::{DISP_HEIGHT, DISP_WIDTH, Quirks, pack_video};
+use crate::hash::fnv1a;
+use crate::random::VipRandom;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Start of every save state file.
+const MAGIC: &[u8; 4] = b"C8ST";
+
+/// Layout version, bumped whenever fields are added or reordered.
+const VERSION: u8 = 3;
+
+/// Size of a version 3 save state in bytes without the banks, which follow.
+const SIZE: usize = SIZE_V2 + 1 + 1;
+
+/// Bytes of every bank.
+const BANK_LEN: usize = BANK_WINDOW.end - BANK_WINDOW.start;
+
+/// Size of a version 2 save state, from before the banks, still read.
+const SIZE_V2: usize = SIZE_V1 + 2 + 1;
+
+/// Size of a version 1 save state, from before `vip_rng`, still read.
+const SIZE_V1: usize = 4
+    + 1
+    + 4096
+    + 16
+    + 16 * 2
+    + 1
+    + 2
+    + 2
+    + 1
+    + 1
+    + 16
+    + DISP_WIDTH * DISP_HEIGHT / 8
+    + 1
+    + 32
+    + 8
+    + 16;
+
+/// Complete machine state, enough to resume a run exactly where it was taken,
+/// RND results included. See `Chip8::save_state` and `Chip8::load_state`.
+#[derive(Clone, PartialEq, Eq)]
+pub struct SaveState {
+    pub ram: [u8; 4096],
+    pub registers: [u8; 16],
+    pub stack: [u16; 16],
+    pub sp: u8,
+    pub pc: u16,
+    pub i: u16,
+    pub dt: u8,
+    pub st: u8,
+    pub keypad: [u8; 16],
+    pub video: [bool; DISP_WIDTH * DISP_HEIGHT],
+    pub quirks: Quirks,
+
+    /// Seed, stream and word position of the RND generator
+    pub rng_seed: [u8; 32],
+    pub rng_stream: u64,
+    pub rng_word_pos: u128,
+
+    /// Generator used with `Quirks::vip_random`
+    pub vip_rng: VipRandom,
+
+    /// Bank in `BANK_WINDOW`, 0 unless banked
+    pub bank: u8,
+
+    /// Contents of every bank, the one in the window as in `ram`; empty unless banked
+    pub banks: Vec<Vec<u8>>,
+}
+
+impl SaveState {
+    /// Binary form: `C8ST`, a version byte, then the fields in declaration order,
+    /// integers little endian and the display packed 8 pixels per byte, MSB first.
+    /// The banks are a count byte and the contents of each.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(SIZE + self.banks.len() * BANK_LEN);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.ram);
+        bytes.extend_from_slice(&self.registers);
+        for entry in self.stack {
+            bytes.extend_from_slice(&entry.to_le_bytes());
+        }
+        bytes.push(self.sp);
+        bytes.extend_from_slice(&self.pc.to_le_bytes());
+        bytes.extend_from_slice(&self.i.to_le_bytes());
+        bytes.push(self.dt);
+        bytes.push(self.st);
+        bytes.extend_from_slice(&self.keypad);
+        bytes.extend_from_slice(&pack_video(&self.video));
+        bytes.push(
+            self.quirks.shift_uses_vy as u8
+                | (self.quirks.jump_uses_vx as u8) << 1
+                | (self.quirks.vf_reset as u8) << 2
+                | (self.quirks.clip_sprites as u8) << 3
+                | (self.quirks.vip_random as u8) << 4
+                | (self.quirks.add_i_sets_vf as u8) << 5
+                | (self.quirks.memory_increments_i as u8) << 6
+                | (self.quirks.wrap_memory as u8) << 7,
+        );
+        bytes.extend_from_slice(&self.rng_seed);
+        bytes.extend_from_slice(&self.rng_stream.to_le_bytes());
+        bytes.extend_from_slice(&self.rng_word_pos.to_le_bytes());
+        bytes.extend_from_slice(&self.vip_rng.lfsr.to_le_bytes());
+        bytes.push(self.vip_rng.frame);
+        bytes.push(self.bank);
+        bytes.push(self.banks.len() as u8);
+        for bank in &self.banks {
+            bytes.extend_from_slice(bank);
+        }
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> io::Result<SaveState> {
+        if bytes.len() < 5 || &bytes[..4] != MAGIC {
+            return Err(invalid("not a save state"));
+        }
+        let size = match bytes[4] {
+            1 => SIZE_V1,
+            2 => SIZE_V2,
+            VERSION if bytes.len() >= SIZE => SIZE + bytes[SIZE - 1] as usize * BANK_LEN,
+            VERSION => SIZE,
+            version => {
+                return Err(invalid(format!(
+                    "unsupported save state version {}",
+                    version
+                )));
+            }
+        };
+        if bytes.len() != size {
+            return Err(invalid(format!(
+                "truncated save state: {} bytes",
+                bytes.len()
+            )));
+        }
+
+        let mut reader = Reader(&bytes[5..]);
+        let mut state = SaveState {
+            ram: reader.array(),
+            registers: reader.array(),
+            stack: [0; 16],
+            sp: 0,
+            pc: 0,
+            i: 0,
+            dt: 0,
+            st: 0,
+            keypad: [0; 16],
+            video: [false; DISP_WIDTH * DISP_HEIGHT],
+            quirks: Quirks::default(),
+            rng_seed: [0; 32],
+            rng_stream: 0,
+            rng_word_pos: 0,
+            vip_rng: VipRandom::default(),
+            bank: 0,
+            banks: Vec::new(),
+        };
+        for entry in &mut state.stack {
+            *entry = u16::from_le_bytes(reader.array());
+        }
+        state.sp = reader.byte();
+        state.pc = u16::from_le_bytes(reader.array());
+        state.i = u16::from_le_bytes(reader.array());
+        state.dt = reader.byte();
+        state.st = reader.byte();
+        state.keypad = reader.array();
+        for pixels in state.video.chunks_mut(8) {
+            let byte = reader.byte();
+            for (bit, pixel) in pixels.iter_mut().enumerate() {
+                *pixel = byte & (0x80 >> bit) != 0;
+            }
+        }
+        let quirks = reader.byte();
+        state.quirks = Quirks {
+            shift_uses_vy: quirks & 1 != 0,
+            jump_uses_vx: quirks & 2 != 0,
+            vf_reset: quirks & 4 != 0,
+            clip_sprites: quirks & 8 != 0,
+            vip_random: quirks & 16 != 0,
+            add_i_sets_vf: quirks & 32 != 0,
+            memory_increments_i: quirks & 64 != 0,
+            wrap_memory: quirks & 128 != 0,
+        };
+        state.rng_seed = reader.array();
+        state.rng_stream = u64::from_le_bytes(reader.array());
+        state.rng_word_pos = u128::from_le_bytes(reader.array());
+        if bytes[4] >= 2 {
+            state.vip_rng = VipRandom {
+                lfsr: u16::from_le_bytes(reader.array()).max(1),
+                frame: reader.byte(),
+            };
+        }
+        if bytes[4] >= 3 {
+            state.bank = reader.byte();
+            let count = reader.byte();
+            state.banks = (0..count)
+                .map(|_| reader.array::<BANK_LEN>().to_vec())
+                .collect();
+        }
+        validate(&state)?;
+        Ok(state)
+    }
+
+    /// JSON form for tools that do not link this crate, with the same fields as the binary
+    /// form: numbers in decimal, RAM and the RND seed as hex strings and the display
+    /// as 32 rows of `#` (lit) and `.` (dark).
+    pub fn to_json(&self) -> String {
+        let ram = to_hex(&self.ram);
+        let seed = to_hex(&self.rng_seed);
+        let json = JsonState {
+            version: VERSION,
+            pc: self.pc,
+            i: self.i,
+            sp: self.sp,
+            dt: self.dt,
+            st: self.st,
+            registers: self.registers,
+            stack: self.stack,
+            keypad: self.keypad,
+            quirks: self.quirks,
+            ram,
+            video: self
+                .video
+                .chunks(DISP_WIDTH)
+                .map(|row| row.iter().map(|&on| if on { '#' } else { '.' }).collect())
+                .collect(),
+            rng: JsonRng {
+                seed,
+                stream: self.rng_stream,
+                word_pos: self.rng_word_pos,
+            },
+            vip_rng: JsonVipRng {
+                lfsr: self.vip_rng.lfsr,
+                frame: self.vip_rng.frame,
+            },
+            bank: self.bank,
+            banks: self.banks.iter().map(|bank| to_hex(bank)).collect(),
+        };
+        serde_json::to_string_pretty(&json).expect("Save state should serialize")
+    }
+
+    /// Read the JSON form written by `to_json`, or by the version before without banks.
+    pub fn from_json(json: &str) -> io::Result<SaveState> {
+        let json: JsonState = serde_json::from_str(json).map_err(|err| invalid(err.to_string()))?;
+        if json.version != VERSION && json.version != 2 {
+            return Err(invalid(format!(
+                "unsupported save state version {}",
+                json.version
+            )));
+        }
+        let ram: [u8; 4096] = hex(&json.ram, "ram")?;
+        let rng_seed: [u8; 32] = hex(&json.rng.seed, "rng.seed")?;
+        if json.video.len() != DISP_HEIGHT
+            || json
+                .video
+                .iter()
+                .any(|row| row.chars().count() != DISP_WIDTH)
+        {
+            return Err(invalid(format!(
+                "video should be {} rows of {} pixels",
+                DISP_HEIGHT, DISP_WIDTH
+            )));
+        }
+        let mut video = [false; DISP_WIDTH * DISP_HEIGHT];
+        for (pixel, char) in video
+            .iter_mut()
+            .zip(json.video.iter().flat_map(|row| row.chars()))
+        {
+            *pixel = match char {
+                '#' => true,
+                '.' => false,
+                other => return Err(invalid(format!("invalid pixel {:?} in video", other))),
+            };
+        }
+        let banks = json
+            .banks
+            .iter()
+            .enumerate()
+            .map(|(index, bank)| Ok(hex::<BANK_LEN>(bank, &format!("banks[{}]", index))?.to_vec()))
+            .collect::<io::Result<_>>()?;
+        let state = SaveState {
+            ram,
+            registers: json.registers,
+            stack: json.stack,
+            sp: json.sp,
+            pc: json.pc,
+            i: json.i,
+            dt: json.dt,
+            st: json.st,
+            keypad: json.keypad,
+            video,
+            quirks: json.quirks,
+            rng_seed,
+            rng_stream: json.rng.stream,
+            rng_word_pos: json.rng.word_pos,
+            vip_rng: VipRandom {
+                lfsr: json.vip_rng.lfsr.max(1),
+                frame: json.vip_rng.frame,
+            },
+            bank: json.bank,
+            banks,
+        };
+        validate(&state)?;
+        Ok(state)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.to_bytes())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<SaveState> {
+        SaveState::from_bytes(&fs::read(path)?)
+    }
+
+    /// FNV-1a over `to_bytes`, equal hashes mean the runs are in the same state.
+    pub fn hash(&self) -> u64 {
+        fnv1a(self.to_bytes())
+    }
+
+    /// Everything that differs from `other`, for tracking down where two runs split.
+    pub fn diff(&self, other: &SaveState) -> StateDiff {
+        let mut diff = StateDiff::default();
+
+        let mut values = vec![
+            ("PC".to_string(), self.pc, other.pc),
+            ("I".to_string(), self.i, other.i),
+            ("SP".to_string(), self.sp as u16, other.sp as u16),
+            ("DT".to_string(), self.dt as u16, other.dt as u16),
+            ("ST".to_string(), self.st as u16, other.st as u16),
+        ];
+        for (reg, (&a, &b)) in self.registers.iter().zip(&other.registers).enumerate() {
+            values.push((format!("V{:X}", reg), a as u16, b as u16));
+        }
+        diff.registers = values.into_iter().filter(|(_, a, b)| a != b).collect();
+
+        diff.stack = (0..self.stack.len())
+            .filter(|&idx| self.stack[idx] != other.stack[idx])
+            .map(|idx| (idx, self.stack[idx], other.stack[idx]))
+            .collect();
+
+        let mut addr = 0;
+        while addr < self.ram.len() {
+            if self.ram[addr] == other.ram[addr] {
+                addr += 1;
+                continue;
+            }
+            let start = addr;
+            while addr < self.ram.len() && self.ram[addr] != other.ram[addr] {
+                addr += 1;
+            }
+            diff.ram.push(start as u16..addr as u16);
+        }
+
+        diff.pixels = (0..self.video.len())
+            .filter(|&idx| self.video[idx] != other.video[idx])
+            .map(|idx| ((idx % DISP_WIDTH) as u8, (idx / DISP_WIDTH) as u8))
+            .collect();
+
+        diff.keypad = (0..self.keypad.len() as u8)
+            .filter(|&key| self.keypad[key as usize] != other.keypad[key as usize])
+            .collect();
+        diff.quirks = self.quirks != other.quirks;
+        diff.banks = (self.bank, &self.banks) != (other.bank, &other.banks);
+        diff.rng = (
+            self.rng_seed,
+            self.rng_stream,
+            self.rng_word_pos,
+            self.vip_rng,
+        ) != (
+            other.rng_seed,
+            other.rng_stream,
+            other.rng_word_pos,
+            other.vip_rng,
+        );
+        diff
+    }
+}
+
+/// Differences between two save states, see `SaveState::diff`.
+/// Values are listed as (first, second).
+#[derive(Default)]
+pub struct StateDiff {
+    /// PC, I, SP, timers and V registers, by name
+    pub registers: Vec<(String, u16, u16)>,
+
+    /// Stack slot and its values
+    pub stack: Vec<(usize, u16, u16)>,
+
+    /// Ranges of differing RAM addresses
+    pub ram: Vec<std::ops::Range<u16>>,
+
+    /// Coordinates of differing pixels
+    pub pixels: Vec<(u8, u8)>,
+
+    /// Keys pressed in only one of the states
+    pub keypad: Vec<u8>,
+
+    pub quirks: bool,
+
+    /// Another bank is in the window, or the banks hold other contents
+    pub banks: bool,
+
+    /// RND generators would give different results from here on
+    pub rng: bool,
+}
+
+impl StateDiff {
+    pub fn is_empty(&self) -> bool {
+        self.registers.is_empty()
+            && self.stack.is_empty()
+            && self.ram.is_empty()
+            && self.pixels.is_empty()
+            && self.keypad.is_empty()
+            && !self.quirks
+            && !self.banks
+            && !self.rng
+    }
+}
+
+/// Report with one line per difference, RAM ranges and pixels summarized.
+impl fmt::Display for StateDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "States are identical");
+        }
+        for (name, a, b) in &self.registers {
+            writeln!(f, "{:<3} {:#06x} -> {:#06x}", name, a, b)?;
+        }
+        for (slot, a, b) in &self.stack {
+            writeln!(f, "stack[{:X}] {:#05x} -> {:#05x}", slot, a, b)?;
+        }
+        for range in &self.ram {
+            writeln!(
+                f,
+                "RAM {:#05x}..{:#05x} ({} bytes)",
+                range.start,
+                range.end,
+                range.len()
+            )?;
+        }
+        if !self.pixels.is_empty() {
+            let (xs, ys): (Vec<u8>, Vec<u8>) = self.pixels.iter().copied().unzip();
+            writeln!(
+                f,
+                "{} pixels in ({},{})..=({},{})",
+                self.pixels.len(),
+                xs.iter().min().unwrap(),
+                ys.iter().min().unwrap(),
+                xs.iter().max().unwrap(),
+                ys.iter().max().unwrap()
+            )?;
+        }
+        if !self.keypad.is_empty() {
+            let keys: Vec<String> = self.keypad.iter().map(|key| format!("{:X}", key)).collect();
+            writeln!(f, "keys {}", keys.join(" "))?;
+        }
+        if self.quirks {
+            writeln!(f, "quirks")?;
+        }
+        if self.banks {
+            writeln!(f, "banks")?;
+        }
+        if self.rng {
+            writeln!(f, "RND state")?;
+        }
+        Ok(())
+    }
+}
+
+/// JSON form of `SaveState`, see `SaveState::to_json`.
+#[derive(Serialize, Deserialize)]
+struct JsonState {
+    version: u8,
+    pc: u16,
+    i: u16,
+    sp: u8,
+    dt: u8,
+    st: u8,
+    registers: [u8; 16],
+    stack: [u16; 16],
+    keypad: [u8; 16],
+    quirks: Quirks,
+    ram: String,
+    video: Vec<String>,
+    rng: JsonRng,
+    vip_rng: JsonVipRng,
+    #[serde(default)]
+    bank: u8,
+    #[serde(default)]
+    banks: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonRng {
+    seed: String,
+    stream: u64,
+    word_pos: u128,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonVipRng {
+    lfsr: u16,
+    frame: u8,
+}
+
+/// Fail unless the stack pointer of `state` is within the stack, and it has
+/// at most `MAX_BANKS` banks with `bank` one of them.
+fn validate(state: &SaveState) -> io::Result<()> {
+    if state.sp as usize > state.stack.len() {
+        return Err(invalid(format!(
+            "stack pointer {} past the {} stack entries",
+            state.sp,
+            state.stack.len()
+        )));
+    }
+    if state.banks.len() > MAX_BANKS {
+        return Err(invalid(format!("more than {} banks", MAX_BANKS)));
+    }
+    if state.bank as usize >= state.banks.len().max(1) {
+        return Err(invalid(format!("bank {} does not exist", state.bank)));
+    }
+    Ok(())
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        write!(text, "{:02x}", byte).unwrap();
+    }
+    text
+}
+
+/// Bytes of the hex string `text` in the JSON field `field`.
+fn hex<const N: usize>(text: &str, field: &str) -> io::Result<[u8; N]> {
+    let wrong = || invalid(format!("{} should be {} bytes in hex", field, N));
+    if text.len() != N * 2 || !text.is_ascii() {
+        return Err(wrong());
+    }
+    let mut bytes = [0; N];
+    for (byte, digits) in bytes.iter_mut().zip(text.as_bytes().chunks(2)) {
+        let digits = std::str::from_utf8(digits).map_err(|_| wrong())?;
+        *byte = u8::from_str_radix(digits, 16).map_err(|_| wrong())?;
+    }
+    Ok(bytes)
+}
+
+fn invalid(msg: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.into())
+}
+
+/// Cursor over a buffer already checked to be long enough.
+struct Reader<'a>(&'a [u8]);
+
+impl Reader<'_> {
+    fn byte(&mut self) -> u8 {
+        self.array::<1>()[0]
+    }
+
+    fn array<const N: usize>(&mut self) -> [u8; N] {
+        let (head, rest) = self.0.split_at(N);
+        self.0 = rest;
+        head.try_into().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Chip8;
+    use serde_json::{Value, json};
+
+    /// State with something in every part the JSON form spells out.
+    fn state() -> SaveState {
+        let mut chip8 = Chip8::new();
+        chip8.seed(7);
+        chip8.pc = 0x234;
+        chip8.registers[3] = 0x12;
+        chip8.bus.ram[0x300] = 0xAB;
+        chip8.video[0] = true;
+        chip8.video[DISP_WIDTH + 1] = true;
+        let mut state = chip8.save_state();
+        state.banks = vec![vec![0x01; BANK_LEN], vec![0x02; BANK_LEN]];
+        state.bank = 1;
+        state
+    }
+
+    fn json(state: &SaveState) -> Value {
+        serde_json::from_str(&state.to_json()).unwrap()
+    }
+
+    fn error(json: &Value) -> String {
+        let err = SaveState::from_json(&json.to_string()).err().unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        err.to_string()
+    }
+
+    #[test]
+    fn json_spells_out_every_field() {
+        let json = json(&state());
+        let mut fields: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort();
+        assert_eq!(
+            fields,
+            [
+                "bank",
+                "banks",
+                "dt",
+                "i",
+                "keypad",
+                "pc",
+                "quirks",
+                "ram",
+                "registers",
+                "rng",
+                "sp",
+                "st",
+                "stack",
+                "version",
+                "video",
+                "vip_rng"
+            ]
+        );
+        assert_eq!(json["version"], 3);
+        assert_eq!(json["pc"], 0x234);
+        assert_eq!(json["registers"][3], 0x12);
+
+        let ram = json["ram"].as_str().unwrap();
+        assert_eq!(ram.len(), 4096 * 2);
+        assert_eq!(&ram[0x300 * 2..0x301 * 2], "ab");
+        let video = json["video"].as_array().unwrap();
+        assert_eq!(video.len(), DISP_HEIGHT);
+        assert_eq!(video[0].as_str().unwrap().len(), DISP_WIDTH);
+        assert!(video[0].as_str().unwrap().starts_with("#."));
+        assert!(video[1].as_str().unwrap().starts_with(".#"));
+
+        assert_eq!(json["rng"]["seed"].as_str().unwrap().len(), 64);
+        assert!(json["rng"]["stream"].is_u64() && json["rng"]["word_pos"].is_u64());
+        assert!(json["vip_rng"]["lfsr"].is_u64() && json["vip_rng"]["frame"].is_u64());
+        assert_eq!(json["bank"], 1);
+        assert_eq!(json["banks"][1], "02".repeat(BANK_LEN));
+    }
+
+    #[test]
+    fn json_reads_back_what_it_wrote() {
+        let state = state();
+        assert!(SaveState::from_json(&state.to_json()).unwrap() == state);
+    }
+
+    #[test]
+    fn version_2_json_without_banks_is_read() {
+        let mut json = json(&state());
+        let object = json.as_object_mut().unwrap();
+        object.remove("bank");
+        object.remove("banks");
+        object.insert("version".to_string(), json!(2));
+        let state = SaveState::from_json(&json.to_string()).unwrap();
+        assert!(state.banks.is_empty());
+        assert_eq!(state.bank, 0);
+    }
+
+    #[test]
+    fn malformed_json_is_refused_as_invalid_data() {
+        let valid = json(&state());
+        let with = |field: &str, value: Value| {
+            let mut json = valid.clone();
+            json[field] = value;
+            json
+        };
+        assert_eq!(
+            error(&with("version", json!(9))),
+            "unsupported save state version 9"
+        );
+        assert_eq!(
+            error(&with("ram", json!("00".repeat(4095)))),
+            "ram should be 4096 bytes in hex"
+        );
+        assert_eq!(
+            error(&with("ram", json!("zz".repeat(4096)))),
+            "ram should be 4096 bytes in hex"
+        );
+        let mut video = valid["video"].clone();
+        video[0] = json!("x".repeat(DISP_WIDTH));
+        assert_eq!(error(&with("video", video)), "invalid pixel 'x' in video");
+        assert_eq!(
+            error(&with("video", json!([".", ".", "."]))),
+            "video should be 32 rows of 64 pixels"
+        );
+        assert_eq!(
+            error(&with("banks", json!(["00"]))),
+            format!("banks[0] should be {} bytes in hex", BANK_LEN)
+        );
+        assert_eq!(error(&with("bank", json!(2))), "bank 2 does not exist");
+        assert_eq!(
+            error(&with("sp", json!(17))),
+            "stack pointer 17 past the 16 stack entries"
+        );
+        assert!(error(&with("pc", json!("0x200"))).contains("invalid type"));
+    }
+}
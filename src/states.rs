@@ -0,0 +1,95 @@
+use chip_8::SaveState;
+use clap::{Args, Subcommand};
+use std::fs;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use tracing::error;
+
+#[derive(Args)]
+pub struct StateArgs {
+    #[command(subcommand)]
+    command: StateCommand,
+}
+
+#[derive(Subcommand)]
+enum StateCommand {
+    /// Print a save state, as text or as JSON
+    Dump {
+        /// Save state to print
+        file: PathBuf,
+
+        /// Print the JSON form, which `state build` reads back
+        #[arg(long)]
+        json: bool,
+    },
+    /// Write a save state from its JSON form
+    Build {
+        /// JSON written by `state dump --json` or by another tool
+        json: PathBuf,
+
+        /// Save state to write
+        output: PathBuf,
+    },
+}
+
+/// `chip-8 state`: convert save states to and from JSON.
+///
+/// Exits with 0 on success and 2 when a file could not be read or written.
+pub fn state(args: StateArgs) -> ExitCode {
+    match args.command {
+        StateCommand::Dump { file, json } => {
+            let state = match SaveState::load(&file) {
+                Ok(state) => state,
+                Err(err) => {
+                    error!(file = %file.display(), %err, "Could not read save state");
+                    return ExitCode::from(2);
+                }
+            };
+            if json {
+                println!("{}", state.to_json());
+            } else {
+                print!("{}", summary(&state));
+            }
+        }
+        StateCommand::Build { json, output } => {
+            let state = match fs::read_to_string(&json).and_then(|json| SaveState::from_json(&json))
+            {
+                Ok(state) => state,
+                Err(err) => {
+                    error!(file = %json.display(), %err, "Could not read save state JSON");
+                    return ExitCode::from(2);
+                }
+            };
+            if let Err(err) = state.save(&output) {
+                error!(file = %output.display(), %err, "Could not write save state");
+                return ExitCode::from(2);
+            }
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+/// Registers, stack and display of `state` for reading.
+fn summary(state: &SaveState) -> String {
+    let mut text = format!(
+        "PC {:#05x}  I {:#05x}  SP {}  DT {}  ST {}\n",
+        state.pc, state.i, state.sp, state.dt, state.st
+    );
+    for (reg, value) in state.registers.iter().enumerate() {
+        let separator = if reg % 8 == 7 { "\n" } else { "  " };
+        text += &format!("V{:X} {:#04x}{}", reg, value, separator);
+    }
+    let stack: Vec<String> = state.stack[..(state.sp as usize).min(state.stack.len())]
+        .iter()
+        .map(|addr| format!("{:#05x}", addr))
+        .collect();
+    text += &format!("stack [{}]\n", stack.join(", "));
+    if !state.banks.is_empty() {
+        text += &format!("bank {} of {}\n", state.bank, state.banks.len());
+    }
+    for row in state.video.chunks(chip_8::DISP_WIDTH) {
+        text.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+        text.push('\n');
+    }
+    text
+}
@@ -0,0 +1,73 @@
+use crate::i18n::tr;
+use crate::menu::{draw_entry, draw_footer};
+use crate::recent::RecentRoms;
+use crate::text::{GREY, LINE_HEIGHT, MARGIN, TEXT_SCALE, WHITE, dim, draw_text};
+use sdl2::keyboard::Keycode;
+use sdl2::render::WindowCanvas;
+use std::path::PathBuf;
+
+pub enum SwitcherAction {
+    None,
+    Close,
+    Load(PathBuf),
+}
+
+/// Quick switcher listing recently played ROMs over the paused game.
+pub struct Switcher {
+    selected: usize,
+}
+
+impl Switcher {
+    pub fn new() -> Switcher {
+        // The newest entry is the running game, so preselect the one before it.
+        Switcher { selected: 1 }
+    }
+
+    pub fn handle_key(&mut self, key: Keycode, recent: &RecentRoms) -> SwitcherAction {
+        let count = recent.paths().len();
+        match key {
+            Keycode::Escape => SwitcherAction::Close,
+            Keycode::Up if count > 0 => {
+                self.selected = (self.selected.min(count - 1) + count - 1) % count;
+                SwitcherAction::None
+            }
+            Keycode::Down if count > 0 => {
+                self.selected = (self.selected.min(count - 1) + 1) % count;
+                SwitcherAction::None
+            }
+            Keycode::Return | Keycode::KpEnter => match recent.paths().get(self.selected) {
+                Some(path) => SwitcherAction::Load(path.clone()),
+                None => SwitcherAction::Close,
+            },
+            _ => SwitcherAction::None,
+        }
+    }
+
+    pub fn draw(&self, canvas: &mut WindowCanvas, recent: &RecentRoms) {
+        dim(canvas);
+        draw_text(
+            canvas,
+            tr("switcher.title"),
+            MARGIN,
+            MARGIN,
+            TEXT_SCALE,
+            WHITE,
+        );
+
+        let mut y = MARGIN + 2 * LINE_HEIGHT;
+        if recent.paths().is_empty() {
+            draw_text(canvas, tr("switcher.empty"), MARGIN, y, TEXT_SCALE, GREY);
+        }
+        for (n, path) in recent.paths().iter().enumerate() {
+            let name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_else(|| path.display().to_string());
+            let selected = n == self.selected.min(recent.paths().len() - 1);
+            draw_entry(canvas, &name, MARGIN, y, selected);
+            y += LINE_HEIGHT;
+        }
+
+        draw_footer(canvas, tr("switcher.footer"));
+    }
+}
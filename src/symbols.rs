@@ -0,0 +1,81 @@
+use crate::cartridge::invalid;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::Path;
+
+/// Label addresses of an assembled program, for debuggers to show names instead of numbers.
+///
+/// The text form, written by `chip-8 asm --symbols`, has one label a line, the address in hex
+/// first and sorted by it:
+///
+/// ```text
+/// 0x0200 main
+/// 0x0248 draw-ship
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+    addresses: BTreeMap<String, u16>,
+}
+
+impl Symbols {
+    pub fn insert(&mut self, name: impl Into<String>, addr: u16) {
+        self.addresses.insert(name.into(), addr);
+    }
+
+    pub fn address(&self, name: &str) -> Option<u16> {
+        self.addresses.get(name).copied()
+    }
+
+    /// Name of a label at `addr`, the first in alphabetical order when there are several.
+    pub fn name(&self, addr: u16) -> Option<&str> {
+        self.addresses
+            .iter()
+            .find(|&(_, &at)| at == addr)
+            .map(|(name, _)| name.as_str())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.addresses.is_empty()
+    }
+
+    pub fn parse(text: &str) -> io::Result<Symbols> {
+        let mut symbols = Symbols::default();
+        for (index, line) in text.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let label = line
+                .split_once(char::is_whitespace)
+                .and_then(|(addr, name)| {
+                    let addr = addr.strip_prefix("0x").unwrap_or(addr);
+                    Some((u16::from_str_radix(addr, 16).ok()?, name.trim()))
+                });
+            match label {
+                Some((addr, name)) => symbols.insert(name, addr),
+                None => return Err(invalid(format!("line {}: expected `ADDR NAME`", index + 1))),
+            }
+        }
+        Ok(symbols)
+    }
+
+    pub fn load(path: &Path) -> io::Result<Symbols> {
+        Symbols::parse(&std::fs::read_to_string(path)?)
+    }
+}
+
+impl fmt::Display for Symbols {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut labels: Vec<(&u16, &String)> = self
+            .addresses
+            .iter()
+            .map(|(name, addr)| (addr, name))
+            .collect();
+        labels.sort();
+        for (addr, name) in labels {
+            writeln!(f, "{:#06x} {}", addr, name)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,209 @@
+//! Bitmap font text drawn over the scaled framebuffer, shared by all overlays.
+
+use sdl2::pixels::Color;
+use sdl2::rect::Rect;
+use sdl2::render::{BlendMode, WindowCanvas};
+
+pub const GLYPH_WIDTH: u32 = 5;
+pub const GLYPH_HEIGHT: u32 = 7;
+
+/// Horizontal distance between glyph origins, in font pixels.
+const ADVANCE: u32 = GLYPH_WIDTH + 1;
+
+/// Vertical distance between lines, in font pixels.
+const LINE_ADVANCE: u32 = GLYPH_HEIGHT + 3;
+
+/// Font pixel size of overlay text.
+pub const TEXT_SCALE: u32 = 3;
+pub const LINE_HEIGHT: i32 = (LINE_ADVANCE * TEXT_SCALE) as i32;
+
+/// Distance of overlay text from the window edges.
+pub const MARGIN: i32 = 30;
+
+pub const WHITE: Color = Color::RGB(255, 255, 255);
+pub const GREY: Color = Color::RGB(140, 140, 140);
+
+/// Backdrop of labels and dimmed screens, dark enough to read text over any game.
+const SHADE: Color = Color::RGBA(0, 0, 0, 200);
+
+/// Space between a label and the edge of its backdrop.
+const PADDING: i32 = 8;
+
+/// Where `draw_label` puts its text, `MARGIN` away from the window edges.
+#[derive(Clone, Copy)]
+pub enum Anchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+}
+
+/// Draw `text` with its top-left corner at `(x, y)`, `\n` starting a new line.
+/// Every font pixel is drawn as a `scale` x `scale` square.
+pub fn draw_text(canvas: &mut WindowCanvas, text: &str, x: i32, y: i32, scale: u32, color: Color) {
+    let mut rects = Vec::new();
+    for (line_no, line) in text.lines().enumerate() {
+        let origin_y = y + (line_no as u32 * LINE_ADVANCE * scale) as i32;
+        for (n, c) in line.chars().enumerate() {
+            let origin_x = x + (n as u32 * ADVANCE * scale) as i32;
+            for (row, bits) in glyph(c).iter().enumerate() {
+                for col in 0..GLYPH_WIDTH {
+                    if bits & (0x10 >> col) > 0 {
+                        rects.push(Rect::new(
+                            origin_x + (col * scale) as i32,
+                            origin_y + (row as u32 * scale) as i32,
+                            scale,
+                            scale,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    canvas.set_draw_color(color);
+    canvas.fill_rects(&rects).expect("Error when drawing text");
+}
+
+/// Width and height `text` takes up when drawn at `scale`.
+pub fn text_size(text: &str, scale: u32) -> (u32, u32) {
+    let columns = text
+        .lines()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+    let lines = text.lines().count().max(1) as u32;
+    (
+        (columns * ADVANCE).saturating_sub(1) * scale,
+        ((lines - 1) * LINE_ADVANCE + GLYPH_HEIGHT) * scale,
+    )
+}
+
+/// Size overlays lay themselves out in: the window size in points, which on high-DPI
+/// displays is smaller than the output in pixels by the canvas scale, see `window::ui_scale`.
+pub fn overlay_size(canvas: &WindowCanvas) -> (u32, u32) {
+    let (width, height) = canvas.output_size().expect("Error when drawing");
+    let (scale_x, scale_y) = canvas.scale();
+    (
+        (width as f32 / scale_x) as u32,
+        (height as f32 / scale_y) as u32,
+    )
+}
+
+/// Draw `text` at overlay size on a dark backdrop, placed by `anchor`.
+/// For status lines and notifications over the running game.
+pub fn draw_label(canvas: &mut WindowCanvas, text: &str, anchor: Anchor, color: Color) {
+    draw_label_scaled(canvas, text, anchor, TEXT_SCALE, color);
+}
+
+/// `draw_label` at font pixel size `scale`.
+pub fn draw_label_scaled(
+    canvas: &mut WindowCanvas,
+    text: &str,
+    anchor: Anchor,
+    scale: u32,
+    color: Color,
+) {
+    let (window_width, window_height) = overlay_size(canvas);
+    let (width, height) = text_size(text, scale);
+    let (x, y) = match anchor {
+        Anchor::TopLeft => (MARGIN, MARGIN),
+        Anchor::TopRight => (window_width as i32 - MARGIN - width as i32, MARGIN),
+        Anchor::BottomLeft => (MARGIN, window_height as i32 - MARGIN - height as i32),
+    };
+    shade(
+        canvas,
+        Rect::new(
+            x - PADDING,
+            y - PADDING,
+            width + 2 * PADDING as u32,
+            height + 2 * PADDING as u32,
+        ),
+    );
+    draw_text(canvas, text, x, y, scale, color);
+}
+
+/// Darken the whole window so overlay text stays readable over the game.
+pub fn dim(canvas: &mut WindowCanvas) {
+    let (width, height) = overlay_size(canvas);
+    shade(canvas, Rect::new(0, 0, width, height));
+}
+
+/// Darken `rect` as the backdrop of an overlay.
+pub fn shade(canvas: &mut WindowCanvas, rect: Rect) {
+    canvas.set_blend_mode(BlendMode::Blend);
+    canvas.set_draw_color(SHADE);
+    canvas.fill_rect(rect).expect("Error when drawing");
+    canvas.set_blend_mode(BlendMode::None);
+}
+
+/// 5x7 glyph rows, most significant of the low 5 bits is the leftmost column.
+/// Lowercase letters are drawn as uppercase, unknown characters as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    match c.to_ascii_uppercase() {
+        'A' => [0x0E, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'B' => [0x1E, 0x11, 0x11, 0x1E, 0x11, 0x11, 0x1E],
+        'C' => [0x0E, 0x11, 0x10, 0x10, 0x10, 0x11, 0x0E],
+        'D' => [0x1E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x1E],
+        'E' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x1F],
+        'F' => [0x1F, 0x10, 0x10, 0x1E, 0x10, 0x10, 0x10],
+        'G' => [0x0E, 0x11, 0x10, 0x17, 0x11, 0x11, 0x0F],
+        'H' => [0x11, 0x11, 0x11, 0x1F, 0x11, 0x11, 0x11],
+        'I' => [0x0E, 0x04, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        'J' => [0x07, 0x02, 0x02, 0x02, 0x02, 0x12, 0x0C],
+        'K' => [0x11, 0x12, 0x14, 0x18, 0x14, 0x12, 0x11],
+        'L' => [0x10, 0x10, 0x10, 0x10, 0x10, 0x10, 0x1F],
+        'M' => [0x11, 0x1B, 0x15, 0x15, 0x11, 0x11, 0x11],
+        'N' => [0x11, 0x11, 0x19, 0x15, 0x13, 0x11, 0x11],
+        'O' => [0x0E, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'P' => [0x1E, 0x11, 0x11, 0x1E, 0x10, 0x10, 0x10],
+        'Q' => [0x0E, 0x11, 0x11, 0x11, 0x15, 0x12, 0x0D],
+        'R' => [0x1E, 0x11, 0x11, 0x1E, 0x14, 0x12, 0x11],
+        'S' => [0x0F, 0x10, 0x10, 0x0E, 0x01, 0x01, 0x1E],
+        'T' => [0x1F, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        'U' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x11, 0x0E],
+        'V' => [0x11, 0x11, 0x11, 0x11, 0x11, 0x0A, 0x04],
+        'W' => [0x11, 0x11, 0x11, 0x15, 0x15, 0x15, 0x0A],
+        'X' => [0x11, 0x11, 0x0A, 0x04, 0x0A, 0x11, 0x11],
+        'Y' => [0x11, 0x11, 0x11, 0x0A, 0x04, 0x04, 0x04],
+        'Z' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x10, 0x1F],
+        '0' => [0x0E, 0x11, 0x13, 0x15, 0x19, 0x11, 0x0E],
+        '1' => [0x04, 0x0C, 0x04, 0x04, 0x04, 0x04, 0x0E],
+        '2' => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x08, 0x1F],
+        '3' => [0x1F, 0x02, 0x04, 0x02, 0x01, 0x11, 0x0E],
+        '4' => [0x02, 0x06, 0x0A, 0x12, 0x1F, 0x02, 0x02],
+        '5' => [0x1F, 0x10, 0x1E, 0x01, 0x01, 0x11, 0x0E],
+        '6' => [0x06, 0x08, 0x10, 0x1E, 0x11, 0x11, 0x0E],
+        '7' => [0x1F, 0x01, 0x02, 0x04, 0x08, 0x08, 0x08],
+        '8' => [0x0E, 0x11, 0x11, 0x0E, 0x11, 0x11, 0x0E],
+        '9' => [0x0E, 0x11, 0x11, 0x0F, 0x01, 0x02, 0x0C],
+        ' ' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00],
+        '.' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x0C, 0x0C],
+        ',' => [0x00, 0x00, 0x00, 0x00, 0x0C, 0x04, 0x08],
+        ':' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x0C, 0x00],
+        ';' => [0x00, 0x0C, 0x0C, 0x00, 0x0C, 0x04, 0x08],
+        '-' => [0x00, 0x00, 0x00, 0x1F, 0x00, 0x00, 0x00],
+        '_' => [0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1F],
+        '+' => [0x00, 0x04, 0x04, 0x1F, 0x04, 0x04, 0x00],
+        '=' => [0x00, 0x00, 0x1F, 0x00, 0x1F, 0x00, 0x00],
+        '*' => [0x00, 0x04, 0x15, 0x0E, 0x15, 0x04, 0x00],
+        '/' => [0x01, 0x01, 0x02, 0x04, 0x08, 0x10, 0x10],
+        '\\' => [0x10, 0x10, 0x08, 0x04, 0x02, 0x01, 0x01],
+        '|' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x04, 0x04],
+        '(' => [0x02, 0x04, 0x08, 0x08, 0x08, 0x04, 0x02],
+        ')' => [0x08, 0x04, 0x02, 0x02, 0x02, 0x04, 0x08],
+        '[' => [0x0E, 0x08, 0x08, 0x08, 0x08, 0x08, 0x0E],
+        ']' => [0x0E, 0x02, 0x02, 0x02, 0x02, 0x02, 0x0E],
+        '<' => [0x02, 0x04, 0x08, 0x10, 0x08, 0x04, 0x02],
+        '>' => [0x08, 0x04, 0x02, 0x01, 0x02, 0x04, 0x08],
+        '!' => [0x04, 0x04, 0x04, 0x04, 0x04, 0x00, 0x04],
+        '\'' => [0x04, 0x04, 0x08, 0x00, 0x00, 0x00, 0x00],
+        '"' => [0x0A, 0x0A, 0x0A, 0x00, 0x00, 0x00, 0x00],
+        '#' => [0x0A, 0x0A, 0x1F, 0x0A, 0x1F, 0x0A, 0x0A],
+        '$' => [0x04, 0x0F, 0x14, 0x0E, 0x05, 0x1E, 0x04],
+        '%' => [0x18, 0x19, 0x02, 0x04, 0x08, 0x13, 0x03],
+        '&' => [0x0C, 0x12, 0x14, 0x08, 0x15, 0x12, 0x0D],
+        '@' => [0x0E, 0x11, 0x01, 0x0D, 0x15, 0x15, 0x0E],
+        '^' => [0x04, 0x0A, 0x11, 0x00, 0x00, 0x00, 0x00],
+        '~' => [0x00, 0x00, 0x08, 0x15, 0x02, 0x00, 0x00],
+        _ => [0x0E, 0x11, 0x01, 0x02, 0x04, 0x00, 0x04],
+    }
+}
@@ -0,0 +1,130 @@
+use crate::remote;
+use crate::run::parse_quirks;
+use crate::soak::rom_files;
+use chip_8::palette::palette;
+use chip_8::{CaptureInfo, Cartridge, Chip8, Quirks};
+use clap::Args;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, info, warn};
+
+#[derive(Args)]
+pub struct ThumbsArgs {
+    /// Directory with the ROMs, every file in it
+    dir: PathBuf,
+
+    /// Frames to run each ROM for before taking its picture
+    #[arg(long, default_value_t = 300)]
+    frames: u64,
+
+    /// Directory for the thumbnails, `thumbs` in the ROM directory by default
+    #[arg(long, value_name = "DIR")]
+    out: Option<PathBuf>,
+
+    /// Size of a CHIP-8 pixel in the thumbnails
+    #[arg(long, default_value_t = 4)]
+    scale: usize,
+
+    /// Palette for ROMs without one of their own
+    #[arg(long, default_value = "classic")]
+    palette: String,
+
+    /// Quirks preset (modern, cosmac, superchip), instead of each ROM's own
+    #[arg(long, value_parser = parse_quirks)]
+    quirks: Option<Quirks>,
+
+    /// Seed for RND results
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
+
+    /// Instructions per 60 Hz frame, for ROMs that do not set their own
+    #[arg(long, default_value_t = 10)]
+    ticks_per_frame: u32,
+}
+
+/// `chip-8 thumbs`: run every ROM in a directory headless without input and save the
+/// display after `--frames` frames as `<ROM name>.png`, for ROM browser cover images.
+///
+/// Exits with 0 when every ROM got a thumbnail, 1 when some did not
+/// and 2 when the directories could not be read or created.
+pub fn thumbs(args: ThumbsArgs) -> ExitCode {
+    let roms = match rom_files(&args.dir) {
+        Ok(roms) => roms,
+        Err(err) => {
+            error!(dir = %args.dir.display(), %err, "Could not read ROM directory");
+            return ExitCode::from(2);
+        }
+    };
+    let out = args.out.clone().unwrap_or_else(|| args.dir.join("thumbs"));
+    if let Err(err) = fs::create_dir_all(&out) {
+        error!(dir = %out.display(), %err, "Could not create thumbnail directory");
+        return ExitCode::from(2);
+    }
+
+    let mut failed = false;
+    for rom in &roms {
+        let Some(name) = rom.file_name() else {
+            continue;
+        };
+        let path = out.join(name).with_added_extension("png");
+        match thumbnail(rom, &path, &args) {
+            Ok(()) => println!("{}", path.display()),
+            Err(err) => {
+                error!(rom = %rom.display(), %err, "No thumbnail");
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
+
+/// Run `rom` for `args.frames` and save the display to `path`.
+fn thumbnail(rom: &Path, path: &Path, args: &ThumbsArgs) -> Result<(), String> {
+    let cartridge = remote::local_file(rom)
+        .and_then(Cartridge::load)
+        .map_err(|err| format!("could not load: {}", err))?;
+    let mut chip8 = Chip8::new();
+    chip8.quirks = args
+        .quirks
+        .or_else(|| cartridge.metadata.quirks.as_ref()?.resolve())
+        .unwrap_or_default();
+    chip8.seed(args.seed);
+    chip8
+        .load_rom_bytes(&cartridge.rom)
+        .map_err(|err| format!("could not load: {}", err))?;
+
+    let ticks_per_frame = cartridge
+        .metadata
+        .ticks_per_frame
+        .unwrap_or(args.ticks_per_frame)
+        .max(1);
+    info!(rom = %rom.display(), "Running");
+    let mut frame = 0;
+    while frame < args.frames {
+        if let Err(err) = (0..ticks_per_frame).try_for_each(|_| chip8.cycle().map(|_| ())) {
+            // What it drew until then still makes a picture.
+            warn!(rom = %rom.display(), frame, %err, "Machine stopped");
+            break;
+        }
+        chip8.tick_timers();
+        frame += 1;
+    }
+
+    let colors = cartridge
+        .metadata
+        .palette()
+        .unwrap_or_else(|| palette(&args.palette).clone());
+    chip8
+        .save_screenshot(
+            path,
+            &colors,
+            args.scale.max(1),
+            &CaptureInfo::new(rom, &cartridge.rom, frame),
+        )
+        .map_err(|err| format!("could not write {}: {}", path.display(), err))
+}
@@ -0,0 +1,151 @@
+use crate::i18n::tr_with;
+use crate::text::{self, GLYPH_HEIGHT, GREY, MARGIN, draw_text, overlay_size};
+use sdl2::pixels::Color;
+use sdl2::rect::{Point, Rect};
+use sdl2::render::WindowCanvas;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Loop iterations the graph shows, one column each, newest on the right.
+const SAMPLES: usize = 120;
+
+/// Width of a column in points.
+const COLUMN_WIDTH: u32 = 2;
+
+/// Height of the plot in points, showing up to `SCALE_MAX`.
+const GRAPH_HEIGHT: u32 = 100;
+
+/// Time at the top of the plot, two 60 Hz frames.
+const SCALE_MAX: Duration = Duration::from_micros(33_333);
+
+/// Time of one 60 Hz frame, marked across the plot.
+const BUDGET: Duration = Duration::from_micros(16_667);
+
+/// Font pixel size of the legend.
+const LEGEND_SCALE: u32 = 2;
+
+const EMULATION: Color = Color::RGB(0x40, 0xE0, 0x40);
+const RENDERING: Color = Color::RGB(0x40, 0x80, 0xFF);
+const WAITING: Color = Color::RGB(0x60, 0x60, 0x60);
+const CYCLES: Color = Color::RGB(0xF0, 0xD0, 0x40);
+
+/// Rolling graph of where the time of every main loop iteration goes, for telling whether
+/// stutter comes from emulation, rendering or waiting on vsync. Every column stacks
+/// emulation (green) and rendering (blue) with the rest of the iteration (grey), which is
+/// vsync or the sleep until the next frame; the yellow line follows the instructions
+/// executed, relative to the most in view.
+#[derive(Default)]
+pub struct TimingGraph {
+    samples: VecDeque<Sample>,
+}
+
+#[derive(Clone, Copy)]
+struct Sample {
+    /// The whole iteration
+    total: Duration,
+    emulation: Duration,
+    rendering: Duration,
+    cycles: u64,
+}
+
+impl TimingGraph {
+    /// Add a loop iteration that took `total`, of which `emulation` went to running
+    /// `cycles` instructions and `rendering` to drawing before the present.
+    pub fn record(
+        &mut self,
+        total: Duration,
+        emulation: Duration,
+        rendering: Duration,
+        cycles: u64,
+    ) {
+        if self.samples.len() == SAMPLES {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(Sample {
+            total,
+            emulation,
+            rendering,
+            cycles,
+        });
+    }
+
+    /// Draw the graph in the bottom-right corner with a legend of the latest figures,
+    /// times in milliseconds.
+    pub fn draw(&self, canvas: &mut WindowCanvas) {
+        let (window_width, window_height) = overlay_size(canvas);
+        let width = SAMPLES as u32 * COLUMN_WIDTH;
+        let x = window_width as i32 - MARGIN - width as i32;
+        let y = window_height as i32 - MARGIN - GRAPH_HEIGHT as i32;
+        let line_height = (GLYPH_HEIGHT + 3) * LEGEND_SCALE;
+        let legend_height = 2 * line_height;
+        text::shade(
+            canvas,
+            Rect::new(
+                x,
+                y - legend_height as i32,
+                width,
+                GRAPH_HEIGHT + legend_height,
+            ),
+        );
+
+        let height = |time: Duration| {
+            ((time.as_secs_f64() / SCALE_MAX.as_secs_f64()).min(1.0) * GRAPH_HEIGHT as f64) as u32
+        };
+        let most_cycles = self.samples.iter().map(|sample| sample.cycles).max();
+        let most_cycles = most_cycles.unwrap_or(0).max(1);
+        let mut cycle_points = Vec::new();
+        for (n, sample) in self.samples.iter().enumerate() {
+            let column_x = x + (n as u32 * COLUMN_WIDTH) as i32;
+            let mut bottom = y + GRAPH_HEIGHT as i32;
+            let waiting = sample
+                .total
+                .saturating_sub(sample.emulation + sample.rendering);
+            for (time, color) in [
+                (sample.emulation, EMULATION),
+                (sample.rendering, RENDERING),
+                (waiting, WAITING),
+            ] {
+                let bar = height(time).min((bottom - y) as u32);
+                if bar > 0 {
+                    bottom -= bar as i32;
+                    canvas.set_draw_color(color);
+                    canvas
+                        .fill_rect(Rect::new(column_x, bottom, COLUMN_WIDTH, bar))
+                        .expect("Error when drawing");
+                }
+            }
+            let level = sample.cycles * (GRAPH_HEIGHT as u64 - 1) / most_cycles;
+            cycle_points.push(Point::new(
+                column_x,
+                y + GRAPH_HEIGHT as i32 - 1 - level as i32,
+            ));
+        }
+        canvas.set_draw_color(CYCLES);
+        canvas
+            .draw_lines(cycle_points.as_slice())
+            .expect("Error when drawing");
+        canvas.set_draw_color(GREY);
+        let budget_y = y + (GRAPH_HEIGHT - height(BUDGET)) as i32;
+        canvas
+            .draw_line((x, budget_y), (x + width as i32 - 1, budget_y))
+            .expect("Error when drawing");
+
+        if let Some(last) = self.samples.back() {
+            let ms = |key, time: Duration| {
+                let ms = time.as_secs_f64() * 1000.0;
+                tr_with(key, &[("ms", &format_args!("{:.1}", ms))])
+            };
+            let labels = [
+                (ms("timing.emulation", last.emulation), EMULATION),
+                (ms("timing.rendering", last.rendering), RENDERING),
+                (ms("timing.total", last.total), GREY),
+                (tr_with("timing.cycles", &[("count", &last.cycles)]), CYCLES),
+            ];
+            for (n, (label, color)) in labels.iter().enumerate() {
+                let label_x = x + 4 + (n as u32 % 2 * width / 2) as i32;
+                let label_y = y - legend_height as i32 + 2 + (n as u32 / 2 * line_height) as i32;
+                draw_text(canvas, label, label_x, label_y, LEGEND_SCALE, *color);
+            }
+        }
+    }
+}
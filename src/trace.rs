@@ -0,0 +1,67 @@
+use crate::chip8::{Chip8, SpriteDraw};
+use crate::disasm::disassemble;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+/// One executed instruction.
+#[derive(Serialize)]
+struct Record {
+    cycle: u64,
+    pc: u16,
+    opcode: u16,
+    mnemonic: String,
+    /// Registers changed by the instruction, `"V3": 12`
+    registers: BTreeMap<String, u8>,
+    i: u16,
+    dt: u8,
+    st: u8,
+    /// Sprite drawn by DXYN
+    #[serde(skip_serializing_if = "Option::is_none")]
+    sprite: Option<SpriteDraw>,
+}
+
+/// Writes the execution trace as JSON Lines, one object per instruction.
+///
+/// ```text
+/// {"cycle":0,"pc":512,"opcode":24832,"mnemonic":"LD V1, 0x00","registers":{},"i":0,"dt":0,"st":0}
+/// ```
+pub struct JsonTrace<W: Write> {
+    out: W,
+    cycle: u64,
+}
+
+impl<W: Write> JsonTrace<W> {
+    pub fn new(out: W) -> JsonTrace<W> {
+        JsonTrace { out, cycle: 0 }
+    }
+
+    /// Record the instruction just executed by `chip8`.
+    /// `pc` and `registers` are their values before the instruction ran.
+    pub fn record(&mut self, pc: u16, registers: &[u8; 16], chip8: &Chip8) -> io::Result<()> {
+        let record = Record {
+            cycle: self.cycle,
+            pc,
+            opcode: chip8.opcode,
+            mnemonic: disassemble(chip8.opcode),
+            registers: (0..16)
+                .filter(|&r| registers[r] != chip8.registers[r])
+                .map(|r| (format!("V{:X}", r), chip8.registers[r]))
+                .collect(),
+            i: chip8.i,
+            dt: chip8.dt,
+            st: chip8.st,
+            sprite: chip8
+                .last_draw()
+                .filter(|_| chip8.opcode & 0xF000 == 0xD000),
+        };
+        self.cycle += 1;
+
+        serde_json::to_writer(&mut self.out, &record)?;
+        self.out.write_all(b"\n")
+    }
+
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
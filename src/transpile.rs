@@ -0,0 +1,70 @@
+use crate::remote;
+use crate::run::parse_quirks;
+use chip_8::recompiler::{RUNTIME, transpile as recompile};
+use chip_8::{Cartridge, Quirks};
+use clap::Args;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{error, info};
+
+#[derive(Args)]
+pub struct TranspileArgs {
+    /// Path to the ROM to translate
+    rom: PathBuf,
+
+    /// Directory for the generated Cargo project
+    #[arg(long, short, value_name = "DIR")]
+    output: PathBuf,
+
+    /// Quirks preset baked into the generated code (modern, cosmac, superchip)
+    #[arg(long, default_value = "modern", value_parser = parse_quirks)]
+    quirks: Quirks,
+
+    /// Instructions per 60 Hz frame
+    #[arg(long, default_value_t = 10)]
+    ticks_per_frame: u32,
+}
+
+/// `chip-8 transpile`: translate a ROM into a standalone Rust program.
+pub fn transpile(args: TranspileArgs) -> ExitCode {
+    let rom = match remote::local_file(&args.rom).and_then(Cartridge::load) {
+        Ok(cartridge) => cartridge.rom,
+        Err(err) => {
+            error!(rom = %args.rom.display(), %err, "Could not load ROM");
+            return ExitCode::FAILURE;
+        }
+    };
+    let name = args
+        .rom
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "rom".to_string());
+
+    let project = match recompile(&rom, &name, args.quirks, args.ticks_per_frame.max(1)) {
+        Ok(project) => project,
+        Err(err) => {
+            error!(rom = %args.rom.display(), %err, "Could not translate ROM");
+            return ExitCode::FAILURE;
+        }
+    };
+    if let Err(err) = write_project(&args.output, &project.cargo_toml, &project.main_rs, &rom) {
+        error!(dir = %args.output.display(), %err, "Could not write project");
+        return ExitCode::FAILURE;
+    }
+    info!(
+        dir = %args.output.display(),
+        translated = project.translated,
+        "Project written, build it with `cargo run --release`"
+    );
+    ExitCode::SUCCESS
+}
+
+fn write_project(dir: &Path, cargo_toml: &str, main_rs: &str, rom: &[u8]) -> io::Result<()> {
+    fs::create_dir_all(dir.join("src"))?;
+    fs::write(dir.join("Cargo.toml"), cargo_toml)?;
+    fs::write(dir.join("src/main.rs"), main_rs)?;
+    fs::write(dir.join("src/runtime.rs"), RUNTIME)?;
+    fs::write(dir.join("src/rom.ch8"), rom)
+}
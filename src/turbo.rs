@@ -0,0 +1,55 @@
+use crate::config::TurboKey;
+use chip_8::Chip8;
+
+/// Emulated frames per second, the rate the keypad is toggled at.
+const FRAME_HZ: u32 = 60;
+
+struct Held {
+    turbo: TurboKey,
+    /// Emulated frame the host key went down in
+    since: u64,
+}
+
+/// Turbo buttons currently held down.
+///
+/// A held turbo key alternates its CHIP-8 key between pressed and released,
+/// so games that poll EX9E every frame see a fresh press each cycle.
+/// The phase follows emulated frames, not wall-clock time, so it survives pauses
+/// and stays in step with the game at any speed.
+#[derive(Default)]
+pub struct Turbo {
+    held: Vec<Held>,
+}
+
+impl Turbo {
+    pub fn key_down(&mut self, turbo: &TurboKey, frame: u64) {
+        // Host key repeat sends more key downs, those must not restart the phase.
+        if !self.held.iter().any(|held| held.turbo.key == turbo.key) {
+            self.held.push(Held {
+                turbo: turbo.clone(),
+                since: frame,
+            });
+        }
+    }
+
+    pub fn key_up(&mut self, turbo: &TurboKey, chip8: &mut Chip8) {
+        self.held.retain(|held| held.turbo.key != turbo.key);
+        chip8.set_key_value(turbo.chip8 as usize, 0);
+    }
+
+    /// Forget every held key, for when the game loses keyboard focus.
+    pub fn release_all(&mut self) {
+        self.held.clear();
+    }
+
+    /// Set the keypad for emulated `frame`, called before running it.
+    pub fn update(&self, frame: u64, chip8: &mut Chip8) {
+        for held in &self.held {
+            let hz = held.turbo.hz.clamp(1, FRAME_HZ / 2) as u64;
+            // Pressed for the first half of every period, starting with the key down.
+            let phase = (frame - held.since) * hz * 2 / FRAME_HZ as u64;
+            let pressed = phase.is_multiple_of(2);
+            chip8.set_key_value(held.turbo.chip8 as usize, pressed as u8);
+        }
+    }
+}
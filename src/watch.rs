@@ -0,0 +1,228 @@
+use chip_8::{Chip8, Symbols};
+use std::fmt;
+
+/// Binary operators from the loosest binding to the tightest, the ones of a level
+/// longest first so `<<` is not read as something else.
+const LEVELS: [&[(&str, Op)]; 7] = [
+    &[("==", Op::Eq), ("!=", Op::Ne)],
+    &[("|", Op::Or)],
+    &[("^", Op::Xor)],
+    &[("&", Op::And)],
+    &[("<<", Op::Shl), (">>", Op::Shr)],
+    &[("+", Op::Add), ("-", Op::Sub)],
+    &[("*", Op::Mul), ("/", Op::Div), ("%", Op::Rem)],
+];
+
+#[derive(Clone, Copy)]
+enum Op {
+    Eq,
+    Ne,
+    Or,
+    Xor,
+    And,
+    Shl,
+    Shr,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+}
+
+enum Expr {
+    Number(u32),
+    Register(u8),
+    I,
+    Pc,
+    Sp,
+    Dt,
+    St,
+    /// Byte of RAM at the address, written `[I + 1]`
+    Byte(Box<Expr>),
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+}
+
+/// Expression over machine state the monitor evaluates at every stop, like `[I + V1] * 2`.
+///
+/// Values are `V0`-`VF`, `I`, `PC`, `SP`, `DT`, `ST`, decimal or `0x` hex numbers, label names
+/// standing for their address and RAM bytes `[addr]`, combined with `+ - * / % & | ^ << >>`,
+/// unary `-` and `~` and parentheses, in 32 bit arithmetic that wraps around. `==` and `!=`
+/// bind loosest and give 1 or 0, for conditions like `[0x3F4] == 0`.
+pub struct Watch {
+    text: String,
+    expr: Expr,
+}
+
+impl Watch {
+    pub fn parse(text: &str, symbols: &Symbols) -> Result<Watch, String> {
+        let mut parser = Parser {
+            rest: text,
+            symbols,
+        };
+        let expr = parser.expr(0)?;
+        if !parser.rest.trim().is_empty() {
+            return Err(format!("unexpected `{}`", parser.rest.trim()));
+        }
+        Ok(Watch {
+            text: text.trim().to_string(),
+            expr,
+        })
+    }
+
+    pub fn eval(&self, chip8: &Chip8) -> Result<u32, String> {
+        eval(&self.expr, chip8)
+    }
+}
+
+impl fmt::Display for Watch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+fn eval(expr: &Expr, chip8: &Chip8) -> Result<u32, String> {
+    Ok(match expr {
+        Expr::Number(value) => *value,
+        Expr::Register(reg) => chip8.registers[*reg as usize] as u32,
+        Expr::I => chip8.i as u32,
+        Expr::Pc => chip8.pc as u32,
+        Expr::Sp => chip8.sp as u32,
+        Expr::Dt => chip8.dt as u32,
+        Expr::St => chip8.st as u32,
+        Expr::Byte(addr) => {
+            let addr = eval(addr, chip8)?;
+            match chip8.bus.ram.get(addr as usize) {
+                Some(&byte) => byte as u32,
+                None => return Err(format!("{:#x} is outside of RAM", addr)),
+            }
+        }
+        Expr::Neg(value) => eval(value, chip8)?.wrapping_neg(),
+        Expr::Not(value) => !eval(value, chip8)?,
+        Expr::Binary(op, left, right) => {
+            let (left, right) = (eval(left, chip8)?, eval(right, chip8)?);
+            match op {
+                Op::Eq => u32::from(left == right),
+                Op::Ne => u32::from(left != right),
+                Op::Or => left | right,
+                Op::Xor => left ^ right,
+                Op::And => left & right,
+                Op::Shl => left.wrapping_shl(right),
+                Op::Shr => left.wrapping_shr(right),
+                Op::Add => left.wrapping_add(right),
+                Op::Sub => left.wrapping_sub(right),
+                Op::Mul => left.wrapping_mul(right),
+                Op::Div | Op::Rem if right == 0 => return Err("division by zero".to_string()),
+                Op::Div => left / right,
+                Op::Rem => left % right,
+            }
+        }
+    })
+}
+
+/// Recursive descent over the text left to read.
+struct Parser<'a> {
+    rest: &'a str,
+    symbols: &'a Symbols,
+}
+
+impl Parser<'_> {
+    /// Expression of operators at `level` and tighter binding ones.
+    fn expr(&mut self, level: usize) -> Result<Expr, String> {
+        let Some(ops) = LEVELS.get(level) else {
+            return self.unary();
+        };
+        let mut expr = self.expr(level + 1)?;
+        while let Some(&(symbol, op)) = ops
+            .iter()
+            .find(|(symbol, _)| self.rest.trim_start().starts_with(symbol))
+        {
+            self.rest = &self.rest.trim_start()[symbol.len()..];
+            let right = self.expr(level + 1)?;
+            expr = Expr::Binary(op, Box::new(expr), Box::new(right));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        self.rest = self.rest.trim_start();
+        let Some(first) = self.rest.chars().next() else {
+            return Err("expression ends early".to_string());
+        };
+        match first {
+            '-' | '~' | '(' | '[' => self.rest = &self.rest[1..],
+            _ => return self.value(),
+        }
+        match first {
+            '-' => Ok(Expr::Neg(Box::new(self.unary()?))),
+            '~' => Ok(Expr::Not(Box::new(self.unary()?))),
+            '(' => {
+                let expr = self.expr(0)?;
+                self.close(')')?;
+                Ok(expr)
+            }
+            _ => {
+                let addr = self.expr(0)?;
+                self.close(']')?;
+                Ok(Expr::Byte(Box::new(addr)))
+            }
+        }
+    }
+
+    fn close(&mut self, bracket: char) -> Result<(), String> {
+        match self.rest.trim_start().strip_prefix(bracket) {
+            Some(rest) => {
+                self.rest = rest;
+                Ok(())
+            }
+            None => Err(format!("missing `{}`", bracket)),
+        }
+    }
+
+    /// Number, register or label.
+    fn value(&mut self) -> Result<Expr, String> {
+        let len = self
+            .rest
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == '-'))
+            .unwrap_or(self.rest.len());
+        // Labels may have dashes in them, which otherwise subtract.
+        let mut word = &self.rest[..len];
+        while !word.is_empty() && self.symbols.address(word).is_none() {
+            match word.rfind('-') {
+                Some(dash) => word = &word[..dash],
+                None => break,
+            }
+        }
+        if word.is_empty() {
+            return Err(format!("unexpected `{}`", self.rest));
+        }
+        self.rest = &self.rest[word.len()..];
+
+        if let Some(addr) = self.symbols.address(word) {
+            return Ok(Expr::Number(addr as u32));
+        }
+        let upper = word.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => return Ok(Expr::I),
+            "PC" => return Ok(Expr::Pc),
+            "SP" => return Ok(Expr::Sp),
+            "DT" => return Ok(Expr::Dt),
+            "ST" => return Ok(Expr::St),
+            _ => {}
+        }
+        if let Some(reg) = upper.strip_prefix('V')
+            && reg.len() == 1
+            && let Ok(reg) = u8::from_str_radix(reg, 16)
+        {
+            return Ok(Expr::Register(reg));
+        }
+        let number = match upper.strip_prefix("0X") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => word.parse(),
+        };
+        number
+            .map(Expr::Number)
+            .map_err(|_| format!("unknown value `{}`", word))
+    }
+}
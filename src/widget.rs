@@ -0,0 +1,221 @@
+//! egui widget running a CHIP-8 machine inside another application.
+//!
+//! ```ignore
+//! let mut chip8 = Chip8::new();
+//! chip8.load_rom("pong.ch8")?;
+//! let mut widget = Chip8Widget::new(chip8);
+//! // every frame:
+//! ui.add(&mut widget);
+//! ```
+
+use crate::chip8::{Chip8, Chip8Error, DISP_HEIGHT, DISP_WIDTH};
+use crate::pacer::FramePacer;
+use crate::palette::{PALETTES, Palette};
+use crate::state::SaveState;
+use egui::{
+    Color32, ColorImage, EventFilter, Key, Rect, Response, Sense, StrokeKind, TextureHandle,
+    TextureOptions, Ui, Widget, pos2, vec2,
+};
+
+/// Rate of the timer clock.
+const FRAME_HZ: u64 = 60;
+
+/// COSMAC VIP keypad on the left of a QWERTY keyboard, the same layout as the standalone frontend.
+pub const DEFAULT_KEYMAP: [(Key, u8); 16] = [
+    (Key::Num1, 0x1),
+    (Key::Num2, 0x2),
+    (Key::Num3, 0x3),
+    (Key::Num4, 0xC),
+    (Key::Q, 0x4),
+    (Key::W, 0x5),
+    (Key::E, 0x6),
+    (Key::R, 0xD),
+    (Key::A, 0x7),
+    (Key::S, 0x8),
+    (Key::D, 0x9),
+    (Key::F, 0xE),
+    (Key::Z, 0xA),
+    (Key::X, 0x0),
+    (Key::C, 0xB),
+    (Key::V, 0xF),
+];
+
+/// Display with play/pause and reset controls. The keyboard drives the keypad
+/// only while the display has focus, click it to focus.
+pub struct Chip8Widget {
+    chip8: Chip8,
+
+    /// Machine as it was handed over, with its ROM, banks and seed; restored by `reset`
+    start: SaveState,
+
+    /// Instructions per frame
+    pub ticks_per_frame: u32,
+
+    /// Colors of the display
+    pub palette: &'static Palette,
+
+    /// egui key for every CHIP-8 key, see `DEFAULT_KEYMAP`
+    pub keymap: [(Key, u8); 16],
+
+    paused: bool,
+    halted: Option<Chip8Error>,
+    pacer: FramePacer,
+    texture: Option<TextureHandle>,
+}
+
+impl Chip8Widget {
+    /// Widget running `chip8`, which should already have its ROM loaded.
+    pub fn new(chip8: Chip8) -> Chip8Widget {
+        Chip8Widget {
+            start: chip8.save_state(),
+            chip8,
+            ticks_per_frame: 10,
+            palette: &PALETTES[0],
+            keymap: DEFAULT_KEYMAP,
+            paused: false,
+            halted: None,
+            pacer: FramePacer::new(FRAME_HZ),
+            texture: None,
+        }
+    }
+
+    pub fn chip8(&self) -> &Chip8 {
+        &self.chip8
+    }
+
+    pub fn chip8_mut(&mut self) -> &mut Chip8 {
+        &mut self.chip8
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_paused(&mut self, paused: bool) {
+        if self.paused && !paused {
+            // Don't make up for the time spent paused.
+            self.pacer = FramePacer::new(FRAME_HZ);
+        }
+        self.paused = paused;
+    }
+
+    /// Error the machine stopped on, if any.
+    pub fn halted(&self) -> Option<Chip8Error> {
+        self.halted
+    }
+
+    /// Restart the program from the state it was handed over in, keeping the quirks.
+    pub fn reset(&mut self) {
+        let quirks = self.chip8.quirks;
+        self.chip8.load_state(&self.start);
+        self.chip8.quirks = quirks;
+        self.halted = None;
+        self.pacer = FramePacer::new(FRAME_HZ);
+    }
+
+    fn step(&mut self) {
+        for _ in 0..self.pacer.frames_due() {
+            for _ in 0..self.ticks_per_frame {
+                if let Err(err) = self.chip8.cycle() {
+                    self.halted = Some(err);
+                    return;
+                }
+            }
+            self.chip8.tick_timers();
+        }
+    }
+
+    fn controls(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            let label = if self.paused { "Play" } else { "Pause" };
+            if ui.button(label).clicked() {
+                self.set_paused(!self.paused);
+            }
+            if ui.button("Reset").clicked() {
+                self.reset();
+            }
+            if let Some(err) = self.halted {
+                ui.colored_label(Color32::RED, err.to_string());
+            }
+        });
+    }
+
+    fn display(&mut self, ui: &mut Ui) -> Response {
+        let width = ui.available_width();
+        let size = vec2(width, width * DISP_HEIGHT as f32 / DISP_WIDTH as f32);
+        let response = ui.allocate_response(size, Sense::click());
+        if response.clicked() {
+            response.request_focus();
+        }
+
+        if response.has_focus() {
+            // Keep arrows and tab for the game instead of moving focus.
+            ui.memory_mut(|memory| {
+                memory.set_focus_lock_filter(
+                    response.id,
+                    EventFilter {
+                        tab: true,
+                        horizontal_arrows: true,
+                        vertical_arrows: true,
+                        escape: false,
+                    },
+                )
+            });
+            ui.input(|input| {
+                for &(key, chip8_key) in &self.keymap {
+                    let down = input.key_down(key);
+                    self.chip8.set_key_value(chip8_key as usize, down as u8);
+                }
+            });
+        } else {
+            self.chip8.keypad = [0; 16];
+        }
+
+        let image = ColorImage::from_rgba_unmultiplied(
+            [DISP_WIDTH, DISP_HEIGHT],
+            &self.chip8.render_rgba(self.palette),
+        );
+        let texture = match &mut self.texture {
+            Some(texture) => {
+                texture.set(image, TextureOptions::NEAREST);
+                texture
+            }
+            None => self.texture.insert(ui.ctx().load_texture(
+                "chip8",
+                image,
+                TextureOptions::NEAREST,
+            )),
+        };
+
+        let painter = ui.painter_at(response.rect);
+        painter.image(
+            texture.id(),
+            response.rect,
+            Rect::from_min_max(pos2(0.0, 0.0), pos2(1.0, 1.0)),
+            Color32::WHITE,
+        );
+        if response.has_focus() {
+            painter.rect_stroke(
+                response.rect,
+                0.0,
+                ui.visuals().selection.stroke,
+                StrokeKind::Inside,
+            );
+        }
+        response
+    }
+}
+
+impl Widget for &mut Chip8Widget {
+    fn ui(self, ui: &mut Ui) -> Response {
+        if !self.paused && self.halted.is_none() {
+            self.step();
+            ui.ctx().request_repaint();
+        }
+        ui.vertical(|ui| {
+            self.controls(ui);
+            self.display(ui)
+        })
+        .inner
+    }
+}
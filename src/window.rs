@@ -0,0 +1,250 @@
+use chip_8::{DISP_HEIGHT, DISP_WIDTH};
+use sdl2::VideoSubsystem;
+use sdl2::event::WindowEvent;
+use sdl2::hint;
+use sdl2::rect::Rect;
+use sdl2::video::{FullscreenType, Window, WindowPos};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+/// How the display is fitted into the window.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Scaling {
+    /// As large as fits, keeping the 2:1 shape
+    #[default]
+    Fit,
+    /// Whole multiples of the display size only, every pixel the same size
+    Integer,
+    /// Fill the window, distorting the shape
+    Stretch,
+}
+
+impl Scaling {
+    pub const ALL: [Scaling; 3] = [Scaling::Fit, Scaling::Integer, Scaling::Stretch];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Scaling::Fit => "fit",
+            Scaling::Integer => "integer",
+            Scaling::Stretch => "stretch",
+        }
+    }
+
+    /// Part of a `width` x `height` output the display is drawn to, centered.
+    pub fn viewport(self, (width, height): (u32, u32)) -> Rect {
+        let (display_width, display_height) = (DISP_WIDTH as u32, DISP_HEIGHT as u32);
+        let (w, h) = match self {
+            Scaling::Stretch => (width, height),
+            Scaling::Fit if width * display_height > height * display_width => {
+                (height * display_width / display_height, height)
+            }
+            Scaling::Fit => (width, width * display_height / display_width),
+            Scaling::Integer => {
+                let scale = (width / display_width).min(height / display_height).max(1);
+                (display_width * scale, display_height * scale)
+            }
+        };
+        let x = (width as i32 - w as i32) / 2;
+        let y = (height as i32 - h as i32) / 2;
+        Rect::new(x, y, w.max(1), h.max(1))
+    }
+}
+
+/// How the window is rendered on high-DPI displays.
+#[derive(Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PixelScaling {
+    /// Render at the display's full resolution, keeping the display sharp and overlays
+    /// at the same size in points as on other screens
+    #[default]
+    Native,
+    /// Render at the window size in points and let the system enlarge it, blurry on high-DPI displays
+    Logical,
+}
+
+/// Window placement remembered between runs, see `Config::window`.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WindowState {
+    /// Size when not fullscreen
+    pub width: u32,
+    pub height: u32,
+
+    /// Top-left corner on the desktop when not fullscreen, centered when unset
+    pub x: Option<i32>,
+    pub y: Option<i32>,
+
+    /// Display the window was last on, used when the saved position is off every display
+    pub display: i32,
+
+    pub fullscreen: bool,
+
+    /// Display to go fullscreen on, 0 being the primary one; the display the window is on when unset
+    pub fullscreen_display: Option<i32>,
+
+    pub scaling: Scaling,
+
+    /// Applies from the next start
+    pub pixels: PixelScaling,
+}
+
+impl Default for WindowState {
+    fn default() -> WindowState {
+        WindowState {
+            width: 960,
+            height: 480,
+            x: None,
+            y: None,
+            display: 0,
+            fullscreen: false,
+            fullscreen_display: None,
+            scaling: Scaling::Fit,
+            pixels: PixelScaling::Native,
+        }
+    }
+}
+
+impl WindowState {
+    /// Tell SDL how to handle high-DPI displays, before the video subsystem starts.
+    pub fn set_hints(&self) {
+        // Only Windows reads these; elsewhere `allow_highdpi` alone decides.
+        let (awareness, scaling) = match self.pixels {
+            PixelScaling::Native => ("permonitorv2", "1"),
+            PixelScaling::Logical => ("unaware", "0"),
+        };
+        hint::set("SDL_WINDOWS_DPI_AWARENESS", awareness);
+        hint::set("SDL_WINDOWS_DPI_SCALING", scaling);
+    }
+
+    /// Window placed as it was left.
+    pub fn open(&self, video: &VideoSubsystem, title: &str) -> Window {
+        let mut builder = video.window(title, self.width.max(1), self.height.max(1));
+        builder.resizable().opengl();
+        if self.pixels == PixelScaling::Native {
+            builder.allow_highdpi();
+        }
+        let fullscreen_display = self
+            .fullscreen_display
+            .filter(|&display| self.fullscreen && display_exists(video, display));
+        match (fullscreen_display, self.x, self.y) {
+            (Some(display), _, _) => builder.position(centered_on(display), centered_on(display)),
+            (None, Some(x), Some(y)) if on_a_display(video, x, y) => builder.position(x, y),
+            _ => builder.position(centered_on(self.display), centered_on(self.display)),
+        };
+        if self.fullscreen {
+            builder.fullscreen_desktop();
+        }
+        let mut window = builder.build().unwrap();
+        let _ = window.set_minimum_size(DISP_WIDTH as u32, DISP_HEIGHT as u32);
+        window
+    }
+
+    /// Follow the window being moved or resized.
+    pub fn track(&mut self, window: &Window, event: &WindowEvent) {
+        if window.fullscreen_state() != FullscreenType::Off {
+            return;
+        }
+        match *event {
+            WindowEvent::Moved(x, y) => {
+                self.x = Some(x);
+                self.y = Some(y);
+            }
+            WindowEvent::Resized(width, height) | WindowEvent::SizeChanged(width, height) => {
+                self.width = width as u32;
+                self.height = height as u32;
+            }
+            _ => {}
+        }
+    }
+
+    /// Take the display and fullscreen state before the window closes.
+    pub fn capture(&mut self, window: &Window) {
+        self.fullscreen = window.fullscreen_state() != FullscreenType::Off;
+        if let Ok(display) = window.display_index() {
+            self.display = display;
+        }
+        if !self.fullscreen {
+            (self.width, self.height) = window.size();
+            let (x, y) = window.position();
+            (self.x, self.y) = (Some(x), Some(y));
+        }
+    }
+
+    pub fn toggle_fullscreen(&mut self, window: &mut Window) {
+        self.fullscreen = window.fullscreen_state() == FullscreenType::Off;
+        if self.fullscreen
+            && let Some(index) = self.fullscreen_display
+        {
+            if !display_exists(window.subsystem(), index) {
+                warn!(
+                    display = index,
+                    "No such display, going fullscreen on the current one"
+                );
+            } else if window.display_index() != Ok(index) {
+                move_to(window, index);
+            }
+        }
+        let mode = if self.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Off
+        };
+        if let Err(err) = window.set_fullscreen(mode) {
+            warn!(%err, "Could not change fullscreen");
+        }
+    }
+
+    /// Move the window to the next display, staying fullscreen if it was.
+    pub fn next_display(&mut self, window: &mut Window) {
+        let displays = window.subsystem().num_video_displays().unwrap_or(1);
+        if displays < 2 {
+            return;
+        }
+        let index = (window.display_index().unwrap_or(0) + 1) % displays;
+        let fullscreen = window.fullscreen_state();
+        if fullscreen != FullscreenType::Off {
+            let _ = window.set_fullscreen(FullscreenType::Off);
+        }
+        move_to(window, index);
+        if fullscreen != FullscreenType::Off {
+            let _ = window.set_fullscreen(fullscreen);
+        }
+        info!(display = index, "Moved to display");
+        self.display = index;
+        if self.fullscreen_display.is_some() {
+            self.fullscreen_display = Some(index);
+        }
+    }
+}
+
+/// Window position that centers it on `display`.
+fn centered_on(display: i32) -> i32 {
+    sdl2::sys::SDL_WINDOWPOS_CENTERED_MASK as i32 | display
+}
+
+fn move_to(window: &mut Window, display: i32) {
+    let position = WindowPos::Positioned(centered_on(display));
+    window.set_position(position, position);
+}
+
+fn display_exists(video: &VideoSubsystem, display: i32) -> bool {
+    (0..video.num_video_displays().unwrap_or(0)).contains(&display)
+}
+
+/// Output pixels per window point, 2 on most high-DPI displays and 1 elsewhere.
+/// Overlays are drawn at this scale so they keep their size in points.
+pub fn ui_scale(window: &Window) -> f32 {
+    let (points, _) = window.size();
+    let (pixels, _) = window.drawable_size();
+    (pixels as f32 / points.max(1) as f32).max(1.0)
+}
+
+fn on_a_display(video: &VideoSubsystem, x: i32, y: i32) -> bool {
+    let displays = video.num_video_displays().unwrap_or(0);
+    (0..displays).any(|display| {
+        video
+            .display_bounds(display)
+            .is_ok_and(|bounds| bounds.contains_point((x, y)))
+    })
+}